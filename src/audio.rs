@@ -0,0 +1,209 @@
+//! Audio inputs and outputs (VIDIOC_ENUMAUDIO / G_AUDIO / S_AUDIO and the
+//! output counterparts)
+//!
+//! A video input or output lists the audio channels it can be combined with
+//! in its `audioset` mask.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/audio.html
+
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+use crate::input::{Input, Output};
+
+/// One entry of VIDIOC_ENUMAUDIO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioInput {
+    pub index: u32,
+    pub name: String,
+    /// `V4L2_AUDCAP_*`
+    pub capability: u32,
+    /// `V4L2_AUDMODE_*`
+    pub mode: u32,
+}
+
+impl AudioInput {
+    pub fn is_stereo(&self) -> bool {
+        self.capability & crate::V4L2_AUDCAP_STEREO != 0
+    }
+
+    /// Automatic volume level is supported
+    pub fn supports_avl(&self) -> bool {
+        self.capability & crate::V4L2_AUDCAP_AVL != 0
+    }
+
+    pub fn avl(&self) -> bool {
+        self.mode & crate::V4L2_AUDMODE_AVL != 0
+    }
+}
+
+impl From<&crate::v4l2_audio> for AudioInput {
+    fn from(a: &crate::v4l2_audio) -> Self {
+        AudioInput {
+            index: a.index,
+            name: c_string(&a.name),
+            capability: a.capability,
+            mode: a.mode,
+        }
+    }
+}
+
+/// One entry of VIDIOC_ENUMAUDOUT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioOutput {
+    pub index: u32,
+    pub name: String,
+    /// `V4L2_AUDCAP_*`
+    pub capability: u32,
+    /// `V4L2_AUDMODE_*`
+    pub mode: u32,
+}
+
+impl AudioOutput {
+    pub fn is_stereo(&self) -> bool {
+        self.capability & crate::V4L2_AUDCAP_STEREO != 0
+    }
+}
+
+impl From<&crate::v4l2_audioout> for AudioOutput {
+    fn from(a: &crate::v4l2_audioout) -> Self {
+        AudioOutput {
+            index: a.index,
+            name: c_string(&a.name),
+            capability: a.capability,
+            mode: a.mode,
+        }
+    }
+}
+
+/// Indices of the bits set in an `audioset` mask, lowest first
+pub fn audioset_indices(audioset: u32) -> Vec<u32> {
+    (0..32).filter(|i| audioset & (1 << i) != 0).collect()
+}
+
+pub fn enum_audio_inputs<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<AudioInput>> {
+    let mut inputs = Vec::new();
+    for index in 0.. {
+        let mut a: crate::v4l2_audio = unsafe { mem::zeroed() };
+        a.index = index;
+        match ioctl(dev, codes::VIDIOC_ENUMAUDIO, &mut a) {
+            Ok(()) => inputs.push(AudioInput::from(&a)),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(inputs)
+}
+
+/// VIDIOC_G_AUDIO: the audio input currently selected
+pub fn audio_input<D: Ioctl + ?Sized>(dev: &D) -> io::Result<AudioInput> {
+    let mut a: crate::v4l2_audio = unsafe { mem::zeroed() };
+    ioctl(dev, codes::VIDIOC_G_AUDIO, &mut a)?;
+    Ok(AudioInput::from(&a))
+}
+
+/// VIDIOC_S_AUDIO; `mode` takes `V4L2_AUDMODE_*` flags
+pub fn set_audio_input<D: Ioctl + ?Sized>(dev: &D, index: u32, mode: u32) -> io::Result<()> {
+    let mut a: crate::v4l2_audio = unsafe { mem::zeroed() };
+    a.index = index;
+    a.mode = mode;
+    ioctl(dev, codes::VIDIOC_S_AUDIO, &mut a)
+}
+
+pub fn enum_audio_outputs<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<AudioOutput>> {
+    let mut outputs = Vec::new();
+    for index in 0.. {
+        let mut a: crate::v4l2_audioout = unsafe { mem::zeroed() };
+        a.index = index;
+        match ioctl(dev, codes::VIDIOC_ENUMAUDOUT, &mut a) {
+            Ok(()) => outputs.push(AudioOutput::from(&a)),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(outputs)
+}
+
+/// VIDIOC_G_AUDOUT: the audio output currently selected
+pub fn audio_output<D: Ioctl + ?Sized>(dev: &D) -> io::Result<AudioOutput> {
+    let mut a: crate::v4l2_audioout = unsafe { mem::zeroed() };
+    ioctl(dev, codes::VIDIOC_G_AUDOUT, &mut a)?;
+    Ok(AudioOutput::from(&a))
+}
+
+/// VIDIOC_S_AUDOUT
+pub fn set_audio_output<D: Ioctl + ?Sized>(dev: &D, index: u32, mode: u32) -> io::Result<()> {
+    let mut a: crate::v4l2_audioout = unsafe { mem::zeroed() };
+    a.index = index;
+    a.mode = mode;
+    ioctl(dev, codes::VIDIOC_S_AUDOUT, &mut a)
+}
+
+/// Make the selected audio input one that belongs to `input`.
+///
+/// The current audio input is kept if `input.audioset` allows it, otherwise
+/// the lowest allowed one is selected with its mode preserved. Returns `None`
+/// for video inputs without audio.
+pub fn follow_input<D: Ioctl + ?Sized>(dev: &D, input: &Input) -> io::Result<Option<AudioInput>> {
+    let allowed = audioset_indices(input.audioset);
+    let Some(&first) = allowed.first() else {
+        return Ok(None);
+    };
+    let current = audio_input(dev)?;
+    if allowed.contains(&current.index) {
+        return Ok(Some(current));
+    }
+    set_audio_input(dev, first, current.mode)?;
+    audio_input(dev).map(Some)
+}
+
+/// Output counterpart of [`follow_input`]
+pub fn follow_output<D: Ioctl + ?Sized>(
+    dev: &D,
+    output: &Output,
+) -> io::Result<Option<AudioOutput>> {
+    let allowed = audioset_indices(output.audioset);
+    let Some(&first) = allowed.first() else {
+        return Ok(None);
+    };
+    let current = audio_output(dev)?;
+    if allowed.contains(&current.index) {
+        return Ok(Some(current));
+    }
+    set_audio_output(dev, first, current.mode)?;
+    audio_output(dev).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+    use crate::input::{self, InputStatus};
+
+    #[test]
+    fn audio_follows_video_input() {
+        assert_eq!(audioset_indices(0b1010), [1, 3]);
+
+        let dev = FakeDevice::default();
+        dev.inputs
+            .borrow_mut()
+            .extend([("Composite", InputStatus(0)), ("S-Video", InputStatus(0))]);
+        dev.audio_inputs.borrow_mut().extend(["Line 1", "Line 2"]);
+        let audio = enum_audio_inputs(&dev).unwrap();
+        assert_eq!(audio.len(), 2);
+        assert!(audio[0].is_stereo() && audio[0].supports_avl());
+
+        let svideo = input::input(&dev, 1).unwrap();
+        assert_eq!(audioset_indices(svideo.audioset), [1]);
+        set_audio_input(&dev, 0, crate::V4L2_AUDMODE_AVL).unwrap();
+        let selected = follow_input(&dev, &svideo).unwrap().unwrap();
+        assert_eq!(selected.name, "Line 2");
+        assert!(selected.avl());
+        assert_eq!(audio_input(&dev).unwrap().index, 1);
+
+        let mut silent = svideo;
+        silent.audioset = 0;
+        assert_eq!(follow_input(&dev, &silent).unwrap(), None);
+    }
+}
@@ -0,0 +1,287 @@
+//! Streaming I/O with memory mapped buffers (VIDIOC_REQBUFS, VIDIOC_QUERYBUF,
+//! VIDIOC_QBUF, VIDIOC_DQBUF, VIDIOC_STREAMON / VIDIOC_STREAMOFF)
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/mmap.html
+
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::slice;
+
+use crate::codes;
+use crate::device::{ioctl, Device, Ioctl};
+
+/// Something buffer memory can be mapped from.
+///
+/// Implemented by [`Device`] through `v4l2_mmap`; tests hand out their own memory.
+pub trait Mmap: Ioctl {
+    /// Map `length` bytes at the `offset` reported by VIDIOC_QUERYBUF.
+    ///
+    /// # Safety
+    /// The mapping must be released with [`Mmap::munmap`] before the buffers
+    /// are freed.
+    unsafe fn mmap(&self, offset: u32, length: usize) -> io::Result<*mut u8>;
+
+    /// # Safety
+    /// `ptr` and `length` must come from a previous [`Mmap::mmap`].
+    unsafe fn munmap(&self, ptr: *mut u8, length: usize);
+}
+
+impl Mmap for Device {
+    unsafe fn mmap(&self, offset: u32, length: usize) -> io::Result<*mut u8> {
+        let ptr = crate::v4l2_mmap(
+            ptr::null_mut(),
+            length,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            self.as_raw_fd(),
+            offset as i64,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ptr as *mut u8)
+    }
+
+    unsafe fn munmap(&self, ptr: *mut u8, length: usize) {
+        crate::v4l2_munmap(ptr as *mut libc::c_void, length);
+    }
+}
+
+impl<T: Mmap + ?Sized> Mmap for &T {
+    unsafe fn mmap(&self, offset: u32, length: usize) -> io::Result<*mut u8> {
+        (**self).mmap(offset, length)
+    }
+
+    unsafe fn munmap(&self, ptr: *mut u8, length: usize) {
+        (**self).munmap(ptr, length)
+    }
+}
+
+pub fn is_multiplanar(type_: u32) -> bool {
+    type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
+        || type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE
+}
+
+/// `struct timeval` in buffers to nanoseconds, as the kernel converts it
+/// for `reference_ts` style fields
+pub(crate) fn timeval_to_ns(tv: &crate::timeval) -> u64 {
+    tv.tv_sec as u64 * 1_000_000_000 + tv.tv_usec as u64 * 1000
+}
+
+/// Inverse of [`timeval_to_ns`], truncated to microseconds
+pub(crate) fn ns_to_timeval(ns: u64) -> crate::timeval {
+    let mut tv: crate::timeval = unsafe { mem::zeroed() };
+    tv.tv_sec = (ns / 1_000_000_000) as _;
+    tv.tv_usec = (ns % 1_000_000_000 / 1000) as _;
+    tv
+}
+
+/// A buffer handed back by VIDIOC_DQBUF
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dequeued {
+    pub index: usize,
+    /// `V4L2_BUF_FLAG_*`
+    pub flags: u32,
+    pub sequence: u32,
+    /// In nanoseconds; on memory-to-memory devices copied from the OUTPUT
+    /// buffer the data came from
+    pub timestamp: u64,
+    /// Bytes used in each plane
+    pub bytesused: Vec<u32>,
+}
+
+impl Dequeued {
+    /// The driver could not fill the buffer properly
+    pub fn is_error(&self) -> bool {
+        self.flags & crate::V4L2_BUF_FLAG_ERROR != 0
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.flags & crate::V4L2_BUF_FLAG_LAST != 0
+    }
+}
+
+struct Plane {
+    ptr: *mut u8,
+    length: usize,
+}
+
+/// MMAP buffers of one queue, mapped while the value lives
+pub struct Buffers<'a, D: Mmap + ?Sized> {
+    dev: &'a D,
+    type_: u32,
+    capabilities: u32,
+    buffers: Vec<Vec<Plane>>,
+}
+
+impl<'a, D: Mmap + ?Sized> Buffers<'a, D> {
+    /// Allocate up to `count` buffers on the `type_` queue and map them
+    pub fn new(dev: &'a D, type_: u32, count: u32) -> io::Result<Self> {
+        let mut req = crate::uapi::v4l2_requestbuffers {
+            count,
+            type_,
+            memory: crate::v4l2_memory_V4L2_MEMORY_MMAP,
+            ..Default::default()
+        };
+        ioctl(dev, codes::VIDIOC_REQBUFS, &mut req)?;
+
+        // On error, dropping `buffers` unmaps what is mapped and frees the queue
+        let mut buffers = Buffers {
+            dev,
+            type_,
+            capabilities: req.capabilities,
+            buffers: Vec::new(),
+        };
+        for index in 0..req.count as usize {
+            let mut planes: [crate::v4l2_plane; crate::VIDEO_MAX_PLANES as usize] =
+                unsafe { mem::zeroed() };
+            let mut buf = buffers.raw(index, &mut planes);
+            ioctl(dev, codes::VIDIOC_QUERYBUF, &mut buf)?;
+            let layout: Vec<(u32, u32)> = if is_multiplanar(type_) {
+                planes[..buf.length as usize]
+                    .iter()
+                    .map(|p| (unsafe { p.m.mem_offset }, p.length))
+                    .collect()
+            } else {
+                vec![(unsafe { buf.m.offset }, buf.length)]
+            };
+            buffers.buffers.push(Vec::new());
+            for (offset, length) in layout {
+                let ptr = unsafe { dev.mmap(offset, length as usize)? };
+                buffers.buffers[index].push(Plane {
+                    ptr,
+                    length: length as usize,
+                });
+            }
+        }
+        Ok(buffers)
+    }
+
+    fn raw(&self, index: usize, planes: &mut [crate::v4l2_plane]) -> crate::v4l2_buffer {
+        let mut buf: crate::v4l2_buffer = unsafe { mem::zeroed() };
+        buf.index = index as u32;
+        buf.type_ = self.type_;
+        buf.memory = crate::v4l2_memory_V4L2_MEMORY_MMAP;
+        if is_multiplanar(self.type_) {
+            buf.m.planes = planes.as_mut_ptr();
+            buf.length = planes.len() as u32;
+        }
+        buf
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn buf_type(&self) -> u32 {
+        self.type_
+    }
+
+    /// `V4L2_BUF_CAP_*` reported by VIDIOC_REQBUFS
+    pub fn capabilities(&self) -> u32 {
+        self.capabilities
+    }
+
+    pub fn supports_requests(&self) -> bool {
+        self.capabilities & crate::uapi::V4L2_BUF_CAP_SUPPORTS_REQUESTS != 0
+    }
+
+    pub fn planes(&self, index: usize) -> usize {
+        self.buffers[index].len()
+    }
+
+    pub fn plane(&self, index: usize, plane: usize) -> &[u8] {
+        let p = &self.buffers[index][plane];
+        unsafe { slice::from_raw_parts(p.ptr, p.length) }
+    }
+
+    pub fn plane_mut(&mut self, index: usize, plane: usize) -> &mut [u8] {
+        let p = &self.buffers[index][plane];
+        unsafe { slice::from_raw_parts_mut(p.ptr, p.length) }
+    }
+
+    /// VIDIOC_QBUF. `timestamp` is in nanoseconds with microsecond
+    /// resolution; `request` binds the buffer to a request instead of
+    /// queueing it right away.
+    pub fn queue(
+        &self,
+        index: usize,
+        bytesused: &[u32],
+        timestamp: u64,
+        flags: u32,
+        request: Option<RawFd>,
+    ) -> io::Result<()> {
+        let mut planes: [crate::v4l2_plane; crate::VIDEO_MAX_PLANES as usize] =
+            unsafe { mem::zeroed() };
+        let count = self.buffers[index].len();
+        let mut buf = self.raw(index, &mut planes[..count]);
+        if is_multiplanar(self.type_) {
+            for (plane, &used) in planes.iter_mut().zip(bytesused) {
+                plane.bytesused = used;
+            }
+        } else {
+            buf.bytesused = bytesused.first().copied().unwrap_or(0);
+        }
+        buf.timestamp = ns_to_timeval(timestamp);
+        buf.flags = flags;
+        if let Some(fd) = request {
+            // Same size whatever the headers declare; request_fd was reserved
+            let mut buf: crate::uapi::v4l2_buffer = unsafe { mem::transmute(buf) };
+            buf.flags |= crate::uapi::V4L2_BUF_FLAG_REQUEST_FD;
+            buf.request_fd = fd;
+            return ioctl(self.dev, codes::VIDIOC_QBUF, &mut buf);
+        }
+        ioctl(self.dev, codes::VIDIOC_QBUF, &mut buf)
+    }
+
+    /// VIDIOC_DQBUF; blocks unless the device was opened non-blocking
+    pub fn dequeue(&self) -> io::Result<Dequeued> {
+        let mut planes: [crate::v4l2_plane; crate::VIDEO_MAX_PLANES as usize] =
+            unsafe { mem::zeroed() };
+        let mut buf = self.raw(0, &mut planes);
+        ioctl(self.dev, codes::VIDIOC_DQBUF, &mut buf)?;
+        let bytesused = if is_multiplanar(self.type_) {
+            planes[..buf.length as usize]
+                .iter()
+                .map(|p| p.bytesused)
+                .collect()
+        } else {
+            vec![buf.bytesused]
+        };
+        Ok(Dequeued {
+            index: buf.index as usize,
+            flags: buf.flags,
+            sequence: buf.sequence,
+            timestamp: timeval_to_ns(&buf.timestamp),
+            bytesused,
+        })
+    }
+
+    pub fn stream_on(&self) -> io::Result<()> {
+        let mut type_ = self.type_ as libc::c_int;
+        ioctl(self.dev, codes::VIDIOC_STREAMON, &mut type_)
+    }
+
+    /// Stop streaming; all buffers return to the application
+    pub fn stream_off(&self) -> io::Result<()> {
+        let mut type_ = self.type_ as libc::c_int;
+        ioctl(self.dev, codes::VIDIOC_STREAMOFF, &mut type_)
+    }
+}
+
+impl<D: Mmap + ?Sized> Drop for Buffers<'_, D> {
+    fn drop(&mut self) {
+        for plane in self.buffers.iter().flatten() {
+            unsafe { self.dev.munmap(plane.ptr, plane.length) };
+        }
+        let mut req: crate::v4l2_requestbuffers = unsafe { mem::zeroed() };
+        req.type_ = self.type_;
+        req.memory = crate::v4l2_memory_V4L2_MEMORY_MMAP;
+        let _ = ioctl(self.dev, codes::VIDIOC_REQBUFS, &mut req);
+    }
+}
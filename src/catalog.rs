@@ -0,0 +1,752 @@
+//! Catalog of the standard control ids
+//!
+//! Names, classes, types and menu items follow the kernel's
+//! `v4l2-ctrls-defs.c`, so lookups by canonical name match what `v4l2-ctl`
+//! prints.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/extended-controls.html
+
+use std::fmt;
+use std::io;
+
+use crate::control::{self, canonical_name, ControlInfo};
+use crate::device::{c_string, ioctl, Ioctl};
+
+/// `enum v4l2_ctrl_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlType {
+    Integer,
+    Boolean,
+    Menu,
+    Button,
+    Integer64,
+    CtrlClass,
+    String,
+    Bitmask,
+    IntegerMenu,
+    U8,
+    U16,
+    U32,
+    Area,
+    /// Any other compound type
+    Compound(u32),
+}
+
+impl ControlType {
+    pub fn from_raw(type_: u32) -> ControlType {
+        match type_ {
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER => ControlType::Integer,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BOOLEAN => ControlType::Boolean,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU => ControlType::Menu,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BUTTON => ControlType::Button,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 => ControlType::Integer64,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_CTRL_CLASS => ControlType::CtrlClass,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING => ControlType::String,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BITMASK => ControlType::Bitmask,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU => ControlType::IntegerMenu,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U8 => ControlType::U8,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U16 => ControlType::U16,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U32 => ControlType::U32,
+            crate::uapi::V4L2_CTRL_TYPE_AREA => ControlType::Area,
+            other => ControlType::Compound(other),
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            ControlType::Integer => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER,
+            ControlType::Boolean => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BOOLEAN,
+            ControlType::Menu => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU,
+            ControlType::Button => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BUTTON,
+            ControlType::Integer64 => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64,
+            ControlType::CtrlClass => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_CTRL_CLASS,
+            ControlType::String => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING,
+            ControlType::Bitmask => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BITMASK,
+            ControlType::IntegerMenu => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU,
+            ControlType::U8 => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U8,
+            ControlType::U16 => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U16,
+            ControlType::U32 => crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U32,
+            ControlType::Area => crate::uapi::V4L2_CTRL_TYPE_AREA,
+            ControlType::Compound(raw) => raw,
+        }
+    }
+
+    /// Short name as printed by `v4l2-ctl -l`
+    pub fn short_name(self) -> &'static str {
+        match self {
+            ControlType::Integer => "int",
+            ControlType::Boolean => "bool",
+            ControlType::Menu => "menu",
+            ControlType::Button => "button",
+            ControlType::Integer64 => "int64",
+            ControlType::CtrlClass => "ctrl_class",
+            ControlType::String => "str",
+            ControlType::Bitmask => "bitmask",
+            ControlType::IntegerMenu => "intmenu",
+            ControlType::U8 => "u8",
+            ControlType::U16 => "u16",
+            ControlType::U32 => "u32",
+            ControlType::Area => "area",
+            ControlType::Compound(_) => "compound",
+        }
+    }
+
+    /// Value travels in `v4l2_ext_control.value`/`value64`
+    pub fn is_scalar(self) -> bool {
+        matches!(
+            self,
+            ControlType::Integer
+                | ControlType::Boolean
+                | ControlType::Menu
+                | ControlType::Button
+                | ControlType::Integer64
+                | ControlType::Bitmask
+                | ControlType::IntegerMenu
+        )
+    }
+}
+
+/// A standard control known to the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlDef {
+    pub id: u32,
+    /// Name as reported by the kernel in `v4l2_query_ext_ctrl.name`
+    pub name: &'static str,
+    pub type_: ControlType,
+    /// Menu item names indexed by value, empty for non-menus and driver-defined menus
+    pub menu: &'static [&'static str],
+}
+
+impl ControlDef {
+    /// `V4L2_CTRL_CLASS_*` this control belongs to
+    pub fn class(&self) -> u32 {
+        class_of(self.id)
+    }
+
+    pub fn canonical_name(&self) -> String {
+        canonical_name(self.name)
+    }
+
+    pub fn menu_item(&self, value: i64) -> Option<&'static str> {
+        usize::try_from(value)
+            .ok()
+            .and_then(|i| self.menu.get(i).copied())
+    }
+
+    /// Check `value` against the type and menu of this control, without
+    /// knowing the driver's range
+    pub fn validate(&self, value: i64) -> Result<(), InvalidValue> {
+        match self.type_ {
+            ControlType::Boolean if value != 0 && value != 1 => Err(InvalidValue::NotBoolean),
+            ControlType::Menu if !self.menu.is_empty() && self.menu_item(value).is_none() => {
+                Err(InvalidValue::NoSuchMenuItem)
+            }
+            ControlType::Integer
+            | ControlType::Boolean
+            | ControlType::Menu
+            | ControlType::IntegerMenu
+            | ControlType::Bitmask
+                if i32::try_from(value).is_err() =>
+            {
+                Err(InvalidValue::OutOfRange)
+            }
+            t if !t.is_scalar() => Err(InvalidValue::NotScalar),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why [`validate`] rejected a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidValue {
+    /// Compound, string and class controls have no integer value
+    NotScalar,
+    ReadOnly,
+    NotBoolean,
+    OutOfRange,
+    /// Not a multiple of `step` away from `minimum`
+    BadStep,
+    /// Bits set outside of `maximum` for a bitmask control
+    BadMask,
+    NoSuchMenuItem,
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            InvalidValue::NotScalar => "control does not take an integer value",
+            InvalidValue::ReadOnly => "control is read-only",
+            InvalidValue::NotBoolean => "boolean control takes 0 or 1",
+            InvalidValue::OutOfRange => "value out of range",
+            InvalidValue::BadStep => "value is not a multiple of the step",
+            InvalidValue::BadMask => "value sets bits outside of the mask",
+            InvalidValue::NoSuchMenuItem => "no such menu item",
+        })
+    }
+}
+
+impl std::error::Error for InvalidValue {}
+
+/// Validate `value` against what the driver reported for the control, and
+/// the catalog's menu when the id is a standard one
+pub fn validate(info: &ControlInfo, value: i64) -> Result<(), InvalidValue> {
+    let type_ = ControlType::from_raw(info.type_);
+    if !type_.is_scalar() {
+        return Err(InvalidValue::NotScalar);
+    }
+    if !info.is_writable() {
+        return Err(InvalidValue::ReadOnly);
+    }
+    match type_ {
+        ControlType::Button => return Ok(()),
+        ControlType::Boolean if value != 0 && value != 1 => return Err(InvalidValue::NotBoolean),
+        ControlType::Bitmask => {
+            return if value as u64 & !(info.maximum as u64) != 0 {
+                Err(InvalidValue::BadMask)
+            } else {
+                Ok(())
+            };
+        }
+        _ => {}
+    }
+    if value < info.minimum || value > info.maximum {
+        return Err(InvalidValue::OutOfRange);
+    }
+    if matches!(type_, ControlType::Integer | ControlType::Integer64) && info.step > 1 {
+        let offset = value.wrapping_sub(info.minimum) as u64;
+        if offset / info.step * info.step != offset {
+            return Err(InvalidValue::BadStep);
+        }
+    }
+    if let Some(def) = by_id(info.id) {
+        if type_ == ControlType::Menu && !def.menu.is_empty() && def.menu_item(value).is_none() {
+            return Err(InvalidValue::NoSuchMenuItem);
+        }
+    }
+    Ok(())
+}
+
+/// `V4L2_CTRL_ID2CLASS`
+pub fn class_of(id: u32) -> u32 {
+    id & 0x0fff_0000
+}
+
+/// Heading `v4l2-ctl` prints for a control class
+pub fn class_name(class: u32) -> Option<&'static str> {
+    Some(match class {
+        crate::V4L2_CTRL_CLASS_USER => "User Controls",
+        crate::V4L2_CTRL_CLASS_MPEG => "Codec Controls",
+        crate::V4L2_CTRL_CLASS_CAMERA => "Camera Controls",
+        crate::V4L2_CTRL_CLASS_FM_TX => "FM Radio Modulator Controls",
+        crate::V4L2_CTRL_CLASS_FLASH => "Flash Controls",
+        crate::V4L2_CTRL_CLASS_JPEG => "JPEG Compression Controls",
+        crate::V4L2_CTRL_CLASS_IMAGE_SOURCE => "Image Source Controls",
+        crate::V4L2_CTRL_CLASS_IMAGE_PROC => "Image Processing Controls",
+        crate::V4L2_CTRL_CLASS_DV => "Digital Video Controls",
+        crate::V4L2_CTRL_CLASS_FM_RX => "FM Radio Receiver Controls",
+        crate::V4L2_CTRL_CLASS_RF_TUNER => "RF Tuner Controls",
+        crate::V4L2_CTRL_CLASS_DETECT => "Detection Controls",
+        _ => return None,
+    })
+}
+
+/// Look up a standard control by id
+pub fn by_id(id: u32) -> Option<&'static ControlDef> {
+    CONTROLS.iter().find(|def| def.id == id)
+}
+
+/// Look up a standard control by canonical (`exposure_time_absolute`) or
+/// kernel (`Exposure Time, Absolute`) name
+pub fn by_name(name: &str) -> Option<&'static ControlDef> {
+    let wanted = canonical_name(name);
+    CONTROLS
+        .iter()
+        .find(|def| canonical_name(def.name) == wanted)
+}
+
+/// All controls of one class
+pub fn in_class(class: u32) -> impl Iterator<Item = &'static ControlDef> {
+    CONTROLS.iter().filter(move |def| def.class() == class)
+}
+
+/// A menu entry reported by VIDIOC_QUERYMENU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem {
+    Name(String),
+    /// Item of a V4L2_CTRL_TYPE_INTEGER_MENU control
+    Value(i64),
+}
+
+impl fmt::Display for MenuItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuItem::Name(name) => f.write_str(name),
+            MenuItem::Value(value) => write!(f, "{} ({:#x})", value, value),
+        }
+    }
+}
+
+/// Enumerate the valid items of a menu control; indices the driver skips are left out
+pub fn query_menu<D: Ioctl + ?Sized>(dev: &D, info: &ControlInfo) -> Vec<(u32, MenuItem)> {
+    let integer = info.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU;
+    let (min, max) = (info.minimum.max(0) as u32, info.maximum.max(0) as u32);
+    (min..=max)
+        .filter_map(|index| {
+            let mut q: crate::v4l2_querymenu = unsafe { std::mem::zeroed() };
+            q.id = info.id;
+            q.index = index;
+            ioctl(dev, crate::codes::VIDIOC_QUERYMENU, &mut q).ok()?;
+            let item = if integer {
+                MenuItem::Value(unsafe { q.__bindgen_anon_1.value })
+            } else {
+                MenuItem::Name(c_string(unsafe { &q.__bindgen_anon_1.name }))
+            };
+            Some((index, item))
+        })
+        .collect()
+}
+
+fn flag_names(flags: u32) -> String {
+    const NAMES: [(u32, &str); 12] = [
+        (crate::V4L2_CTRL_FLAG_DISABLED, "disabled"),
+        (crate::V4L2_CTRL_FLAG_GRABBED, "grabbed"),
+        (crate::V4L2_CTRL_FLAG_READ_ONLY, "read-only"),
+        (crate::V4L2_CTRL_FLAG_UPDATE, "update"),
+        (crate::V4L2_CTRL_FLAG_INACTIVE, "inactive"),
+        (crate::V4L2_CTRL_FLAG_SLIDER, "slider"),
+        (crate::V4L2_CTRL_FLAG_WRITE_ONLY, "write-only"),
+        (crate::V4L2_CTRL_FLAG_VOLATILE, "volatile"),
+        (crate::V4L2_CTRL_FLAG_HAS_PAYLOAD, "has-payload"),
+        (crate::V4L2_CTRL_FLAG_EXECUTE_ON_WRITE, "execute-on-write"),
+        (crate::V4L2_CTRL_FLAG_MODIFY_LAYOUT, "modify-layout"),
+        (crate::uapi::V4L2_CTRL_FLAG_DYNAMIC_ARRAY, "dynamic-array"),
+    ];
+    NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format one control the way `v4l2-ctl -l` does; `value` is `None` for
+/// write-only and compound controls
+pub fn format_control(info: &ControlInfo, value: Option<&str>) -> String {
+    let type_ = ControlType::from_raw(info.type_);
+    let mut line = format!(
+        "{:>31} {:#010x} ({}){:pad$}:",
+        canonical_name(&info.name),
+        info.id,
+        type_.short_name(),
+        "",
+        pad = 7usize.saturating_sub(type_.short_name().len())
+    );
+    match type_ {
+        ControlType::Boolean => line += &format!(" default={}", info.default_value),
+        ControlType::Menu | ControlType::IntegerMenu => {
+            line += &format!(
+                " min={} max={} default={}",
+                info.minimum, info.maximum, info.default_value
+            )
+        }
+        ControlType::Bitmask => {
+            line += &format!(
+                " max={:#010x} default={:#010x}",
+                info.maximum, info.default_value
+            )
+        }
+        ControlType::Button | ControlType::CtrlClass => {}
+        ControlType::String => {
+            line += &format!(
+                " min={} max={} step={}",
+                info.minimum, info.maximum, info.step
+            )
+        }
+        _ if info.is_array() => {
+            let dims: Vec<_> = info.dims.iter().map(|d| format!("[{}]", d)).collect();
+            line += &format!(
+                " min={} max={} step={} default={} dims={}",
+                info.minimum,
+                info.maximum,
+                info.step,
+                info.default_value,
+                dims.concat()
+            )
+        }
+        _ => {
+            line += &format!(
+                " min={} max={} step={} default={}",
+                info.minimum, info.maximum, info.step, info.default_value
+            )
+        }
+    }
+    if let Some(value) = value {
+        line += &format!(" value={}", value);
+    }
+    if info.flags != 0 {
+        line += &format!(" flags={}", flag_names(info.flags));
+    }
+    line
+}
+
+fn read_value<D: Ioctl + ?Sized>(dev: &D, info: &ControlInfo) -> io::Result<String> {
+    let type_ = ControlType::from_raw(info.type_);
+    Ok(match type_ {
+        ControlType::Integer64 => control::get_value64(dev, info.id)?.to_string(),
+        ControlType::Bitmask => format!("{:#010x}", control::get_value(dev, info.id)? as u32),
+        _ => {
+            let v = control::get_value(dev, info.id)?;
+            match by_id(info.id).and_then(|def| def.menu_item(v as i64)) {
+                Some(item) if type_ == ControlType::Menu => format!("{} ({})", v, item),
+                _ => v.to_string(),
+            }
+        }
+    })
+}
+
+/// List all controls of `dev` like `v4l2-ctl -L`: class headings, one line
+/// per control and the menu items below menu controls
+pub fn list_controls<D: Ioctl + ?Sized>(dev: &D) -> io::Result<String> {
+    let mut out = String::new();
+    for info in control::query_ext_ctrls(dev)? {
+        let type_ = ControlType::from_raw(info.type_);
+        if info.is_disabled() {
+            continue;
+        }
+        if type_ == ControlType::CtrlClass {
+            out += &format!("\n{}\n\n", info.name);
+            continue;
+        }
+        let value = if !info.is_readable() || !type_.is_scalar() || type_ == ControlType::Button {
+            None
+        } else {
+            // One control the driver refuses to read, e.g. EBUSY while it is
+            // grabbed, shouldn't hide the rest of the listing
+            Some(read_value(dev, &info).unwrap_or_else(|e| format!("<{}>", e)))
+        };
+        out += &format_control(&info, value.as_deref());
+        out.push('\n');
+        if matches!(type_, ControlType::Menu | ControlType::IntegerMenu) {
+            for (index, item) in query_menu(dev, &info) {
+                out += &format!("\t\t\t\t{}: {}\n", index, item);
+            }
+        }
+    }
+    Ok(out)
+}
+
+macro_rules! controls {
+    ($(($($id:ident)::+, $name:expr, $type:ident $(, [$($item:expr),* $(,)?])?)),* $(,)?) => {
+        /// Every control known to the catalog, ordered by id within each class
+        pub static CONTROLS: &[ControlDef] = &[
+            $(ControlDef {
+                id: crate::$($id)::+,
+                name: $name,
+                type_: ControlType::$type,
+                menu: &[$($($item),*)?],
+            }),*
+        ];
+    };
+}
+
+controls! {
+    // User class
+    (V4L2_CID_BRIGHTNESS, "Brightness", Integer),
+    (V4L2_CID_CONTRAST, "Contrast", Integer),
+    (V4L2_CID_SATURATION, "Saturation", Integer),
+    (V4L2_CID_HUE, "Hue", Integer),
+    (V4L2_CID_AUDIO_VOLUME, "Volume", Integer),
+    (V4L2_CID_AUDIO_BALANCE, "Balance", Integer),
+    (V4L2_CID_AUDIO_BASS, "Bass", Integer),
+    (V4L2_CID_AUDIO_TREBLE, "Treble", Integer),
+    (V4L2_CID_AUDIO_MUTE, "Mute", Boolean),
+    (V4L2_CID_AUDIO_LOUDNESS, "Loudness", Boolean),
+    (V4L2_CID_BLACK_LEVEL, "Black Level", Integer),
+    (V4L2_CID_AUTO_WHITE_BALANCE, "White Balance, Automatic", Boolean),
+    (V4L2_CID_DO_WHITE_BALANCE, "Do White Balance", Button),
+    (V4L2_CID_RED_BALANCE, "Red Balance", Integer),
+    (V4L2_CID_BLUE_BALANCE, "Blue Balance", Integer),
+    (V4L2_CID_GAMMA, "Gamma", Integer),
+    (V4L2_CID_EXPOSURE, "Exposure", Integer),
+    (V4L2_CID_AUTOGAIN, "Gain, Automatic", Boolean),
+    (V4L2_CID_GAIN, "Gain", Integer),
+    (V4L2_CID_HFLIP, "Horizontal Flip", Boolean),
+    (V4L2_CID_VFLIP, "Vertical Flip", Boolean),
+    (V4L2_CID_POWER_LINE_FREQUENCY, "Power Line Frequency", Menu,
+        ["Disabled", "50 Hz", "60 Hz", "Auto"]),
+    (V4L2_CID_HUE_AUTO, "Hue, Automatic", Boolean),
+    (V4L2_CID_WHITE_BALANCE_TEMPERATURE, "White Balance Temperature", Integer),
+    (V4L2_CID_SHARPNESS, "Sharpness", Integer),
+    (V4L2_CID_BACKLIGHT_COMPENSATION, "Backlight Compensation", Integer),
+    (V4L2_CID_CHROMA_AGC, "Chroma AGC", Boolean),
+    (V4L2_CID_COLOR_KILLER, "Color Killer", Boolean),
+    (V4L2_CID_COLORFX, "Color Effects", Menu,
+        ["None", "Black & White", "Sepia", "Negative", "Emboss", "Sketch", "Sky Blue",
+         "Grass Green", "Skin Whiten", "Vivid", "Aqua", "Art Freeze", "Silhouette",
+         "Solarization", "Antique", "Set Cb/Cr"]),
+    (V4L2_CID_AUTOBRIGHTNESS, "Brightness, Automatic", Boolean),
+    (V4L2_CID_BAND_STOP_FILTER, "Band-Stop Filter", Integer),
+    (V4L2_CID_ROTATE, "Rotate", Integer),
+    (V4L2_CID_BG_COLOR, "Background Color", Integer),
+    (V4L2_CID_CHROMA_GAIN, "Chroma Gain", Integer),
+    (V4L2_CID_ILLUMINATORS_1, "Illuminator 1", Boolean),
+    (V4L2_CID_ILLUMINATORS_2, "Illuminator 2", Boolean),
+    (V4L2_CID_MIN_BUFFERS_FOR_CAPTURE, "Min Number of Capture Buffers", Integer),
+    (V4L2_CID_MIN_BUFFERS_FOR_OUTPUT, "Min Number of Output Buffers", Integer),
+    (V4L2_CID_ALPHA_COMPONENT, "Alpha Component", Integer),
+    (V4L2_CID_COLORFX_CBCR, "Color Effects, CbCr", Integer),
+
+    // Camera class
+    (V4L2_CID_EXPOSURE_AUTO, "Auto Exposure", Menu,
+        ["Auto Mode", "Manual Mode", "Shutter Priority Mode", "Aperture Priority Mode"]),
+    (V4L2_CID_EXPOSURE_ABSOLUTE, "Exposure Time, Absolute", Integer),
+    (V4L2_CID_EXPOSURE_AUTO_PRIORITY, "Exposure, Dynamic Framerate", Boolean),
+    (V4L2_CID_PAN_RELATIVE, "Pan, Relative", Integer),
+    (V4L2_CID_TILT_RELATIVE, "Tilt, Relative", Integer),
+    (V4L2_CID_PAN_RESET, "Pan, Reset", Button),
+    (V4L2_CID_TILT_RESET, "Tilt, Reset", Button),
+    (V4L2_CID_PAN_ABSOLUTE, "Pan, Absolute", Integer),
+    (V4L2_CID_TILT_ABSOLUTE, "Tilt, Absolute", Integer),
+    (V4L2_CID_FOCUS_ABSOLUTE, "Focus, Absolute", Integer),
+    (V4L2_CID_FOCUS_RELATIVE, "Focus, Relative", Integer),
+    (V4L2_CID_FOCUS_AUTO, "Focus, Automatic Continuous", Boolean),
+    (V4L2_CID_ZOOM_ABSOLUTE, "Zoom, Absolute", Integer),
+    (V4L2_CID_ZOOM_RELATIVE, "Zoom, Relative", Integer),
+    (V4L2_CID_ZOOM_CONTINUOUS, "Zoom, Continuous", Integer),
+    (V4L2_CID_PRIVACY, "Privacy", Boolean),
+    (V4L2_CID_IRIS_ABSOLUTE, "Iris, Absolute", Integer),
+    (V4L2_CID_IRIS_RELATIVE, "Iris, Relative", Integer),
+    (V4L2_CID_AUTO_EXPOSURE_BIAS, "Auto Exposure, Bias", IntegerMenu),
+    (V4L2_CID_AUTO_N_PRESET_WHITE_BALANCE, "White Balance, Auto & Preset", Menu,
+        ["Manual", "Auto", "Incandescent", "Fluorescent", "Fluorescent H", "Horizon",
+         "Daylight", "Flash", "Cloudy", "Shade"]),
+    (V4L2_CID_WIDE_DYNAMIC_RANGE, "Wide Dynamic Range", Boolean),
+    (V4L2_CID_IMAGE_STABILIZATION, "Image Stabilization", Boolean),
+    (V4L2_CID_ISO_SENSITIVITY, "ISO Sensitivity", IntegerMenu),
+    (V4L2_CID_ISO_SENSITIVITY_AUTO, "ISO Sensitivity, Auto", Menu, ["Manual", "Auto"]),
+    (V4L2_CID_EXPOSURE_METERING, "Exposure, Metering Mode", Menu,
+        ["Average", "Center Weighted", "Spot", "Matrix"]),
+    (V4L2_CID_SCENE_MODE, "Scene Mode", Menu,
+        ["None", "Backlight", "Beach/Snow", "Candle Light", "Dusk/Dawn", "Fall Colors",
+         "Fireworks", "Landscape", "Night", "Party/Indoor", "Portrait", "Sports", "Sunset",
+         "Text"]),
+    (V4L2_CID_3A_LOCK, "3A Lock", Bitmask),
+    (V4L2_CID_AUTO_FOCUS_START, "Auto Focus, Start", Button),
+    (V4L2_CID_AUTO_FOCUS_STOP, "Auto Focus, Stop", Button),
+    (V4L2_CID_AUTO_FOCUS_STATUS, "Auto Focus, Status", Bitmask),
+    (V4L2_CID_AUTO_FOCUS_RANGE, "Auto Focus, Range", Menu,
+        ["Auto", "Normal", "Macro", "Infinity"]),
+    (V4L2_CID_PAN_SPEED, "Pan, Speed", Integer),
+    (V4L2_CID_TILT_SPEED, "Tilt, Speed", Integer),
+    (uapi::V4L2_CID_CAMERA_ORIENTATION, "Camera Orientation", Menu, ["Front", "Back", "External"]),
+    (uapi::V4L2_CID_CAMERA_SENSOR_ROTATION, "Camera Sensor Rotation", Integer),
+
+    // Flash class
+    (V4L2_CID_FLASH_LED_MODE, "LED Mode", Menu, ["Off", "Flash", "Torch"]),
+    (V4L2_CID_FLASH_STROBE_SOURCE, "Strobe Source", Menu, ["Software", "External"]),
+    (V4L2_CID_FLASH_STROBE, "Strobe", Button),
+    (V4L2_CID_FLASH_STROBE_STOP, "Stop Strobe", Button),
+    (V4L2_CID_FLASH_STROBE_STATUS, "Strobe Status", Boolean),
+    (V4L2_CID_FLASH_TIMEOUT, "Strobe Timeout", Integer),
+    (V4L2_CID_FLASH_INTENSITY, "Intensity, Flash Mode", Integer),
+    (V4L2_CID_FLASH_TORCH_INTENSITY, "Intensity, Torch Mode", Integer),
+    (V4L2_CID_FLASH_INDICATOR_INTENSITY, "Intensity, Indicator", Integer),
+    (V4L2_CID_FLASH_FAULT, "Faults", Bitmask),
+    (V4L2_CID_FLASH_CHARGE, "Charge", Boolean),
+    (V4L2_CID_FLASH_READY, "Ready to Strobe", Boolean),
+
+    // JPEG class
+    (V4L2_CID_JPEG_CHROMA_SUBSAMPLING, "Chroma Subsampling", Menu,
+        ["4:4:4", "4:2:2", "4:2:0", "4:1:1", "4:1:0", "Gray Scale"]),
+    (V4L2_CID_JPEG_RESTART_INTERVAL, "Restart Interval", Integer),
+    (V4L2_CID_JPEG_COMPRESSION_QUALITY, "Compression Quality", Integer),
+    (V4L2_CID_JPEG_ACTIVE_MARKER, "Active Markers", Bitmask),
+
+    // Image source class
+    (V4L2_CID_VBLANK, "Vertical Blanking", Integer),
+    (V4L2_CID_HBLANK, "Horizontal Blanking", Integer),
+    (V4L2_CID_ANALOGUE_GAIN, "Analogue Gain", Integer),
+    (V4L2_CID_TEST_PATTERN_RED, "Red Pixel Value", Integer),
+    (V4L2_CID_TEST_PATTERN_GREENR, "Green (Red) Pixel Value", Integer),
+    (V4L2_CID_TEST_PATTERN_BLUE, "Blue Pixel Value", Integer),
+    (V4L2_CID_TEST_PATTERN_GREENB, "Green (Blue) Pixel Value", Integer),
+    (uapi::V4L2_CID_UNIT_CELL_SIZE, "Unit Cell Size", Area),
+
+    // Image processing class
+    (V4L2_CID_LINK_FREQ, "Link Frequency", IntegerMenu),
+    (V4L2_CID_PIXEL_RATE, "Pixel Rate", Integer64),
+    (V4L2_CID_TEST_PATTERN, "Test Pattern", Menu),
+    (V4L2_CID_DEINTERLACING_MODE, "Deinterlacing Mode", Menu),
+    (V4L2_CID_DIGITAL_GAIN, "Digital Gain", Integer),
+
+    // Detection class
+    (V4L2_CID_DETECT_MD_MODE, "Motion Detection Mode", Menu,
+        ["Disabled", "Global", "Threshold Grid", "Region Grid"]),
+    (V4L2_CID_DETECT_MD_GLOBAL_THRESHOLD, "MD Global Threshold", Integer),
+    (V4L2_CID_DETECT_MD_THRESHOLD_GRID, "MD Threshold Grid", U16),
+    (V4L2_CID_DETECT_MD_REGION_GRID, "MD Region Grid", U8),
+
+    // Codec class
+    (V4L2_CID_MPEG_STREAM_TYPE, "Stream Type", Menu,
+        ["MPEG-2 Program Stream", "MPEG-2 Transport Stream", "MPEG-1 System Stream",
+         "MPEG-2 DVD-compatible Stream", "MPEG-1 VCD-compatible Stream",
+         "MPEG-2 SVCD-compatible Stream"]),
+    (V4L2_CID_MPEG_AUDIO_SAMPLING_FREQ, "Audio Sampling Frequency", Menu,
+        ["44.1 kHz", "48 kHz", "32 kHz"]),
+    (V4L2_CID_MPEG_AUDIO_ENCODING, "Audio Encoding", Menu,
+        ["MPEG-1/2 Layer I", "MPEG-1/2 Layer II", "MPEG-1/2 Layer III", "MPEG-2/4 AAC",
+         "AC-3"]),
+    (V4L2_CID_MPEG_VIDEO_ENCODING, "Video Encoding", Menu, ["MPEG-1", "MPEG-2", "MPEG-4 AVC"]),
+    (V4L2_CID_MPEG_VIDEO_ASPECT, "Video Aspect", Menu, ["1x1", "4x3", "16x9", "2.21x1"]),
+    (V4L2_CID_MPEG_VIDEO_B_FRAMES, "Video B Frames", Integer),
+    (V4L2_CID_MPEG_VIDEO_GOP_SIZE, "Video GOP Size", Integer),
+    (V4L2_CID_MPEG_VIDEO_GOP_CLOSURE, "Video GOP Closure", Boolean),
+    (V4L2_CID_MPEG_VIDEO_BITRATE_MODE, "Video Bitrate Mode", Menu,
+        ["Variable Bitrate", "Constant Bitrate", "Constant Quality"]),
+    (V4L2_CID_MPEG_VIDEO_BITRATE, "Video Bitrate", Integer),
+    (V4L2_CID_MPEG_VIDEO_BITRATE_PEAK, "Video Peak Bitrate", Integer),
+    (V4L2_CID_MPEG_VIDEO_MUTE, "Video Mute", Boolean),
+    (V4L2_CID_MPEG_VIDEO_FRAME_RC_ENABLE, "Frame Level Rate Control Enable", Boolean),
+    (V4L2_CID_MPEG_VIDEO_HEADER_MODE, "Sequence Header Mode", Menu,
+        ["Separate Buffer", "Joined With 1st Frame"]),
+    (V4L2_CID_MPEG_VIDEO_MB_RC_ENABLE, "MB Level Rate Control", Boolean),
+    (V4L2_CID_MPEG_VIDEO_REPEAT_SEQ_HEADER, "Repeat Sequence Header", Boolean),
+    (V4L2_CID_MPEG_VIDEO_FORCE_KEY_FRAME, "Force Key Frame", Button),
+    (V4L2_CID_MPEG_VIDEO_H264_I_FRAME_QP, "H264 I-Frame QP Value", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_P_FRAME_QP, "H264 P-Frame QP Value", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_B_FRAME_QP, "H264 B-Frame QP Value", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_MIN_QP, "H264 Minimum QP Value", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_MAX_QP, "H264 Maximum QP Value", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_ENTROPY_MODE, "H264 Entropy Mode", Menu, ["CAVLC", "CABAC"]),
+    (V4L2_CID_MPEG_VIDEO_H264_I_PERIOD, "H264 I-Frame Period", Integer),
+    (V4L2_CID_MPEG_VIDEO_H264_LEVEL, "H264 Level", Menu,
+        ["1", "1b", "1.1", "1.2", "1.3", "2", "2.1", "2.2", "3", "3.1", "3.2", "4", "4.1",
+         "4.2", "5", "5.1", "5.2", "6.0", "6.1", "6.2"]),
+    (V4L2_CID_MPEG_VIDEO_H264_PROFILE, "H264 Profile", Menu,
+        ["Baseline", "Constrained Baseline", "Main", "Extended", "High", "High 10",
+         "High 422", "High 444 Predictive", "High 10 Intra", "High 422 Intra",
+         "High 444 Intra", "CAVLC 444 Intra", "Scalable Baseline", "Scalable High",
+         "Scalable High Intra", "Stereo High", "Multiview High", "Constrained High"]),
+    (uapi::V4L2_CID_MPEG_VIDEO_VP8_PROFILE, "VP8 Profile", Menu, ["0", "1", "2", "3"]),
+    (uapi::V4L2_CID_MPEG_VIDEO_VP9_PROFILE, "VP9 Profile", Menu, ["0", "1", "2", "3"]),
+    (V4L2_CID_MPEG_VIDEO_HEVC_PROFILE, "HEVC Profile", Menu,
+        ["Main", "Main Still Picture", "Main 10"]),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn info(id: u32, type_: ControlType, minimum: i64, maximum: i64, step: u64) -> ControlInfo {
+        ControlInfo {
+            id,
+            type_: type_.as_raw(),
+            name: by_id(id).unwrap().name.into(),
+            minimum,
+            maximum,
+            step,
+            default_value: minimum,
+            flags: 0,
+            elem_size: 4,
+            elems: 1,
+            dims: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let def = by_name("exposure_time_absolute").unwrap();
+        assert_eq!(def.id, crate::V4L2_CID_EXPOSURE_ABSOLUTE);
+        assert_eq!(def.class(), crate::V4L2_CTRL_CLASS_CAMERA);
+        assert_eq!(by_name("Exposure Time, Absolute"), Some(def));
+        assert_eq!(
+            by_id(crate::V4L2_CID_POWER_LINE_FREQUENCY)
+                .unwrap()
+                .menu_item(1),
+            Some("50 Hz")
+        );
+        assert_eq!(
+            class_name(class_of(crate::V4L2_CID_BRIGHTNESS)),
+            Some("User Controls")
+        );
+        assert!(by_name("no_such_control").is_none());
+    }
+
+    #[test]
+    fn catalog_is_consistent() {
+        for (i, def) in CONTROLS.iter().enumerate() {
+            assert!(class_name(def.class()).is_some(), "{}", def.name);
+            assert!(
+                CONTROLS[..i]
+                    .iter()
+                    .all(|d| d.id != def.id && d.canonical_name() != def.canonical_name()),
+                "duplicate {}",
+                def.name
+            );
+            assert!(def.menu.is_empty() || def.type_ == ControlType::Menu);
+        }
+    }
+
+    #[test]
+    fn validate_values() {
+        let exposure = info(
+            crate::V4L2_CID_EXPOSURE_ABSOLUTE,
+            ControlType::Integer,
+            3,
+            2047,
+            2,
+        );
+        assert_eq!(validate(&exposure, 5), Ok(()));
+        assert_eq!(validate(&exposure, 4), Err(InvalidValue::BadStep));
+        assert_eq!(validate(&exposure, 2048), Err(InvalidValue::OutOfRange));
+
+        let mode = info(crate::V4L2_CID_EXPOSURE_AUTO, ControlType::Menu, 0, 7, 1);
+        assert_eq!(validate(&mode, 3), Ok(()));
+        assert_eq!(validate(&mode, 5), Err(InvalidValue::NoSuchMenuItem));
+
+        let lock = info(crate::V4L2_CID_3A_LOCK, ControlType::Bitmask, 0, 7, 0);
+        assert_eq!(validate(&lock, 5), Ok(()));
+        assert_eq!(validate(&lock, 8), Err(InvalidValue::BadMask));
+
+        let def = by_id(crate::V4L2_CID_HFLIP).unwrap();
+        assert_eq!(def.validate(2), Err(InvalidValue::NotBoolean));
+        let grid = by_id(crate::V4L2_CID_DETECT_MD_REGION_GRID).unwrap();
+        assert_eq!(grid.validate(0), Err(InvalidValue::NotScalar));
+    }
+
+    #[test]
+    fn format_like_v4l2_ctl() {
+        let mut brightness = info(crate::V4L2_CID_BRIGHTNESS, ControlType::Integer, -64, 64, 1);
+        brightness.default_value = 0;
+        assert_eq!(
+            format_control(&brightness, Some("0")),
+            "                     brightness 0x00980900 (int)    : \
+             min=-64 max=64 step=1 default=0 value=0"
+        );
+
+        let mut mode = info(crate::V4L2_CID_EXPOSURE_AUTO, ControlType::Menu, 0, 3, 1);
+        mode.default_value = 3;
+        mode.flags = crate::V4L2_CTRL_FLAG_UPDATE;
+        assert_eq!(
+            format_control(&mode, Some("1 (Manual Mode)")),
+            "                  auto_exposure 0x009a0901 (menu)   : \
+             min=0 max=3 default=3 value=1 (Manual Mode) flags=update"
+        );
+    }
+
+    #[test]
+    fn list_past_unreadable_control() {
+        use crate::fake::{FakeControl, FakeDevice};
+
+        let dev = FakeDevice::with_controls(vec![
+            FakeControl::integer(crate::V4L2_CID_BRIGHTNESS, "Brightness", -64, 64, 0)
+                .read_error(libc::EBUSY),
+            FakeControl::integer(crate::V4L2_CID_CONTRAST, "Contrast", 0, 95, 32),
+        ]);
+        let listing = list_controls(&dev).unwrap();
+        let lines: Vec<_> = listing.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 2, "{}", listing);
+        assert!(lines[0].contains("brightness") && lines[0].contains("value=<"));
+        assert!(lines[0].contains(&io::Error::from_raw_os_error(libc::EBUSY).to_string()));
+        assert!(lines[1].ends_with("value=32"), "{}", lines[1]);
+    }
+}
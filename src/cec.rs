@@ -0,0 +1,50 @@
+///! import linux/cec.h
+
+/// ioctl codes for HDMI CEC adapters (`/dev/cecN`)
+/// ref. https://www.kernel.org/doc/html/latest/userspace-api/media/cec/cec-funcs.html
+pub mod cec_codes {
+    const CEC_IOC_MAGIC: u8 = b'a';
+
+    /// Query device capabilities.
+    pub const CEC_ADAP_G_CAPS: libc::c_ulong = iowr!(CEC_IOC_MAGIC, 0, crate::cec_caps);
+    /// Get the physical address.
+    pub const CEC_ADAP_G_PHYS_ADDR: libc::c_ulong = ior!(CEC_IOC_MAGIC, 1, u16);
+    /// Set the physical address.
+    pub const CEC_ADAP_S_PHYS_ADDR: libc::c_ulong = iow!(CEC_IOC_MAGIC, 2, u16);
+    /// Get the logical addresses.
+    pub const CEC_ADAP_G_LOG_ADDRS: libc::c_ulong = ior!(CEC_IOC_MAGIC, 3, crate::cec_log_addrs);
+    /// Claim logical addresses.
+    pub const CEC_ADAP_S_LOG_ADDRS: libc::c_ulong = iowr!(CEC_IOC_MAGIC, 4, crate::cec_log_addrs);
+    /// Transmit a message.
+    pub const CEC_TRANSMIT: libc::c_ulong = iowr!(CEC_IOC_MAGIC, 5, crate::cec_msg);
+    /// Receive a message.
+    pub const CEC_RECEIVE: libc::c_ulong = iowr!(CEC_IOC_MAGIC, 6, crate::cec_msg);
+    /// Dequeue a CEC event.
+    pub const CEC_DQEVENT: libc::c_ulong = iowr!(CEC_IOC_MAGIC, 7, crate::cec_event);
+    /// Get the initiator and follower mode of the file handle.
+    pub const CEC_G_MODE: libc::c_ulong = ior!(CEC_IOC_MAGIC, 8, u32);
+    /// Set the initiator and follower mode of the file handle.
+    pub const CEC_S_MODE: libc::c_ulong = iow!(CEC_IOC_MAGIC, 9, u32);
+    /// Query the HDMI connector the adapter is associated with.
+    pub const CEC_ADAP_G_CONNECTOR_INFO: libc::c_ulong =
+        ior!(CEC_IOC_MAGIC, 10, crate::uapi::cec_connector_info);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ioctl_code() {
+        // Fixed by the kernel ABI
+        assert_eq!(cec_codes::CEC_ADAP_G_CAPS, 0xc04c6100);
+        assert_eq!(cec_codes::CEC_ADAP_G_PHYS_ADDR, 0x80026101);
+        assert_eq!(cec_codes::CEC_ADAP_S_PHYS_ADDR, 0x40026102);
+        assert_eq!(cec_codes::CEC_ADAP_S_LOG_ADDRS, 0xc05c6104);
+        assert_eq!(cec_codes::CEC_TRANSMIT, 0xc0386105);
+        assert_eq!(cec_codes::CEC_RECEIVE, 0xc0386106);
+        assert_eq!(cec_codes::CEC_DQEVENT, 0xc0506107);
+        assert_eq!(cec_codes::CEC_S_MODE, 0x40046109);
+        assert_eq!(cec_codes::CEC_ADAP_G_CONNECTOR_INFO, 0x8044610a);
+    }
+}
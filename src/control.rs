@@ -0,0 +1,402 @@
+//! Extended controls (VIDIOC_QUERY_EXT_CTRL / VIDIOC_[GS]_EXT_CTRLS)
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-queryctrl.html
+
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+
+/// Description of a control as reported by VIDIOC_QUERY_EXT_CTRL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlInfo {
+    pub id: u32,
+    /// `enum v4l2_ctrl_type`
+    pub type_: u32,
+    pub name: String,
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: u64,
+    pub default_value: i64,
+    /// `V4L2_CTRL_FLAG_*`
+    pub flags: u32,
+    /// Size in bytes of a single element
+    pub elem_size: u32,
+    /// Total number of elements (product of `dims`, 1 for scalars)
+    pub elems: u32,
+    /// Size of each dimension, `nr_of_dims` long
+    pub dims: Vec<u32>,
+}
+
+impl From<&crate::v4l2_query_ext_ctrl> for ControlInfo {
+    fn from(q: &crate::v4l2_query_ext_ctrl) -> Self {
+        let nr_of_dims = (q.nr_of_dims as usize).min(q.dims.len());
+        ControlInfo {
+            id: q.id,
+            type_: q.type_,
+            name: c_string(&q.name),
+            minimum: q.minimum,
+            maximum: q.maximum,
+            step: q.step,
+            default_value: q.default_value,
+            flags: q.flags,
+            elem_size: q.elem_size,
+            elems: q.elems,
+            dims: q.dims[..nr_of_dims].to_vec(),
+        }
+    }
+}
+
+impl ControlInfo {
+    pub fn nr_of_dims(&self) -> usize {
+        self.dims.len()
+    }
+
+    /// The value is passed through `v4l2_ext_control.ptr`
+    pub fn has_payload(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_HAS_PAYLOAD != 0
+            || self.type_ >= crate::v4l2_ctrl_type_V4L2_CTRL_COMPOUND_TYPES
+            || self.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING
+    }
+
+    pub fn is_array(&self) -> bool {
+        !self.dims.is_empty()
+    }
+
+    pub fn is_class(&self) -> bool {
+        self.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_CTRL_CLASS
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_DISABLED != 0
+    }
+
+    pub fn is_inactive(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_INACTIVE != 0
+    }
+
+    pub fn is_grabbed(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_GRABBED != 0
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_WRITE_ONLY == 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & crate::V4L2_CTRL_FLAG_READ_ONLY == 0
+    }
+
+    /// Payload size in bytes
+    pub fn payload_size(&self) -> usize {
+        self.elem_size as usize * self.elems as usize
+    }
+}
+
+/// Lower-case identifier derived from a control name the way `v4l2-ctl` does,
+/// e.g. "Exposure Time, Absolute" becomes `exposure_time_absolute`
+pub fn canonical_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    while out.ends_with('_') {
+        out.pop();
+    }
+    out
+}
+
+/// Query a single control by id
+pub fn query_ext_ctrl<D: Ioctl + ?Sized>(dev: &D, id: u32) -> io::Result<ControlInfo> {
+    let mut q: crate::v4l2_query_ext_ctrl = unsafe { mem::zeroed() };
+    q.id = id;
+    ioctl(dev, codes::VIDIOC_QUERY_EXT_CTRL, &mut q)?;
+    Ok(ControlInfo::from(&q))
+}
+
+/// Enumerate all controls, including compound ones and control classes
+pub fn query_ext_ctrls<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<ControlInfo>> {
+    let next = crate::V4L2_CTRL_FLAG_NEXT_CTRL | crate::V4L2_CTRL_FLAG_NEXT_COMPOUND;
+    let mut controls = Vec::new();
+    let mut id = next;
+    loop {
+        match query_ext_ctrl(dev, id) {
+            Ok(info) => {
+                id = info.id | next;
+                controls.push(info);
+            }
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => return Ok(controls),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Run G/S/TRY_EXT_CTRLS on `controls`.
+///
+/// `which` is a control class, `V4L2_CTRL_WHICH_*_VAL` or 0; `request_fd` is only
+/// looked at with `V4L2_CTRL_WHICH_REQUEST_VAL`.
+pub(crate) fn ext_ctrls<D: Ioctl + ?Sized>(
+    dev: &D,
+    request: libc::c_ulong,
+    which: u32,
+    request_fd: i32,
+    controls: &mut [crate::v4l2_ext_control],
+) -> io::Result<()> {
+    let mut ctrls: crate::uapi::v4l2_ext_controls = unsafe { mem::zeroed() };
+    ctrls.which = which;
+    ctrls.count = controls.len() as u32;
+    ctrls.request_fd = request_fd;
+    ctrls.controls = controls.as_mut_ptr();
+    ioctl(dev, request, &mut ctrls)
+}
+
+fn scalar(id: u32) -> crate::v4l2_ext_control {
+    let mut ctrl: crate::v4l2_ext_control = unsafe { mem::zeroed() };
+    ctrl.id = id;
+    ctrl
+}
+
+/// Read the current value of a 32-bit control (integer, boolean, menu, bitmask)
+pub fn get_value<D: Ioctl + ?Sized>(dev: &D, id: u32) -> io::Result<i32> {
+    let mut ctrl = [scalar(id)];
+    ext_ctrls(dev, codes::VIDIOC_G_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(unsafe { ctrl[0].__bindgen_anon_1.value })
+}
+
+/// Set a 32-bit control, returning the value the driver settled on
+pub fn set_value<D: Ioctl + ?Sized>(dev: &D, id: u32, value: i32) -> io::Result<i32> {
+    let mut ctrl = [scalar(id)];
+    ctrl[0].__bindgen_anon_1.value = value;
+    ext_ctrls(dev, codes::VIDIOC_S_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(unsafe { ctrl[0].__bindgen_anon_1.value })
+}
+
+/// Read the current value of a V4L2_CTRL_TYPE_INTEGER64 control
+pub fn get_value64<D: Ioctl + ?Sized>(dev: &D, id: u32) -> io::Result<i64> {
+    let mut ctrl = [scalar(id)];
+    ext_ctrls(dev, codes::VIDIOC_G_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(unsafe { ctrl[0].__bindgen_anon_1.value64 })
+}
+
+/// Set a V4L2_CTRL_TYPE_INTEGER64 control
+pub fn set_value64<D: Ioctl + ?Sized>(dev: &D, id: u32, value: i64) -> io::Result<i64> {
+    let mut ctrl = [scalar(id)];
+    ctrl[0].__bindgen_anon_1.value64 = value;
+    ext_ctrls(dev, codes::VIDIOC_S_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(unsafe { ctrl[0].__bindgen_anon_1.value64 })
+}
+
+/// Read a V4L2_CTRL_TYPE_STRING control
+pub fn get_string<D: Ioctl + ?Sized>(dev: &D, info: &ControlInfo) -> io::Result<String> {
+    let mut buf = vec![0u8; info.maximum.max(0) as usize + 1];
+    let mut ctrl = [scalar(info.id)];
+    ctrl[0].size = buf.len() as u32;
+    ctrl[0].__bindgen_anon_1.string = buf.as_mut_ptr() as *mut libc::c_char;
+    ext_ctrls(dev, codes::VIDIOC_G_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(c_string(&buf))
+}
+
+/// Set a V4L2_CTRL_TYPE_STRING control
+pub fn set_string<D: Ioctl + ?Sized>(dev: &D, id: u32, value: &str) -> io::Result<()> {
+    let mut buf = value.as_bytes().to_vec();
+    buf.push(0);
+    let mut ctrl = [scalar(id)];
+    ctrl[0].size = buf.len() as u32;
+    ctrl[0].__bindgen_anon_1.string = buf.as_mut_ptr() as *mut libc::c_char;
+    ext_ctrls(dev, codes::VIDIOC_S_EXT_CTRLS, 0, 0, &mut ctrl)
+}
+
+/// Plain-old-data element type of a compound or array control.
+///
+/// # Safety
+/// Implementors must be `repr(C)` without padding-sensitive invariants, and
+/// the all-zero bit pattern must be a valid value.
+pub unsafe trait Payload: Copy + 'static {}
+
+unsafe impl Payload for u8 {}
+unsafe impl Payload for u16 {}
+unsafe impl Payload for u32 {}
+unsafe impl Payload for i32 {}
+unsafe impl Payload for i64 {}
+unsafe impl Payload for crate::uapi::v4l2_area {}
+unsafe impl Payload for crate::v4l2_rect {}
+
+/// Typed storage for the value of a compound or N-dimensional array control
+#[derive(Clone)]
+pub struct CompoundBuffer<T: Payload> {
+    id: u32,
+    dims: Vec<u32>,
+    data: Vec<T>,
+}
+
+impl<T: Payload> CompoundBuffer<T> {
+    /// Allocate a zeroed buffer shaped after `info`.
+    ///
+    /// Fails with `InvalidInput` when `T` does not match the control's `elem_size`.
+    pub fn new(info: &ControlInfo) -> io::Result<Self> {
+        if info.elem_size as usize != mem::size_of::<T>() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "control {:#x} has {}-byte elements, buffer type has {}",
+                    info.id,
+                    info.elem_size,
+                    mem::size_of::<T>()
+                ),
+            ));
+        }
+        let elems = info.elems.max(1) as usize;
+        Ok(CompoundBuffer {
+            id: info.id,
+            dims: info.dims.clone(),
+            data: vec![unsafe { mem::zeroed() }; elems],
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn dims(&self) -> &[u32] {
+        &self.dims
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// Row-major offset of an N-dimensional index, `None` when out of bounds
+    pub fn offset(&self, index: &[usize]) -> Option<usize> {
+        if index.len() != self.dims.len().max(1) {
+            return None;
+        }
+        if self.dims.is_empty() {
+            return if index[0] < self.data.len() {
+                Some(index[0])
+            } else {
+                None
+            };
+        }
+        index
+            .iter()
+            .zip(self.dims.iter())
+            .try_fold(0, |offset, (&i, &dim)| {
+                if i < dim as usize {
+                    Some(offset * dim as usize + i)
+                } else {
+                    None
+                }
+            })
+    }
+
+    pub fn get(&self, index: &[usize]) -> Option<&T> {
+        self.offset(index).map(|o| &self.data[o])
+    }
+
+    pub fn get_mut(&mut self, index: &[usize]) -> Option<&mut T> {
+        self.offset(index).map(move |o| &mut self.data[o])
+    }
+
+    pub(crate) fn as_ext_control(&mut self) -> crate::v4l2_ext_control {
+        let mut ctrl = scalar(self.id);
+        ctrl.size = (self.data.len() * mem::size_of::<T>()) as u32;
+        ctrl.__bindgen_anon_1.ptr = self.data.as_mut_ptr() as *mut libc::c_void;
+        ctrl
+    }
+}
+
+/// Read the current value of a compound/array control into a new buffer
+pub fn get_compound<D, T>(dev: &D, info: &ControlInfo) -> io::Result<CompoundBuffer<T>>
+where
+    D: Ioctl + ?Sized,
+    T: Payload,
+{
+    let mut buf = CompoundBuffer::new(info)?;
+    let mut ctrl = [buf.as_ext_control()];
+    ext_ctrls(dev, codes::VIDIOC_G_EXT_CTRLS, 0, 0, &mut ctrl)?;
+    Ok(buf)
+}
+
+/// Write a compound/array control
+pub fn set_compound<D, T>(dev: &D, buf: &mut CompoundBuffer<T>) -> io::Result<()>
+where
+    D: Ioctl + ?Sized,
+    T: Payload,
+{
+    let mut ctrl = [buf.as_ext_control()];
+    ext_ctrls(dev, codes::VIDIOC_S_EXT_CTRLS, 0, 0, &mut ctrl)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn info(elem_size: u32, dims: &[u32]) -> ControlInfo {
+        ControlInfo {
+            id: 0x00a4_0001,
+            type_: crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_U16,
+            name: "ROI".into(),
+            minimum: 0,
+            maximum: 0xffff,
+            step: 1,
+            default_value: 0,
+            flags: crate::V4L2_CTRL_FLAG_HAS_PAYLOAD,
+            elem_size,
+            elems: dims.iter().product(),
+            dims: dims.to_vec(),
+        }
+    }
+
+    #[test]
+    fn canonical_names() {
+        assert_eq!(canonical_name("Brightness"), "brightness");
+        assert_eq!(
+            canonical_name("Exposure Time, Absolute"),
+            "exposure_time_absolute"
+        );
+        assert_eq!(
+            canonical_name("White Balance (Auto) "),
+            "white_balance_auto"
+        );
+    }
+
+    #[test]
+    fn compound_buffer_layout() {
+        let mut buf = CompoundBuffer::<u16>::new(&info(2, &[3, 4])).unwrap();
+        assert_eq!(buf.len(), 12);
+        assert_eq!(buf.offset(&[0, 0]), Some(0));
+        assert_eq!(buf.offset(&[1, 2]), Some(6));
+        assert_eq!(buf.offset(&[2, 3]), Some(11));
+        assert_eq!(buf.offset(&[3, 0]), None);
+        assert_eq!(buf.offset(&[0]), None);
+
+        *buf.get_mut(&[1, 2]).unwrap() = 42;
+        assert_eq!(buf.as_slice()[6], 42);
+
+        let ctrl = buf.as_ext_control();
+        let size = ctrl.size;
+        assert_eq!(size, 24);
+    }
+
+    #[test]
+    fn compound_buffer_elem_size_mismatch() {
+        assert!(CompoundBuffer::<u32>::new(&info(2, &[8])).is_err());
+        assert!(CompoundBuffer::<crate::uapi::v4l2_area>::new(&info(8, &[])).is_ok());
+    }
+}
@@ -0,0 +1,240 @@
+//! Advanced debugging (VIDIOC_DBG_G/S_REGISTER, VIDIOC_DBG_G_CHIP_INFO,
+//! VIDIOC_LOG_STATUS)
+//!
+//! Register access is only available on kernels built with
+//! CONFIG_VIDEO_ADV_DEBUG and needs CAP_SYS_ADMIN. It is meant for driver
+//! bring-up, not for applications.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-dbg-g-register.html
+
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+
+/// Which chip of the device a debug request is for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ChipMatch {
+    /// Bridge chip `n`, 0 being the main one
+    Bridge(u32),
+    /// Sub-device `n` of the `v4l2_device`
+    Subdev(u32),
+    /// Legacy: I2C chip by driver name
+    I2cDriver(String),
+    /// Legacy: I2C chip by 7-bit address
+    I2cAddr(u32),
+    /// Legacy: AC97 codec
+    Ac97(u32),
+}
+
+impl ChipMatch {
+    fn to_raw(&self) -> crate::v4l2_dbg_match {
+        let mut m: crate::v4l2_dbg_match = unsafe { mem::zeroed() };
+        let (type_, addr) = match self {
+            ChipMatch::Bridge(n) => (crate::V4L2_CHIP_MATCH_BRIDGE, *n),
+            ChipMatch::Subdev(n) => (crate::V4L2_CHIP_MATCH_SUBDEV, *n),
+            ChipMatch::I2cAddr(addr) => (crate::V4L2_CHIP_MATCH_I2C_ADDR, *addr),
+            ChipMatch::Ac97(n) => (crate::V4L2_CHIP_MATCH_AC97, *n),
+            ChipMatch::I2cDriver(name) => {
+                m.type_ = crate::V4L2_CHIP_MATCH_I2C_DRIVER;
+                let dst = unsafe { &mut m.__bindgen_anon_1.name };
+                // Keep the terminating NUL
+                for (d, &s) in dst.iter_mut().zip(name.as_bytes().iter().take(31)) {
+                    *d = s as libc::c_char;
+                }
+                return m;
+            }
+        };
+        m.type_ = type_;
+        m.__bindgen_anon_1.addr = addr;
+        m
+    }
+}
+
+/// One register as read by VIDIOC_DBG_G_REGISTER
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    pub reg: u64,
+    pub value: u64,
+    /// Register width in bytes, as reported by the driver
+    pub size: u32,
+}
+
+/// VIDIOC_DBG_G_REGISTER
+pub fn get_register<D: Ioctl + ?Sized>(
+    dev: &D,
+    chip: &ChipMatch,
+    reg: u64,
+) -> io::Result<Register> {
+    let mut r: crate::v4l2_dbg_register = unsafe { mem::zeroed() };
+    r.match_ = chip.to_raw();
+    r.reg = reg;
+    ioctl(dev, codes::VIDIOC_DBG_G_REGISTER, &mut r)?;
+    Ok(Register {
+        reg: r.reg,
+        value: r.val,
+        size: r.size,
+    })
+}
+
+/// VIDIOC_DBG_S_REGISTER
+pub fn set_register<D: Ioctl + ?Sized>(
+    dev: &D,
+    chip: &ChipMatch,
+    reg: u64,
+    value: u64,
+) -> io::Result<()> {
+    let mut r: crate::v4l2_dbg_register = unsafe { mem::zeroed() };
+    r.match_ = chip.to_raw();
+    r.reg = reg;
+    r.val = value;
+    ioctl(dev, codes::VIDIOC_DBG_S_REGISTER, &mut r)
+}
+
+/// Result of VIDIOC_DBG_G_CHIP_INFO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipInfo {
+    pub chip: ChipMatch,
+    pub name: String,
+    /// `V4L2_CHIP_FL_*`
+    pub flags: u32,
+}
+
+impl ChipInfo {
+    pub fn is_readable(&self) -> bool {
+        self.flags & crate::V4L2_CHIP_FL_READABLE != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & crate::V4L2_CHIP_FL_WRITABLE != 0
+    }
+}
+
+/// VIDIOC_DBG_G_CHIP_INFO
+pub fn chip_info<D: Ioctl + ?Sized>(dev: &D, chip: &ChipMatch) -> io::Result<ChipInfo> {
+    let mut c: crate::v4l2_dbg_chip_info = unsafe { mem::zeroed() };
+    c.match_ = chip.to_raw();
+    ioctl(dev, codes::VIDIOC_DBG_G_CHIP_INFO, &mut c)?;
+    Ok(ChipInfo {
+        chip: chip.clone(),
+        name: c_string(&c.name),
+        flags: c.flags,
+    })
+}
+
+/// All bridge chips, then all sub-devices
+pub fn enum_chips<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<ChipInfo>> {
+    let mut chips = Vec::new();
+    for kind in [ChipMatch::Bridge, ChipMatch::Subdev] {
+        for n in 0.. {
+            match chip_info(dev, &kind(n)) {
+                Ok(info) => chips.push(info),
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(chips)
+}
+
+/// VIDIOC_LOG_STATUS: have the driver print its state to the kernel log
+pub fn log_status<D: Ioctl + ?Sized>(dev: &D) -> io::Result<()> {
+    ioctl(dev, codes::VIDIOC_LOG_STATUS, &mut ())
+}
+
+/// Issue VIDIOC_LOG_STATUS and return the lines it logged.
+///
+/// The report is read back from `/dev/kmsg`, which needs CAP_SYSLOG when
+/// `kernel.dmesg_restrict` is set.
+pub fn log_status_lines<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<String>> {
+    let mut kmsg = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open("/dev/kmsg")?;
+    kmsg.seek(SeekFrom::End(0))?;
+    log_status(dev)?;
+
+    // Every read() returns one record
+    let mut records = Vec::new();
+    let mut buf = vec![0u8; 8192];
+    loop {
+        match kmsg.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => records.push(String::from_utf8_lossy(&buf[..n]).into_owned()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            // Records were overwritten before they could be read
+            Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(status_lines(records.iter().map(String::as_str)))
+}
+
+/// The messages between the START STATUS and END STATUS markers in
+/// `/dev/kmsg` records (`prio,seq,time,flags;message`)
+pub fn status_lines<'a, I: IntoIterator<Item = &'a str>>(records: I) -> Vec<String> {
+    let mut lines = Vec::new();
+    // Drivers often log their own markers inside the ones of the core
+    let mut depth = 0usize;
+    for record in records {
+        let Some((_, text)) = record.split_once(';') else {
+            continue;
+        };
+        // Continuation lines carry the record's key=value dictionary
+        let message = text.lines().next().unwrap_or_default();
+        if message.contains("START STATUS") {
+            depth += 1;
+        } else if message.contains("END STATUS") {
+            depth = depth.saturating_sub(1);
+        } else if depth > 0 {
+            lines.push(message.to_string());
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    #[test]
+    fn registers() {
+        let dev = FakeDevice::default();
+        let chips = enum_chips(&dev).unwrap();
+        let names: Vec<_> = chips.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["fake-bridge", "fake-sensor"]);
+        assert_eq!(chips[1].chip, ChipMatch::Subdev(0));
+        assert!(chips[1].is_readable() && chips[1].is_writable());
+
+        let sensor = ChipMatch::Subdev(0);
+        set_register(&dev, &sensor, 0x3008, 0x82).unwrap();
+        let r = get_register(&dev, &sensor, 0x3008).unwrap();
+        assert_eq!((r.reg, r.value, r.size), (0x3008, 0x82, 1));
+        let bridge = get_register(&dev, &ChipMatch::Bridge(0), 0x3008).unwrap();
+        assert_eq!(bridge.value, 0);
+        let err = get_register(&dev, &ChipMatch::Subdev(1), 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert!(log_status(&dev).is_ok());
+    }
+
+    #[test]
+    fn status_report() {
+        let records = [
+            "6,1001,5000,-;usb 1-1: new high-speed USB device",
+            "6,1002,5100,-;fake0: =================  START STATUS  =================",
+            "6,1003,5101,-;fake0: ===== START STATUS CARD #0 =====",
+            "6,1004,5102,-;fake0: Brightness: 128\n SUBSYSTEM=video4linux\n",
+            "6,1005,5103,-;fake0: ===== END STATUS CARD #0 =====",
+            "6,1006,5104,-;fake0: Input: 0",
+            "6,1007,5105,-;fake0: ==================  END STATUS  ==================",
+            "6,1008,5200,-;fake0: streaming stopped",
+        ];
+        assert_eq!(
+            status_lines(records),
+            ["fake0: Brightness: 128", "fake0: Input: 0"]
+        );
+    }
+}
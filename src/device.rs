@@ -0,0 +1,227 @@
+//! Thin owners of a libv4l2 or plain file descriptor
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::codes;
+use crate::priority::{self, Priority};
+
+/// Something ioctls can be issued to.
+///
+/// Implemented by [`Device`] and [`RawDevice`]; tests substitute a fake that interprets the requests itself.
+pub trait Ioctl {
+    /// Issue `request` with `arg`, retrying on `EINTR`.
+    ///
+    /// # Safety
+    /// `arg` must point to a value of the type `request` was encoded with.
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()>;
+}
+
+/// Issue `request` with a reference to its argument struct.
+///
+/// The size encoded in `request` is checked against `T` in debug builds.
+pub(crate) fn ioctl<D, T>(dev: &D, request: libc::c_ulong, arg: &mut T) -> io::Result<()>
+where
+    D: Ioctl + ?Sized,
+{
+    let size = (request >> crate::ioctl::SIZESHIFT) & ((1 << crate::ioctl::SIZEBITS) - 1);
+    debug_assert!(size == 0 || size as usize == mem::size_of::<T>());
+    unsafe { dev.ioctl(request, arg as *mut T as *mut libc::c_void) }
+}
+
+/// A video device opened through libv4l2
+#[derive(Debug)]
+pub struct Device {
+    fd: RawFd,
+    /// Priority claimed through this handle, `V4L2_PRIORITY_*`
+    priority: AtomicU32,
+}
+
+impl Device {
+    /// Open `path` read-write through `v4l2_open`
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with(path, libc::O_RDWR)
+    }
+
+    /// Open `path` with explicit `open(2)` flags
+    pub fn open_with<P: AsRef<Path>>(path: P, flags: libc::c_int) -> io::Result<Self> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { crate::v4l2_open(path.as_ptr(), flags) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Device::new(fd))
+    }
+
+    /// Open `path` read-write and claim `priority` for the handle, e.g.
+    /// [`Priority::Record`] to keep other applications from changing the
+    /// device while it is open
+    pub fn open_with_priority<P: AsRef<Path>>(path: P, priority: Priority) -> io::Result<Self> {
+        let dev = Self::open(path)?;
+        dev.set_priority(priority)?;
+        Ok(dev)
+    }
+
+    fn new(fd: RawFd) -> Self {
+        Device {
+            fd,
+            priority: AtomicU32::new(Priority::default().as_raw()),
+        }
+    }
+
+    /// Take ownership of a descriptor already registered with libv4l2
+    ///
+    /// # Safety
+    /// `fd` must be open and not owned by anything else.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Device::new(fd)
+    }
+
+    /// Priority of this handle, as opposed to [`priority::priority`] which
+    /// reports the highest one on the device
+    pub fn priority(&self) -> Priority {
+        Priority::from_raw(self.priority.load(Ordering::Relaxed)).unwrap_or_default()
+    }
+
+    /// VIDIOC_S_PRIORITY for this handle
+    pub fn set_priority(&self, priority: Priority) -> io::Result<()> {
+        priority::set_priority(self, priority)?;
+        self.priority.store(priority.as_raw(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// `read(2)` through libv4l2
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let r = unsafe {
+                crate::v4l2_read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+            if r >= 0 {
+                return Ok(r as usize);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// EBUSY while another handle holds a higher priority comes back as a
+/// [`PriorityError`](priority::PriorityError); any other EBUSY is left alone.
+impl Ioctl for Device {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        let err = loop {
+            if crate::v4l2_ioctl(self.fd, request, arg) != -1 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                break err;
+            }
+        };
+        if request == codes::VIDIOC_G_PRIORITY {
+            return Err(err);
+        }
+        Err(priority::busy_error(self, self.priority(), err))
+    }
+}
+
+impl<T: Ioctl + ?Sized> Ioctl for &T {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        (**self).ioctl(request, arg)
+    }
+}
+
+/// A device node opened with plain `open(2)`, bypassing libv4l2.
+///
+/// For media, sub-device and CEC nodes: `v4l2_open` issues VIDIOC_QUERYCAP,
+/// which those nodes do not implement.
+#[derive(Debug)]
+pub struct RawDevice {
+    fd: RawFd,
+}
+
+impl RawDevice {
+    /// Open `path` read-write
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with(path, libc::O_RDWR)
+    }
+
+    /// Open `path` with explicit `open(2)` flags
+    pub fn open_with<P: AsRef<Path>>(path: P, flags: libc::c_int) -> io::Result<Self> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::open(path.as_ptr(), flags) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawDevice { fd })
+    }
+
+    /// Take ownership of an open descriptor
+    ///
+    /// # Safety
+    /// `fd` must be open and not owned by anything else.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        RawDevice { fd }
+    }
+}
+
+impl Ioctl for RawDevice {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        loop {
+            if libc::ioctl(self.fd, request as _, arg) != -1 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl AsRawFd for RawDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for RawDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Convert a NUL-padded `char`/`__u8` array from a v4l2 struct
+pub(crate) fn c_string<C: Copy + Into<i32>>(raw: &[C]) -> String {
+    let bytes: Vec<u8> = raw
+        .iter()
+        .map(|&c| c.into() as u8)
+        .take_while(|&c| c != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl AsRawFd for Device {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            crate::v4l2_close(self.fd);
+        }
+    }
+}
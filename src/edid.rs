@@ -0,0 +1,1090 @@
+//! EDID access (VIDIOC_G_EDID / VIDIOC_S_EDID), parsing and editing
+//!
+//! Covers the EDID 1.3/1.4 base block and CEA-861 extension blocks: enough to
+//! inspect what a sink advertises and to program a receiver with an EDID that
+//! only lists the modes the capture path can handle.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-g-edid.html
+
+use std::fmt;
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+
+pub const BLOCK_SIZE: usize = 128;
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const CEA_TAG: u8 = 0x02;
+const DESCRIPTOR_NAME: u8 = 0xfc;
+const DESCRIPTOR_SERIAL: u8 = 0xff;
+const DESCRIPTOR_DUMMY: u8 = 0x10;
+/// IEEE OUI of the HDMI Licensing vendor-specific data block
+const HDMI_OUI: [u8; 3] = [0x03, 0x0c, 0x00];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdidError {
+    /// Not a non-zero multiple of 128 bytes, or fewer blocks than announced
+    Length(usize),
+    /// The base block does not start with the fixed EDID header
+    Header,
+    /// The given block does not sum to zero
+    Checksum(usize),
+    /// Data blocks and timings do not fit in one extension block, or a data
+    /// block is longer than 31 bytes
+    TooLarge,
+    /// Manufacturer id is not three letters A-Z
+    Manufacturer,
+    /// No free display descriptor slot in the base block
+    NoDescriptorSlot,
+    /// The base block already announces 255 extensions
+    TooManyExtensions,
+}
+
+impl fmt::Display for EdidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdidError::Length(len) => write!(f, "invalid EDID length {}", len),
+            EdidError::Header => f.write_str("missing EDID header"),
+            EdidError::Checksum(block) => write!(f, "bad checksum in EDID block {}", block),
+            EdidError::TooLarge => f.write_str("CEA-861 data does not fit in 128 bytes"),
+            EdidError::Manufacturer => f.write_str("manufacturer id must be three letters"),
+            EdidError::NoDescriptorSlot => f.write_str("no free display descriptor"),
+            EdidError::TooManyExtensions => f.write_str("EDID already has 255 extensions"),
+        }
+    }
+}
+
+impl std::error::Error for EdidError {}
+
+impl From<EdidError> for io::Error {
+    fn from(e: EdidError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+fn checksum(block: &[u8]) -> u8 {
+    let sum = block[..BLOCK_SIZE - 1]
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_add(b));
+    0u8.wrapping_sub(sum)
+}
+
+/// 18-byte detailed timing descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DetailedTiming {
+    /// Multiple of 10 kHz
+    pub pixel_clock_khz: u32,
+    pub hactive: u16,
+    pub hblank: u16,
+    pub hsync_offset: u16,
+    pub hsync_width: u16,
+    pub vactive: u16,
+    pub vblank: u16,
+    pub vsync_offset: u16,
+    pub vsync_width: u16,
+    pub width_mm: u16,
+    pub height_mm: u16,
+    pub interlaced: bool,
+    pub hsync_positive: bool,
+    pub vsync_positive: bool,
+}
+
+impl DetailedTiming {
+    /// `None` for a display descriptor (pixel clock 0) or fewer than 18 bytes
+    pub fn parse(d: &[u8]) -> Option<Self> {
+        if d.len() < 18 {
+            return None;
+        }
+        let clock = u16::from_le_bytes([d[0], d[1]]) as u32;
+        if clock == 0 {
+            return None;
+        }
+        let hi = |byte: u8, shift: u32| ((byte >> shift) & 0xf) as u16;
+        Some(DetailedTiming {
+            pixel_clock_khz: clock * 10,
+            hactive: d[2] as u16 | hi(d[4], 4) << 8,
+            hblank: d[3] as u16 | hi(d[4], 0) << 8,
+            vactive: d[5] as u16 | hi(d[7], 4) << 8,
+            vblank: d[6] as u16 | hi(d[7], 0) << 8,
+            hsync_offset: d[8] as u16 | ((d[11] >> 6) as u16 & 0x3) << 8,
+            hsync_width: d[9] as u16 | ((d[11] >> 4) as u16 & 0x3) << 8,
+            vsync_offset: (d[10] >> 4) as u16 | ((d[11] >> 2) as u16 & 0x3) << 4,
+            vsync_width: (d[10] & 0xf) as u16 | (d[11] as u16 & 0x3) << 4,
+            width_mm: d[12] as u16 | hi(d[14], 4) << 8,
+            height_mm: d[13] as u16 | hi(d[14], 0) << 8,
+            interlaced: d[17] & 0x80 != 0,
+            hsync_positive: d[17] & 0x02 != 0,
+            vsync_positive: d[17] & 0x04 != 0,
+        })
+    }
+
+    /// Digital separate sync descriptor bytes
+    pub fn to_bytes(&self) -> [u8; 18] {
+        let lo = |v: u16| (v & 0xff) as u8;
+        let hi = |v: u16| ((v >> 8) & 0xf) as u8;
+        let clock = ((self.pixel_clock_khz / 10) as u16).to_le_bytes();
+        let mut flags = 0x18;
+        if self.interlaced {
+            flags |= 0x80;
+        }
+        if self.vsync_positive {
+            flags |= 0x04;
+        }
+        if self.hsync_positive {
+            flags |= 0x02;
+        }
+        [
+            clock[0],
+            clock[1],
+            lo(self.hactive),
+            lo(self.hblank),
+            hi(self.hactive) << 4 | hi(self.hblank),
+            lo(self.vactive),
+            lo(self.vblank),
+            hi(self.vactive) << 4 | hi(self.vblank),
+            lo(self.hsync_offset),
+            lo(self.hsync_width),
+            ((self.vsync_offset & 0xf) as u8) << 4 | (self.vsync_width & 0xf) as u8,
+            ((self.hsync_offset >> 8) as u8 & 0x3) << 6
+                | ((self.hsync_width >> 8) as u8 & 0x3) << 4
+                | ((self.vsync_offset >> 4) as u8 & 0x3) << 2
+                | ((self.vsync_width >> 4) as u8 & 0x3),
+            lo(self.width_mm),
+            lo(self.height_mm),
+            hi(self.width_mm) << 4 | hi(self.height_mm),
+            0,
+            0,
+            flags,
+        ]
+    }
+
+    /// Field rate in mHz. An interlaced DTD describes one field, so 1080i
+    /// reports 60 Hz, not 120.
+    pub fn refresh_millihz(&self) -> u32 {
+        let total = (self.hactive + self.hblank) as u64 * (self.vactive + self.vblank) as u64;
+        if total == 0 {
+            return 0;
+        }
+        (self.pixel_clock_khz as u64 * 1_000_000 / total) as u32
+    }
+}
+
+impl fmt::Display for DetailedTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rate = self.refresh_millihz();
+        write!(
+            f,
+            "{}x{}{} {}.{:02} Hz",
+            self.hactive,
+            if self.interlaced {
+                self.vactive * 2
+            } else {
+                self.vactive
+            },
+            if self.interlaced { "i" } else { "p" },
+            rate / 1000,
+            rate % 1000 / 10
+        )
+    }
+}
+
+/// Decoded fields of the base block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseBlock {
+    /// Three letter PNP id, e.g. "SAM"
+    pub manufacturer: String,
+    pub product_code: u16,
+    pub serial: u32,
+    pub week: u8,
+    pub year: u16,
+    /// EDID version and revision, e.g. (1, 3)
+    pub version: (u8, u8),
+    pub name: Option<String>,
+    pub serial_string: Option<String>,
+    /// The first detailed timing, the preferred mode
+    pub preferred_timing: Option<DetailedTiming>,
+    pub detailed_timings: Vec<DetailedTiming>,
+    pub extension_count: u8,
+}
+
+fn descriptor_text(d: &[u8]) -> String {
+    d[5..18]
+        .iter()
+        .take_while(|&&c| c != 0x0a)
+        .map(|&c| c as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+fn text_descriptor(tag: u8, text: &str) -> [u8; 18] {
+    let mut d = [0u8; 18];
+    d[3] = tag;
+    d[5..].fill(0x20);
+    let bytes: Vec<u8> = text.bytes().filter(|c| c.is_ascii()).take(13).collect();
+    d[5..5 + bytes.len()].copy_from_slice(&bytes);
+    if bytes.len() < 13 {
+        d[5 + bytes.len()] = 0x0a;
+    }
+    d
+}
+
+fn decode_manufacturer(id: u16) -> String {
+    [10, 5, 0]
+        .iter()
+        .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+        .collect()
+}
+
+fn encode_manufacturer(name: &str) -> Result<u16, EdidError> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_uppercase) {
+        return Err(EdidError::Manufacturer);
+    }
+    Ok(bytes
+        .iter()
+        .fold(0u16, |id, &c| id << 5 | (c - b'A' + 1) as u16))
+}
+
+/// Short video descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vic {
+    /// CEA-861 video identification code
+    pub vic: u8,
+    pub native: bool,
+}
+
+impl Vic {
+    fn parse(b: u8) -> Self {
+        // since CEA-861-F, codes 129..=192 are native VICs 1..=64
+        if (129..=192).contains(&b) {
+            Vic {
+                vic: b & 0x7f,
+                native: true,
+            }
+        } else {
+            Vic {
+                vic: b,
+                native: false,
+            }
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        if self.native && (1..=64).contains(&self.vic) {
+            self.vic | 0x80
+        } else {
+            self.vic
+        }
+    }
+}
+
+/// Short audio descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioDescriptor {
+    /// Audio format code, 1 for LPCM, 2 for AC-3, ...
+    pub format: u8,
+    pub channels: u8,
+    /// Bit 0 32 kHz, 1 44.1 kHz, 2 48 kHz, 3 88.2 kHz, 4 96 kHz,
+    /// 5 176.4 kHz, 6 192 kHz
+    pub sample_rates: u8,
+    /// LPCM: bit 0 16 bit, 1 20 bit, 2 24 bit; other formats: format specific
+    pub detail: u8,
+}
+
+impl AudioDescriptor {
+    pub const LPCM: u8 = 1;
+
+    pub fn lpcm(channels: u8, sample_rates: u8, bit_depths: u8) -> Self {
+        AudioDescriptor {
+            format: Self::LPCM,
+            channels,
+            sample_rates,
+            detail: bit_depths,
+        }
+    }
+
+    fn parse(b: &[u8]) -> Self {
+        AudioDescriptor {
+            format: (b[0] >> 3) & 0xf,
+            channels: (b[0] & 0x7) + 1,
+            sample_rates: b[1] & 0x7f,
+            detail: b[2],
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 3] {
+        [
+            (self.format & 0xf) << 3 | (self.channels.clamp(1, 8) - 1),
+            self.sample_rates & 0x7f,
+            self.detail,
+        ]
+    }
+
+    pub fn format_name(&self) -> &'static str {
+        const NAMES: [&str; 15] = [
+            "Reserved",
+            "Linear PCM",
+            "AC-3",
+            "MPEG 1",
+            "MP3",
+            "MPEG2",
+            "AAC LC",
+            "DTS",
+            "ATRAC",
+            "One Bit Audio",
+            "Enhanced AC-3",
+            "DTS-HD",
+            "MAT (MLP)",
+            "DST",
+            "WMA Pro",
+        ];
+        NAMES
+            .get(self.format as usize)
+            .copied()
+            .unwrap_or("Extended")
+    }
+}
+
+/// HDR static metadata data block (extended tag 6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HdrStaticMetadata {
+    /// Bit 0 traditional SDR, 1 traditional HDR, 2 SMPTE ST 2084, 3 HLG
+    pub eotf: u8,
+    /// Bit 0 static metadata type 1
+    pub metadata_types: u8,
+    /// Coded values, see CTA-861.3
+    pub max_luminance: Option<u8>,
+    pub max_frame_avg_luminance: Option<u8>,
+    pub min_luminance: Option<u8>,
+}
+
+impl HdrStaticMetadata {
+    pub fn supports_pq(&self) -> bool {
+        self.eotf & 0x04 != 0
+    }
+
+    pub fn supports_hlg(&self) -> bool {
+        self.eotf & 0x08 != 0
+    }
+
+    /// Desired content max luminance in cd/m²
+    pub fn max_luminance_nits(&self) -> Option<f64> {
+        self.max_luminance
+            .map(|cv| 50.0 * 2f64.powf(cv as f64 / 32.0))
+    }
+
+    fn parse(b: &[u8]) -> Self {
+        HdrStaticMetadata {
+            eotf: b.first().copied().unwrap_or(0),
+            metadata_types: b.get(1).copied().unwrap_or(0),
+            max_luminance: b.get(2).copied(),
+            max_frame_avg_luminance: b.get(3).copied(),
+            min_luminance: b.get(4).copied(),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut b = vec![self.eotf, self.metadata_types];
+        // the optional luminance bytes can only be left off from the end
+        let lum = [
+            self.max_luminance,
+            self.max_frame_avg_luminance,
+            self.min_luminance,
+        ];
+        let present = lum.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        b.extend(lum[..present].iter().map(|v| v.unwrap_or(0)));
+        b
+    }
+}
+
+/// One entry of the CEA-861 data block collection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataBlock {
+    Audio(Vec<AudioDescriptor>),
+    Video(Vec<Vic>),
+    Hdr(HdrStaticMetadata),
+    /// Any other block: tag code and payload (for extended blocks the
+    /// payload starts with the extended tag)
+    Other {
+        tag: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl DataBlock {
+    fn parse(tag: u8, data: &[u8]) -> Self {
+        match tag {
+            1 => DataBlock::Audio(data.chunks_exact(3).map(AudioDescriptor::parse).collect()),
+            2 => DataBlock::Video(data.iter().map(|&b| Vic::parse(b)).collect()),
+            7 if data.first() == Some(&6) => DataBlock::Hdr(HdrStaticMetadata::parse(&data[1..])),
+            _ => DataBlock::Other {
+                tag,
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let (tag, payload) = match self {
+            DataBlock::Audio(sads) => (1, sads.iter().flat_map(|s| s.to_bytes()).collect()),
+            DataBlock::Video(vics) => (2, vics.iter().map(|v| v.to_byte()).collect()),
+            DataBlock::Hdr(hdr) => {
+                let mut payload = vec![6];
+                payload.extend(hdr.to_bytes());
+                (7, payload)
+            }
+            DataBlock::Other { tag, data } => (*tag, data.clone()),
+        };
+        let mut b = vec![tag << 5 | (payload.len() as u8 & 0x1f)];
+        b.extend(payload);
+        b
+    }
+}
+
+/// CEA-861 extension block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CeaExtension {
+    pub revision: u8,
+    pub underscan: bool,
+    pub basic_audio: bool,
+    pub ycbcr444: bool,
+    pub ycbcr422: bool,
+    /// Number of the detailed timings that are native formats
+    pub native_dtds: u8,
+    pub data_blocks: Vec<DataBlock>,
+    pub detailed_timings: Vec<DetailedTiming>,
+}
+
+impl Default for CeaExtension {
+    fn default() -> Self {
+        CeaExtension {
+            revision: 3,
+            underscan: false,
+            basic_audio: false,
+            ycbcr444: false,
+            ycbcr422: false,
+            native_dtds: 0,
+            data_blocks: Vec::new(),
+            detailed_timings: Vec::new(),
+        }
+    }
+}
+
+impl CeaExtension {
+    pub fn parse(block: &[u8]) -> Option<Self> {
+        if block.len() < BLOCK_SIZE || block[0] != CEA_TAG {
+            return None;
+        }
+        let dtd_offset = (block[2] as usize).min(BLOCK_SIZE - 1);
+        let mut cea = CeaExtension {
+            revision: block[1],
+            underscan: block[3] & 0x80 != 0,
+            basic_audio: block[3] & 0x40 != 0,
+            ycbcr444: block[3] & 0x20 != 0,
+            ycbcr422: block[3] & 0x10 != 0,
+            native_dtds: block[3] & 0xf,
+            ..Default::default()
+        };
+        let mut pos = 4;
+        while pos < dtd_offset {
+            let tag = block[pos] >> 5;
+            let len = (block[pos] & 0x1f) as usize;
+            let end = (pos + 1 + len).min(dtd_offset);
+            cea.data_blocks
+                .push(DataBlock::parse(tag, &block[pos + 1..end]));
+            pos = end;
+        }
+        if dtd_offset >= 4 {
+            let mut pos = dtd_offset;
+            while pos + 18 < BLOCK_SIZE {
+                match DetailedTiming::parse(&block[pos..pos + 18]) {
+                    Some(t) => cea.detailed_timings.push(t),
+                    None => break,
+                }
+                pos += 18;
+            }
+        }
+        Some(cea)
+    }
+
+    /// Serialize to a 128-byte block with a valid checksum
+    pub fn to_block(&self) -> Result<[u8; BLOCK_SIZE], EdidError> {
+        let mut collection = Vec::new();
+        for block in &self.data_blocks {
+            let bytes = block.to_bytes();
+            // the length field is five bits wide
+            if bytes.len() > 32 {
+                return Err(EdidError::TooLarge);
+            }
+            collection.extend(bytes);
+        }
+        let dtd_offset = 4 + collection.len();
+        if dtd_offset + 18 * self.detailed_timings.len() > BLOCK_SIZE - 1 {
+            return Err(EdidError::TooLarge);
+        }
+        let mut block = [0u8; BLOCK_SIZE];
+        block[0] = CEA_TAG;
+        block[1] = self.revision;
+        block[2] = dtd_offset as u8;
+        block[3] = (self.underscan as u8) << 7
+            | (self.basic_audio as u8) << 6
+            | (self.ycbcr444 as u8) << 5
+            | (self.ycbcr422 as u8) << 4
+            | (self.native_dtds & 0xf);
+        block[4..dtd_offset].copy_from_slice(&collection);
+        for (i, t) in self.detailed_timings.iter().enumerate() {
+            let pos = dtd_offset + 18 * i;
+            block[pos..pos + 18].copy_from_slice(&t.to_bytes());
+        }
+        block[BLOCK_SIZE - 1] = checksum(&block);
+        Ok(block)
+    }
+
+    /// All VICs of the video data blocks
+    pub fn vics(&self) -> Vec<Vic> {
+        self.data_blocks
+            .iter()
+            .filter_map(|b| match b {
+                DataBlock::Video(vics) => Some(vics.iter().copied()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Keep only the VICs accepted by `f`
+    pub fn retain_vics<F: FnMut(&Vic) -> bool>(&mut self, mut f: F) {
+        for block in &mut self.data_blocks {
+            if let DataBlock::Video(vics) = block {
+                vics.retain(&mut f);
+            }
+        }
+    }
+
+    pub fn audio_descriptors(&self) -> Vec<AudioDescriptor> {
+        self.data_blocks
+            .iter()
+            .filter_map(|b| match b {
+                DataBlock::Audio(sads) => Some(sads.iter().copied()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    pub fn hdr(&self) -> Option<&HdrStaticMetadata> {
+        self.data_blocks.iter().find_map(|b| match b {
+            DataBlock::Hdr(hdr) => Some(hdr),
+            _ => None,
+        })
+    }
+
+    fn hdmi_vsdb(&self) -> Option<&Vec<u8>> {
+        self.data_blocks.iter().find_map(|b| match b {
+            DataBlock::Other { tag: 3, data } if data.starts_with(&HDMI_OUI) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// CEC physical address from the HDMI vendor block, e.g. 0x1000 for 1.0.0.0
+    pub fn physical_address(&self) -> Option<u16> {
+        let data = self.hdmi_vsdb()?;
+        Some(u16::from_be_bytes([*data.get(3)?, *data.get(4)?]))
+    }
+
+    /// Set the physical address, adding a minimal HDMI vendor block if needed
+    pub fn set_physical_address(&mut self, address: u16) {
+        let [ab, cd] = address.to_be_bytes();
+        for block in &mut self.data_blocks {
+            if let DataBlock::Other { tag: 3, data } = block {
+                if data.starts_with(&HDMI_OUI) && data.len() >= 5 {
+                    data[3] = ab;
+                    data[4] = cd;
+                    return;
+                }
+            }
+        }
+        let mut data = HDMI_OUI.to_vec();
+        data.extend([ab, cd]);
+        self.data_blocks.push(DataBlock::Other { tag: 3, data });
+    }
+}
+
+/// A complete EDID, base block plus extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    data: Vec<u8>,
+}
+
+impl Edid {
+    /// Checks the layout; checksums are not verified so a broken EDID can
+    /// still be repaired with [`Edid::fix_checksums`].
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, EdidError> {
+        if data.is_empty() || !data.chunks_exact(BLOCK_SIZE).remainder().is_empty() {
+            return Err(EdidError::Length(data.len()));
+        }
+        if data[..8] != HEADER {
+            return Err(EdidError::Header);
+        }
+        if data.len() < (data[126] as usize + 1) * BLOCK_SIZE {
+            return Err(EdidError::Length(data.len()));
+        }
+        Ok(Edid { data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.data.len() / BLOCK_SIZE
+    }
+
+    pub fn block(&self, n: usize) -> Option<&[u8]> {
+        self.data.get(n * BLOCK_SIZE..(n + 1) * BLOCK_SIZE)
+    }
+
+    /// Every block must sum to zero
+    pub fn verify(&self) -> Result<(), EdidError> {
+        for (i, block) in self.data.chunks(BLOCK_SIZE).enumerate() {
+            if block[BLOCK_SIZE - 1] != checksum(block) {
+                return Err(EdidError::Checksum(i));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fix_checksums(&mut self) {
+        for block in self.data.chunks_mut(BLOCK_SIZE) {
+            block[BLOCK_SIZE - 1] = checksum(block);
+        }
+    }
+
+    pub fn base(&self) -> BaseBlock {
+        let b = &self.data[..BLOCK_SIZE];
+        let mut base = BaseBlock {
+            manufacturer: decode_manufacturer(u16::from_be_bytes([b[8], b[9]])),
+            product_code: u16::from_le_bytes([b[10], b[11]]),
+            serial: u32::from_le_bytes([b[12], b[13], b[14], b[15]]),
+            week: b[16],
+            year: 1990 + b[17] as u16,
+            version: (b[18], b[19]),
+            name: None,
+            serial_string: None,
+            preferred_timing: None,
+            detailed_timings: Vec::new(),
+            extension_count: b[126],
+        };
+        for d in b[54..126].chunks(18) {
+            if let Some(t) = DetailedTiming::parse(d) {
+                base.detailed_timings.push(t);
+                continue;
+            }
+            match d[3] {
+                DESCRIPTOR_NAME => base.name = Some(descriptor_text(d)),
+                DESCRIPTOR_SERIAL => base.serial_string = Some(descriptor_text(d)),
+                _ => (),
+            }
+        }
+        // the first descriptor is always the preferred timing, if it is one
+        base.preferred_timing = DetailedTiming::parse(&b[54..72]);
+        base
+    }
+
+    fn cea_index(&self) -> Option<usize> {
+        (1..self.block_count()).find(|&i| self.data[i * BLOCK_SIZE] == CEA_TAG)
+    }
+
+    /// The first CEA-861 extension
+    pub fn cea(&self) -> Option<CeaExtension> {
+        CeaExtension::parse(self.block(self.cea_index()?)?)
+    }
+
+    /// Replace the first CEA-861 extension, or append one
+    pub fn set_cea(&mut self, cea: &CeaExtension) -> Result<(), EdidError> {
+        let block = cea.to_block()?;
+        match self.cea_index() {
+            Some(i) => self.data[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE].copy_from_slice(&block),
+            None => {
+                self.data[126] = self.data[126]
+                    .checked_add(1)
+                    .ok_or(EdidError::TooManyExtensions)?;
+                self.data.extend_from_slice(&block);
+                self.data[127] = checksum(&self.data[..BLOCK_SIZE]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop all extension blocks
+    pub fn remove_extensions(&mut self) {
+        self.data.truncate(BLOCK_SIZE);
+        self.data[126] = 0;
+        self.fix_checksums();
+    }
+
+    /// Write the preferred (first) detailed timing
+    pub fn set_preferred_timing(&mut self, timing: &DetailedTiming) {
+        self.data[54..72].copy_from_slice(&timing.to_bytes());
+        self.fix_checksums();
+    }
+
+    fn set_text_descriptor(&mut self, tag: u8, text: &str) -> Result<(), EdidError> {
+        // reuse a descriptor with the same tag, else take a dummy/unused slot
+        let slots = [54, 72, 90, 108];
+        let slot = slots
+            .iter()
+            .find(|&&s| self.data[s..s + 2] == [0, 0] && self.data[s + 3] == tag)
+            .or_else(|| {
+                slots.iter().find(|&&s| {
+                    self.data[s..s + 2] == [0, 0]
+                        && (self.data[s + 3] == DESCRIPTOR_DUMMY || self.data[s + 3] == 0)
+                })
+            })
+            .copied()
+            .ok_or(EdidError::NoDescriptorSlot)?;
+        self.data[slot..slot + 18].copy_from_slice(&text_descriptor(tag, text));
+        self.fix_checksums();
+        Ok(())
+    }
+
+    /// Monitor name descriptor, at most 13 characters
+    pub fn set_name(&mut self, name: &str) -> Result<(), EdidError> {
+        self.set_text_descriptor(DESCRIPTOR_NAME, name)
+    }
+
+    pub fn set_serial_string(&mut self, serial: &str) -> Result<(), EdidError> {
+        self.set_text_descriptor(DESCRIPTOR_SERIAL, serial)
+    }
+}
+
+/// Builds an EDID 1.3 for a digital sink
+#[derive(Debug, Clone)]
+pub struct EdidBuilder {
+    manufacturer: String,
+    product_code: u16,
+    serial: u32,
+    year: u16,
+    name: Option<String>,
+    preferred_timing: Option<DetailedTiming>,
+    cea: Option<CeaExtension>,
+}
+
+impl EdidBuilder {
+    pub fn new(manufacturer: &str, product_code: u16) -> Self {
+        EdidBuilder {
+            manufacturer: manufacturer.into(),
+            product_code,
+            serial: 0,
+            year: 2020,
+            name: None,
+            preferred_timing: None,
+            cea: None,
+        }
+    }
+
+    pub fn serial(mut self, serial: u32) -> Self {
+        self.serial = serial;
+        self
+    }
+
+    /// Year of manufacture, 1990 to 2245
+    pub fn year(mut self, year: u16) -> Self {
+        self.year = year;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn preferred_timing(mut self, timing: DetailedTiming) -> Self {
+        self.preferred_timing = Some(timing);
+        self
+    }
+
+    pub fn cea(mut self, cea: CeaExtension) -> Self {
+        self.cea = Some(cea);
+        self
+    }
+
+    pub fn build(self) -> Result<Edid, EdidError> {
+        let mut b = [0u8; BLOCK_SIZE];
+        b[..8].copy_from_slice(&HEADER);
+        b[8..10].copy_from_slice(&encode_manufacturer(&self.manufacturer)?.to_be_bytes());
+        b[10..12].copy_from_slice(&self.product_code.to_le_bytes());
+        b[12..16].copy_from_slice(&self.serial.to_le_bytes());
+        b[17] = self.year.saturating_sub(1990).min(255) as u8;
+        b[18] = 1;
+        b[19] = 3;
+        // digital input, gamma 2.2, RGB, preferred timing in the first descriptor
+        b[20] = 0x80;
+        b[23] = 120;
+        b[24] = 0x0a;
+        // sRGB primaries and white point
+        b[25..35].copy_from_slice(&[0xee, 0x91, 0xa3, 0x54, 0x4c, 0x99, 0x26, 0x0f, 0x50, 0x54]);
+        // no standard timings
+        b[38..54].fill(0x01);
+        let mut slots = [54, 72, 90, 108].into_iter();
+        if let Some(t) = &self.preferred_timing {
+            let s = slots.next().unwrap();
+            b[s..s + 18].copy_from_slice(&t.to_bytes());
+        }
+        if let Some(name) = &self.name {
+            let s = slots.next().unwrap();
+            b[s..s + 18].copy_from_slice(&text_descriptor(DESCRIPTOR_NAME, name));
+        }
+        for s in slots {
+            b[s + 3] = DESCRIPTOR_DUMMY;
+        }
+        b[127] = checksum(&b);
+        let mut edid = Edid { data: b.to_vec() };
+        if let Some(cea) = &self.cea {
+            edid.set_cea(cea)?;
+        }
+        Ok(edid)
+    }
+}
+
+/// VIDIOC_G_EDID for `pad` (the input or output index on a video node).
+/// Fails with ENODATA when no EDID is available.
+pub fn get_edid<D: Ioctl + ?Sized>(dev: &D, pad: u32) -> io::Result<Edid> {
+    let mut e: crate::v4l2_edid = unsafe { mem::zeroed() };
+    e.pad = pad;
+    // blocks == 0 asks the driver for the number of blocks
+    ioctl(dev, codes::VIDIOC_G_EDID, &mut e)?;
+    if e.blocks == 0 {
+        return Err(io::Error::from_raw_os_error(libc::ENODATA));
+    }
+    let mut data = vec![0u8; e.blocks as usize * BLOCK_SIZE];
+    e.start_block = 0;
+    e.edid = data.as_mut_ptr();
+    ioctl(dev, codes::VIDIOC_G_EDID, &mut e)?;
+    data.truncate(e.blocks as usize * BLOCK_SIZE);
+    Ok(Edid::from_bytes(data)?)
+}
+
+/// VIDIOC_S_EDID. The EDID is verified first; E2BIG means the receiver
+/// cannot hold that many blocks.
+pub fn set_edid<D: Ioctl + ?Sized>(dev: &D, pad: u32, edid: &Edid) -> io::Result<()> {
+    edid.verify()?;
+    let mut data = edid.as_bytes().to_vec();
+    let mut e: crate::v4l2_edid = unsafe { mem::zeroed() };
+    e.pad = pad;
+    e.blocks = edid.block_count() as u32;
+    e.edid = data.as_mut_ptr();
+    ioctl(dev, codes::VIDIOC_S_EDID, &mut e)
+}
+
+/// VIDIOC_S_EDID with no blocks, which pulls the hotplug detect low
+pub fn clear_edid<D: Ioctl + ?Sized>(dev: &D, pad: u32) -> io::Result<()> {
+    let mut e: crate::v4l2_edid = unsafe { mem::zeroed() };
+    e.pad = pad;
+    ioctl(dev, codes::VIDIOC_S_EDID, &mut e)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// CEA-861 VIC 16, 1920x1080p60
+    fn timing_1080p60() -> DetailedTiming {
+        DetailedTiming {
+            pixel_clock_khz: 148_500,
+            hactive: 1920,
+            hblank: 280,
+            hsync_offset: 88,
+            hsync_width: 44,
+            vactive: 1080,
+            vblank: 45,
+            vsync_offset: 4,
+            vsync_width: 5,
+            width_mm: 600,
+            height_mm: 340,
+            interlaced: false,
+            hsync_positive: true,
+            vsync_positive: true,
+        }
+    }
+
+    fn sample() -> Edid {
+        let mut cea = CeaExtension {
+            basic_audio: true,
+            ycbcr444: true,
+            native_dtds: 1,
+            ..Default::default()
+        };
+        cea.data_blocks = vec![
+            DataBlock::Video(vec![
+                Vic {
+                    vic: 16,
+                    native: true,
+                },
+                Vic {
+                    vic: 4,
+                    native: false,
+                },
+                Vic {
+                    vic: 97,
+                    native: false,
+                },
+            ]),
+            DataBlock::Audio(vec![AudioDescriptor::lpcm(2, 0x07, 0x07)]),
+            DataBlock::Hdr(HdrStaticMetadata {
+                eotf: 0x0d,
+                metadata_types: 1,
+                max_luminance: Some(96),
+                max_frame_avg_luminance: None,
+                min_luminance: None,
+            }),
+        ];
+        cea.set_physical_address(0x1000);
+        cea.detailed_timings.push(timing_1080p60());
+        EdidBuilder::new("LNX", 0x1234)
+            .serial(42)
+            .year(2023)
+            .name("Capture HDMI")
+            .preferred_timing(timing_1080p60())
+            .cea(cea)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn build_and_parse() {
+        let edid = sample();
+        assert_eq!(edid.block_count(), 2);
+        edid.verify().unwrap();
+        let edid = Edid::from_bytes(edid.into_bytes()).unwrap();
+
+        let base = edid.base();
+        assert_eq!(base.manufacturer, "LNX");
+        assert_eq!(
+            (base.product_code, base.serial, base.year),
+            (0x1234, 42, 2023)
+        );
+        assert_eq!(base.version, (1, 3));
+        assert_eq!(base.name.as_deref(), Some("Capture HDMI"));
+        assert_eq!(base.preferred_timing, Some(timing_1080p60()));
+        assert_eq!(timing_1080p60().to_string(), "1920x1080p 60.00 Hz");
+
+        let cea = edid.cea().unwrap();
+        assert!(cea.basic_audio && cea.ycbcr444 && !cea.underscan);
+        let vics: Vec<u8> = cea.vics().iter().map(|v| v.vic).collect();
+        assert_eq!(vics, [16, 4, 97]);
+        assert!(cea.vics()[0].native);
+        let audio = cea.audio_descriptors();
+        assert_eq!(audio[0].format_name(), "Linear PCM");
+        assert_eq!(audio[0].channels, 2);
+        let hdr = cea.hdr().unwrap();
+        assert!(hdr.supports_pq() && hdr.supports_hlg());
+        assert_eq!(hdr.max_luminance_nits(), Some(400.0));
+        assert_eq!(cea.physical_address(), Some(0x1000));
+        assert_eq!(cea.detailed_timings, [timing_1080p60()]);
+    }
+
+    #[test]
+    fn edit_and_checksums() {
+        let mut edid = sample();
+        let mut cea = edid.cea().unwrap();
+        // advertise 1080p only
+        cea.retain_vics(|v| v.vic == 16);
+        cea.set_physical_address(0x2100);
+        edid.set_cea(&cea).unwrap();
+        edid.set_name("Grabber").unwrap();
+        edid.verify().unwrap();
+        let cea = edid.cea().unwrap();
+        assert_eq!(cea.vics().len(), 1);
+        assert_eq!(cea.physical_address(), Some(0x2100));
+        assert_eq!(edid.base().name.as_deref(), Some("Grabber"));
+
+        let mut bytes = edid.clone().into_bytes();
+        bytes[200] ^= 0xff;
+        let mut broken = Edid::from_bytes(bytes).unwrap();
+        assert_eq!(broken.verify(), Err(EdidError::Checksum(1)));
+        broken.fix_checksums();
+        broken.verify().unwrap();
+
+        edid.remove_extensions();
+        assert_eq!(edid.block_count(), 1);
+        assert_eq!(edid.base().extension_count, 0);
+        edid.verify().unwrap();
+
+        assert_eq!(Edid::from_bytes(vec![0; 128]), Err(EdidError::Header));
+        assert_eq!(Edid::from_bytes(vec![0; 100]), Err(EdidError::Length(100)));
+        assert_eq!(
+            EdidBuilder::new("lnx", 1).build(),
+            Err(EdidError::Manufacturer)
+        );
+    }
+
+    #[test]
+    fn interlaced_timing() {
+        // CEA-861 VIC 5, 1920x1080i60: vertical values are per field
+        let t = DetailedTiming {
+            pixel_clock_khz: 74_250,
+            hblank: 280,
+            vactive: 540,
+            vblank: 22,
+            vsync_offset: 2,
+            interlaced: true,
+            ..timing_1080p60()
+        };
+        assert_eq!(t.refresh_millihz(), 60_053);
+        assert_eq!(t.to_string(), "1920x1080i 60.05 Hz");
+        assert_eq!(DetailedTiming::parse(&t.to_bytes()), Some(t));
+    }
+
+    #[test]
+    fn extensions_and_malformed_input() {
+        // a non-CEA extension first, so set_cea appends a second block
+        let base = EdidBuilder::new("LNX", 1).build().unwrap();
+        let mut bytes = base.into_bytes();
+        let mut ext = [0u8; BLOCK_SIZE];
+        ext[0] = 0x70;
+        ext[BLOCK_SIZE - 1] = checksum(&ext);
+        bytes.extend_from_slice(&ext);
+        bytes[126] = 1;
+        let mut edid = Edid::from_bytes(bytes).unwrap();
+        assert_eq!(edid.verify(), Err(EdidError::Checksum(0)));
+        edid.fix_checksums();
+        assert!(edid.cea().is_none());
+        edid.set_cea(&CeaExtension::default()).unwrap();
+        edid.verify().unwrap();
+        assert_eq!(edid.block_count(), 3);
+        assert_eq!(edid.base().extension_count, 2);
+        assert!(edid.cea().is_some());
+
+        // more extensions announced than present
+        let mut bytes = edid.clone().into_bytes();
+        bytes[126] = 3;
+        assert_eq!(Edid::from_bytes(bytes), Err(EdidError::Length(384)));
+        let mut bytes = edid.into_bytes();
+        bytes.truncate(200);
+        assert_eq!(Edid::from_bytes(bytes), Err(EdidError::Length(200)));
+
+        // a descriptor cut short
+        let timing = timing_1080p60().to_bytes();
+        assert!(DetailedTiming::parse(&timing).is_some());
+        assert_eq!(DetailedTiming::parse(&timing[..17]), None);
+        assert_eq!(DetailedTiming::parse(&[]), None);
+
+        // the extension count is a byte
+        let mut bytes = EdidBuilder::new("LNX", 1).build().unwrap().into_bytes();
+        bytes[126] = 255;
+        bytes.resize(256 * BLOCK_SIZE, 0);
+        let mut full = Edid::from_bytes(bytes).unwrap();
+        assert_eq!(
+            full.set_cea(&CeaExtension::default()),
+            Err(EdidError::TooManyExtensions)
+        );
+        assert_eq!(full.block_count(), 256);
+    }
+}
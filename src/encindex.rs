@@ -0,0 +1,216 @@
+//! Encoder frame index (VIDIOC_G_ENC_INDEX)
+//!
+//! Hardware MPEG encoders (cx18, ivtv) report where each I/P/B frame starts
+//! in the `V4L2_PIX_FMT_MPEG` stream they produce. Polling the index while
+//! recording is enough to build a seek table for the file.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-g-enc-index.html
+
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+
+/// MPEG presentation timestamps count a 90 kHz clock in 33 bits
+pub const PTS_CLOCK_HZ: u64 = 90_000;
+const PTS_WRAP: u64 = 1 << 33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameType {
+    I,
+    P,
+    B,
+    Other(u32),
+}
+
+impl FrameType {
+    pub fn from_flags(flags: u32) -> Self {
+        match flags & crate::V4L2_ENC_IDX_FRAME_MASK {
+            crate::V4L2_ENC_IDX_FRAME_I => FrameType::I,
+            crate::V4L2_ENC_IDX_FRAME_P => FrameType::P,
+            crate::V4L2_ENC_IDX_FRAME_B => FrameType::B,
+            other => FrameType::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Byte offset of the frame from the start of the stream
+    pub offset: u64,
+    /// 33-bit presentation timestamp in 90 kHz units
+    pub pts: u64,
+    /// Length of the frame in bytes
+    pub length: u32,
+    pub frame_type: FrameType,
+}
+
+impl IndexEntry {
+    pub fn is_keyframe(&self) -> bool {
+        self.frame_type == FrameType::I
+    }
+}
+
+impl From<&crate::v4l2_enc_idx_entry> for IndexEntry {
+    fn from(e: &crate::v4l2_enc_idx_entry) -> Self {
+        IndexEntry {
+            offset: e.offset,
+            pts: e.pts & (PTS_WRAP - 1),
+            length: e.length,
+            frame_type: FrameType::from_flags(e.flags),
+        }
+    }
+}
+
+/// VIDIOC_G_ENC_INDEX: the entries recorded since the previous call, at most
+/// `V4L2_ENC_IDX_ENTRIES`. Older entries are lost if the index is not read
+/// often enough.
+pub fn read_index<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<IndexEntry>> {
+    let mut idx: crate::v4l2_enc_idx = unsafe { mem::zeroed() };
+    ioctl(dev, codes::VIDIOC_G_ENC_INDEX, &mut idx)?;
+    let n = (idx.entries as usize).min(idx.entry.len());
+    Ok(idx.entry[..n].iter().map(IndexEntry::from).collect())
+}
+
+/// Seek table built from successive index reads.
+///
+/// Timestamps are unwrapped across the 33-bit rollover, so `pts` values in
+/// the table keep increasing for recordings longer than 26.5 hours.
+#[derive(Debug, Clone, Default)]
+pub struct SeekIndex {
+    entries: Vec<IndexEntry>,
+    /// Amount added to the raw PTS to undo wraparounds
+    epoch: u64,
+    last_raw_pts: Option<u64>,
+}
+
+impl SeekIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the encoder index once and append the new entries
+    pub fn update<D: Ioctl + ?Sized>(&mut self, dev: &D) -> io::Result<usize> {
+        let entries = read_index(dev)?;
+        let n = entries.len();
+        self.extend(entries);
+        Ok(n)
+    }
+
+    pub fn extend<I: IntoIterator<Item = IndexEntry>>(&mut self, entries: I) {
+        for mut e in entries {
+            if let Some(last) = self.last_raw_pts {
+                // B frames run slightly backwards, a wrap jumps by almost 2^33
+                if last > e.pts && last - e.pts > PTS_WRAP / 2 {
+                    self.epoch += PTS_WRAP;
+                }
+            }
+            self.last_raw_pts = Some(e.pts);
+            e.pts += self.epoch;
+            self.entries.push(e);
+        }
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn keyframes(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.iter().filter(|e| e.is_keyframe())
+    }
+
+    /// The last I frame presented at or before `pts` (unwrapped, 90 kHz)
+    pub fn seek(&self, pts: u64) -> Option<&IndexEntry> {
+        self.keyframes().filter(|e| e.pts <= pts).last()
+    }
+
+    /// As [`SeekIndex::seek`], with the position relative to the first frame.
+    /// Positions past the end give the last keyframe.
+    pub fn seek_time(&self, position: Duration) -> Option<&IndexEntry> {
+        let start = self.entries.iter().map(|e| e.pts).min()?;
+        let ticks = position.as_micros() * PTS_CLOCK_HZ as u128 / 1_000_000;
+        self.seek(start.saturating_add(u64::try_from(ticks).unwrap_or(u64::MAX)))
+    }
+
+    /// Presentation time between the first and the last frame
+    pub fn duration(&self) -> Duration {
+        let pts = self.entries.iter().map(|e| e.pts);
+        match (pts.clone().min(), pts.max()) {
+            (Some(first), Some(last)) => {
+                Duration::from_micros((last - first) * 1_000_000 / PTS_CLOCK_HZ)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(offset: u64, pts: u64, flags: u32) -> IndexEntry {
+        let mut raw: crate::v4l2_enc_idx_entry = unsafe { mem::zeroed() };
+        raw.offset = offset;
+        raw.pts = pts;
+        raw.length = 1000;
+        raw.flags = flags;
+        IndexEntry::from(&raw)
+    }
+
+    #[test]
+    fn seek_table() {
+        let (i, p, b) = (
+            crate::V4L2_ENC_IDX_FRAME_I,
+            crate::V4L2_ENC_IDX_FRAME_P,
+            crate::V4L2_ENC_IDX_FRAME_B,
+        );
+        let mut index = SeekIndex::new();
+        // 25 fps, 3600 ticks per frame, GOP of I B P starting near the wrap
+        let start = PTS_WRAP - 3 * 3600;
+        index.extend([
+            entry(0, start, i),
+            entry(8000, start + 2 * 3600, p),
+            entry(12000, start + 3600, b),
+        ]);
+        index.extend([
+            entry(20000, (start + 3 * 3600) % PTS_WRAP, i),
+            entry(28000, (start + 5 * 3600) % PTS_WRAP, p),
+            entry(32000, (start + 4 * 3600) % PTS_WRAP, b),
+        ]);
+        assert_eq!(index.entries()[1].frame_type, FrameType::P);
+        assert_eq!(index.entries()[2].frame_type, FrameType::B);
+        assert_eq!(index.keyframes().count(), 2);
+        assert_eq!(index.entries()[4].pts, start + 5 * 3600);
+        assert_eq!(index.duration(), Duration::from_millis(200));
+
+        assert_eq!(index.seek(start + 3600).unwrap().offset, 0);
+        assert_eq!(index.seek(start + 4 * 3600).unwrap().offset, 20000);
+        assert!(index.seek(start - 1).is_none());
+        assert_eq!(
+            index.seek_time(Duration::from_millis(150)).unwrap().offset,
+            20000
+        );
+    }
+
+    #[test]
+    fn seek_time_across_wraps() {
+        let i = crate::V4L2_ENC_IDX_FRAME_I;
+        let mut index = SeekIndex::new();
+        // one keyframe every 10 hours for 30 hours, wrapping at 26.5 hours
+        let hour = 3600 * PTS_CLOCK_HZ;
+        index.extend((0..4).map(|n| entry(n * 1000, (n * 10 * hour) % PTS_WRAP, i)));
+        assert_eq!(index.entries()[3].pts, 30 * hour);
+        assert_eq!(index.duration(), Duration::from_secs(30 * 3600));
+
+        let at = |secs| index.seek_time(Duration::from_secs(secs)).unwrap().offset;
+        assert_eq!(at(0), 0);
+        assert_eq!(at(25 * 3600), 2000);
+        assert_eq!(at(29 * 3600), 2000);
+        assert_eq!(at(30 * 3600), 3000);
+        // far past the end, where the tick count no longer fits in u64
+        assert_eq!(at(u64::MAX), 3000);
+        assert_eq!(index.seek_time(Duration::MAX).unwrap().offset, 3000);
+        assert!(SeekIndex::new().seek_time(Duration::ZERO).is_none());
+    }
+}
@@ -0,0 +1,1642 @@
+//! In-memory stand-in for a video device, used by the unit tests
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::slice;
+
+use crate::buffer::Mmap;
+use crate::cec_codes;
+use crate::codes;
+use crate::control::ControlInfo;
+use crate::device::Ioctl;
+use crate::input::InputStatus;
+use crate::jpeg::JpegCompression;
+use crate::media_codes;
+use crate::overlay::{FbufCapability, Framebuffer, Rect, Window};
+use crate::request::RequestAlloc;
+use crate::streamparm::Fraction;
+use crate::subdev::{MbusFormat, Route};
+use crate::subdev_codes;
+use crate::topology::Topology;
+use crate::uvc::XuInfo;
+use crate::uvc_codes;
+
+pub(crate) struct FakeControl {
+    pub info: ControlInfo,
+    pub value: i64,
+    /// Control is flagged inactive unless control `.0` currently holds `.1`
+    pub active_when: Option<(u32, i64)>,
+    /// Errno returned when the value is read
+    pub read_error: Option<i32>,
+}
+
+impl FakeControl {
+    pub fn integer(id: u32, name: &str, minimum: i64, maximum: i64, value: i64) -> Self {
+        Self::new(
+            id,
+            name,
+            crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER,
+            minimum,
+            maximum,
+            value,
+        )
+    }
+
+    pub fn new(id: u32, name: &str, type_: u32, minimum: i64, maximum: i64, value: i64) -> Self {
+        FakeControl {
+            info: ControlInfo {
+                id,
+                type_,
+                name: name.into(),
+                minimum,
+                maximum,
+                step: 1,
+                default_value: value,
+                flags: 0,
+                elem_size: if type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 {
+                    8
+                } else {
+                    4
+                },
+                elems: 1,
+                dims: Vec::new(),
+            },
+            value,
+            active_when: None,
+            read_error: None,
+        }
+    }
+
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.info.flags |= flags;
+        self
+    }
+
+    pub fn active_when(mut self, id: u32, value: i64) -> Self {
+        self.active_when = Some((id, value));
+        self
+    }
+
+    pub fn read_error(mut self, errno: i32) -> Self {
+        self.read_error = Some(errno);
+        self
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FakeDevice {
+    pub controls: RefCell<Vec<FakeControl>>,
+    /// Every successful control write, in order
+    pub writes: RefCell<Vec<(u32, i64)>>,
+    /// Successive VIDIOC_QUERYSTD answers, a mask or an errno
+    pub query_std: RefCell<VecDeque<Result<u64, i32>>>,
+    /// Camera inputs by index, with their current status. Input `n` is
+    /// paired with audio input `n`.
+    pub inputs: RefCell<Vec<(&'static str, InputStatus)>>,
+    pub input: Cell<u32>,
+    /// Stereo audio inputs with AVL
+    pub audio_inputs: RefCell<Vec<&'static str>>,
+    /// Selected audio input and its mode
+    pub audio: Cell<(u32, u32)>,
+    /// Stations of the FM radio tuner, in 62.5 Hz units
+    pub stations: RefCell<Vec<u32>>,
+    pub frequency: Cell<u32>,
+    /// VIDIOC_G/S_JPEGCOMP state, ENOTTY when `None`
+    pub jpegcomp: RefCell<Option<JpegCompression>>,
+    /// `V4L2_PRIORITY_*` of this handle, 0 for the default, and the highest
+    /// one held by other handles
+    pub priority: Cell<u32>,
+    pub other_priority: Cell<u32>,
+    /// Overlay state. The fake overlay can chroma key and clip by list, and
+    /// keeps the window inside the framebuffer.
+    pub framebuffer: RefCell<Option<Framebuffer>>,
+    pub window: RefCell<Option<Window>>,
+    pub overlay: Cell<bool>,
+    /// Debug register contents by (match type, chip, register)
+    pub registers: RefCell<BTreeMap<(u32, u32, u64), u64>>,
+    /// Graph of the media device, ENOTTY for media ioctls when `None`
+    pub media: RefCell<Option<Topology>>,
+    /// Single-planar capture format: fourcc, width, height
+    pub capture: Cell<(u32, u32, u32)>,
+}
+
+fn errno(code: i32) -> io::Error {
+    io::Error::from_raw_os_error(code)
+}
+
+impl FakeDevice {
+    pub fn with_controls(controls: Vec<FakeControl>) -> Self {
+        FakeDevice {
+            controls: RefCell::new(controls),
+            ..Default::default()
+        }
+    }
+
+    pub fn value(&self, id: u32) -> Option<i64> {
+        let controls = self.controls.borrow();
+        controls.iter().find(|c| c.info.id == id).map(|c| c.value)
+    }
+
+    /// EBUSY while another handle has a higher priority
+    fn prio_check(&self) -> io::Result<()> {
+        let own = match self.priority.get() {
+            0 => crate::v4l2_priority_V4L2_PRIORITY_DEFAULT,
+            p => p,
+        };
+        if own < self.other_priority.get() {
+            return Err(errno(libc::EBUSY));
+        }
+        Ok(())
+    }
+
+    fn flags(&self, ctrl: &FakeControl) -> u32 {
+        match ctrl.active_when {
+            Some((id, value)) if self.value(id) != Some(value) => {
+                ctrl.info.flags | crate::V4L2_CTRL_FLAG_INACTIVE
+            }
+            _ => ctrl.info.flags,
+        }
+    }
+
+    fn query_ext_ctrl(&self, q: &mut crate::v4l2_query_ext_ctrl) -> io::Result<()> {
+        let next = crate::V4L2_CTRL_FLAG_NEXT_CTRL | crate::V4L2_CTRL_FLAG_NEXT_COMPOUND;
+        let id = q.id & !next;
+        let controls = self.controls.borrow();
+        let ctrl = if q.id & next != 0 {
+            controls
+                .iter()
+                .filter(|c| c.info.id > id)
+                .min_by_key(|c| c.info.id)
+        } else {
+            controls.iter().find(|c| c.info.id == id)
+        }
+        .ok_or_else(|| errno(libc::EINVAL))?;
+
+        q.id = ctrl.info.id;
+        q.type_ = ctrl.info.type_;
+        for (dst, src) in q.name.iter_mut().zip(ctrl.info.name.bytes().take(31)) {
+            *dst = src as libc::c_char;
+        }
+        q.minimum = ctrl.info.minimum;
+        q.maximum = ctrl.info.maximum;
+        q.step = ctrl.info.step;
+        q.default_value = ctrl.info.default_value;
+        q.flags = self.flags(ctrl);
+        q.elem_size = ctrl.info.elem_size;
+        q.elems = ctrl.info.elems;
+        q.nr_of_dims = ctrl.info.dims.len() as u32;
+        for (dst, src) in q.dims.iter_mut().zip(ctrl.info.dims.iter()) {
+            *dst = *src;
+        }
+        Ok(())
+    }
+
+    fn ext_ctrls(
+        &self,
+        request: libc::c_ulong,
+        c: &mut crate::v4l2_ext_controls,
+    ) -> io::Result<()> {
+        let ctrls = unsafe { slice::from_raw_parts_mut(c.controls, c.count as usize) };
+        for (i, ctrl) in ctrls.iter_mut().enumerate() {
+            c.error_idx = i as u32;
+            let id = ctrl.id;
+            let mut controls = self.controls.borrow_mut();
+            let fake = controls
+                .iter_mut()
+                .find(|f| f.info.id == id)
+                .ok_or_else(|| errno(libc::EINVAL))?;
+            let is64 = fake.info.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64;
+            if request == codes::VIDIOC_G_EXT_CTRLS {
+                if let Some(e) = fake.read_error {
+                    return Err(errno(e));
+                }
+                if is64 {
+                    ctrl.__bindgen_anon_1.value64 = fake.value;
+                } else {
+                    ctrl.__bindgen_anon_1.value = fake.value as i32;
+                }
+                continue;
+            }
+            if fake.info.flags & crate::V4L2_CTRL_FLAG_READ_ONLY != 0 {
+                return Err(errno(libc::EACCES));
+            }
+            if fake.info.flags & crate::V4L2_CTRL_FLAG_GRABBED != 0 {
+                return Err(errno(libc::EBUSY));
+            }
+            let value = unsafe {
+                if is64 {
+                    ctrl.__bindgen_anon_1.value64
+                } else {
+                    ctrl.__bindgen_anon_1.value as i64
+                }
+            };
+            if value < fake.info.minimum || value > fake.info.maximum {
+                return Err(errno(libc::ERANGE));
+            }
+            fake.value = value;
+            drop(controls);
+            self.writes.borrow_mut().push((id, value));
+        }
+        c.error_idx = c.count;
+        Ok(())
+    }
+
+    fn enum_input(&self, i: &mut crate::v4l2_input) -> io::Result<()> {
+        let inputs = self.inputs.borrow();
+        let (name, status) = inputs
+            .get(i.index as usize)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        for (dst, src) in i.name.iter_mut().zip(name.bytes().take(31)) {
+            *dst = src;
+        }
+        i.type_ = crate::V4L2_INPUT_TYPE_CAMERA;
+        i.audioset = 1 << i.index;
+        i.status = status.0;
+        Ok(())
+    }
+
+    fn audio(&self, a: &mut crate::v4l2_audio, request: libc::c_ulong) -> io::Result<()> {
+        if request == codes::VIDIOC_G_AUDIO {
+            (a.index, a.mode) = self.audio.get();
+        }
+        let inputs = self.audio_inputs.borrow();
+        let name = inputs
+            .get(a.index as usize)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        if request == codes::VIDIOC_S_AUDIO {
+            self.audio.set((a.index, a.mode));
+            return Ok(());
+        }
+        for (dst, src) in a.name.iter_mut().zip(name.bytes().take(31)) {
+            *dst = src;
+        }
+        a.capability = crate::V4L2_AUDCAP_STEREO | crate::V4L2_AUDCAP_AVL;
+        Ok(())
+    }
+
+    /// 87.5-108 MHz in 62.5 Hz units
+    const FM_RANGE: (u32, u32) = (1_400_000, 1_728_000);
+
+    fn tuner(&self, t: &mut crate::v4l2_tuner) -> io::Result<()> {
+        if t.index != 0 {
+            return Err(errno(libc::EINVAL));
+        }
+        for (dst, src) in t.name.iter_mut().zip(b"FM Radio".iter()) {
+            *dst = *src;
+        }
+        t.type_ = crate::v4l2_tuner_type_V4L2_TUNER_RADIO;
+        t.capability = crate::V4L2_TUNER_CAP_LOW
+            | crate::V4L2_TUNER_CAP_STEREO
+            | crate::V4L2_TUNER_CAP_HWSEEK_BOUNDED;
+        (t.rangelow, t.rangehigh) = Self::FM_RANGE;
+        t.rxsubchans = crate::V4L2_TUNER_SUB_STEREO;
+        t.signal = 0xffff;
+        Ok(())
+    }
+
+    unsafe fn capture_fmt(
+        &self,
+        request: libc::c_ulong,
+        f: &mut crate::v4l2_format,
+    ) -> io::Result<()> {
+        let pix = &mut f.fmt.pix;
+        if request != codes::VIDIOC_G_FMT {
+            let width = pix.width.clamp(16, 4096);
+            let height = pix.height.clamp(16, 4096);
+            if request == codes::VIDIOC_S_FMT {
+                self.capture.set((pix.pixelformat, width, height));
+            }
+            (pix.width, pix.height) = (width, height);
+            return Ok(());
+        }
+        (pix.pixelformat, pix.width, pix.height) = self.capture.get();
+        Ok(())
+    }
+
+    unsafe fn overlay_fmt(
+        &self,
+        request: libc::c_ulong,
+        f: &mut crate::v4l2_format,
+    ) -> io::Result<()> {
+        if f.type_ != crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OVERLAY {
+            return Err(errno(libc::EINVAL));
+        }
+        let win = &mut f.fmt.win;
+        if request == codes::VIDIOC_G_FMT {
+            let window = self.window.borrow().clone().unwrap_or_default();
+            win.w = window.rect.into();
+            win.field = window.field;
+            win.chromakey = window.chromakey;
+            win.global_alpha = window.global_alpha;
+            if !win.clips.is_null() {
+                let room = slice::from_raw_parts_mut(win.clips, win.clipcount as usize);
+                for (dst, &src) in room.iter_mut().zip(&window.clips) {
+                    dst.c = src.into();
+                }
+            }
+            win.clipcount = window.clips.len() as u32;
+            return Ok(());
+        }
+        let mut window = Window::from_raw(win);
+        if let Some(fb) = *self.framebuffer.borrow() {
+            let room = (fb.width as i64 - window.rect.left as i64).max(0) as u32;
+            window.rect.width = window.rect.width.min(room);
+        }
+        win.w = window.rect.into();
+        if request == codes::VIDIOC_S_FMT {
+            *self.window.borrow_mut() = Some(window);
+        }
+        Ok(())
+    }
+
+    /// Bridge 0 and sub-device 0 exist, both with 8-bit registers
+    fn chip_name(m: &crate::v4l2_dbg_match) -> io::Result<&'static str> {
+        match (m.type_, unsafe { m.__bindgen_anon_1.addr }) {
+            (crate::V4L2_CHIP_MATCH_BRIDGE, 0) => Ok("fake-bridge"),
+            (crate::V4L2_CHIP_MATCH_SUBDEV, 0) => Ok("fake-sensor"),
+            _ => Err(errno(libc::EINVAL)),
+        }
+    }
+
+    unsafe fn g_topology(&self, t: &mut crate::media_v2_topology) -> io::Result<()> {
+        let media = self.media.borrow();
+        let topology = media.as_ref().ok_or_else(|| errno(libc::ENOTTY))?;
+        t.topology_version = topology.version;
+        let fits = |ptr: u64, room: u32, len: usize| ptr == 0 || room as usize >= len;
+        if !fits(t.ptr_entities, t.num_entities, topology.entities.len())
+            || !fits(
+                t.ptr_interfaces,
+                t.num_interfaces,
+                topology.interfaces.len(),
+            )
+            || !fits(t.ptr_pads, t.num_pads, topology.pads.len())
+            || !fits(t.ptr_links, t.num_links, topology.links.len())
+        {
+            return Err(errno(libc::ENOSPC));
+        }
+        if t.ptr_entities != 0 {
+            let dst = slice::from_raw_parts_mut(
+                t.ptr_entities as *mut crate::media_v2_entity,
+                topology.entities.len(),
+            );
+            for (d, e) in dst.iter_mut().zip(&topology.entities) {
+                d.id = e.id;
+                for (c, s) in d.name.iter_mut().zip(e.name.bytes().take(63)) {
+                    *c = s as libc::c_char;
+                }
+                d.function = e.function;
+                d.flags = e.flags;
+            }
+        }
+        if t.ptr_interfaces != 0 {
+            let dst = slice::from_raw_parts_mut(
+                t.ptr_interfaces as *mut crate::media_v2_interface,
+                topology.interfaces.len(),
+            );
+            for (d, i) in dst.iter_mut().zip(&topology.interfaces) {
+                d.id = i.id;
+                d.intf_type = i.intf_type;
+                d.flags = i.flags;
+                d.__bindgen_anon_1.devnode.major = i.major;
+                d.__bindgen_anon_1.devnode.minor = i.minor;
+            }
+        }
+        if t.ptr_pads != 0 {
+            let dst = slice::from_raw_parts_mut(
+                t.ptr_pads as *mut crate::media_v2_pad,
+                topology.pads.len(),
+            );
+            for (d, p) in dst.iter_mut().zip(&topology.pads) {
+                d.id = p.id;
+                d.entity_id = p.entity_id;
+                d.index = p.index;
+                d.flags = p.flags;
+            }
+        }
+        if t.ptr_links != 0 {
+            let dst = slice::from_raw_parts_mut(
+                t.ptr_links as *mut crate::media_v2_link,
+                topology.links.len(),
+            );
+            for (d, l) in dst.iter_mut().zip(&topology.links) {
+                d.id = l.id;
+                d.source_id = l.source_id;
+                d.sink_id = l.sink_id;
+                d.flags = l.flags;
+            }
+        }
+        t.num_entities = topology.entities.len() as u32;
+        t.num_interfaces = topology.interfaces.len() as u32;
+        t.num_pads = topology.pads.len() as u32;
+        t.num_links = topology.links.len() as u32;
+        Ok(())
+    }
+
+    fn setup_link(&self, desc: &crate::media_link_desc) -> io::Result<()> {
+        let mut media = self.media.borrow_mut();
+        let topology = media.as_mut().ok_or_else(|| errno(libc::ENOTTY))?;
+        let pad = |d: &crate::media_pad_desc| {
+            topology
+                .pad_at(d.entity, d.index as u32)
+                .map(|p| p.id)
+                .ok_or_else(|| errno(libc::EINVAL))
+        };
+        let (source, sink) = (pad(&desc.source)?, pad(&desc.sink)?);
+        let id = topology
+            .link_between(source, sink)
+            .filter(|l| !l.is_immutable())
+            .ok_or_else(|| errno(libc::EINVAL))?
+            .id;
+        for l in topology.links.iter_mut().filter(|l| l.id == id) {
+            l.flags = (l.flags & !crate::MEDIA_LNK_FL_ENABLED)
+                | (desc.flags & crate::MEDIA_LNK_FL_ENABLED);
+        }
+        Ok(())
+    }
+
+    fn seek(&self, s: &crate::v4l2_hw_freq_seek) -> io::Result<()> {
+        let current = self.frequency.get();
+        let stations = self.stations.borrow();
+        let found = if s.seek_upward != 0 {
+            stations.iter().filter(|&&f| f > current).min()
+        } else {
+            stations.iter().filter(|&&f| f < current).max()
+        };
+        let found = found.ok_or_else(|| errno(libc::ENODATA))?;
+        self.frequency.set(*found);
+        Ok(())
+    }
+}
+
+impl Ioctl for FakeDevice {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        match request {
+            codes::VIDIOC_QUERY_EXT_CTRL => {
+                self.query_ext_ctrl(&mut *(arg as *mut crate::v4l2_query_ext_ctrl))
+            }
+            codes::VIDIOC_G_EXT_CTRLS | codes::VIDIOC_S_EXT_CTRLS => {
+                if request == codes::VIDIOC_S_EXT_CTRLS {
+                    self.prio_check()?;
+                }
+                self.ext_ctrls(request, &mut *(arg as *mut crate::v4l2_ext_controls))
+            }
+            codes::VIDIOC_ENUMINPUT => self.enum_input(&mut *(arg as *mut crate::v4l2_input)),
+            codes::VIDIOC_G_PRIORITY => {
+                let own = match self.priority.get() {
+                    0 => crate::v4l2_priority_V4L2_PRIORITY_DEFAULT,
+                    p => p,
+                };
+                *(arg as *mut u32) = own.max(self.other_priority.get());
+                Ok(())
+            }
+            codes::VIDIOC_S_PRIORITY => {
+                self.prio_check()?;
+                self.priority.set(*(arg as *mut u32));
+                Ok(())
+            }
+            codes::VIDIOC_G_FBUF => {
+                let fb = self.framebuffer.borrow().unwrap_or_default();
+                *(arg as *mut crate::v4l2_framebuffer) = fb.to_raw();
+                Ok(())
+            }
+            codes::VIDIOC_S_FBUF => {
+                let mut fb = Framebuffer::from(&*(arg as *mut crate::v4l2_framebuffer));
+                fb.capability = FbufCapability(
+                    crate::V4L2_FBUF_CAP_CHROMAKEY
+                        | crate::V4L2_FBUF_CAP_LIST_CLIPPING
+                        | crate::V4L2_FBUF_CAP_GLOBAL_ALPHA,
+                );
+                *self.framebuffer.borrow_mut() = Some(fb);
+                Ok(())
+            }
+            codes::VIDIOC_G_FMT | codes::VIDIOC_S_FMT | codes::VIDIOC_TRY_FMT => {
+                let f = &mut *(arg as *mut crate::v4l2_format);
+                if f.type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE {
+                    self.capture_fmt(request, f)
+                } else {
+                    self.overlay_fmt(request, f)
+                }
+            }
+            codes::VIDIOC_OVERLAY => {
+                let on = *(arg as *mut libc::c_int) != 0;
+                if on && (self.framebuffer.borrow().is_none() || self.window.borrow().is_none()) {
+                    return Err(errno(libc::EINVAL));
+                }
+                self.overlay.set(on);
+                Ok(())
+            }
+            codes::VIDIOC_DBG_G_CHIP_INFO => {
+                let c = &mut *(arg as *mut crate::v4l2_dbg_chip_info);
+                let name = Self::chip_name(&c.match_)?;
+                for (dst, src) in c.name.iter_mut().zip(name.bytes()) {
+                    *dst = src as libc::c_char;
+                }
+                c.flags = crate::V4L2_CHIP_FL_READABLE | crate::V4L2_CHIP_FL_WRITABLE;
+                Ok(())
+            }
+            codes::VIDIOC_DBG_G_REGISTER | codes::VIDIOC_DBG_S_REGISTER => {
+                let r = &mut *(arg as *mut crate::v4l2_dbg_register);
+                Self::chip_name(&r.match_)?;
+                let key = (r.match_.type_, r.match_.__bindgen_anon_1.addr, r.reg);
+                let mut registers = self.registers.borrow_mut();
+                if request == codes::VIDIOC_DBG_S_REGISTER {
+                    registers.insert(key, r.val & 0xff);
+                } else {
+                    r.val = registers.get(&key).copied().unwrap_or(0);
+                    r.size = 1;
+                }
+                Ok(())
+            }
+            codes::VIDIOC_LOG_STATUS => Ok(()),
+            codes::VIDIOC_G_INPUT => {
+                *(arg as *mut libc::c_int) = self.input.get() as libc::c_int;
+                Ok(())
+            }
+            codes::VIDIOC_S_INPUT => {
+                self.prio_check()?;
+                let index = *(arg as *mut libc::c_int) as u32;
+                if index as usize >= self.inputs.borrow().len() {
+                    return Err(errno(libc::EINVAL));
+                }
+                self.input.set(index);
+                Ok(())
+            }
+            codes::VIDIOC_ENUMAUDIO | codes::VIDIOC_G_AUDIO | codes::VIDIOC_S_AUDIO => {
+                self.audio(&mut *(arg as *mut crate::v4l2_audio), request)
+            }
+            codes::VIDIOC_G_JPEGCOMP => match &*self.jpegcomp.borrow() {
+                Some(params) => {
+                    *(arg as *mut crate::v4l2_jpegcompression) = params.to_raw()?;
+                    Ok(())
+                }
+                None => Err(errno(libc::ENOTTY)),
+            },
+            codes::VIDIOC_S_JPEGCOMP => match &mut *self.jpegcomp.borrow_mut() {
+                Some(params) => {
+                    *params = JpegCompression::from(&*(arg as *mut crate::v4l2_jpegcompression));
+                    Ok(())
+                }
+                None => Err(errno(libc::ENOTTY)),
+            },
+            codes::VIDIOC_G_TUNER => self.tuner(&mut *(arg as *mut crate::v4l2_tuner)),
+            codes::VIDIOC_G_FREQUENCY => {
+                let f = &mut *(arg as *mut crate::v4l2_frequency);
+                f.frequency = self.frequency.get();
+                Ok(())
+            }
+            codes::VIDIOC_S_FREQUENCY => {
+                let f = &*(arg as *mut crate::v4l2_frequency);
+                let (low, high) = Self::FM_RANGE;
+                self.frequency.set(f.frequency.clamp(low, high));
+                Ok(())
+            }
+            codes::VIDIOC_S_HW_FREQ_SEEK => self.seek(&*(arg as *mut crate::v4l2_hw_freq_seek)),
+            codes::VIDIOC_QUERYSTD => match self.query_std.borrow_mut().pop_front() {
+                Some(Ok(std)) => {
+                    *(arg as *mut crate::v4l2_std_id) = std;
+                    Ok(())
+                }
+                Some(Err(code)) => Err(errno(code)),
+                None => Err(errno(libc::ENODATA)),
+            },
+            media_codes::MEDIA_IOC_DEVICE_INFO if self.media.borrow().is_some() => {
+                let info = &mut *(arg as *mut crate::media_device_info);
+                for (dst, src) in info.driver.iter_mut().zip(b"fake-media".iter()) {
+                    *dst = *src as libc::c_char;
+                }
+                Ok(())
+            }
+            media_codes::MEDIA_IOC_G_TOPOLOGY => {
+                self.g_topology(&mut *(arg as *mut crate::media_v2_topology))
+            }
+            media_codes::MEDIA_IOC_SETUP_LINK => {
+                self.setup_link(&*(arg as *mut crate::media_link_desc))
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+/// In-memory sub-device whose source pads pass the cropped sink pad 0
+/// through unchanged. Entities without sink pads act as sensors.
+pub(crate) struct FakeSubdev {
+    pub codes: Vec<u32>,
+    pub max_size: (u32, u32),
+    /// `MEDIA_PAD_FL_*` by pad index
+    pub pads: Vec<u32>,
+    /// Formats and sink crops by (which, pad)
+    pub formats: RefCell<BTreeMap<(u32, u32), MbusFormat>>,
+    pub crops: RefCell<BTreeMap<(u32, u32), Rect>>,
+    pub interval: Cell<Fraction>,
+    pub routes: RefCell<Vec<Route>>,
+}
+
+impl FakeSubdev {
+    pub fn new(codes: &[u32], max_size: (u32, u32), pads: &[u32]) -> Self {
+        FakeSubdev {
+            codes: codes.to_vec(),
+            max_size,
+            pads: pads.to_vec(),
+            formats: RefCell::default(),
+            crops: RefCell::default(),
+            interval: Cell::new(Fraction::new(1, 30)),
+            routes: RefCell::default(),
+        }
+    }
+
+    fn pad(&self, pad: u32) -> io::Result<u32> {
+        self.pads
+            .get(pad as usize)
+            .copied()
+            .ok_or_else(|| errno(libc::EINVAL))
+    }
+
+    /// Whether `pad` follows sink pad 0
+    fn is_derived(&self, pad: u32) -> bool {
+        let has_sink = self.pads.iter().any(|&f| f & crate::MEDIA_PAD_FL_SINK != 0);
+        has_sink && self.pads[pad as usize] & crate::MEDIA_PAD_FL_SOURCE != 0
+    }
+
+    fn format(&self, which: u32, pad: u32) -> MbusFormat {
+        if self.is_derived(pad) {
+            let crop = self.crop(which, 0);
+            let sink = self.format(which, 0);
+            let code = self
+                .formats
+                .borrow()
+                .get(&(which, pad))
+                .map_or(sink.code, |f| f.code);
+            return MbusFormat {
+                code,
+                width: crop.width,
+                height: crop.height,
+                ..sink
+            };
+        }
+        let (width, height) = self.max_size;
+        self.formats
+            .borrow()
+            .get(&(which, pad))
+            .copied()
+            .unwrap_or_else(|| MbusFormat::new(self.codes[0], width, height))
+    }
+
+    fn crop(&self, which: u32, pad: u32) -> Rect {
+        let f = self.format(which, pad);
+        let crops = self.crops.borrow();
+        crops
+            .get(&(which, pad))
+            .copied()
+            .unwrap_or(Rect::new(0, 0, f.width, f.height))
+    }
+
+    fn set_format(&self, f: &mut crate::v4l2_subdev_format) -> io::Result<()> {
+        self.pad(f.pad)?;
+        if !self.is_derived(f.pad) {
+            let mut format = MbusFormat::from(&f.format);
+            if !self.codes.contains(&format.code) {
+                format.code = self.codes[0];
+            }
+            format.width = format.width.clamp(32, self.max_size.0);
+            format.height = format.height.clamp(32, self.max_size.1);
+            self.formats.borrow_mut().insert((f.which, f.pad), format);
+            self.crops.borrow_mut().remove(&(f.which, f.pad));
+        } else if self.codes.contains(&f.format.code) {
+            // Only the code of a source pad can be changed, e.g. by a
+            // debayering block
+            let format = MbusFormat::new(f.format.code, 0, 0);
+            self.formats.borrow_mut().insert((f.which, f.pad), format);
+        }
+        f.format = self.format(f.which, f.pad).to_raw();
+        Ok(())
+    }
+
+    fn selection(
+        &self,
+        request: libc::c_ulong,
+        s: &mut crate::v4l2_subdev_selection,
+    ) -> io::Result<()> {
+        self.pad(s.pad)?;
+        if self.is_derived(s.pad) {
+            return Err(errno(libc::EINVAL));
+        }
+        let f = self.format(s.which, s.pad);
+        let bounds = Rect::new(0, 0, f.width, f.height);
+        let rect = match s.target {
+            crate::V4L2_SEL_TGT_CROP_BOUNDS | crate::V4L2_SEL_TGT_CROP_DEFAULT => bounds,
+            crate::V4L2_SEL_TGT_CROP if request == subdev_codes::VIDIOC_SUBDEV_S_SELECTION => {
+                let r = Rect::from(s.r);
+                let left = r.left.clamp(0, f.width as i32 - 32);
+                let top = r.top.clamp(0, f.height as i32 - 32);
+                let rect = Rect::new(
+                    left,
+                    top,
+                    r.width.clamp(32, f.width - left as u32),
+                    r.height.clamp(32, f.height - top as u32),
+                );
+                self.crops.borrow_mut().insert((s.which, s.pad), rect);
+                rect
+            }
+            crate::V4L2_SEL_TGT_CROP => self.crop(s.which, s.pad),
+            _ => return Err(errno(libc::EINVAL)),
+        };
+        s.r = rect.into();
+        Ok(())
+    }
+}
+
+impl Ioctl for FakeSubdev {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        match request {
+            subdev_codes::VIDIOC_SUBDEV_QUERYCAP => {
+                let c = &mut *(arg as *mut crate::uapi::v4l2_subdev_capability);
+                c.capabilities = crate::uapi::V4L2_SUBDEV_CAP_STREAMS;
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_S_CLIENT_CAP => {
+                let c = &mut *(arg as *mut crate::uapi::v4l2_subdev_client_capability);
+                c.capabilities &= crate::uapi::V4L2_SUBDEV_CLIENT_CAP_STREAMS;
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_ENUM_MBUS_CODE => {
+                let c = &mut *(arg as *mut crate::v4l2_subdev_mbus_code_enum);
+                self.pad(c.pad)?;
+                c.code = *self
+                    .codes
+                    .get(c.index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_ENUM_FRAME_SIZE => {
+                let s = &mut *(arg as *mut crate::v4l2_subdev_frame_size_enum);
+                self.pad(s.pad)?;
+                if s.index != 0 || !self.codes.contains(&s.code) {
+                    return Err(errno(libc::EINVAL));
+                }
+                (s.min_width, s.min_height) = (32, 32);
+                (s.max_width, s.max_height) = self.max_size;
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_ENUM_FRAME_INTERVAL => {
+                let i = &mut *(arg as *mut crate::v4l2_subdev_frame_interval_enum);
+                self.pad(i.pad)?;
+                let rate = [30, 60]
+                    .get(i.index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                i.interval = Fraction::new(1, *rate).into();
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_G_FRAME_INTERVAL => {
+                let i = &mut *(arg as *mut crate::v4l2_subdev_frame_interval);
+                i.interval = self.interval.get().into();
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_S_FRAME_INTERVAL => {
+                let i = &mut *(arg as *mut crate::v4l2_subdev_frame_interval);
+                self.interval.set(i.interval.into());
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_G_FMT => {
+                let f = &mut *(arg as *mut crate::v4l2_subdev_format);
+                self.pad(f.pad)?;
+                f.format = self.format(f.which, f.pad).to_raw();
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_S_FMT => {
+                self.set_format(&mut *(arg as *mut crate::v4l2_subdev_format))
+            }
+            subdev_codes::VIDIOC_SUBDEV_G_SELECTION | subdev_codes::VIDIOC_SUBDEV_S_SELECTION => {
+                self.selection(request, &mut *(arg as *mut crate::v4l2_subdev_selection))
+            }
+            subdev_codes::VIDIOC_SUBDEV_G_ROUTING => {
+                let r = &mut *(arg as *mut crate::uapi::v4l2_subdev_routing);
+                let routes = self.routes.borrow();
+                r.num_routes = routes.len() as u32;
+                if r.len_routes < r.num_routes {
+                    return Err(errno(libc::ENOSPC));
+                }
+                let dst = slice::from_raw_parts_mut(
+                    r.routes as *mut crate::uapi::v4l2_subdev_route,
+                    routes.len(),
+                );
+                for (d, route) in dst.iter_mut().zip(routes.iter()) {
+                    *d = route.into();
+                }
+                Ok(())
+            }
+            subdev_codes::VIDIOC_SUBDEV_S_ROUTING => {
+                let r = &*(arg as *mut crate::uapi::v4l2_subdev_routing);
+                let src = slice::from_raw_parts(
+                    r.routes as *const crate::uapi::v4l2_subdev_route,
+                    r.num_routes as usize,
+                );
+                *self.routes.borrow_mut() = src.iter().map(Route::from).collect();
+                Ok(())
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum RequestState {
+    #[default]
+    Idle,
+    Queued,
+    Complete,
+}
+
+#[derive(Default)]
+struct FakeRequestSlot {
+    state: RequestState,
+    /// Controls staged, in order
+    controls: Vec<u32>,
+    decode_params: Option<crate::uapi::v4l2_ctrl_h264_decode_params>,
+    /// OUTPUT buffer bound to the request
+    buffer: Option<usize>,
+}
+
+type RequestSlots = Rc<RefCell<Vec<FakeRequestSlot>>>;
+
+/// Request allocated from a [`FakeDecoder`]; its fd is 100 + index
+pub(crate) struct FakeRequest {
+    index: usize,
+    slots: RequestSlots,
+}
+
+impl AsRawFd for FakeRequest {
+    fn as_raw_fd(&self) -> RawFd {
+        100 + self.index as RawFd
+    }
+}
+
+impl Ioctl for FakeRequest {
+    unsafe fn ioctl(&self, request: libc::c_ulong, _arg: *mut libc::c_void) -> io::Result<()> {
+        let mut slots = self.slots.borrow_mut();
+        let slot = &mut slots[self.index];
+        match request {
+            media_codes::MEDIA_REQUEST_IOC_QUEUE => {
+                if slot.state != RequestState::Idle {
+                    return Err(errno(libc::EBUSY));
+                }
+                let required = [
+                    crate::uapi::V4L2_CID_STATELESS_H264_SPS,
+                    crate::uapi::V4L2_CID_STATELESS_H264_PPS,
+                    crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS,
+                ];
+                if slot.buffer.is_none() || required.iter().any(|id| !slot.controls.contains(id)) {
+                    return Err(errno(libc::ENOENT));
+                }
+                slot.state = RequestState::Queued;
+                Ok(())
+            }
+            media_codes::MEDIA_REQUEST_IOC_REINIT => {
+                if slot.state == RequestState::Queued {
+                    return Err(errno(libc::EBUSY));
+                }
+                *slot = FakeRequestSlot::default();
+                Ok(())
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct FakeBuffer {
+    data: Vec<u8>,
+    bytesused: u32,
+    flags: u32,
+    timestamp: u64,
+    sequence: u32,
+    /// With the driver, or waiting in a request
+    queued: bool,
+    done: bool,
+}
+
+/// Stateless H.264 decoder and its media device in one. Requests are
+/// checked the way the media core checks them, and run when CAPTURE is
+/// dequeued. A picture is flagged as an error when its DPB refers to a
+/// timestamp that was not decoded.
+#[derive(Default)]
+pub(crate) struct FakeDecoder {
+    /// Coded size; pictures are NV12 of the same size
+    size: Cell<(u32, u32)>,
+    controls: RefCell<BTreeMap<u32, i32>>,
+    output: RefCell<Vec<FakeBuffer>>,
+    capture: RefCell<Vec<FakeBuffer>>,
+    /// OUTPUT and CAPTURE streaming
+    streaming: Cell<(bool, bool)>,
+    /// CAPTURE buffer kept for the next slice
+    held: Cell<Option<usize>>,
+    slots: RequestSlots,
+    /// Timestamp and control ids of every request run, in order
+    pub decoded: RefCell<Vec<(u64, Vec<u32>)>>,
+}
+
+impl FakeDecoder {
+    fn queue_of(&self, type_: u32) -> io::Result<&RefCell<Vec<FakeBuffer>>> {
+        match type_ {
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT => Ok(&self.output),
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE => Ok(&self.capture),
+            _ => Err(errno(libc::EINVAL)),
+        }
+    }
+
+    fn sizeimage(&self, type_: u32) -> u32 {
+        let (width, height) = self.size.get();
+        if type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT {
+            (width * height).max(4096)
+        } else {
+            width * height * 3 / 2
+        }
+    }
+
+    fn fmt(&self, request: libc::c_ulong, f: &mut crate::v4l2_format) -> io::Result<()> {
+        self.queue_of(f.type_)?;
+        let output = f.type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT;
+        let pix = unsafe { &mut f.fmt.pix };
+        if request == codes::VIDIOC_S_FMT && output {
+            if !self.output.borrow().is_empty() {
+                return Err(errno(libc::EBUSY));
+            }
+            let (width, height) = (pix.width.clamp(16, 4096), pix.height.clamp(16, 4096));
+            self.size.set((width, height));
+        }
+        let (width, height) = self.size.get();
+        pix.pixelformat = if output {
+            crate::pixel_format::V4L2_PIX_FMT_H264_SLICE
+        } else {
+            crate::pixel_format::V4L2_PIX_FMT_NV12
+        };
+        (pix.width, pix.height) = (width, height);
+        pix.sizeimage = self.sizeimage(f.type_);
+        Ok(())
+    }
+
+    fn ext_ctrls(&self, c: &mut crate::uapi::v4l2_ext_controls) -> io::Result<()> {
+        let ctrls = unsafe { slice::from_raw_parts(c.controls, c.count as usize) };
+        if c.which != crate::uapi::V4L2_CTRL_WHICH_REQUEST_VAL {
+            for ctrl in ctrls {
+                let (id, value) = (ctrl.id, unsafe { ctrl.__bindgen_anon_1.value });
+                match id {
+                    crate::uapi::V4L2_CID_STATELESS_H264_DECODE_MODE
+                    | crate::uapi::V4L2_CID_STATELESS_H264_START_CODE
+                        if (0..=1).contains(&value) =>
+                    {
+                        self.controls.borrow_mut().insert(id, value);
+                    }
+                    _ => return Err(errno(libc::EINVAL)),
+                }
+            }
+            return Ok(());
+        }
+        let mut slots = self.slots.borrow_mut();
+        let slot = (c.request_fd as usize)
+            .checked_sub(100)
+            .and_then(|i| slots.get_mut(i))
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        if slot.state != RequestState::Idle {
+            return Err(errno(libc::EBUSY));
+        }
+        for ctrl in ctrls {
+            let size = match ctrl.id {
+                crate::uapi::V4L2_CID_STATELESS_H264_SPS => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_sps>()
+                }
+                crate::uapi::V4L2_CID_STATELESS_H264_PPS => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_pps>()
+                }
+                crate::uapi::V4L2_CID_STATELESS_H264_SCALING_MATRIX => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_scaling_matrix>()
+                }
+                crate::uapi::V4L2_CID_STATELESS_H264_PRED_WEIGHTS => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_pred_weights>()
+                }
+                crate::uapi::V4L2_CID_STATELESS_H264_SLICE_PARAMS => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_slice_params>()
+                }
+                crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS => {
+                    mem::size_of::<crate::uapi::v4l2_ctrl_h264_decode_params>()
+                }
+                _ => return Err(errno(libc::EINVAL)),
+            };
+            if ctrl.size as usize != size {
+                return Err(errno(libc::EINVAL));
+            }
+            if ctrl.id == crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS {
+                let ptr = unsafe { ctrl.__bindgen_anon_1.ptr };
+                slot.decode_params = Some(unsafe { *(ptr as *const _) });
+            }
+            slot.controls.push(ctrl.id);
+        }
+        Ok(())
+    }
+
+    fn reqbufs(&self, r: &mut crate::uapi::v4l2_requestbuffers) -> io::Result<()> {
+        let queue = self.queue_of(r.type_)?;
+        if r.memory != crate::v4l2_memory_V4L2_MEMORY_MMAP {
+            return Err(errno(libc::EINVAL));
+        }
+        let output = r.type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT;
+        let count = if r.count == 0 { 0 } else { r.count.min(8) };
+        let buffer = FakeBuffer {
+            data: vec![0; self.sizeimage(r.type_) as usize],
+            ..Default::default()
+        };
+        *queue.borrow_mut() = vec![buffer; count as usize];
+        r.count = count;
+        r.capabilities = crate::uapi::V4L2_BUF_CAP_SUPPORTS_MMAP;
+        if output {
+            r.capabilities |= crate::uapi::V4L2_BUF_CAP_SUPPORTS_REQUESTS
+                | crate::uapi::V4L2_BUF_CAP_SUPPORTS_M2M_HOLD_CAPTURE_BUF;
+        }
+        Ok(())
+    }
+
+    fn qbuf(&self, b: &mut crate::uapi::v4l2_buffer) -> io::Result<()> {
+        let output = b.type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT;
+        let mut queue = self.queue_of(b.type_)?.borrow_mut();
+        let buf = queue
+            .get_mut(b.index as usize)
+            .filter(|buf| !buf.queued)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        // Only OUTPUT supports requests, and requires them
+        let in_request = b.flags & crate::uapi::V4L2_BUF_FLAG_REQUEST_FD != 0;
+        if in_request != output {
+            return Err(errno(libc::EBADR));
+        }
+        if in_request {
+            let mut slots = self.slots.borrow_mut();
+            let slot = (b.request_fd as usize)
+                .checked_sub(100)
+                .and_then(|i| slots.get_mut(i))
+                .ok_or_else(|| errno(libc::EINVAL))?;
+            if slot.state != RequestState::Idle || slot.buffer.is_some() {
+                return Err(errno(libc::EBUSY));
+            }
+            slot.buffer = Some(b.index as usize);
+        }
+        buf.bytesused = b.bytesused;
+        buf.flags = b.flags & crate::uapi::V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF;
+        buf.timestamp = crate::buffer::timeval_to_ns(&b.timestamp);
+        buf.queued = true;
+        buf.done = false;
+        Ok(())
+    }
+
+    /// Run queued requests while there are CAPTURE buffers to decode into
+    fn process(&self) {
+        if self.streaming.get() != (true, true) {
+            return;
+        }
+        let mut slots = self.slots.borrow_mut();
+        let mut output = self.output.borrow_mut();
+        let mut capture = self.capture.borrow_mut();
+        for slot in slots.iter_mut() {
+            if slot.state != RequestState::Queued {
+                continue;
+            }
+            let target = self
+                .held
+                .take()
+                .or_else(|| capture.iter().position(|c| c.queued && !c.done));
+            let Some(target) = target else {
+                return;
+            };
+            let mut decoded = self.decoded.borrow_mut();
+            let known = |ts: u64| decoded.iter().any(|(t, _)| *t == ts);
+            let broken = match slot.decode_params {
+                Some(p) => p.dpb.iter().any(|e| {
+                    e.flags & crate::uapi::V4L2_H264_DPB_ENTRY_FLAG_VALID != 0
+                        && !known(e.reference_ts)
+                }),
+                None => true,
+            };
+            let out = &mut output[slot.buffer.unwrap()];
+            out.done = true;
+            let pic = &mut capture[target];
+            pic.timestamp = out.timestamp;
+            pic.sequence = decoded.len() as u32;
+            pic.bytesused = pic.data.len() as u32;
+            pic.data.fill(0x80);
+            if broken {
+                pic.flags |= crate::V4L2_BUF_FLAG_ERROR;
+            }
+            if out.flags & crate::uapi::V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF != 0 {
+                self.held.set(Some(target));
+            } else {
+                pic.done = true;
+            }
+            decoded.push((out.timestamp, slot.controls.clone()));
+            slot.state = RequestState::Complete;
+        }
+    }
+
+    fn dqbuf(&self, b: &mut crate::v4l2_buffer) -> io::Result<()> {
+        self.process();
+        let mut queue = self.queue_of(b.type_)?.borrow_mut();
+        let (index, buf) = queue
+            .iter_mut()
+            .enumerate()
+            .find(|(_, buf)| buf.queued && buf.done)
+            .ok_or_else(|| errno(libc::EAGAIN))?;
+        buf.queued = false;
+        b.index = index as u32;
+        b.bytesused = buf.bytesused;
+        b.flags = buf.flags & crate::V4L2_BUF_FLAG_ERROR;
+        b.sequence = buf.sequence;
+        b.timestamp = crate::buffer::ns_to_timeval(buf.timestamp);
+        buf.flags = 0;
+        Ok(())
+    }
+}
+
+impl Ioctl for FakeDecoder {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        match request {
+            codes::VIDIOC_S_EXT_CTRLS => {
+                self.ext_ctrls(&mut *(arg as *mut crate::uapi::v4l2_ext_controls))
+            }
+            codes::VIDIOC_G_FMT | codes::VIDIOC_S_FMT => {
+                self.fmt(request, &mut *(arg as *mut crate::v4l2_format))
+            }
+            codes::VIDIOC_REQBUFS => {
+                self.reqbufs(&mut *(arg as *mut crate::uapi::v4l2_requestbuffers))
+            }
+            codes::VIDIOC_QUERYBUF => {
+                let b = &mut *(arg as *mut crate::v4l2_buffer);
+                let queue = self.queue_of(b.type_)?.borrow();
+                let buf = queue
+                    .get(b.index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                let capture = b.type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+                b.m.offset = (capture as u32) << 16 | b.index;
+                b.length = buf.data.len() as u32;
+                Ok(())
+            }
+            codes::VIDIOC_QBUF => self.qbuf(&mut *(arg as *mut crate::uapi::v4l2_buffer)),
+            codes::VIDIOC_DQBUF => self.dqbuf(&mut *(arg as *mut crate::v4l2_buffer)),
+            codes::VIDIOC_STREAMON | codes::VIDIOC_STREAMOFF => {
+                let type_ = *(arg as *mut libc::c_int) as u32;
+                let on = request == codes::VIDIOC_STREAMON;
+                let (output, capture) = self.streaming.get();
+                if type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT {
+                    self.streaming.set((on, capture));
+                } else {
+                    self.queue_of(type_)?;
+                    self.streaming.set((output, on));
+                }
+                if !on {
+                    for buf in self.queue_of(type_)?.borrow_mut().iter_mut() {
+                        buf.queued = false;
+                        buf.done = false;
+                    }
+                    self.held.set(None);
+                }
+                // Requests lose their OUTPUT buffer; queued ones complete
+                if !on && type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT {
+                    for slot in self.slots.borrow_mut().iter_mut() {
+                        match slot.state {
+                            RequestState::Queued => slot.state = RequestState::Complete,
+                            RequestState::Idle => slot.buffer = None,
+                            RequestState::Complete => (),
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+impl Mmap for FakeDecoder {
+    unsafe fn mmap(&self, offset: u32, length: usize) -> io::Result<*mut u8> {
+        let queue = if offset >> 16 == 0 {
+            &self.output
+        } else {
+            &self.capture
+        };
+        let mut queue = queue.borrow_mut();
+        let buf = queue
+            .get_mut((offset & 0xffff) as usize)
+            .filter(|buf| buf.data.len() >= length)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        Ok(buf.data.as_mut_ptr())
+    }
+
+    unsafe fn munmap(&self, _ptr: *mut u8, _length: usize) {}
+}
+
+impl RequestAlloc for FakeDecoder {
+    type Handle = FakeRequest;
+
+    fn alloc_request(&self) -> io::Result<FakeRequest> {
+        let mut slots = self.slots.borrow_mut();
+        slots.push(FakeRequestSlot::default());
+        Ok(FakeRequest {
+            index: slots.len() - 1,
+            slots: self.slots.clone(),
+        })
+    }
+}
+
+/// CEC adapter on a bus where only a TV at 0.0.0.0 answers. The TV follows
+/// Image View On and Standby and answers Give Physical Address.
+pub(crate) struct FakeCec {
+    phys_addr: Cell<u16>,
+    log_addrs: RefCell<crate::cec_log_addrs>,
+    mode: Cell<u32>,
+    events: RefCell<VecDeque<crate::cec_event>>,
+    pub tv_on: Cell<bool>,
+    /// Every message sent, as raw bytes
+    pub transmitted: RefCell<Vec<Vec<u8>>>,
+    /// Messages waiting for CEC_RECEIVE
+    pub inbox: RefCell<VecDeque<Vec<u8>>>,
+}
+
+impl FakeCec {
+    pub fn new(phys_addr: u16) -> Self {
+        FakeCec {
+            phys_addr: Cell::new(phys_addr),
+            log_addrs: RefCell::new(unsafe { mem::zeroed() }),
+            mode: Cell::new(crate::CEC_MODE_INITIATOR),
+            events: RefCell::new(VecDeque::new()),
+            tv_on: Cell::new(false),
+            transmitted: RefCell::new(Vec::new()),
+            inbox: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    fn claim(&self, l: &mut crate::cec_log_addrs) {
+        l.log_addr_mask = 0;
+        for i in 0..l.num_log_addrs as usize {
+            let addr = match l.log_addr_type[i] as u32 {
+                crate::CEC_LOG_ADDR_TYPE_PLAYBACK => crate::CEC_LOG_ADDR_PLAYBACK_1,
+                crate::CEC_LOG_ADDR_TYPE_RECORD => crate::CEC_LOG_ADDR_RECORD_1,
+                crate::CEC_LOG_ADDR_TYPE_TUNER => crate::CEC_LOG_ADDR_TUNER_1,
+                crate::CEC_LOG_ADDR_TYPE_AUDIOSYSTEM => crate::CEC_LOG_ADDR_AUDIOSYSTEM,
+                _ => crate::CEC_LOG_ADDR_UNREGISTERED,
+            };
+            l.log_addr[i] = addr as u8;
+            l.log_addr_mask |= 1 << addr;
+        }
+        let mut event: crate::cec_event = unsafe { mem::zeroed() };
+        event.event = crate::CEC_EVENT_STATE_CHANGE;
+        event.__bindgen_anon_1.state_change.phys_addr = self.phys_addr.get();
+        event.__bindgen_anon_1.state_change.log_addr_mask = l.log_addr_mask;
+        self.events.borrow_mut().push_back(event);
+        *self.log_addrs.borrow_mut() = *l;
+    }
+
+    fn transmit(&self, msg: &mut crate::cec_msg) -> io::Result<()> {
+        let len = msg.len as usize;
+        if len == 0 || len > msg.msg.len() {
+            return Err(errno(libc::EINVAL));
+        }
+        let initiator = msg.msg[0] >> 4;
+        let destination = msg.msg[0] & 0xf;
+        if self.log_addrs.borrow().log_addr_mask & (1 << initiator) == 0 {
+            return Err(errno(libc::EINVAL));
+        }
+        self.transmitted.borrow_mut().push(msg.msg[..len].to_vec());
+        msg.sequence = self.transmitted.borrow().len() as u32;
+
+        let broadcast = destination as u32 == crate::CEC_LOG_ADDR_BROADCAST;
+        if !broadcast && destination as u32 != crate::CEC_LOG_ADDR_TV {
+            msg.tx_status = (crate::CEC_TX_STATUS_NACK | crate::CEC_TX_STATUS_MAX_RETRIES) as u8;
+            msg.tx_nack_cnt = 1;
+            return Ok(());
+        }
+        msg.tx_status = crate::CEC_TX_STATUS_OK as u8;
+        match msg.msg[1..len].first().map(|&op| op as u32) {
+            Some(crate::CEC_MSG_IMAGE_VIEW_ON) => self.tv_on.set(true),
+            Some(crate::CEC_MSG_STANDBY) => self.tv_on.set(false),
+            Some(crate::CEC_MSG_GIVE_PHYSICAL_ADDR)
+                if !broadcast && msg.reply as u32 == crate::CEC_MSG_REPORT_PHYSICAL_ADDR =>
+            {
+                let report = [
+                    (crate::CEC_LOG_ADDR_TV << 4 | crate::CEC_LOG_ADDR_BROADCAST) as u8,
+                    crate::CEC_MSG_REPORT_PHYSICAL_ADDR as u8,
+                    0,
+                    0,
+                    crate::CEC_OP_PRIM_DEVTYPE_TV as u8,
+                ];
+                msg.msg[..report.len()].copy_from_slice(&report);
+                msg.len = report.len() as u32;
+                msg.rx_status = crate::CEC_RX_STATUS_OK as u8;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Ioctl for FakeCec {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        match request {
+            cec_codes::CEC_ADAP_G_CAPS => {
+                let c = &mut *(arg as *mut crate::cec_caps);
+                for (dst, src) in c.driver.iter_mut().zip(b"fake-cec") {
+                    *dst = *src as libc::c_char;
+                }
+                c.available_log_addrs = 4;
+                c.capabilities = crate::CEC_CAP_LOG_ADDRS | crate::CEC_CAP_TRANSMIT;
+                Ok(())
+            }
+            cec_codes::CEC_ADAP_G_PHYS_ADDR => {
+                *(arg as *mut u16) = self.phys_addr.get();
+                Ok(())
+            }
+            cec_codes::CEC_ADAP_G_LOG_ADDRS => {
+                *(arg as *mut crate::cec_log_addrs) = *self.log_addrs.borrow();
+                Ok(())
+            }
+            cec_codes::CEC_ADAP_S_LOG_ADDRS => {
+                self.claim(&mut *(arg as *mut crate::cec_log_addrs));
+                Ok(())
+            }
+            cec_codes::CEC_G_MODE => {
+                *(arg as *mut u32) = self.mode.get();
+                Ok(())
+            }
+            cec_codes::CEC_S_MODE => {
+                self.mode.set(*(arg as *const u32));
+                Ok(())
+            }
+            cec_codes::CEC_TRANSMIT => self.transmit(&mut *(arg as *mut crate::cec_msg)),
+            cec_codes::CEC_RECEIVE => {
+                let msg = &mut *(arg as *mut crate::cec_msg);
+                let bytes = self
+                    .inbox
+                    .borrow_mut()
+                    .pop_front()
+                    .ok_or_else(|| errno(libc::ETIMEDOUT))?;
+                msg.msg[..bytes.len()].copy_from_slice(&bytes);
+                msg.len = bytes.len() as u32;
+                msg.rx_status = crate::CEC_RX_STATUS_OK as u8;
+                Ok(())
+            }
+            cec_codes::CEC_DQEVENT => {
+                let event = self
+                    .events
+                    .borrow_mut()
+                    .pop_front()
+                    .ok_or_else(|| errno(libc::EAGAIN))?;
+                *(arg as *mut crate::cec_event) = event;
+                Ok(())
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+/// Extension unit `unit` of a UVC camera, answering UVCIOC_CTRL_QUERY the
+/// way uvcvideo checks it
+pub(crate) struct FakeUvc {
+    unit: u8,
+    /// Selector to GET_INFO bits and current value
+    controls: RefCell<BTreeMap<u8, (XuInfo, Vec<u8>)>>,
+}
+
+impl FakeUvc {
+    pub fn new(unit: u8) -> Self {
+        FakeUvc {
+            unit,
+            controls: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn add(&self, selector: u8, info: XuInfo, value: Vec<u8>) {
+        self.controls.borrow_mut().insert(selector, (info, value));
+    }
+}
+
+impl Ioctl for FakeUvc {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        if request != uvc_codes::UVCIOC_CTRL_QUERY {
+            return Err(errno(libc::ENOTTY));
+        }
+        let q = &mut *(arg as *mut crate::uvc_xu_control_query);
+        let mut controls = self.controls.borrow_mut();
+        let (info, value) = controls
+            .get_mut(&q.selector)
+            .filter(|_| q.unit == self.unit)
+            .ok_or_else(|| errno(libc::ENOENT))?;
+        let data = slice::from_raw_parts_mut(q.data, q.size as usize);
+        let (expected, allowed) = match q.query as u32 {
+            crate::UVC_GET_LEN => (2, true),
+            crate::UVC_GET_INFO => (1, true),
+            crate::UVC_GET_CUR => (value.len(), info.can_get()),
+            crate::UVC_SET_CUR => (value.len(), info.can_set()),
+            _ => return Err(errno(libc::EINVAL)),
+        };
+        if !allowed {
+            return Err(errno(libc::EBADRQC));
+        }
+        if data.len() != expected {
+            return Err(errno(libc::EINVAL));
+        }
+        match q.query as u32 {
+            crate::UVC_GET_LEN => data.copy_from_slice(&(value.len() as u16).to_le_bytes()),
+            crate::UVC_GET_INFO => data[0] = info.0,
+            crate::UVC_GET_CUR => data.copy_from_slice(value),
+            _ => value.copy_from_slice(data),
+        }
+        Ok(())
+    }
+}
+
+/// Metadata node of one direction. A capture node fills each DQBUF from
+/// `pending`, tagged with its sequence number; an output node records the
+/// payload of every QBUF in `received` and returns the buffer right away.
+pub(crate) struct FakeMeta {
+    type_: u32,
+    formats: Vec<u32>,
+    dataformat: Cell<u32>,
+    buffers: RefCell<Vec<FakeBuffer>>,
+    pub streaming: Cell<bool>,
+    pub pending: RefCell<VecDeque<(u32, Vec<u8>)>>,
+    pub received: RefCell<Vec<(u32, Vec<u8>)>>,
+}
+
+impl FakeMeta {
+    const BUFFERSIZE: u32 = 10240;
+
+    /// Capture node producing only `dataformat`
+    pub fn new(dataformat: u32) -> Self {
+        Self::with_formats(
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE,
+            &[dataformat],
+        )
+    }
+
+    /// The first of `formats` is the default
+    pub fn with_formats(type_: u32, formats: &[u32]) -> Self {
+        FakeMeta {
+            type_,
+            formats: formats.to_vec(),
+            dataformat: Cell::new(formats[0]),
+            buffers: RefCell::new(Vec::new()),
+            streaming: Cell::new(false),
+            pending: RefCell::new(VecDeque::new()),
+            received: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn buffer_type(&self, type_: u32) -> io::Result<()> {
+        if type_ == self.type_ {
+            Ok(())
+        } else {
+            Err(errno(libc::EINVAL))
+        }
+    }
+
+    fn is_output(&self) -> bool {
+        self.type_ == crate::uapi::V4L2_BUF_TYPE_META_OUTPUT
+    }
+
+    fn qbuf(&self, b: &mut crate::v4l2_buffer) -> io::Result<()> {
+        let mut buffers = self.buffers.borrow_mut();
+        let buf = buffers
+            .get_mut(b.index as usize)
+            .filter(|buf| !buf.queued)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        if self.is_output() {
+            if b.bytesused as usize > buf.data.len() {
+                return Err(errno(libc::EINVAL));
+            }
+            let mut received = self.received.borrow_mut();
+            let sequence = received.len() as u32;
+            received.push((sequence, buf.data[..b.bytesused as usize].to_vec()));
+            buf.bytesused = b.bytesused;
+            buf.sequence = sequence;
+            buf.done = true;
+        }
+        buf.queued = true;
+        Ok(())
+    }
+
+    fn dqbuf(&self, b: &mut crate::v4l2_buffer) -> io::Result<()> {
+        let mut buffers = self.buffers.borrow_mut();
+        let output = self.is_output();
+        let (index, buf) = buffers
+            .iter_mut()
+            .enumerate()
+            .find(|(_, buf)| buf.queued && (buf.done || !output))
+            .filter(|_| self.streaming.get())
+            .ok_or_else(|| errno(libc::EAGAIN))?;
+        if !output {
+            let (sequence, payload) = self
+                .pending
+                .borrow_mut()
+                .pop_front()
+                .ok_or_else(|| errno(libc::EAGAIN))?;
+            let len = payload.len().min(buf.data.len());
+            buf.data[..len].copy_from_slice(&payload[..len]);
+            buf.bytesused = len as u32;
+            buf.sequence = sequence;
+        }
+        buf.queued = false;
+        buf.done = false;
+        b.index = index as u32;
+        b.bytesused = buf.bytesused;
+        b.sequence = buf.sequence;
+        Ok(())
+    }
+}
+
+impl Ioctl for FakeMeta {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        match request {
+            codes::VIDIOC_ENUM_FMT => {
+                let d = &mut *(arg as *mut crate::v4l2_fmtdesc);
+                self.buffer_type(d.type_)?;
+                d.pixelformat = *self
+                    .formats
+                    .get(d.index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                Ok(())
+            }
+            codes::VIDIOC_G_FMT | codes::VIDIOC_S_FMT | codes::VIDIOC_TRY_FMT => {
+                let f = &mut *(arg as *mut crate::v4l2_format);
+                self.buffer_type(f.type_)?;
+                let wanted = f.fmt.meta.dataformat;
+                let format = match request {
+                    codes::VIDIOC_G_FMT => self.dataformat.get(),
+                    _ if self.formats.contains(&wanted) => wanted,
+                    _ => self.dataformat.get(),
+                };
+                if request == codes::VIDIOC_S_FMT {
+                    if !self.buffers.borrow().is_empty() {
+                        return Err(errno(libc::EBUSY));
+                    }
+                    self.dataformat.set(format);
+                }
+                f.fmt.meta.dataformat = format;
+                f.fmt.meta.buffersize = Self::BUFFERSIZE;
+                Ok(())
+            }
+            codes::VIDIOC_REQBUFS => {
+                let r = &mut *(arg as *mut crate::uapi::v4l2_requestbuffers);
+                self.buffer_type(r.type_)?;
+                let buffer = FakeBuffer {
+                    data: vec![0; Self::BUFFERSIZE as usize],
+                    ..Default::default()
+                };
+                r.count = r.count.min(8);
+                *self.buffers.borrow_mut() = vec![buffer; r.count as usize];
+                r.capabilities = crate::uapi::V4L2_BUF_CAP_SUPPORTS_MMAP;
+                Ok(())
+            }
+            codes::VIDIOC_QUERYBUF => {
+                let b = &mut *(arg as *mut crate::v4l2_buffer);
+                self.buffer_type(b.type_)?;
+                let buffers = self.buffers.borrow();
+                let buf = buffers
+                    .get(b.index as usize)
+                    .ok_or_else(|| errno(libc::EINVAL))?;
+                b.m.offset = b.index;
+                b.length = buf.data.len() as u32;
+                Ok(())
+            }
+            codes::VIDIOC_QBUF => {
+                let b = &mut *(arg as *mut crate::v4l2_buffer);
+                self.buffer_type(b.type_)?;
+                self.qbuf(b)
+            }
+            codes::VIDIOC_DQBUF => self.dqbuf(&mut *(arg as *mut crate::v4l2_buffer)),
+            codes::VIDIOC_STREAMON | codes::VIDIOC_STREAMOFF => {
+                self.buffer_type(*(arg as *mut libc::c_int) as u32)?;
+                let on = request == codes::VIDIOC_STREAMON;
+                self.streaming.set(on);
+                if !on {
+                    for buf in self.buffers.borrow_mut().iter_mut() {
+                        buf.queued = false;
+                        buf.done = false;
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(errno(libc::ENOTTY)),
+        }
+    }
+}
+
+impl Mmap for FakeMeta {
+    unsafe fn mmap(&self, offset: u32, length: usize) -> io::Result<*mut u8> {
+        let mut buffers = self.buffers.borrow_mut();
+        let buf = buffers
+            .get_mut(offset as usize)
+            .filter(|buf| buf.data.len() >= length)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+        Ok(buf.data.as_mut_ptr())
+    }
+
+    unsafe fn munmap(&self, _ptr: *mut u8, _length: usize) {}
+}
@@ -0,0 +1,868 @@
+//! Stateless H.264 decoding (V4L2_PIX_FMT_H264_SLICE) through the request API
+//!
+//! The application parses the bitstream. Each frame goes to the driver as
+//! slice data in an OUTPUT buffer, queued in a request together with the
+//! SPS, PPS, scaling matrix and decode parameters. This is the interface of
+//! cedrus, hantro and rkvdec.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-stateless-decoder.html
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/ext-ctrls-codec-stateless.html
+
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+use crate::buffer::{is_multiplanar, Buffers, Dequeued, Mmap};
+use crate::codes;
+use crate::control::{self, Payload};
+use crate::device::{ioctl, Ioctl};
+use crate::pixel_format::V4L2_PIX_FMT_H264_SLICE;
+use crate::request::{Controls, Request, RequestAlloc, RequestFd};
+
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_sps {}
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_pps {}
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_scaling_matrix {}
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_pred_weights {}
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_slice_params {}
+unsafe impl Payload for crate::uapi::v4l2_ctrl_h264_decode_params {}
+
+fn too_many(what: &str, len: usize, max: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} {} entries, at most {} fit", len, what, max),
+    )
+}
+
+/// Sequence parameter set, `V4L2_CID_STATELESS_H264_SPS`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sps {
+    pub profile_idc: u8,
+    /// `V4L2_H264_SPS_CONSTRAINT_SET*_FLAG`
+    pub constraint_set_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_frame_num_minus4: u8,
+    pub pic_order_cnt_type: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub max_num_ref_frames: u8,
+    /// One entry per frame in the picture order count cycle
+    pub offset_for_ref_frame: Vec<i32>,
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub pic_width_in_mbs_minus1: u16,
+    pub pic_height_in_map_units_minus1: u16,
+    /// `V4L2_H264_SPS_FLAG_*`
+    pub flags: u32,
+}
+
+impl Sps {
+    pub fn width(&self) -> u32 {
+        (self.pic_width_in_mbs_minus1 as u32 + 1) * 16
+    }
+
+    /// Frame height; map units are field macroblock pairs unless the
+    /// stream is frame-only
+    pub fn height(&self) -> u32 {
+        let units = self.pic_height_in_map_units_minus1 as u32 + 1;
+        if self.flags & crate::uapi::V4L2_H264_SPS_FLAG_FRAME_MBS_ONLY != 0 {
+            units * 16
+        } else {
+            units * 32
+        }
+    }
+
+    pub fn to_raw(&self) -> io::Result<crate::uapi::v4l2_ctrl_h264_sps> {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_sps = unsafe { mem::zeroed() };
+        if self.offset_for_ref_frame.len() > raw.offset_for_ref_frame.len() {
+            return Err(too_many(
+                "offset_for_ref_frame",
+                self.offset_for_ref_frame.len(),
+                raw.offset_for_ref_frame.len() as u32,
+            ));
+        }
+        raw.profile_idc = self.profile_idc;
+        raw.constraint_set_flags = self.constraint_set_flags;
+        raw.level_idc = self.level_idc;
+        raw.seq_parameter_set_id = self.seq_parameter_set_id;
+        raw.chroma_format_idc = self.chroma_format_idc;
+        raw.bit_depth_luma_minus8 = self.bit_depth_luma_minus8;
+        raw.bit_depth_chroma_minus8 = self.bit_depth_chroma_minus8;
+        raw.log2_max_frame_num_minus4 = self.log2_max_frame_num_minus4;
+        raw.pic_order_cnt_type = self.pic_order_cnt_type;
+        raw.log2_max_pic_order_cnt_lsb_minus4 = self.log2_max_pic_order_cnt_lsb_minus4;
+        raw.max_num_ref_frames = self.max_num_ref_frames;
+        raw.num_ref_frames_in_pic_order_cnt_cycle = self.offset_for_ref_frame.len() as u8;
+        raw.offset_for_ref_frame[..self.offset_for_ref_frame.len()]
+            .copy_from_slice(&self.offset_for_ref_frame);
+        raw.offset_for_non_ref_pic = self.offset_for_non_ref_pic;
+        raw.offset_for_top_to_bottom_field = self.offset_for_top_to_bottom_field;
+        raw.pic_width_in_mbs_minus1 = self.pic_width_in_mbs_minus1;
+        raw.pic_height_in_map_units_minus1 = self.pic_height_in_map_units_minus1;
+        raw.flags = self.flags;
+        Ok(raw)
+    }
+}
+
+/// Picture parameter set, `V4L2_CID_STATELESS_H264_PPS`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pps {
+    pub pic_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub num_slice_groups_minus1: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub weighted_bipred_idc: u8,
+    pub pic_init_qp_minus26: i8,
+    pub pic_init_qs_minus26: i8,
+    pub chroma_qp_index_offset: i8,
+    pub second_chroma_qp_index_offset: i8,
+    /// `V4L2_H264_PPS_FLAG_*`
+    pub flags: u16,
+}
+
+impl Pps {
+    pub fn to_raw(self) -> crate::uapi::v4l2_ctrl_h264_pps {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_pps = unsafe { mem::zeroed() };
+        raw.pic_parameter_set_id = self.pic_parameter_set_id;
+        raw.seq_parameter_set_id = self.seq_parameter_set_id;
+        raw.num_slice_groups_minus1 = self.num_slice_groups_minus1;
+        raw.num_ref_idx_l0_default_active_minus1 = self.num_ref_idx_l0_default_active_minus1;
+        raw.num_ref_idx_l1_default_active_minus1 = self.num_ref_idx_l1_default_active_minus1;
+        raw.weighted_bipred_idc = self.weighted_bipred_idc;
+        raw.pic_init_qp_minus26 = self.pic_init_qp_minus26;
+        raw.pic_init_qs_minus26 = self.pic_init_qs_minus26;
+        raw.chroma_qp_index_offset = self.chroma_qp_index_offset;
+        raw.second_chroma_qp_index_offset = self.second_chroma_qp_index_offset;
+        raw.flags = self.flags;
+        raw
+    }
+}
+
+/// `V4L2_CID_STATELESS_H264_SCALING_MATRIX`, lists in zig-zag order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalingMatrix {
+    pub list_4x4: [[u8; 16]; 6],
+    pub list_8x8: [[u8; 64]; 6],
+}
+
+impl Default for ScalingMatrix {
+    /// Flat_4x4_16 and Flat_8x8_16
+    fn default() -> Self {
+        ScalingMatrix {
+            list_4x4: [[16; 16]; 6],
+            list_8x8: [[16; 64]; 6],
+        }
+    }
+}
+
+impl ScalingMatrix {
+    pub fn to_raw(self) -> crate::uapi::v4l2_ctrl_h264_scaling_matrix {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_scaling_matrix = unsafe { mem::zeroed() };
+        raw.scaling_list_4x4 = self.list_4x4;
+        raw.scaling_list_8x8 = self.list_8x8;
+        raw
+    }
+}
+
+/// Weights of one reference list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WeightFactors {
+    pub luma_weight: [i16; 32],
+    pub luma_offset: [i16; 32],
+    pub chroma_weight: [[i16; 2]; 32],
+    pub chroma_offset: [[i16; 2]; 32],
+}
+
+/// Explicit weighted prediction, `V4L2_CID_STATELESS_H264_PRED_WEIGHTS`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PredWeights {
+    pub luma_log2_weight_denom: u16,
+    pub chroma_log2_weight_denom: u16,
+    /// For list 0 and list 1
+    pub factors: [WeightFactors; 2],
+}
+
+impl PredWeights {
+    pub fn to_raw(self) -> crate::uapi::v4l2_ctrl_h264_pred_weights {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_pred_weights = unsafe { mem::zeroed() };
+        raw.luma_log2_weight_denom = self.luma_log2_weight_denom;
+        raw.chroma_log2_weight_denom = self.chroma_log2_weight_denom;
+        for (dst, src) in raw.weight_factors.iter_mut().zip(&self.factors) {
+            dst.luma_weight = src.luma_weight;
+            dst.luma_offset = src.luma_offset;
+            dst.chroma_weight = src.chroma_weight;
+            dst.chroma_offset = src.chroma_offset;
+        }
+        raw
+    }
+}
+
+/// Entry of a reference picture list: a DPB index and which fields of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reference {
+    /// `V4L2_H264_{TOP,BOTTOM}_FIELD_REF` or `V4L2_H264_FRAME_REF`
+    pub fields: u8,
+    pub index: u8,
+}
+
+/// Slice header, `V4L2_CID_STATELESS_H264_SLICE_PARAMS`; slice-based mode only
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SliceParams {
+    pub header_bit_size: u32,
+    pub first_mb_in_slice: u32,
+    /// `V4L2_H264_SLICE_TYPE_*`
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub redundant_pic_cnt: u8,
+    pub cabac_init_idc: u8,
+    pub slice_qp_delta: i8,
+    pub slice_qs_delta: i8,
+    pub disable_deblocking_filter_idc: u8,
+    pub slice_alpha_c0_offset_div2: i8,
+    pub slice_beta_offset_div2: i8,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    pub ref_pic_list0: Vec<Reference>,
+    pub ref_pic_list1: Vec<Reference>,
+    /// `V4L2_H264_SLICE_FLAG_*`
+    pub flags: u32,
+}
+
+impl SliceParams {
+    pub fn to_raw(&self) -> io::Result<crate::uapi::v4l2_ctrl_h264_slice_params> {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_slice_params = unsafe { mem::zeroed() };
+        for (list, dst) in [
+            (&self.ref_pic_list0, &mut raw.ref_pic_list0),
+            (&self.ref_pic_list1, &mut raw.ref_pic_list1),
+        ] {
+            if list.len() > dst.len() {
+                return Err(too_many("reference list", list.len(), dst.len() as u32));
+            }
+            for (d, r) in dst.iter_mut().zip(list) {
+                d.fields = r.fields;
+                d.index = r.index;
+            }
+        }
+        raw.header_bit_size = self.header_bit_size;
+        raw.first_mb_in_slice = self.first_mb_in_slice;
+        raw.slice_type = self.slice_type;
+        raw.colour_plane_id = self.colour_plane_id;
+        raw.redundant_pic_cnt = self.redundant_pic_cnt;
+        raw.cabac_init_idc = self.cabac_init_idc;
+        raw.slice_qp_delta = self.slice_qp_delta;
+        raw.slice_qs_delta = self.slice_qs_delta;
+        raw.disable_deblocking_filter_idc = self.disable_deblocking_filter_idc;
+        raw.slice_alpha_c0_offset_div2 = self.slice_alpha_c0_offset_div2;
+        raw.slice_beta_offset_div2 = self.slice_beta_offset_div2;
+        raw.num_ref_idx_l0_active_minus1 = self.num_ref_idx_l0_active_minus1;
+        raw.num_ref_idx_l1_active_minus1 = self.num_ref_idx_l1_active_minus1;
+        raw.flags = self.flags;
+        Ok(raw)
+    }
+}
+
+/// Decoded picture buffer entry. Pictures are identified by the timestamp
+/// their OUTPUT buffer was queued with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DpbEntry {
+    /// Nanoseconds, see [`crate::buffer::Dequeued::timestamp`]
+    pub reference_ts: u64,
+    pub pic_num: u32,
+    pub frame_num: u16,
+    pub fields: u8,
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    /// `V4L2_H264_DPB_ENTRY_FLAG_*`
+    pub flags: u32,
+}
+
+impl DpbEntry {
+    /// A short-term reference frame decoded at `reference_ts`
+    pub fn frame(reference_ts: u64, frame_num: u16) -> Self {
+        DpbEntry {
+            reference_ts,
+            pic_num: frame_num as u32,
+            frame_num,
+            fields: crate::uapi::V4L2_H264_FRAME_REF as u8,
+            flags: crate::uapi::V4L2_H264_DPB_ENTRY_FLAG_VALID
+                | crate::uapi::V4L2_H264_DPB_ENTRY_FLAG_ACTIVE,
+            ..Default::default()
+        }
+    }
+}
+
+/// `V4L2_CID_STATELESS_H264_DECODE_PARAMS`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeParams {
+    /// At most `V4L2_H264_NUM_DPB_ENTRIES`
+    pub dpb: Vec<DpbEntry>,
+    pub nal_ref_idc: u16,
+    pub frame_num: u16,
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt_lsb: u16,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt0: i32,
+    pub delta_pic_order_cnt1: i32,
+    pub dec_ref_pic_marking_bit_size: u32,
+    pub pic_order_cnt_bit_size: u32,
+    pub slice_group_change_cycle: u32,
+    /// `V4L2_H264_DECODE_PARAM_FLAG_*`
+    pub flags: u32,
+}
+
+impl DecodeParams {
+    pub fn is_idr(&self) -> bool {
+        self.flags & crate::uapi::V4L2_H264_DECODE_PARAM_FLAG_IDR_PIC != 0
+    }
+
+    pub fn to_raw(&self) -> io::Result<crate::uapi::v4l2_ctrl_h264_decode_params> {
+        let mut raw: crate::uapi::v4l2_ctrl_h264_decode_params = unsafe { mem::zeroed() };
+        if self.dpb.len() > raw.dpb.len() {
+            return Err(too_many("DPB", self.dpb.len(), raw.dpb.len() as u32));
+        }
+        for (d, e) in raw.dpb.iter_mut().zip(&self.dpb) {
+            d.reference_ts = e.reference_ts;
+            d.pic_num = e.pic_num;
+            d.frame_num = e.frame_num;
+            d.fields = e.fields;
+            d.top_field_order_cnt = e.top_field_order_cnt;
+            d.bottom_field_order_cnt = e.bottom_field_order_cnt;
+            d.flags = e.flags;
+        }
+        raw.nal_ref_idc = self.nal_ref_idc;
+        raw.frame_num = self.frame_num;
+        raw.top_field_order_cnt = self.top_field_order_cnt;
+        raw.bottom_field_order_cnt = self.bottom_field_order_cnt;
+        raw.idr_pic_id = self.idr_pic_id;
+        raw.pic_order_cnt_lsb = self.pic_order_cnt_lsb;
+        raw.delta_pic_order_cnt_bottom = self.delta_pic_order_cnt_bottom;
+        raw.delta_pic_order_cnt0 = self.delta_pic_order_cnt0;
+        raw.delta_pic_order_cnt1 = self.delta_pic_order_cnt1;
+        raw.dec_ref_pic_marking_bit_size = self.dec_ref_pic_marking_bit_size;
+        raw.pic_order_cnt_bit_size = self.pic_order_cnt_bit_size;
+        raw.slice_group_change_cycle = self.slice_group_change_cycle;
+        raw.flags = self.flags;
+        Ok(raw)
+    }
+}
+
+/// One slice NAL unit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Slice<'d> {
+    /// Ignored in frame-based mode
+    pub params: SliceParams,
+    pub pred_weights: Option<PredWeights>,
+    /// The NAL unit, with a start code if the decoder was set up for one
+    pub data: &'d [u8],
+}
+
+/// Everything the driver needs to decode one frame
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame<'d> {
+    pub sps: Sps,
+    pub pps: Pps,
+    /// Flat when `None`
+    pub scaling_matrix: Option<ScalingMatrix>,
+    pub decode_params: DecodeParams,
+    pub slices: Vec<Slice<'d>>,
+    /// Nanoseconds, microsecond resolution. Later frames refer to this one
+    /// with it in [`DpbEntry::reference_ts`].
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// A request per slice, with slice parameters
+    SliceBased,
+    /// A request per frame, holding all its slices
+    #[default]
+    FrameBased,
+}
+
+impl DecodeMode {
+    pub fn as_raw(self) -> u32 {
+        match self {
+            DecodeMode::SliceBased => crate::uapi::V4L2_STATELESS_H264_DECODE_MODE_SLICE_BASED,
+            DecodeMode::FrameBased => crate::uapi::V4L2_STATELESS_H264_DECODE_MODE_FRAME_BASED,
+        }
+    }
+}
+
+/// Session parameters for [`Decoder::new`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    pub mode: DecodeMode,
+    /// Slice data starts with Annex B start codes
+    pub start_code: bool,
+    pub output_buffers: u32,
+    /// Enough for the DPB plus the pictures the application holds on to
+    pub capture_buffers: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            width: 1920,
+            height: 1088,
+            mode: DecodeMode::FrameBased,
+            start_code: true,
+            output_buffers: 4,
+            capture_buffers: 20,
+        }
+    }
+}
+
+/// S_FMT on the OUTPUT queue, single-planar first; returns the buffer type
+/// that took it
+fn set_output_format<D: Ioctl + ?Sized>(dev: &D, width: u32, height: u32) -> io::Result<u32> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT;
+    f.fmt.pix.pixelformat = V4L2_PIX_FMT_H264_SLICE;
+    f.fmt.pix.width = width;
+    f.fmt.pix.height = height;
+    match ioctl(dev, codes::VIDIOC_S_FMT, &mut f) {
+        Ok(()) if unsafe { f.fmt.pix.pixelformat } == V4L2_PIX_FMT_H264_SLICE => return Ok(f.type_),
+        Ok(()) => return Err(unsupported()),
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => (),
+        Err(e) => return Err(e),
+    }
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE;
+    f.fmt.pix_mp.pixelformat = V4L2_PIX_FMT_H264_SLICE;
+    f.fmt.pix_mp.width = width;
+    f.fmt.pix_mp.height = height;
+    f.fmt.pix_mp.num_planes = 1;
+    ioctl(dev, codes::VIDIOC_S_FMT, &mut f)?;
+    if unsafe { f.fmt.pix_mp.pixelformat } != V4L2_PIX_FMT_H264_SLICE {
+        return Err(unsupported());
+    }
+    Ok(f.type_)
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "not a stateless H.264 decoder")
+}
+
+/// Decoding session on a stateless decoder.
+///
+/// Frames are decoded one at a time: [`Decoder::decode`] returns once the
+/// driver is done. The returned picture stays with the application, and
+/// can serve as a reference, until handed back with [`Decoder::release`].
+pub struct Decoder<'a, D: Mmap + ?Sized, H: Ioctl + AsRawFd = RequestFd> {
+    dev: &'a D,
+    mode: DecodeMode,
+    output: Buffers<'a, D>,
+    capture: Buffers<'a, D>,
+    /// One per OUTPUT buffer
+    requests: Vec<Request<H>>,
+    next: usize,
+    /// Fourcc, width and height of decoded pictures
+    format: (u32, u32, u32),
+}
+
+impl<'a, D: Mmap + ?Sized, H: Ioctl + AsRawFd> Decoder<'a, D, H> {
+    /// Set up the video device `dev`, with requests from its media device
+    /// `media`, and start streaming
+    pub fn new<M>(dev: &'a D, media: &M, config: &Config) -> io::Result<Self>
+    where
+        M: RequestAlloc<Handle = H> + ?Sized,
+    {
+        control::set_value(
+            dev,
+            crate::uapi::V4L2_CID_STATELESS_H264_DECODE_MODE,
+            config.mode.as_raw() as i32,
+        )?;
+        let start_code = if config.start_code {
+            crate::uapi::V4L2_STATELESS_H264_START_CODE_ANNEX_B
+        } else {
+            crate::uapi::V4L2_STATELESS_H264_START_CODE_NONE
+        };
+        control::set_value(
+            dev,
+            crate::uapi::V4L2_CID_STATELESS_H264_START_CODE,
+            start_code as i32,
+        )?;
+
+        let output_type = set_output_format(dev, config.width, config.height)?;
+        let capture_type = if is_multiplanar(output_type) {
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
+        } else {
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE
+        };
+        // The driver picks the decoded format from the coded one
+        let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+        f.type_ = capture_type;
+        ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+        let format = unsafe {
+            if is_multiplanar(capture_type) {
+                let pix = f.fmt.pix_mp;
+                (pix.pixelformat, pix.width, pix.height)
+            } else {
+                (f.fmt.pix.pixelformat, f.fmt.pix.width, f.fmt.pix.height)
+            }
+        };
+
+        let output = Buffers::new(dev, output_type, config.output_buffers)?;
+        if !output.supports_requests() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "OUTPUT queue does not support requests",
+            ));
+        }
+        let capture = Buffers::new(dev, capture_type, config.capture_buffers)?;
+        for index in 0..capture.len() {
+            capture.queue(index, &[0], 0, 0, None)?;
+        }
+        let requests = (0..output.len())
+            .map(|_| Request::alloc(media))
+            .collect::<io::Result<_>>()?;
+        output.stream_on()?;
+        capture.stream_on()?;
+        Ok(Decoder {
+            dev,
+            mode: config.mode,
+            output,
+            capture,
+            requests,
+            next: 0,
+            format,
+        })
+    }
+
+    /// Fourcc, width and height of decoded pictures
+    pub fn format(&self) -> (u32, u32, u32) {
+        self.format
+    }
+
+    /// Decode `frame` into the next free CAPTURE buffer.
+    ///
+    /// The picture is returned even if the driver flagged an error, check
+    /// [`Dequeued::is_error`].
+    pub fn decode(&mut self, frame: &Frame) -> io::Result<Dequeued> {
+        if frame.slices.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no slices"));
+        }
+        let mut sps = frame.sps.to_raw()?;
+        let mut pps = frame.pps.to_raw();
+        let mut matrix = frame.scaling_matrix.unwrap_or_default().to_raw();
+        let mut params = frame.decode_params.to_raw()?;
+        let parts: Vec<&[Slice]> = match self.mode {
+            DecodeMode::FrameBased => vec![&frame.slices],
+            DecodeMode::SliceBased => frame.slices.chunks(1).collect(),
+        };
+        let hold = crate::uapi::V4L2_BUF_CAP_SUPPORTS_M2M_HOLD_CAPTURE_BUF;
+        if parts.len() > 1 && self.output.capabilities() & hold == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "driver cannot decode a frame from several requests",
+            ));
+        }
+
+        for (i, slices) in parts.iter().enumerate() {
+            let last = i + 1 == parts.len();
+            let index = self.next;
+            self.next = (self.next + 1) % self.output.len();
+
+            let len: usize = slices.iter().map(|s| s.data.len()).sum();
+            let plane = self.output.plane_mut(index, 0);
+            if len > plane.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} bytes of slice data, buffers hold {}", len, plane.len()),
+                ));
+            }
+            let mut offset = 0;
+            for s in slices.iter() {
+                plane[offset..offset + s.data.len()].copy_from_slice(s.data);
+                offset += s.data.len();
+            }
+
+            let mut slice_params;
+            let mut weights;
+            let mut controls = Controls::new();
+            controls.push_compound(crate::uapi::V4L2_CID_STATELESS_H264_SPS, &mut sps);
+            controls.push_compound(crate::uapi::V4L2_CID_STATELESS_H264_PPS, &mut pps);
+            controls.push_compound(
+                crate::uapi::V4L2_CID_STATELESS_H264_SCALING_MATRIX,
+                &mut matrix,
+            );
+            controls.push_compound(
+                crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS,
+                &mut params,
+            );
+            if self.mode == DecodeMode::SliceBased {
+                slice_params = slices[0].params.to_raw()?;
+                controls.push_compound(
+                    crate::uapi::V4L2_CID_STATELESS_H264_SLICE_PARAMS,
+                    &mut slice_params,
+                );
+                if let Some(w) = slices[0].pred_weights {
+                    weights = w.to_raw();
+                    controls.push_compound(
+                        crate::uapi::V4L2_CID_STATELESS_H264_PRED_WEIGHTS,
+                        &mut weights,
+                    );
+                }
+            }
+
+            let request = &self.requests[index];
+            match self.submit(index, request, &mut controls, len, frame.timestamp, last) {
+                Ok(Some(picture)) => return Ok(picture),
+                Ok(None) => (),
+                Err(e) => {
+                    self.cancel(request);
+                    return Err(e);
+                }
+            }
+        }
+        unreachable!("the last part returns the picture")
+    }
+
+    /// Queue OUTPUT buffer `index` in `request` and wait for the driver;
+    /// returns the picture after the last part of a frame
+    fn submit(
+        &self,
+        index: usize,
+        request: &Request<H>,
+        controls: &mut Controls,
+        len: usize,
+        timestamp: u64,
+        last: bool,
+    ) -> io::Result<Option<Dequeued>> {
+        request.set_controls(self.dev, controls)?;
+        let flags = if last {
+            0
+        } else {
+            crate::uapi::V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF
+        };
+        self.output
+            .queue(index, &[len as u32], timestamp, flags, Some(request.fd()))?;
+        request.queue()?;
+        let picture = if last {
+            Some(self.capture.dequeue()?)
+        } else {
+            None
+        };
+        if let Err(e) = self.output.dequeue().and_then(|_| request.reinit()) {
+            if let Some(picture) = &picture {
+                let _ = self.release(picture);
+            }
+            return Err(e);
+        }
+        Ok(picture)
+    }
+
+    /// Bring the OUTPUT queue and `request` back to idle after a failed
+    /// [`Decoder::submit`]. STREAMOFF returns the OUTPUT buffer, queued or
+    /// only bound to the request, and completes the request so that it can
+    /// be reinitialized.
+    fn cancel(&self, request: &Request<H>) {
+        let _ = self.output.stream_off();
+        let _ = self.output.stream_on();
+        let _ = request.reinit();
+    }
+
+    /// Pixel data of a picture returned by [`Decoder::decode`]
+    pub fn picture(&self, picture: &Dequeued, plane: usize) -> &[u8] {
+        let data = self.capture.plane(picture.index, plane);
+        let used = picture.bytesused.get(plane).copied().unwrap_or(0) as usize;
+        &data[..used.min(data.len())]
+    }
+
+    /// Give a picture back once it is neither displayed nor referenced
+    pub fn release(&self, picture: &Dequeued) -> io::Result<()> {
+        self.capture.queue(picture.index, &[0], 0, 0, None)
+    }
+}
+
+impl<D: Mmap + ?Sized, H: Ioctl + AsRawFd> Drop for Decoder<'_, D, H> {
+    fn drop(&mut self) {
+        let _ = self.output.stream_off();
+        let _ = self.capture.stream_off();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDecoder;
+
+    fn frame<'d>(data: &'d [u8], timestamp: u64, refs: &[u64]) -> Frame<'d> {
+        let sps = Sps {
+            profile_idc: 66,
+            level_idc: 30,
+            pic_width_in_mbs_minus1: 19,
+            pic_height_in_map_units_minus1: 14,
+            flags: crate::uapi::V4L2_H264_SPS_FLAG_FRAME_MBS_ONLY,
+            ..Default::default()
+        };
+        let decode_params = DecodeParams {
+            dpb: refs
+                .iter()
+                .enumerate()
+                .map(|(i, &ts)| DpbEntry::frame(ts, i as u16))
+                .collect(),
+            nal_ref_idc: 1,
+            frame_num: refs.len() as u16,
+            flags: if refs.is_empty() {
+                crate::uapi::V4L2_H264_DECODE_PARAM_FLAG_IDR_PIC
+            } else {
+                crate::uapi::V4L2_H264_DECODE_PARAM_FLAG_PFRAME
+            },
+            ..Default::default()
+        };
+        Frame {
+            sps,
+            decode_params,
+            slices: vec![Slice {
+                data,
+                ..Default::default()
+            }],
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frame_based_session() {
+        let fake = FakeDecoder::default();
+        let config = Config {
+            width: 320,
+            height: 240,
+            capture_buffers: 2,
+            ..Default::default()
+        };
+        let mut decoder = Decoder::new(&fake, &fake, &config).unwrap();
+        assert_eq!(
+            decoder.format(),
+            (crate::pixel_format::V4L2_PIX_FMT_NV12, 320, 240)
+        );
+        assert_eq!(frame(&[], 0, &[]).sps.width(), 320);
+
+        let idr = decoder.decode(&frame(&[0, 0, 1, 0x65], 1000, &[])).unwrap();
+        assert!(!idr.is_error());
+        assert_eq!(idr.timestamp, 1000);
+        assert_eq!(decoder.picture(&idr, 0).len(), 320 * 240 * 3 / 2);
+
+        let p = decoder
+            .decode(&frame(&[0, 0, 1, 0x41], 2000, &[1000]))
+            .unwrap();
+        assert!(!p.is_error());
+        assert_ne!(p.index, idr.index);
+        decoder.release(&idr).unwrap();
+
+        // Referencing a picture that was never decoded
+        let broken = decoder
+            .decode(&frame(&[0, 0, 1, 0x41], 3000, &[1500]))
+            .unwrap();
+        assert!(broken.is_error());
+        assert_eq!(broken.index, idr.index);
+
+        let decoded = fake.decoded.borrow();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(
+            decoded[0].1,
+            [
+                crate::uapi::V4L2_CID_STATELESS_H264_SPS,
+                crate::uapi::V4L2_CID_STATELESS_H264_PPS,
+                crate::uapi::V4L2_CID_STATELESS_H264_SCALING_MATRIX,
+                crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS,
+            ]
+        );
+    }
+
+    #[test]
+    fn recover_from_failed_decode() {
+        let fake = FakeDecoder::default();
+        let config = Config {
+            width: 320,
+            height: 240,
+            output_buffers: 1,
+            capture_buffers: 2,
+            ..Default::default()
+        };
+        let mut decoder = Decoder::new(&fake, &fake, &config).unwrap();
+        let first = decoder.decode(&frame(&[0, 0, 1, 0x65], 1000, &[])).unwrap();
+        decoder.decode(&frame(&[0, 0, 1, 0x65], 2000, &[])).unwrap();
+
+        // Both pictures are held, so there is nothing to decode into
+        let err = decoder
+            .decode(&frame(&[0, 0, 1, 0x65], 3000, &[]))
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EAGAIN));
+
+        // The only OUTPUT buffer and request are usable again
+        decoder.release(&first).unwrap();
+        let picture = decoder.decode(&frame(&[0, 0, 1, 0x65], 4000, &[])).unwrap();
+        assert_eq!(picture.timestamp, 4000);
+        assert_eq!(picture.index, first.index);
+    }
+
+    #[test]
+    fn request_lifecycle() {
+        let fake = FakeDecoder::default();
+        let config = Config {
+            width: 320,
+            height: 240,
+            mode: DecodeMode::SliceBased,
+            output_buffers: 2,
+            capture_buffers: 2,
+            ..Default::default()
+        };
+        let mut decoder = Decoder::new(&fake, &fake, &config).unwrap();
+
+        // Two slices, decoded into one held CAPTURE buffer
+        let mut f = frame(&[0, 0, 1, 0x65], 1000, &[]);
+        f.slices.push(Slice {
+            params: SliceParams {
+                first_mb_in_slice: 150,
+                slice_type: crate::uapi::V4L2_H264_SLICE_TYPE_I as u8,
+                ..Default::default()
+            },
+            data: &[0, 0, 1, 0x65, 0x88],
+            ..Default::default()
+        });
+        let picture = decoder.decode(&f).unwrap();
+        assert!(!picture.is_error());
+        let decoded = fake.decoded.borrow().clone();
+        assert_eq!(decoded.len(), 2);
+        assert!(decoded.iter().all(|(ts, ids)| *ts == 1000
+            && ids.contains(&crate::uapi::V4L2_CID_STATELESS_H264_SLICE_PARAMS)));
+        drop(decoder);
+
+        // What the kernel rejects
+        let errno = |r: io::Result<()>| r.unwrap_err().raw_os_error();
+        let output =
+            Buffers::new(&fake, crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT, 1).unwrap();
+        let request: Request<_> = Request::alloc(&fake).unwrap();
+        assert_eq!(errno(request.queue()), Some(libc::ENOENT));
+        assert_eq!(errno(output.queue(0, &[4], 0, 0, None)), Some(libc::EBADR));
+
+        let mut sps = frame(&[], 0, &[]).sps.to_raw().unwrap();
+        let mut pps = Pps::default().to_raw();
+        let mut params = DecodeParams::default().to_raw().unwrap();
+        let mut controls = Controls::new();
+        controls.push_compound(crate::uapi::V4L2_CID_STATELESS_H264_SPS, &mut sps);
+        controls.push_compound(crate::uapi::V4L2_CID_STATELESS_H264_PPS, &mut pps);
+        controls.push_compound(
+            crate::uapi::V4L2_CID_STATELESS_H264_DECODE_PARAMS,
+            &mut params,
+        );
+        request.set_controls(&fake, &mut controls).unwrap();
+        output.queue(0, &[4], 0, 0, Some(request.fd())).unwrap();
+        request.queue().unwrap();
+        assert_eq!(errno(request.queue()), Some(libc::EBUSY));
+        assert_eq!(
+            errno(request.set_controls(&fake, &mut controls)),
+            Some(libc::EBUSY)
+        );
+        // Nothing streams, so the request stays in flight
+        assert_eq!(errno(request.reinit()), Some(libc::EBUSY));
+    }
+}
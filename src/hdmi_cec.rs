@@ -0,0 +1,769 @@
+//! HDMI CEC adapters (`/dev/cecN`): CEC_ADAP_G_CAPS, CEC_ADAP_S_LOG_ADDRS,
+//! CEC_TRANSMIT, CEC_RECEIVE, CEC_DQEVENT and CEC_S_MODE
+//!
+//! [`Message`] builds and parses the opcodes needed to switch a display on
+//! and off and to follow remote control keys; anything else is passed
+//! through as [`Message::Other`].
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/cec/cec-api.html
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cec_codes;
+use crate::device::{c_string, ioctl, Ioctl, RawDevice};
+
+/// Destination of messages addressed to all devices
+pub const BROADCAST: u8 = crate::CEC_LOG_ADDR_BROADCAST as u8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+    /// Empty, or longer than `CEC_MAX_MSG_SIZE` bytes
+    Length(usize),
+    /// Logical address above 15
+    Address(u8),
+    /// Fewer operands than the opcode requires
+    Operands { opcode: u8, len: usize },
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageError::Length(len) => write!(f, "invalid CEC message length {}", len),
+            MessageError::Address(addr) => write!(f, "invalid logical address {}", addr),
+            MessageError::Operands { opcode, len } => {
+                write!(f, "{} operands are too few for opcode {:#04x}", len, opcode)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+impl From<MessageError> for io::Error {
+    fn from(e: MessageError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A CEC message without its header block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Header only, used to probe whether a logical address is in use
+    Poll,
+    /// Wake the display and switch it to the initiator
+    ImageViewOn,
+    Standby,
+    GivePhysicalAddr,
+    /// Broadcast answer to [`Message::GivePhysicalAddr`]
+    ReportPhysicalAddr {
+        phys_addr: u16,
+        /// `CEC_OP_PRIM_DEVTYPE_*`
+        device_type: u8,
+    },
+    /// A remote key, `CEC_OP_UI_CMD_*`; see [`ui_command_name`]
+    UserControlPressed(u8),
+    UserControlReleased,
+    FeatureAbort {
+        opcode: u8,
+        /// `CEC_OP_ABORT_*`
+        reason: u8,
+    },
+    Other {
+        opcode: u8,
+        operands: Vec<u8>,
+    },
+}
+
+impl Message {
+    pub fn opcode(&self) -> Option<u8> {
+        let opcode = match self {
+            Message::Poll => return None,
+            Message::ImageViewOn => crate::CEC_MSG_IMAGE_VIEW_ON,
+            Message::Standby => crate::CEC_MSG_STANDBY,
+            Message::GivePhysicalAddr => crate::CEC_MSG_GIVE_PHYSICAL_ADDR,
+            Message::ReportPhysicalAddr { .. } => crate::CEC_MSG_REPORT_PHYSICAL_ADDR,
+            Message::UserControlPressed(_) => crate::CEC_MSG_USER_CONTROL_PRESSED,
+            Message::UserControlReleased => crate::CEC_MSG_USER_CONTROL_RELEASED,
+            Message::FeatureAbort { .. } => crate::CEC_MSG_FEATURE_ABORT,
+            Message::Other { opcode, .. } => return Some(*opcode),
+        };
+        Some(opcode as u8)
+    }
+
+    /// Opcode the destination answers with, which CEC_TRANSMIT can wait for
+    pub fn reply(&self) -> Option<u8> {
+        match self {
+            Message::GivePhysicalAddr => Some(crate::CEC_MSG_REPORT_PHYSICAL_ADDR as u8),
+            _ => None,
+        }
+    }
+
+    fn operands(&self) -> Vec<u8> {
+        match self {
+            Message::ReportPhysicalAddr {
+                phys_addr,
+                device_type,
+            } => {
+                let [hi, lo] = phys_addr.to_be_bytes();
+                vec![hi, lo, *device_type]
+            }
+            Message::UserControlPressed(cmd) => vec![*cmd],
+            Message::FeatureAbort { opcode, reason } => vec![*opcode, *reason],
+            Message::Other { operands, .. } => operands.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Operands beyond those defined for an opcode are ignored, as the
+    /// specification asks of followers.
+    fn parse(opcode: u8, operands: &[u8]) -> Result<Self, MessageError> {
+        let need = |n: usize| {
+            if operands.len() < n {
+                Err(MessageError::Operands {
+                    opcode,
+                    len: operands.len(),
+                })
+            } else {
+                Ok(())
+            }
+        };
+        let message = match opcode as u32 {
+            crate::CEC_MSG_IMAGE_VIEW_ON => Message::ImageViewOn,
+            crate::CEC_MSG_STANDBY => Message::Standby,
+            crate::CEC_MSG_GIVE_PHYSICAL_ADDR => Message::GivePhysicalAddr,
+            crate::CEC_MSG_REPORT_PHYSICAL_ADDR => {
+                need(3)?;
+                Message::ReportPhysicalAddr {
+                    phys_addr: u16::from_be_bytes([operands[0], operands[1]]),
+                    device_type: operands[2],
+                }
+            }
+            crate::CEC_MSG_USER_CONTROL_PRESSED => {
+                need(1)?;
+                Message::UserControlPressed(operands[0])
+            }
+            crate::CEC_MSG_USER_CONTROL_RELEASED => Message::UserControlReleased,
+            crate::CEC_MSG_FEATURE_ABORT => {
+                need(2)?;
+                Message::FeatureAbort {
+                    opcode: operands[0],
+                    reason: operands[1],
+                }
+            }
+            _ => Message::Other {
+                opcode,
+                operands: operands.to_vec(),
+            },
+        };
+        Ok(message)
+    }
+}
+
+/// A message with its initiator and destination logical addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub initiator: u8,
+    pub destination: u8,
+    pub message: Message,
+}
+
+impl Frame {
+    pub fn new(initiator: u8, destination: u8, message: Message) -> Self {
+        Frame {
+            initiator,
+            destination,
+            message,
+        }
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.destination == BROADCAST
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MessageError> {
+        for addr in [self.initiator, self.destination] {
+            if addr > 15 {
+                return Err(MessageError::Address(addr));
+            }
+        }
+        let mut bytes = vec![self.initiator << 4 | self.destination];
+        bytes.extend(self.message.opcode());
+        bytes.extend(self.message.operands());
+        if bytes.len() > crate::CEC_MAX_MSG_SIZE as usize {
+            return Err(MessageError::Length(bytes.len()));
+        }
+        Ok(bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, MessageError> {
+        if bytes.is_empty() || bytes.len() > crate::CEC_MAX_MSG_SIZE as usize {
+            return Err(MessageError::Length(bytes.len()));
+        }
+        let message = match bytes[1..] {
+            [] => Message::Poll,
+            [opcode, ref operands @ ..] => Message::parse(opcode, operands)?,
+        };
+        Ok(Frame::new(bytes[0] >> 4, bytes[0] & 0xf, message))
+    }
+
+    fn to_raw(&self) -> Result<crate::cec_msg, MessageError> {
+        let bytes = self.to_bytes()?;
+        let mut msg: crate::cec_msg = unsafe { mem::zeroed() };
+        msg.len = bytes.len() as u32;
+        msg.msg[..bytes.len()].copy_from_slice(&bytes);
+        Ok(msg)
+    }
+
+    fn from_raw(msg: &crate::cec_msg) -> Result<Self, MessageError> {
+        let len = (msg.len as usize).min(msg.msg.len());
+        Frame::parse(&msg.msg[..len])
+    }
+}
+
+/// Name of a `CEC_OP_UI_CMD_*` remote key
+pub fn ui_command_name(cmd: u8) -> Option<&'static str> {
+    let name = match cmd as u32 {
+        crate::CEC_OP_UI_CMD_SELECT => "select",
+        crate::CEC_OP_UI_CMD_UP => "up",
+        crate::CEC_OP_UI_CMD_DOWN => "down",
+        crate::CEC_OP_UI_CMD_LEFT => "left",
+        crate::CEC_OP_UI_CMD_RIGHT => "right",
+        crate::CEC_OP_UI_CMD_DEVICE_ROOT_MENU => "root menu",
+        crate::CEC_OP_UI_CMD_DEVICE_SETUP_MENU => "setup menu",
+        crate::CEC_OP_UI_CMD_CONTENTS_MENU => "contents menu",
+        crate::CEC_OP_UI_CMD_BACK => "back",
+        crate::CEC_OP_UI_CMD_NUMBER_0_OR_NUMBER_10 => "0",
+        crate::CEC_OP_UI_CMD_NUMBER_1 => "1",
+        crate::CEC_OP_UI_CMD_NUMBER_2 => "2",
+        crate::CEC_OP_UI_CMD_NUMBER_3 => "3",
+        crate::CEC_OP_UI_CMD_NUMBER_4 => "4",
+        crate::CEC_OP_UI_CMD_NUMBER_5 => "5",
+        crate::CEC_OP_UI_CMD_NUMBER_6 => "6",
+        crate::CEC_OP_UI_CMD_NUMBER_7 => "7",
+        crate::CEC_OP_UI_CMD_NUMBER_8 => "8",
+        crate::CEC_OP_UI_CMD_NUMBER_9 => "9",
+        crate::CEC_OP_UI_CMD_ENTER => "enter",
+        crate::CEC_OP_UI_CMD_CHANNEL_UP => "channel up",
+        crate::CEC_OP_UI_CMD_CHANNEL_DOWN => "channel down",
+        crate::CEC_OP_UI_CMD_DISPLAY_INFORMATION => "display information",
+        crate::CEC_OP_UI_CMD_POWER => "power",
+        crate::CEC_OP_UI_CMD_VOLUME_UP => "volume up",
+        crate::CEC_OP_UI_CMD_VOLUME_DOWN => "volume down",
+        crate::CEC_OP_UI_CMD_MUTE => "mute",
+        crate::CEC_OP_UI_CMD_PLAY => "play",
+        crate::CEC_OP_UI_CMD_STOP => "stop",
+        crate::CEC_OP_UI_CMD_PAUSE => "pause",
+        crate::CEC_OP_UI_CMD_RECORD => "record",
+        crate::CEC_OP_UI_CMD_REWIND => "rewind",
+        crate::CEC_OP_UI_CMD_FAST_FORWARD => "fast forward",
+        crate::CEC_OP_UI_CMD_SKIP_FORWARD => "skip forward",
+        crate::CEC_OP_UI_CMD_SKIP_BACKWARD => "skip backward",
+        crate::CEC_OP_UI_CMD_F1_BLUE => "blue",
+        crate::CEC_OP_UI_CMD_F2_RED => "red",
+        crate::CEC_OP_UI_CMD_F3_GREEN => "green",
+        crate::CEC_OP_UI_CMD_F4_YELLOW => "yellow",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Typed `cec_caps`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub driver: String,
+    pub name: String,
+    pub available_log_addrs: u32,
+    /// `CEC_CAP_*`
+    pub capabilities: u32,
+    pub version: u32,
+}
+
+impl Capabilities {
+    pub fn can_transmit(&self) -> bool {
+        self.capabilities & crate::CEC_CAP_TRANSMIT != 0
+    }
+
+    /// The application sets the physical address, usually from the EDID
+    pub fn sets_phys_addr(&self) -> bool {
+        self.capabilities & crate::CEC_CAP_PHYS_ADDR != 0
+    }
+
+    pub fn sets_log_addrs(&self) -> bool {
+        self.capabilities & crate::CEC_CAP_LOG_ADDRS != 0
+    }
+}
+
+/// Typed `cec_log_addrs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicalAddresses {
+    /// Claimed addresses, filled in by the adapter
+    pub addresses: Vec<u8>,
+    /// `CEC_OP_CEC_VERSION_*`
+    pub cec_version: u8,
+    /// `CEC_VENDOR_ID_NONE` or an IEEE OUI
+    pub vendor_id: u32,
+    pub osd_name: String,
+    /// `CEC_LOG_ADDR_TYPE_*` to claim, one per address
+    pub types: Vec<u8>,
+    /// `CEC_LOG_ADDRS_FL_*`
+    pub flags: u32,
+}
+
+impl LogicalAddresses {
+    /// One CEC 2.0 address of `type_`
+    pub fn new(osd_name: &str, type_: u8) -> Self {
+        LogicalAddresses {
+            addresses: Vec::new(),
+            cec_version: crate::CEC_OP_CEC_VERSION_2_0 as u8,
+            vendor_id: crate::CEC_VENDOR_ID_NONE,
+            osd_name: osd_name.to_string(),
+            types: vec![type_],
+            flags: 0,
+        }
+    }
+
+    fn from_raw(l: &crate::cec_log_addrs) -> Self {
+        let n = (l.num_log_addrs as usize).min(l.log_addr.len());
+        LogicalAddresses {
+            addresses: l.log_addr[..n]
+                .iter()
+                .copied()
+                .filter(|&a| a as u32 != crate::CEC_LOG_ADDR_INVALID)
+                .collect(),
+            cec_version: l.cec_version,
+            vendor_id: l.vendor_id,
+            osd_name: c_string(&l.osd_name),
+            types: l.log_addr_type[..n].to_vec(),
+            flags: l.flags,
+        }
+    }
+
+    fn to_raw(&self) -> crate::cec_log_addrs {
+        let mut l: crate::cec_log_addrs = unsafe { mem::zeroed() };
+        l.cec_version = self.cec_version;
+        l.vendor_id = self.vendor_id;
+        l.flags = self.flags;
+        // Leave room for the terminating NUL
+        for (dst, src) in l.osd_name.iter_mut().zip(self.osd_name.bytes().take(14)) {
+            *dst = src as libc::c_char;
+        }
+        for (i, &type_) in self.types.iter().take(l.log_addr_type.len()).enumerate() {
+            let (primary, all) = device_types(type_);
+            l.log_addr_type[i] = type_;
+            l.primary_device_type[i] = primary as u8;
+            l.all_device_types[i] = all as u8;
+            l.num_log_addrs += 1;
+        }
+        l
+    }
+}
+
+/// `CEC_OP_PRIM_DEVTYPE_*` and `CEC_OP_ALL_DEVTYPE_*` announced for a
+/// `CEC_LOG_ADDR_TYPE_*`, as cec-ctl picks them
+fn device_types(type_: u8) -> (u32, u32) {
+    match type_ as u32 {
+        crate::CEC_LOG_ADDR_TYPE_TV => {
+            (crate::CEC_OP_PRIM_DEVTYPE_TV, crate::CEC_OP_ALL_DEVTYPE_TV)
+        }
+        crate::CEC_LOG_ADDR_TYPE_RECORD => (
+            crate::CEC_OP_PRIM_DEVTYPE_RECORD,
+            crate::CEC_OP_ALL_DEVTYPE_RECORD,
+        ),
+        crate::CEC_LOG_ADDR_TYPE_TUNER => (
+            crate::CEC_OP_PRIM_DEVTYPE_TUNER,
+            crate::CEC_OP_ALL_DEVTYPE_TUNER,
+        ),
+        crate::CEC_LOG_ADDR_TYPE_PLAYBACK => (
+            crate::CEC_OP_PRIM_DEVTYPE_PLAYBACK,
+            crate::CEC_OP_ALL_DEVTYPE_PLAYBACK,
+        ),
+        crate::CEC_LOG_ADDR_TYPE_AUDIOSYSTEM => (
+            crate::CEC_OP_PRIM_DEVTYPE_AUDIOSYSTEM,
+            crate::CEC_OP_ALL_DEVTYPE_AUDIOSYSTEM,
+        ),
+        crate::CEC_LOG_ADDR_TYPE_SPECIFIC => (
+            crate::CEC_OP_PRIM_DEVTYPE_PROCESSOR,
+            crate::CEC_OP_ALL_DEVTYPE_SWITCH,
+        ),
+        _ => (
+            crate::CEC_OP_PRIM_DEVTYPE_SWITCH,
+            crate::CEC_OP_ALL_DEVTYPE_SWITCH,
+        ),
+    }
+}
+
+/// Outcome of a blocking CEC_TRANSMIT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transmitted {
+    pub sequence: u32,
+    /// `CEC_TX_STATUS_*`
+    pub tx_status: u8,
+    /// `CEC_RX_STATUS_*` of the awaited reply
+    pub rx_status: u8,
+    pub reply: Option<Frame>,
+}
+
+impl Transmitted {
+    pub fn is_ok(&self) -> bool {
+        self.tx_status as u32 & crate::CEC_TX_STATUS_OK != 0
+    }
+
+    /// No device acknowledged the message, e.g. nothing is at the destination
+    pub fn is_nack(&self) -> bool {
+        self.tx_status as u32 & crate::CEC_TX_STATUS_NACK != 0
+    }
+}
+
+/// A message handed back by CEC_RECEIVE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    /// In nanoseconds, `CLOCK_MONOTONIC`
+    pub timestamp: u64,
+    pub frame: Frame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The physical or logical addresses changed; all invalid when the
+    /// HDMI cable is unplugged
+    StateChange { phys_addr: u16, log_addr_mask: u16 },
+    /// Messages were dropped because the receive queue was full
+    LostMessages(u32),
+    /// Any other `CEC_EVENT_*`
+    Other(u32),
+}
+
+/// Typed `cec_event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub timestamp: u64,
+    /// `CEC_EVENT_FL_*`
+    pub flags: u32,
+    pub kind: EventKind,
+}
+
+impl From<&crate::cec_event> for Event {
+    fn from(e: &crate::cec_event) -> Self {
+        let kind = match e.event {
+            crate::CEC_EVENT_STATE_CHANGE => {
+                let s = unsafe { e.__bindgen_anon_1.state_change };
+                EventKind::StateChange {
+                    phys_addr: s.phys_addr,
+                    log_addr_mask: s.log_addr_mask,
+                }
+            }
+            crate::CEC_EVENT_LOST_MSGS => {
+                EventKind::LostMessages(unsafe { e.__bindgen_anon_1.lost_msgs.lost_msgs })
+            }
+            other => EventKind::Other(other),
+        };
+        Event {
+            timestamp: e.ts,
+            flags: e.flags,
+            kind,
+        }
+    }
+}
+
+/// Format a physical address the usual way, e.g. `1.0.0.0`
+pub fn phys_addr_string(phys_addr: u16) -> String {
+    format!(
+        "{}.{}.{}.{}",
+        phys_addr >> 12,
+        phys_addr >> 8 & 0xf,
+        phys_addr >> 4 & 0xf,
+        phys_addr & 0xf
+    )
+}
+
+/// A CEC adapter, by default opened from `/dev/cecN`. libv4l2 only knows
+/// video nodes, so the adapter is opened with open(2).
+#[derive(Debug)]
+pub struct Adapter<D = RawDevice> {
+    dev: D,
+}
+
+impl Adapter<RawDevice> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        RawDevice::open(path).map(Adapter::new)
+    }
+}
+
+impl<D: Ioctl> Adapter<D> {
+    pub fn new(dev: D) -> Self {
+        Adapter { dev }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dev
+    }
+
+    /// CEC_ADAP_G_CAPS
+    pub fn capabilities(&self) -> io::Result<Capabilities> {
+        let mut c: crate::cec_caps = unsafe { mem::zeroed() };
+        ioctl(self, cec_codes::CEC_ADAP_G_CAPS, &mut c)?;
+        Ok(Capabilities {
+            driver: c_string(&c.driver),
+            name: c_string(&c.name),
+            available_log_addrs: c.available_log_addrs,
+            capabilities: c.capabilities,
+            version: c.version,
+        })
+    }
+
+    /// CEC_ADAP_G_PHYS_ADDR; `CEC_PHYS_ADDR_INVALID` while unplugged
+    pub fn phys_addr(&self) -> io::Result<u16> {
+        let mut addr = 0u16;
+        ioctl(self, cec_codes::CEC_ADAP_G_PHYS_ADDR, &mut addr)?;
+        Ok(addr)
+    }
+
+    /// CEC_ADAP_S_PHYS_ADDR, for adapters with `CEC_CAP_PHYS_ADDR`
+    pub fn set_phys_addr(&self, addr: u16) -> io::Result<()> {
+        let mut addr = addr;
+        ioctl(self, cec_codes::CEC_ADAP_S_PHYS_ADDR, &mut addr)
+    }
+
+    /// CEC_ADAP_G_LOG_ADDRS
+    pub fn logical_addresses(&self) -> io::Result<LogicalAddresses> {
+        let mut l: crate::cec_log_addrs = unsafe { mem::zeroed() };
+        ioctl(self, cec_codes::CEC_ADAP_G_LOG_ADDRS, &mut l)?;
+        Ok(LogicalAddresses::from_raw(&l))
+    }
+
+    /// CEC_ADAP_S_LOG_ADDRS. On a blocking handle this returns once the
+    /// addresses are claimed; an empty `types` releases them.
+    pub fn claim(&self, addrs: &LogicalAddresses) -> io::Result<LogicalAddresses> {
+        let mut l = addrs.to_raw();
+        ioctl(self, cec_codes::CEC_ADAP_S_LOG_ADDRS, &mut l)?;
+        Ok(LogicalAddresses::from_raw(&l))
+    }
+
+    /// CEC_G_MODE, `CEC_MODE_*` initiator and follower bits
+    pub fn mode(&self) -> io::Result<u32> {
+        let mut mode = 0u32;
+        ioctl(self, cec_codes::CEC_G_MODE, &mut mode)?;
+        Ok(mode)
+    }
+
+    /// CEC_S_MODE, e.g. `CEC_MODE_INITIATOR | CEC_MODE_FOLLOWER` to receive
+    /// remote keys
+    pub fn set_mode(&self, mode: u32) -> io::Result<()> {
+        let mut mode = mode;
+        ioctl(self, cec_codes::CEC_S_MODE, &mut mode)
+    }
+
+    /// CEC_TRANSMIT. Blocks until the message is sent and, for messages with
+    /// a [`Message::reply`], until the reply arrives or `timeout` passes.
+    pub fn transmit(&self, frame: &Frame, timeout: Duration) -> io::Result<Transmitted> {
+        let mut msg = frame.to_raw()?;
+        if let Some(reply) = frame.message.reply() {
+            msg.reply = reply;
+            msg.timeout = timeout.as_millis().min(u32::MAX as u128) as u32;
+        }
+        ioctl(self, cec_codes::CEC_TRANSMIT, &mut msg)?;
+        let reply = if msg.reply != 0 && msg.rx_status as u32 & crate::CEC_RX_STATUS_OK != 0 {
+            Some(Frame::from_raw(&msg)?)
+        } else {
+            None
+        };
+        Ok(Transmitted {
+            sequence: msg.sequence,
+            tx_status: msg.tx_status,
+            rx_status: msg.rx_status,
+            reply,
+        })
+    }
+
+    /// Send `message` from the first claimed logical address, with the
+    /// default one second reply timeout
+    pub fn send(&self, destination: u8, message: Message) -> io::Result<Transmitted> {
+        let initiator = self
+            .logical_addresses()?
+            .addresses
+            .first()
+            .copied()
+            .unwrap_or(crate::CEC_LOG_ADDR_UNREGISTERED as u8);
+        let frame = Frame::new(initiator, destination, message);
+        self.transmit(&frame, Duration::from_secs(1))
+    }
+
+    /// CEC_RECEIVE; fails with ETIMEDOUT if nothing arrives within `timeout`
+    pub fn receive(&self, timeout: Duration) -> io::Result<Received> {
+        let mut msg: crate::cec_msg = unsafe { mem::zeroed() };
+        msg.timeout = timeout.as_millis().min(u32::MAX as u128) as u32;
+        ioctl(self, cec_codes::CEC_RECEIVE, &mut msg)?;
+        Ok(Received {
+            timestamp: msg.rx_ts,
+            frame: Frame::from_raw(&msg)?,
+        })
+    }
+
+    /// CEC_DQEVENT; EAGAIN on a non-blocking handle without pending events
+    pub fn dequeue_event(&self) -> io::Result<Event> {
+        let mut e: crate::cec_event = unsafe { mem::zeroed() };
+        ioctl(self, cec_codes::CEC_DQEVENT, &mut e)?;
+        Ok(Event::from(&e))
+    }
+}
+
+impl<D: Ioctl> Ioctl for Adapter<D> {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        self.dev.ioctl(request, arg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeCec;
+
+    #[test]
+    fn message() {
+        let tv = crate::CEC_LOG_ADDR_TV as u8;
+        let playback = crate::CEC_LOG_ADDR_PLAYBACK_1 as u8;
+
+        let on = Frame::new(playback, tv, Message::ImageViewOn);
+        assert_eq!(on.to_bytes().unwrap(), [0x40, 0x04]);
+        let report = Frame::new(
+            playback,
+            BROADCAST,
+            Message::ReportPhysicalAddr {
+                phys_addr: 0x1000,
+                device_type: crate::CEC_OP_PRIM_DEVTYPE_PLAYBACK as u8,
+            },
+        );
+        assert_eq!(report.to_bytes().unwrap(), [0x4f, 0x84, 0x10, 0x00, 0x04]);
+        assert!(report.is_broadcast());
+
+        for frame in [
+            on,
+            report,
+            Frame::new(playback, tv, Message::Poll),
+            Frame::new(tv, BROADCAST, Message::Standby),
+            Frame::new(tv, playback, Message::GivePhysicalAddr),
+            Frame::new(tv, playback, Message::UserControlPressed(0x44)),
+            Frame::new(tv, playback, Message::UserControlReleased),
+            Frame::new(
+                tv,
+                playback,
+                Message::FeatureAbort {
+                    opcode: 0x8f,
+                    reason: 0,
+                },
+            ),
+            Frame::new(
+                tv,
+                playback,
+                Message::Other {
+                    opcode: 0x9e,
+                    operands: vec![6],
+                },
+            ),
+        ] {
+            assert_eq!(Frame::parse(&frame.to_bytes().unwrap()).unwrap(), frame);
+        }
+
+        // Extra operands are ignored, missing ones are not
+        assert_eq!(
+            Frame::parse(&[0x04, 0x44, 0x60, 0x01]).unwrap().message,
+            Message::UserControlPressed(0x60)
+        );
+        assert_eq!(
+            Frame::parse(&[0x0f, 0x84, 0x10]),
+            Err(MessageError::Operands {
+                opcode: 0x84,
+                len: 1
+            })
+        );
+        assert_eq!(Frame::parse(&[]), Err(MessageError::Length(0)));
+        assert_eq!(Frame::parse(&[0; 17]), Err(MessageError::Length(17)));
+        let long = Message::Other {
+            opcode: 0x47,
+            operands: vec![b'x'; 15],
+        };
+        assert_eq!(
+            Frame::new(playback, tv, long).to_bytes(),
+            Err(MessageError::Length(17))
+        );
+        assert_eq!(
+            Frame::new(16, tv, Message::Standby).to_bytes(),
+            Err(MessageError::Address(16))
+        );
+
+        assert_eq!(ui_command_name(0x44), Some("play"));
+        assert_eq!(ui_command_name(0xfe), None);
+        assert_eq!(phys_addr_string(0x1200), "1.2.0.0");
+    }
+
+    #[test]
+    fn adapter() {
+        let fake = FakeCec::new(0x1000);
+        let adapter = Adapter::new(&fake);
+        let tv = crate::CEC_LOG_ADDR_TV as u8;
+
+        let caps = adapter.capabilities().unwrap();
+        assert_eq!(caps.driver, "fake-cec");
+        assert!(caps.can_transmit() && caps.sets_log_addrs());
+        assert_eq!(adapter.phys_addr().unwrap(), 0x1000);
+
+        // Nothing claimed yet
+        let err = adapter.send(tv, Message::ImageViewOn).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        let claimed = adapter
+            .claim(&LogicalAddresses::new(
+                "capture",
+                crate::CEC_LOG_ADDR_TYPE_PLAYBACK as u8,
+            ))
+            .unwrap();
+        assert_eq!(claimed.addresses, [crate::CEC_LOG_ADDR_PLAYBACK_1 as u8]);
+        assert_eq!(adapter.logical_addresses().unwrap().osd_name, "capture");
+        let event = adapter.dequeue_event().unwrap();
+        assert_eq!(
+            event.kind,
+            EventKind::StateChange {
+                phys_addr: 0x1000,
+                log_addr_mask: 1 << crate::CEC_LOG_ADDR_PLAYBACK_1,
+            }
+        );
+        let err = adapter.dequeue_event().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EAGAIN));
+
+        assert!(adapter.send(tv, Message::ImageViewOn).unwrap().is_ok());
+        assert!(fake.tv_on.get());
+        let sent = adapter.send(tv, Message::GivePhysicalAddr).unwrap();
+        assert_eq!(
+            sent.reply.unwrap().message,
+            Message::ReportPhysicalAddr {
+                phys_addr: 0,
+                device_type: crate::CEC_OP_PRIM_DEVTYPE_TV as u8,
+            }
+        );
+        assert!(adapter.send(BROADCAST, Message::Standby).unwrap().is_ok());
+        assert!(!fake.tv_on.get());
+        // Only the TV is on the bus
+        let sent = adapter
+            .send(crate::CEC_LOG_ADDR_AUDIOSYSTEM as u8, Message::Standby)
+            .unwrap();
+        assert!(sent.is_nack() && !sent.is_ok());
+        assert_eq!(fake.transmitted.borrow().len(), 4);
+
+        adapter
+            .set_mode(crate::CEC_MODE_INITIATOR | crate::CEC_MODE_FOLLOWER)
+            .unwrap();
+        assert_eq!(
+            adapter.mode().unwrap(),
+            crate::CEC_MODE_INITIATOR | crate::CEC_MODE_FOLLOWER
+        );
+        fake.inbox.borrow_mut().push_back(vec![0x04, 0x44, 0x41]);
+        let received = adapter.receive(Duration::from_millis(100)).unwrap();
+        assert_eq!(received.frame.initiator, tv);
+        assert_eq!(received.frame.message, Message::UserControlPressed(0x41));
+        let err = adapter.receive(Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ETIMEDOUT));
+    }
+}
@@ -0,0 +1,321 @@
+//! Video inputs and outputs (VIDIOC_ENUMINPUT / G_INPUT / S_INPUT and the
+//! output counterparts)
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-enuminput.html
+
+use std::fmt;
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+use crate::standard::VideoStandard;
+
+/// `V4L2_INPUT_TYPE_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputType {
+    Tuner,
+    Camera,
+    Touch,
+    Other(u32),
+}
+
+impl InputType {
+    pub fn from_raw(type_: u32) -> Self {
+        match type_ {
+            crate::V4L2_INPUT_TYPE_TUNER => InputType::Tuner,
+            crate::V4L2_INPUT_TYPE_CAMERA => InputType::Camera,
+            crate::V4L2_INPUT_TYPE_TOUCH => InputType::Touch,
+            other => InputType::Other(other),
+        }
+    }
+}
+
+/// `V4L2_OUTPUT_TYPE_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputType {
+    Modulator,
+    Analog,
+    AnalogVgaOverlay,
+    Other(u32),
+}
+
+impl OutputType {
+    pub fn from_raw(type_: u32) -> Self {
+        match type_ {
+            crate::V4L2_OUTPUT_TYPE_MODULATOR => OutputType::Modulator,
+            crate::V4L2_OUTPUT_TYPE_ANALOG => OutputType::Analog,
+            crate::V4L2_OUTPUT_TYPE_ANALOGVGAOVERLAY => OutputType::AnalogVgaOverlay,
+            other => OutputType::Other(other),
+        }
+    }
+}
+
+/// `V4L2_IN_ST_*` flags of an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct InputStatus(pub u32);
+
+const STATUS_NAMES: [(u32, &str); 15] = [
+    (crate::V4L2_IN_ST_NO_POWER, "no power"),
+    (crate::V4L2_IN_ST_NO_SIGNAL, "no signal"),
+    (crate::V4L2_IN_ST_NO_COLOR, "no color"),
+    (crate::V4L2_IN_ST_HFLIP, "hflip"),
+    (crate::V4L2_IN_ST_VFLIP, "vflip"),
+    (crate::V4L2_IN_ST_NO_H_LOCK, "no hsync lock"),
+    (crate::V4L2_IN_ST_COLOR_KILL, "color kill"),
+    (crate::V4L2_IN_ST_NO_V_LOCK, "no vsync lock"),
+    (crate::V4L2_IN_ST_NO_STD_LOCK, "no standard format lock"),
+    (crate::V4L2_IN_ST_NO_SYNC, "no sync lock"),
+    (crate::V4L2_IN_ST_NO_EQU, "no equalizer lock"),
+    (crate::V4L2_IN_ST_NO_CARRIER, "no carrier"),
+    (crate::V4L2_IN_ST_MACROVISION, "macrovision"),
+    (crate::V4L2_IN_ST_NO_ACCESS, "no conditional access"),
+    (crate::V4L2_IN_ST_VTR, "VTR time constant"),
+];
+
+/// Flags that mean the picture is missing or unusable
+const FAULTS: u32 = crate::V4L2_IN_ST_NO_POWER
+    | crate::V4L2_IN_ST_NO_SIGNAL
+    | crate::V4L2_IN_ST_NO_H_LOCK
+    | crate::V4L2_IN_ST_NO_V_LOCK
+    | crate::V4L2_IN_ST_NO_STD_LOCK
+    | crate::V4L2_IN_ST_NO_SYNC
+    | crate::V4L2_IN_ST_NO_EQU
+    | crate::V4L2_IN_ST_NO_CARRIER
+    | crate::V4L2_IN_ST_NO_ACCESS;
+
+impl InputStatus {
+    pub fn no_power(self) -> bool {
+        self.0 & crate::V4L2_IN_ST_NO_POWER != 0
+    }
+
+    pub fn no_signal(self) -> bool {
+        self.0 & crate::V4L2_IN_ST_NO_SIGNAL != 0
+    }
+
+    pub fn no_color(self) -> bool {
+        self.0 & crate::V4L2_IN_ST_NO_COLOR != 0
+    }
+
+    pub fn no_h_lock(self) -> bool {
+        self.0 & crate::V4L2_IN_ST_NO_H_LOCK != 0
+    }
+
+    pub fn no_v_lock(self) -> bool {
+        self.0 & crate::V4L2_IN_ST_NO_V_LOCK != 0
+    }
+
+    /// No power, signal, sync or access problem is reported
+    pub fn is_locked(self) -> bool {
+        self.0 & FAULTS == 0
+    }
+
+    /// Names of the set flags, unknown bits are skipped
+    pub fn names(self) -> Vec<&'static str> {
+        STATUS_NAMES
+            .iter()
+            .filter(|(flag, _)| self.0 & flag != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+/// Prints "ok" or a comma separated list of flags, as `v4l2-ctl` does
+impl fmt::Display for InputStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            f.write_str("ok")
+        } else {
+            f.write_str(&self.names().join(", "))
+        }
+    }
+}
+
+/// One entry of VIDIOC_ENUMINPUT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    pub index: u32,
+    pub name: String,
+    pub type_: InputType,
+    /// Bitmask of the audio inputs that can be combined with this input
+    pub audioset: u32,
+    /// Tuner index, meaningful for `InputType::Tuner`
+    pub tuner: u32,
+    pub std: VideoStandard,
+    pub status: InputStatus,
+    /// `V4L2_IN_CAP_*`
+    pub capabilities: u32,
+}
+
+impl Input {
+    pub fn supports_std(&self) -> bool {
+        self.capabilities & crate::V4L2_IN_CAP_STD != 0
+    }
+
+    pub fn supports_dv_timings(&self) -> bool {
+        self.capabilities & crate::V4L2_IN_CAP_DV_TIMINGS != 0
+    }
+
+    pub fn supports_native_size(&self) -> bool {
+        self.capabilities & crate::V4L2_IN_CAP_NATIVE_SIZE != 0
+    }
+}
+
+impl From<&crate::v4l2_input> for Input {
+    fn from(i: &crate::v4l2_input) -> Self {
+        Input {
+            index: i.index,
+            name: c_string(&i.name),
+            type_: InputType::from_raw(i.type_),
+            audioset: i.audioset,
+            tuner: i.tuner,
+            std: VideoStandard(i.std),
+            status: InputStatus(i.status),
+            capabilities: i.capabilities,
+        }
+    }
+}
+
+/// One entry of VIDIOC_ENUMOUTPUT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub index: u32,
+    pub name: String,
+    pub type_: OutputType,
+    /// Bitmask of the audio outputs that can be combined with this output
+    pub audioset: u32,
+    /// Modulator index, meaningful for `OutputType::Modulator`
+    pub modulator: u32,
+    pub std: VideoStandard,
+    /// `V4L2_OUT_CAP_*`
+    pub capabilities: u32,
+}
+
+impl Output {
+    pub fn supports_std(&self) -> bool {
+        self.capabilities & crate::V4L2_OUT_CAP_STD != 0
+    }
+
+    pub fn supports_dv_timings(&self) -> bool {
+        self.capabilities & crate::V4L2_OUT_CAP_DV_TIMINGS != 0
+    }
+
+    pub fn supports_native_size(&self) -> bool {
+        self.capabilities & crate::V4L2_OUT_CAP_NATIVE_SIZE != 0
+    }
+}
+
+impl From<&crate::v4l2_output> for Output {
+    fn from(o: &crate::v4l2_output) -> Self {
+        Output {
+            index: o.index,
+            name: c_string(&o.name),
+            type_: OutputType::from_raw(o.type_),
+            audioset: o.audioset,
+            modulator: o.modulator,
+            std: VideoStandard(o.std),
+            capabilities: o.capabilities,
+        }
+    }
+}
+
+/// VIDIOC_ENUMINPUT for a single index; the status is sampled at call time
+pub fn input<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<Input> {
+    let mut i: crate::v4l2_input = unsafe { mem::zeroed() };
+    i.index = index;
+    ioctl(dev, codes::VIDIOC_ENUMINPUT, &mut i)?;
+    Ok(Input::from(&i))
+}
+
+pub fn enum_inputs<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for index in 0.. {
+        match input(dev, index) {
+            Ok(i) => inputs.push(i),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(inputs)
+}
+
+/// VIDIOC_G_INPUT
+pub fn current_input<D: Ioctl + ?Sized>(dev: &D) -> io::Result<u32> {
+    let mut index: libc::c_int = 0;
+    ioctl(dev, codes::VIDIOC_G_INPUT, &mut index)?;
+    Ok(index as u32)
+}
+
+/// VIDIOC_S_INPUT
+pub fn set_input<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<()> {
+    let mut index = index as libc::c_int;
+    ioctl(dev, codes::VIDIOC_S_INPUT, &mut index)
+}
+
+/// VIDIOC_ENUMOUTPUT for a single index
+pub fn output<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<Output> {
+    let mut o: crate::v4l2_output = unsafe { mem::zeroed() };
+    o.index = index;
+    ioctl(dev, codes::VIDIOC_ENUMOUTPUT, &mut o)?;
+    Ok(Output::from(&o))
+}
+
+pub fn enum_outputs<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<Output>> {
+    let mut outputs = Vec::new();
+    for index in 0.. {
+        match output(dev, index) {
+            Ok(o) => outputs.push(o),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(outputs)
+}
+
+/// VIDIOC_G_OUTPUT
+pub fn current_output<D: Ioctl + ?Sized>(dev: &D) -> io::Result<u32> {
+    let mut index: libc::c_int = 0;
+    ioctl(dev, codes::VIDIOC_G_OUTPUT, &mut index)?;
+    Ok(index as u32)
+}
+
+/// VIDIOC_S_OUTPUT
+pub fn set_output<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<()> {
+    let mut index = index as libc::c_int;
+    ioctl(dev, codes::VIDIOC_S_OUTPUT, &mut index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    #[test]
+    fn status_flags() {
+        assert_eq!(InputStatus(0).to_string(), "ok");
+        let st = InputStatus(crate::V4L2_IN_ST_NO_SIGNAL | crate::V4L2_IN_ST_NO_H_LOCK);
+        assert!(st.no_signal() && st.no_h_lock() && !st.no_power());
+        assert!(!st.is_locked());
+        assert_eq!(st.to_string(), "no signal, no hsync lock");
+        assert!(InputStatus(crate::V4L2_IN_ST_NO_COLOR).is_locked());
+    }
+
+    #[test]
+    fn select_input() {
+        let dev = FakeDevice::default();
+        dev.inputs.borrow_mut().extend([
+            ("Composite", InputStatus(0)),
+            ("S-Video", InputStatus(crate::V4L2_IN_ST_NO_SIGNAL)),
+        ]);
+        let inputs = enum_inputs(&dev).unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[1].name, "S-Video");
+        assert_eq!(inputs[1].type_, InputType::Camera);
+        assert!(inputs[1].status.no_signal());
+        set_input(&dev, 1).unwrap();
+        assert_eq!(current_input(&dev).unwrap(), 1);
+        assert_eq!(
+            set_input(&dev, 2).unwrap_err().raw_os_error(),
+            Some(libc::EINVAL)
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! JPEG compression parameters
+//!
+//! Quality and marker selection go through the JPEG control class when the
+//! driver has it, and through the older VIDIOC_G/S_JPEGCOMP otherwise.
+//! Comment and APPn payloads only exist in `v4l2_jpegcompression`.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/ext-ctrls-jpeg.html
+
+use std::io;
+use std::mem;
+use std::ops;
+
+use crate::codes;
+use crate::control;
+use crate::device::{ioctl, Ioctl};
+
+/// Size of the APP and COM payload arrays in `v4l2_jpegcompression`
+pub const MAX_DATA_LEN: usize = 60;
+
+/// Markers the hardware inserts into the stream, `V4L2_JPEG_MARKER_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct JpegMarkers(pub u32);
+
+impl JpegMarkers {
+    /// Huffman tables
+    pub const DHT: Self = Self(crate::V4L2_JPEG_MARKER_DHT);
+    /// Quantization tables
+    pub const DQT: Self = Self(crate::V4L2_JPEG_MARKER_DQT);
+    /// Restart interval
+    pub const DRI: Self = Self(crate::V4L2_JPEG_MARKER_DRI);
+    pub const COM: Self = Self(crate::V4L2_JPEG_MARKER_COM);
+    pub const APP: Self = Self(crate::V4L2_JPEG_MARKER_APP);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Value for `V4L2_CID_JPEG_ACTIVE_MARKER`; DRI has no equivalent there
+    pub fn to_active_marker(self) -> u32 {
+        let mut mask = 0;
+        if self.contains(Self::APP) {
+            mask |= crate::V4L2_JPEG_ACTIVE_MARKER_APP0;
+        }
+        if self.contains(Self::COM) {
+            mask |= crate::V4L2_JPEG_ACTIVE_MARKER_COM;
+        }
+        if self.contains(Self::DQT) {
+            mask |= crate::V4L2_JPEG_ACTIVE_MARKER_DQT;
+        }
+        if self.contains(Self::DHT) {
+            mask |= crate::V4L2_JPEG_ACTIVE_MARKER_DHT;
+        }
+        mask
+    }
+
+    pub fn from_active_marker(mask: u32) -> Self {
+        let mut markers = JpegMarkers::default();
+        let app = crate::V4L2_JPEG_ACTIVE_MARKER_APP0 | crate::V4L2_JPEG_ACTIVE_MARKER_APP1;
+        if mask & app != 0 {
+            markers |= Self::APP;
+        }
+        if mask & crate::V4L2_JPEG_ACTIVE_MARKER_COM != 0 {
+            markers |= Self::COM;
+        }
+        if mask & crate::V4L2_JPEG_ACTIVE_MARKER_DQT != 0 {
+            markers |= Self::DQT;
+        }
+        if mask & crate::V4L2_JPEG_ACTIVE_MARKER_DHT != 0 {
+            markers |= Self::DHT;
+        }
+        markers
+    }
+}
+
+impl ops::BitOr for JpegMarkers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for JpegMarkers {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Typed `v4l2_jpegcompression`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JpegCompression {
+    pub quality: i32,
+    /// Which APPn segment `app_data` goes into, 0 to 15
+    pub app_n: i32,
+    pub app_data: Vec<u8>,
+    pub com_data: Vec<u8>,
+    pub markers: JpegMarkers,
+}
+
+impl From<&crate::v4l2_jpegcompression> for JpegCompression {
+    fn from(j: &crate::v4l2_jpegcompression) -> Self {
+        let data = |raw: &[libc::c_char], len: i32| -> Vec<u8> {
+            raw.iter()
+                .take((len.max(0) as usize).min(MAX_DATA_LEN))
+                .map(|&c| c as u8)
+                .collect()
+        };
+        JpegCompression {
+            quality: j.quality,
+            app_n: j.APPn,
+            app_data: data(&j.APP_data, j.APP_len),
+            com_data: data(&j.COM_data, j.COM_len),
+            markers: JpegMarkers(j.jpeg_markers),
+        }
+    }
+}
+
+impl JpegCompression {
+    pub(crate) fn to_raw(&self) -> io::Result<crate::v4l2_jpegcompression> {
+        if self.app_data.len() > MAX_DATA_LEN || self.com_data.len() > MAX_DATA_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "JPEG APP/COM data is limited to 60 bytes",
+            ));
+        }
+        let mut j: crate::v4l2_jpegcompression = unsafe { mem::zeroed() };
+        j.quality = self.quality;
+        j.APPn = self.app_n;
+        j.APP_len = self.app_data.len() as i32;
+        for (dst, &src) in j.APP_data.iter_mut().zip(&self.app_data) {
+            *dst = src as libc::c_char;
+        }
+        j.COM_len = self.com_data.len() as i32;
+        for (dst, &src) in j.COM_data.iter_mut().zip(&self.com_data) {
+            *dst = src as libc::c_char;
+        }
+        j.jpeg_markers = self.markers.0;
+        Ok(j)
+    }
+}
+
+/// VIDIOC_G_JPEGCOMP
+pub fn get_jpegcomp<D: Ioctl + ?Sized>(dev: &D) -> io::Result<JpegCompression> {
+    let mut j: crate::v4l2_jpegcompression = unsafe { mem::zeroed() };
+    ioctl(dev, codes::VIDIOC_G_JPEGCOMP, &mut j)?;
+    Ok(JpegCompression::from(&j))
+}
+
+/// VIDIOC_S_JPEGCOMP
+pub fn set_jpegcomp<D: Ioctl + ?Sized>(dev: &D, params: &JpegCompression) -> io::Result<()> {
+    let mut j = params.to_raw()?;
+    ioctl(dev, codes::VIDIOC_S_JPEGCOMP, &mut j)
+}
+
+/// The control exists and can be used
+fn has_control<D: Ioctl + ?Sized>(dev: &D, id: u32) -> bool {
+    control::query_ext_ctrl(dev, id).is_ok_and(|info| !info.is_disabled())
+}
+
+/// Compression quality, 1 (smallest) to 100 (best) for the control
+pub fn quality<D: Ioctl + ?Sized>(dev: &D) -> io::Result<i32> {
+    if has_control(dev, crate::V4L2_CID_JPEG_COMPRESSION_QUALITY) {
+        control::get_value(dev, crate::V4L2_CID_JPEG_COMPRESSION_QUALITY)
+    } else {
+        Ok(get_jpegcomp(dev)?.quality)
+    }
+}
+
+/// Set the compression quality and return the value the driver kept
+pub fn set_quality<D: Ioctl + ?Sized>(dev: &D, quality: i32) -> io::Result<i32> {
+    if has_control(dev, crate::V4L2_CID_JPEG_COMPRESSION_QUALITY) {
+        return control::set_value(dev, crate::V4L2_CID_JPEG_COMPRESSION_QUALITY, quality);
+    }
+    let mut params = get_jpegcomp(dev)?;
+    params.quality = quality;
+    set_jpegcomp(dev, &params)?;
+    Ok(get_jpegcomp(dev)?.quality)
+}
+
+pub fn markers<D: Ioctl + ?Sized>(dev: &D) -> io::Result<JpegMarkers> {
+    if has_control(dev, crate::V4L2_CID_JPEG_ACTIVE_MARKER) {
+        let mask = control::get_value(dev, crate::V4L2_CID_JPEG_ACTIVE_MARKER)?;
+        Ok(JpegMarkers::from_active_marker(mask as u32))
+    } else {
+        Ok(get_jpegcomp(dev)?.markers)
+    }
+}
+
+/// Choose the markers to insert. Through the control interface DRI cannot be
+/// selected and APP means APP0.
+pub fn set_markers<D: Ioctl + ?Sized>(dev: &D, markers: JpegMarkers) -> io::Result<()> {
+    if has_control(dev, crate::V4L2_CID_JPEG_ACTIVE_MARKER) {
+        let mask = markers.to_active_marker() as i32;
+        control::set_value(dev, crate::V4L2_CID_JPEG_ACTIVE_MARKER, mask)?;
+        return Ok(());
+    }
+    let mut params = get_jpegcomp(dev)?;
+    params.markers = markers;
+    set_jpegcomp(dev, &params)
+}
+
+/// Set the COM segment payload and enable the COM marker
+pub fn set_comment<D: Ioctl + ?Sized>(dev: &D, comment: &[u8]) -> io::Result<()> {
+    let mut params = get_jpegcomp(dev)?;
+    params.com_data = comment.to_vec();
+    params.markers |= JpegMarkers::COM;
+    set_jpegcomp(dev, &params)
+}
+
+/// Set the payload of segment APP`n` and enable the APP marker
+pub fn set_app_data<D: Ioctl + ?Sized>(dev: &D, n: u8, data: &[u8]) -> io::Result<()> {
+    if n > 15 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "APPn segment number must be 0 to 15",
+        ));
+    }
+    let mut params = get_jpegcomp(dev)?;
+    params.app_n = n as i32;
+    params.app_data = data.to_vec();
+    params.markers |= JpegMarkers::APP;
+    set_jpegcomp(dev, &params)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::{FakeControl, FakeDevice};
+
+    #[test]
+    fn marker_mapping() {
+        let m = JpegMarkers::DHT | JpegMarkers::DQT | JpegMarkers::APP | JpegMarkers::DRI;
+        let mask = m.to_active_marker();
+        assert_eq!(
+            mask,
+            crate::V4L2_JPEG_ACTIVE_MARKER_APP0
+                | crate::V4L2_JPEG_ACTIVE_MARKER_DQT
+                | crate::V4L2_JPEG_ACTIVE_MARKER_DHT
+        );
+        assert_eq!(
+            JpegMarkers::from_active_marker(mask),
+            JpegMarkers::DHT | JpegMarkers::DQT | JpegMarkers::APP
+        );
+    }
+
+    #[test]
+    fn quality_prefers_control() {
+        let dev = FakeDevice::with_controls(vec![FakeControl::integer(
+            crate::V4L2_CID_JPEG_COMPRESSION_QUALITY,
+            "Compression Quality",
+            1,
+            100,
+            80,
+        )]);
+        *dev.jpegcomp.borrow_mut() = Some(JpegCompression {
+            quality: 50,
+            ..Default::default()
+        });
+        assert_eq!(quality(&dev).unwrap(), 80);
+        assert_eq!(set_quality(&dev, 95).unwrap(), 95);
+        assert_eq!(get_jpegcomp(&dev).unwrap().quality, 50);
+    }
+
+    #[test]
+    fn jpegcomp_fallback() {
+        let dev = FakeDevice::default();
+        *dev.jpegcomp.borrow_mut() = Some(JpegCompression {
+            quality: 75,
+            markers: JpegMarkers::DHT,
+            ..Default::default()
+        });
+        assert_eq!(quality(&dev).unwrap(), 75);
+        assert_eq!(set_quality(&dev, 90).unwrap(), 90);
+        set_comment(&dev, b"camera 3").unwrap();
+        set_app_data(&dev, 1, b"Exif\0\0").unwrap();
+        let params = get_jpegcomp(&dev).unwrap();
+        assert_eq!(params.quality, 90);
+        assert_eq!(params.com_data, b"camera 3");
+        assert_eq!(
+            (params.app_n, params.app_data.as_slice()),
+            (1, &b"Exif\0\0"[..])
+        );
+        assert_eq!(
+            markers(&dev).unwrap(),
+            JpegMarkers::DHT | JpegMarkers::COM | JpegMarkers::APP
+        );
+        assert_eq!(
+            set_comment(&dev, &[b'x'; 61]).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
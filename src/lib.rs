@@ -4,9 +4,50 @@
 
 #[macro_use]
 mod ioctl;
+mod cec;
+mod media;
+mod uvcvideo;
+mod v4l2_subdev;
 mod videodev2;
 
+pub mod audio;
+pub mod buffer;
+pub mod catalog;
+pub mod control;
+pub mod debug;
+pub mod device;
+pub mod edid;
+pub mod encindex;
+#[cfg(test)]
+mod fake;
+pub mod h264;
+pub mod hdmi_cec;
+pub mod input;
+pub mod jpeg;
+pub mod mbus;
+pub mod meta;
+pub mod overlay;
+pub mod pipeline;
+pub mod priority;
+pub mod profile;
+pub mod rds;
+pub mod request;
+pub mod sliced;
+pub mod standard;
+pub mod streamparm;
+pub mod subdev;
+pub mod topology;
+pub mod tuner;
+pub mod uapi;
+pub mod uvc;
+pub mod uvc_meta;
+pub mod vbi;
+
+pub use cec::*;
 pub use ioctl::*;
+pub use media::*;
+pub use uvcvideo::*;
+pub use v4l2_subdev::*;
 pub use videodev2::*;
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1,250 @@
+//! Media bus formats (`MEDIA_BUS_FMT_*`), the pixel encodings on the links
+//! between sub-devices, and the memory formats they are captured as
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/subdev-formats.html
+
+use crate::pixel_format::*;
+
+/// One media bus format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MbusInfo {
+    pub code: u32,
+    /// Kernel name without the `MEDIA_BUS_FMT_` prefix
+    pub name: &'static str,
+    /// Pixel format a DMA engine writes when it stores the data unmodified
+    pub fourcc: Option<u32>,
+    /// MIPI CSI-2 packed layout of the same data, for receivers that store
+    /// the bytes as they come off the wire
+    pub packed: Option<u32>,
+}
+
+const fn entry(code: u32, name: &'static str, fourcc: u32) -> MbusInfo {
+    MbusInfo {
+        code,
+        name,
+        fourcc: Some(fourcc),
+        packed: None,
+    }
+}
+
+const fn raw(code: u32, name: &'static str, fourcc: u32, packed: u32) -> MbusInfo {
+    MbusInfo {
+        code,
+        name,
+        fourcc: Some(fourcc),
+        packed: Some(packed),
+    }
+}
+
+const fn internal(code: u32, name: &'static str) -> MbusInfo {
+    MbusInfo {
+        code,
+        name,
+        fourcc: None,
+        packed: None,
+    }
+}
+
+/// Known formats. Serial (`1X`) variants come before their parallel
+/// (`2X8`) twins, which map to the same pixel format.
+pub const MBUS_FORMATS: &[MbusInfo] = &[
+    internal(crate::MEDIA_BUS_FMT_FIXED, "FIXED"),
+    entry(
+        crate::MEDIA_BUS_FMT_RGB565_1X16,
+        "RGB565_1X16",
+        V4L2_PIX_FMT_RGB565,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_RGB565_2X8_LE,
+        "RGB565_2X8_LE",
+        V4L2_PIX_FMT_RGB565,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_RGB565_2X8_BE,
+        "RGB565_2X8_BE",
+        V4L2_PIX_FMT_RGB565X,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_RGB888_1X24,
+        "RGB888_1X24",
+        V4L2_PIX_FMT_RGB24,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_BGR888_1X24,
+        "BGR888_1X24",
+        V4L2_PIX_FMT_BGR24,
+    ),
+    entry(crate::MEDIA_BUS_FMT_Y8_1X8, "Y8_1X8", V4L2_PIX_FMT_GREY),
+    entry(crate::MEDIA_BUS_FMT_Y10_1X10, "Y10_1X10", V4L2_PIX_FMT_Y10),
+    entry(crate::MEDIA_BUS_FMT_Y12_1X12, "Y12_1X12", V4L2_PIX_FMT_Y12),
+    entry(
+        crate::uapi::MEDIA_BUS_FMT_Y16_1X16,
+        "Y16_1X16",
+        V4L2_PIX_FMT_Y16,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_UYVY8_1X16,
+        "UYVY8_1X16",
+        V4L2_PIX_FMT_UYVY,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_VYUY8_1X16,
+        "VYUY8_1X16",
+        V4L2_PIX_FMT_VYUY,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_YUYV8_1X16,
+        "YUYV8_1X16",
+        V4L2_PIX_FMT_YUYV,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_YVYU8_1X16,
+        "YVYU8_1X16",
+        V4L2_PIX_FMT_YVYU,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_UYVY8_2X8,
+        "UYVY8_2X8",
+        V4L2_PIX_FMT_UYVY,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_VYUY8_2X8,
+        "VYUY8_2X8",
+        V4L2_PIX_FMT_VYUY,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_YUYV8_2X8,
+        "YUYV8_2X8",
+        V4L2_PIX_FMT_YUYV,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_YVYU8_2X8,
+        "YVYU8_2X8",
+        V4L2_PIX_FMT_YVYU,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_SBGGR8_1X8,
+        "SBGGR8_1X8",
+        V4L2_PIX_FMT_SBGGR8,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_SGBRG8_1X8,
+        "SGBRG8_1X8",
+        V4L2_PIX_FMT_SGBRG8,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_SGRBG8_1X8,
+        "SGRBG8_1X8",
+        V4L2_PIX_FMT_SGRBG8,
+    ),
+    entry(
+        crate::MEDIA_BUS_FMT_SRGGB8_1X8,
+        "SRGGB8_1X8",
+        V4L2_PIX_FMT_SRGGB8,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SBGGR10_1X10,
+        "SBGGR10_1X10",
+        V4L2_PIX_FMT_SBGGR10,
+        V4L2_PIX_FMT_SBGGR10P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SGBRG10_1X10,
+        "SGBRG10_1X10",
+        V4L2_PIX_FMT_SGBRG10,
+        V4L2_PIX_FMT_SGBRG10P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SGRBG10_1X10,
+        "SGRBG10_1X10",
+        V4L2_PIX_FMT_SGRBG10,
+        V4L2_PIX_FMT_SGRBG10P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SRGGB10_1X10,
+        "SRGGB10_1X10",
+        V4L2_PIX_FMT_SRGGB10,
+        V4L2_PIX_FMT_SRGGB10P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SBGGR12_1X12,
+        "SBGGR12_1X12",
+        V4L2_PIX_FMT_SBGGR12,
+        V4L2_PIX_FMT_SBGGR12P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SGBRG12_1X12,
+        "SGBRG12_1X12",
+        V4L2_PIX_FMT_SGBRG12,
+        V4L2_PIX_FMT_SGBRG12P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SGRBG12_1X12,
+        "SGRBG12_1X12",
+        V4L2_PIX_FMT_SGRBG12,
+        V4L2_PIX_FMT_SGRBG12P,
+    ),
+    raw(
+        crate::MEDIA_BUS_FMT_SRGGB12_1X12,
+        "SRGGB12_1X12",
+        V4L2_PIX_FMT_SRGGB12,
+        V4L2_PIX_FMT_SRGGB12P,
+    ),
+    entry(crate::MEDIA_BUS_FMT_JPEG_1X8, "JPEG_1X8", V4L2_PIX_FMT_JPEG),
+    internal(crate::uapi::MEDIA_BUS_FMT_METADATA_FIXED, "METADATA_FIXED"),
+];
+
+pub fn info(code: u32) -> Option<&'static MbusInfo> {
+    MBUS_FORMATS.iter().find(|f| f.code == code)
+}
+
+/// `"SRGGB10_1X10"` for `MEDIA_BUS_FMT_SRGGB10_1X10`
+pub fn name(code: u32) -> Option<&'static str> {
+    info(code).map(|f| f.name)
+}
+
+/// Inverse of [`name`]; the `MEDIA_BUS_FMT_` prefix is optional
+pub fn from_name(name: &str) -> Option<u32> {
+    let name = name.strip_prefix("MEDIA_BUS_FMT_").unwrap_or(name);
+    MBUS_FORMATS
+        .iter()
+        .find(|f| f.name.eq_ignore_ascii_case(name))
+        .map(|f| f.code)
+}
+
+/// Unpacked pixel format for data arriving as `code`
+pub fn to_fourcc(code: u32) -> Option<u32> {
+    info(code)?.fourcc
+}
+
+/// Bus format that carries `fourcc`, packed or not. Prefers the serial
+/// variant where there is a choice.
+pub fn from_fourcc(fourcc: u32) -> Option<u32> {
+    MBUS_FORMATS
+        .iter()
+        .find(|f| f.fourcc == Some(fourcc) || f.packed == Some(fourcc))
+        .map(|f| f.code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mapping() {
+        let code = crate::MEDIA_BUS_FMT_SRGGB10_1X10;
+        assert_eq!(name(code), Some("SRGGB10_1X10"));
+        assert_eq!(from_name("MEDIA_BUS_FMT_SRGGB10_1X10"), Some(code));
+        assert_eq!(to_fourcc(code), Some(V4L2_PIX_FMT_SRGGB10));
+        assert_eq!(from_fourcc(V4L2_PIX_FMT_SRGGB10P), Some(code));
+        assert_eq!(
+            from_fourcc(V4L2_PIX_FMT_YUYV),
+            Some(crate::MEDIA_BUS_FMT_YUYV8_1X16)
+        );
+        assert_eq!(to_fourcc(crate::MEDIA_BUS_FMT_FIXED), None);
+        assert_eq!(info(0xdead), None);
+
+        for (i, f) in MBUS_FORMATS.iter().enumerate() {
+            assert!(MBUS_FORMATS[..i].iter().all(|g| g.code != f.code));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+///! import linux/media.h
+
+/// ioctl codes for media controller devices (`/dev/mediaN`)
+/// ref. https://www.kernel.org/doc/html/latest/userspace-api/media/mediactl/media-funcs.html
+pub mod media_codes {
+    const MEDIA_IOC_MAGIC: u8 = b'|';
+
+    /// Query device information.
+    pub const MEDIA_IOC_DEVICE_INFO: libc::c_ulong =
+        iowr!(MEDIA_IOC_MAGIC, 0x00, crate::media_device_info);
+    /// Enumerate entities and their properties.
+    pub const MEDIA_IOC_ENUM_ENTITIES: libc::c_ulong =
+        iowr!(MEDIA_IOC_MAGIC, 0x01, crate::media_entity_desc);
+    /// Enumerate all pads and links for a given entity.
+    pub const MEDIA_IOC_ENUM_LINKS: libc::c_ulong =
+        iowr!(MEDIA_IOC_MAGIC, 0x02, crate::media_links_enum);
+    /// Modify the properties of a link.
+    pub const MEDIA_IOC_SETUP_LINK: libc::c_ulong =
+        iowr!(MEDIA_IOC_MAGIC, 0x03, crate::media_link_desc);
+    /// Enumerate the graph topology and graph element properties.
+    pub const MEDIA_IOC_G_TOPOLOGY: libc::c_ulong =
+        iowr!(MEDIA_IOC_MAGIC, 0x04, crate::media_v2_topology);
+    /// Allocate a request, returned as a file descriptor.
+    pub const MEDIA_IOC_REQUEST_ALLOC: libc::c_ulong =
+        ior!(MEDIA_IOC_MAGIC, 0x05, ::std::os::raw::c_int);
+
+    /// Queue a request; issued on the request file descriptor.
+    pub const MEDIA_REQUEST_IOC_QUEUE: libc::c_ulong = io!(MEDIA_IOC_MAGIC, 0x80);
+    /// Return a completed request to its initial state for reuse.
+    pub const MEDIA_REQUEST_IOC_REINIT: libc::c_ulong = io!(MEDIA_IOC_MAGIC, 0x81);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate as v4l;
+    use std::mem;
+
+    #[test]
+    fn ioctl_code() {
+        let MEDIA_IOC_DEVICE_INFO: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'|' as libc::c_ulong) << 8)
+            | (0 as libc::c_ulong)
+            | ((mem::size_of::<v4l::media_device_info>() as libc::c_ulong) << 16);
+        assert_eq!(media_codes::MEDIA_IOC_DEVICE_INFO, MEDIA_IOC_DEVICE_INFO);
+
+        let MEDIA_IOC_SETUP_LINK: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'|' as libc::c_ulong) << 8)
+            | (3 as libc::c_ulong)
+            | ((mem::size_of::<v4l::media_link_desc>() as libc::c_ulong) << 16);
+        assert_eq!(media_codes::MEDIA_IOC_SETUP_LINK, MEDIA_IOC_SETUP_LINK);
+
+        let MEDIA_IOC_G_TOPOLOGY: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'|' as libc::c_ulong) << 8)
+            | (4 as libc::c_ulong)
+            | ((mem::size_of::<v4l::media_v2_topology>() as libc::c_ulong) << 16);
+        assert_eq!(media_codes::MEDIA_IOC_G_TOPOLOGY, MEDIA_IOC_G_TOPOLOGY);
+        // Fixed by the kernel ABI
+        assert_eq!(MEDIA_IOC_G_TOPOLOGY, 0xc0487c04);
+
+        assert_eq!(media_codes::MEDIA_IOC_REQUEST_ALLOC, 0x80047c05);
+        assert_eq!(media_codes::MEDIA_REQUEST_IOC_QUEUE, 0x7c80);
+        assert_eq!(media_codes::MEDIA_REQUEST_IOC_REINIT, 0x7c81);
+    }
+}
@@ -0,0 +1,468 @@
+//! Metadata buffers (V4L2_BUF_TYPE_META_CAPTURE, V4L2_BUF_TYPE_META_OUTPUT)
+//!
+//! ISPs emit statistics on a metadata capture node and take their
+//! parameters on a metadata output node; UVC cameras report payload
+//! headers the same way. Buffers are matched to video frames through their
+//! sequence numbers, and [`Parsers`] decodes payloads by `meta_data` FOURCC.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-meta.html
+
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::mem;
+
+use crate::buffer::{Buffers, Mmap};
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+use crate::uvc_meta;
+
+/// Typed `v4l2_meta_format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetaFormat {
+    /// `V4L2_META_FMT_*`
+    pub dataformat: u32,
+    /// Largest payload a buffer holds, in bytes
+    pub buffersize: u32,
+}
+
+impl From<&crate::v4l2_format> for MetaFormat {
+    fn from(f: &crate::v4l2_format) -> Self {
+        let meta = unsafe { f.fmt.meta };
+        MetaFormat {
+            dataformat: meta.dataformat,
+            buffersize: meta.buffersize,
+        }
+    }
+}
+
+pub fn is_meta(type_: u32) -> bool {
+    type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE
+        || type_ == crate::uapi::V4L2_BUF_TYPE_META_OUTPUT
+}
+
+/// VIDIOC_ENUM_FMT on a metadata queue
+pub fn meta_formats<D: Ioctl + ?Sized>(dev: &D, type_: u32) -> io::Result<Vec<u32>> {
+    let mut formats = Vec::new();
+    for index in 0.. {
+        let mut d: crate::v4l2_fmtdesc = unsafe { mem::zeroed() };
+        d.index = index;
+        d.type_ = type_;
+        match ioctl(dev, codes::VIDIOC_ENUM_FMT, &mut d) {
+            Ok(()) => formats.push(d.pixelformat),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(formats)
+}
+
+fn meta_fmt<D: Ioctl + ?Sized>(
+    dev: &D,
+    request: libc::c_ulong,
+    type_: u32,
+    dataformat: u32,
+) -> io::Result<MetaFormat> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = type_;
+    f.fmt.meta.dataformat = dataformat;
+    ioctl(dev, request, &mut f)?;
+    Ok(MetaFormat::from(&f))
+}
+
+/// VIDIOC_G_FMT on a metadata queue
+pub fn meta_format<D: Ioctl + ?Sized>(dev: &D, type_: u32) -> io::Result<MetaFormat> {
+    meta_fmt(dev, codes::VIDIOC_G_FMT, type_, 0)
+}
+
+/// VIDIOC_S_FMT; the driver substitutes a format it supports for an
+/// unknown `dataformat`
+pub fn set_meta_format<D: Ioctl + ?Sized>(
+    dev: &D,
+    type_: u32,
+    dataformat: u32,
+) -> io::Result<MetaFormat> {
+    meta_fmt(dev, codes::VIDIOC_S_FMT, type_, dataformat)
+}
+
+/// VIDIOC_TRY_FMT
+pub fn try_meta_format<D: Ioctl + ?Sized>(
+    dev: &D,
+    type_: u32,
+    dataformat: u32,
+) -> io::Result<MetaFormat> {
+    meta_fmt(dev, codes::VIDIOC_TRY_FMT, type_, dataformat)
+}
+
+/// One dequeued metadata buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Sequence number of the video frame the data belongs to
+    pub sequence: u32,
+    /// In nanoseconds
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+/// A streaming metadata queue with MMAP buffers
+pub struct MetaStream<'a, D: Mmap + ?Sized> {
+    buffers: Buffers<'a, D>,
+    format: MetaFormat,
+    /// Output buffers not with the driver
+    free: Vec<usize>,
+}
+
+impl<'a, D: Mmap + ?Sized> MetaStream<'a, D> {
+    /// Set `dataformat` on the `type_` queue, allocate `count` buffers and
+    /// start streaming. Capture buffers are queued right away. Fails with
+    /// InvalidInput if the driver does not support `dataformat`.
+    pub fn new(dev: &'a D, type_: u32, dataformat: u32, count: u32) -> io::Result<Self> {
+        if !is_meta(type_) {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        let format = set_meta_format(dev, type_, dataformat)?;
+        if format.dataformat != dataformat {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "metadata format not supported",
+            ));
+        }
+        let buffers = Buffers::new(dev, type_, count)?;
+        let mut free = Vec::new();
+        for index in 0..buffers.len() {
+            if type_ == crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE {
+                buffers.queue(index, &[], 0, 0, None)?;
+            } else {
+                free.push(index);
+            }
+        }
+        buffers.stream_on()?;
+        Ok(MetaStream {
+            buffers,
+            format,
+            free,
+        })
+    }
+
+    pub fn capture(dev: &'a D, dataformat: u32, count: u32) -> io::Result<Self> {
+        Self::new(
+            dev,
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE,
+            dataformat,
+            count,
+        )
+    }
+
+    pub fn output(dev: &'a D, dataformat: u32, count: u32) -> io::Result<Self> {
+        Self::new(
+            dev,
+            crate::uapi::V4L2_BUF_TYPE_META_OUTPUT,
+            dataformat,
+            count,
+        )
+    }
+
+    pub fn format(&self) -> MetaFormat {
+        self.format
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.buffers.buf_type() == crate::uapi::V4L2_BUF_TYPE_META_OUTPUT
+    }
+
+    /// Dequeue the next capture buffer, copy out its payload and requeue it;
+    /// EINVAL on an output stream
+    pub fn dequeue(&self) -> io::Result<Metadata> {
+        if self.is_output() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        let d = self.buffers.dequeue()?;
+        let data = self.buffers.plane(d.index, 0);
+        let used = (d.bytesused[0] as usize).min(data.len());
+        let data = data[..used].to_vec();
+        self.buffers.queue(d.index, &[], 0, 0, None)?;
+        Ok(Metadata {
+            sequence: d.sequence,
+            timestamp: d.timestamp,
+            data,
+        })
+    }
+
+    /// Queue `data` on an output stream, first waiting for a buffer the
+    /// driver is done with if all are in use
+    pub fn queue(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.is_output() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        if data.len() > self.format.buffersize as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "metadata larger than the buffer size",
+            ));
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => self.buffers.dequeue()?.index,
+        };
+        let plane = self.buffers.plane_mut(index, 0);
+        if data.len() > plane.len() {
+            self.free.push(index);
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        plane[..data.len()].copy_from_slice(data);
+        if let Err(e) = self.buffers.queue(index, &[data.len() as u32], 0, 0, None) {
+            self.free.push(index);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+impl<D: Mmap + ?Sized> Drop for MetaStream<'_, D> {
+    fn drop(&mut self) {
+        let _ = self.buffers.stream_off();
+    }
+}
+
+/// Matches items of two streams with equal sequence numbers, e.g. video
+/// frames and their metadata. Items whose partner was skipped are dropped
+/// once the other stream moves past them.
+#[derive(Debug)]
+pub struct Pairer<V, M> {
+    video: VecDeque<(u32, V)>,
+    meta: VecDeque<(u32, M)>,
+    depth: usize,
+}
+
+impl<V, M> Default for Pairer<V, M> {
+    fn default() -> Self {
+        Pairer::new(16)
+    }
+}
+
+/// Drop items of `theirs` behind `sequence` and take the one matching it,
+/// or keep `item` in `mine` until its partner arrives
+fn pair<A, B>(
+    mine: &mut VecDeque<(u32, A)>,
+    theirs: &mut VecDeque<(u32, B)>,
+    depth: usize,
+    sequence: u32,
+    item: A,
+) -> Option<(A, B)> {
+    // Sequence numbers wrap; compare by distance
+    while theirs
+        .front()
+        .is_some_and(|(seq, _)| (sequence.wrapping_sub(*seq) as i32) > 0)
+    {
+        theirs.pop_front();
+    }
+    if theirs.front().is_some_and(|(seq, _)| *seq == sequence) {
+        let (_, partner) = theirs.pop_front().unwrap();
+        return Some((item, partner));
+    }
+    mine.push_back((sequence, item));
+    if mine.len() > depth {
+        mine.pop_front();
+    }
+    None
+}
+
+impl<V, M> Pairer<V, M> {
+    /// Keep at most `depth` unmatched items per stream
+    pub fn new(depth: usize) -> Self {
+        Pairer {
+            video: VecDeque::new(),
+            meta: VecDeque::new(),
+            depth: depth.max(1),
+        }
+    }
+
+    pub fn push_video(&mut self, sequence: u32, video: V) -> Option<(V, M)> {
+        pair(&mut self.video, &mut self.meta, self.depth, sequence, video)
+    }
+
+    pub fn push_meta(&mut self, sequence: u32, meta: M) -> Option<(V, M)> {
+        pair(&mut self.meta, &mut self.video, self.depth, sequence, meta)
+            .map(|(meta, video)| (video, meta))
+    }
+
+    /// Unmatched video and metadata items
+    pub fn pending(&self) -> (usize, usize) {
+        (self.video.len(), self.meta.len())
+    }
+}
+
+type Parser = Box<dyn Fn(&[u8]) -> io::Result<Box<dyn Any>>>;
+
+/// Payload parsers keyed by `V4L2_META_FMT_*`
+pub struct Parsers {
+    parsers: BTreeMap<u32, Parser>,
+}
+
+/// With the formats this crate understands: V4L2_META_FMT_UVC and
+/// V4L2_META_FMT_D4XX, both parsed into `Vec<uvc_meta::Block>`
+impl Default for Parsers {
+    fn default() -> Self {
+        let mut parsers = Parsers::new();
+        parsers.register(crate::pixel_format::V4L2_META_FMT_UVC, uvc_meta::parse);
+        // UVC payload headers with the camera state as vendor data
+        parsers.register(crate::pixel_format::V4L2_META_FMT_D4XX, uvc_meta::parse);
+        parsers
+    }
+}
+
+impl Parsers {
+    /// No parsers registered
+    pub fn new() -> Self {
+        Parsers {
+            parsers: BTreeMap::new(),
+        }
+    }
+
+    /// Parse `dataformat` payloads with `parser`, replacing any previous one
+    pub fn register<T, F>(&mut self, dataformat: u32, parser: F)
+    where
+        T: Any,
+        F: Fn(&[u8]) -> io::Result<T> + 'static,
+    {
+        let parser: Parser = Box::new(move |data| Ok(Box::new(parser(data)?) as Box<dyn Any>));
+        self.parsers.insert(dataformat, parser);
+    }
+
+    pub fn supports(&self, dataformat: u32) -> bool {
+        self.parsers.contains_key(&dataformat)
+    }
+
+    /// Unsupported if no parser is registered for `dataformat`
+    pub fn parse(&self, dataformat: u32, data: &[u8]) -> io::Result<Box<dyn Any>> {
+        let parser = self.parsers.get(&dataformat).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("no parser for metadata format {:#010x}", dataformat),
+            )
+        })?;
+        parser(data)
+    }
+
+    /// [`Parsers::parse`] into the type the parser was registered with
+    pub fn parse_as<T: Any>(&self, dataformat: u32, data: &[u8]) -> io::Result<T> {
+        self.parse(dataformat, data)?
+            .downcast::<T>()
+            .map(|parsed| *parsed)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "metadata parser returns a different type",
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeMeta;
+    use crate::pixel_format::{V4L2_META_FMT_UVC, V4L2_META_FMT_VSP1_HGO, V4L2_META_FMT_VSP1_HGT};
+
+    #[test]
+    fn capture_pair_and_parse() {
+        let capture = crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE;
+        let fake = FakeMeta::with_formats(capture, &[V4L2_META_FMT_VSP1_HGO, V4L2_META_FMT_UVC]);
+        assert_eq!(
+            meta_formats(&fake, capture).unwrap(),
+            [V4L2_META_FMT_VSP1_HGO, V4L2_META_FMT_UVC]
+        );
+        assert_eq!(
+            try_meta_format(&fake, capture, V4L2_META_FMT_VSP1_HGT)
+                .unwrap()
+                .dataformat,
+            V4L2_META_FMT_VSP1_HGO
+        );
+        assert!(MetaStream::capture(&fake, V4L2_META_FMT_VSP1_HGT, 2).is_err());
+
+        // Histogram of three bins per frame; frame 2 has no statistics
+        for seq in [0u32, 1, 3] {
+            let hist: Vec<u8> = (0..3u32)
+                .flat_map(|b| (seq * 10 + b).to_le_bytes())
+                .collect();
+            fake.pending.borrow_mut().push_back((seq, hist));
+        }
+        let stream = MetaStream::capture(&fake, V4L2_META_FMT_VSP1_HGO, 2).unwrap();
+        assert_eq!(stream.format().buffersize, 10240);
+        assert_eq!(
+            meta_format(&fake, capture).unwrap().dataformat,
+            V4L2_META_FMT_VSP1_HGO
+        );
+
+        let mut parsers = Parsers::default();
+        assert!(parsers.supports(V4L2_META_FMT_UVC));
+        let err = parsers.parse(V4L2_META_FMT_VSP1_HGO, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        parsers.register(V4L2_META_FMT_VSP1_HGO, |data: &[u8]| {
+            Ok(data
+                .chunks_exact(4)
+                .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+                .collect::<Vec<u32>>())
+        });
+
+        let mut pairer: Pairer<&str, Metadata> = Pairer::new(4);
+        let mut paired = Vec::new();
+        // Video frame 1 arrives before its metadata, frame 0 after
+        assert!(pairer.push_video(1, "frame 1").is_none());
+        for video in [(0, "frame 0"), (2, "frame 2"), (3, "frame 3")] {
+            let meta = stream.dequeue().unwrap();
+            paired.extend(pairer.push_meta(meta.sequence, meta));
+            paired.extend(pairer.push_video(video.0, video.1));
+        }
+        // Frame 2 has no metadata and is dropped when metadata 3 arrives
+        let frames: Vec<&str> = paired.iter().map(|(v, _)| *v).collect();
+        assert_eq!(frames, ["frame 0", "frame 1", "frame 3"]);
+        assert_eq!(pairer.pending(), (0, 0));
+        let (_, meta) = &paired[2];
+        let bins: Vec<u32> = parsers
+            .parse_as(V4L2_META_FMT_VSP1_HGO, &meta.data)
+            .unwrap();
+        assert_eq!(bins, [30, 31, 32]);
+        assert!(parsers
+            .parse_as::<String>(V4L2_META_FMT_VSP1_HGO, &meta.data)
+            .is_err());
+        assert!(parsers
+            .parse_as::<Vec<uvc_meta::Block>>(V4L2_META_FMT_UVC, &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn output_params() {
+        let output = crate::uapi::V4L2_BUF_TYPE_META_OUTPUT;
+        let format = crate::pixel_format::V4L2_META_FMT_RK_ISP1_PARAMS;
+        let fake = FakeMeta::with_formats(output, &[format]);
+        let mut stream = MetaStream::output(&fake, format, 2).unwrap();
+        assert!(stream.is_output() && stream.dequeue().is_err());
+        for frame in 0..5u8 {
+            stream.queue(&[frame; 16]).unwrap();
+        }
+        let received = fake.received.borrow();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received[4], (4, vec![4; 16]));
+        drop(received);
+        assert!(stream.queue(&vec![0; 10241]).is_err());
+        drop(stream);
+        assert!(!fake.streaming.get());
+    }
+
+    #[test]
+    fn no_dequeue_on_output() {
+        let output = crate::uapi::V4L2_BUF_TYPE_META_OUTPUT;
+        let format = crate::pixel_format::V4L2_META_FMT_RK_ISP1_PARAMS;
+        let fake = FakeMeta::with_formats(output, &[format]);
+        let mut stream = MetaStream::output(&fake, format, 2).unwrap();
+        stream.queue(&[1; 16]).unwrap();
+        stream.queue(&[2; 16]).unwrap();
+        // Would otherwise take back a sent buffer and queue it empty
+        let err = stream.dequeue().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert_eq!(fake.received.borrow().len(), 2);
+        stream.queue(&[3; 16]).unwrap();
+        assert_eq!(fake.received.borrow()[2], (2, vec![3; 16]));
+    }
+}
@@ -0,0 +1,445 @@
+//! Video overlay (VIDIOC_G/S_FBUF, VIDIOC_OVERLAY and the
+//! V4L2_BUF_TYPE_VIDEO_OVERLAY format)
+//!
+//! The card writes the video image straight into a framebuffer. The
+//! framebuffer is described once with S_FBUF, then the window the image
+//! goes into is set as an overlay format, with optional clipping by a list
+//! of rectangles or a bitmap, a chroma key and global alpha.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-overlay.html
+
+use std::io;
+use std::mem;
+use std::ops;
+use std::ptr;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+
+/// `V4L2_FBUF_CAP_*`: what the overlay hardware supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FbufCapability(pub u32);
+
+impl FbufCapability {
+    /// The overlay goes to a separate output, not into the framebuffer
+    pub fn external_overlay(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_EXTERNOVERLAY != 0
+    }
+
+    pub fn chromakey(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_CHROMAKEY != 0
+    }
+
+    pub fn list_clipping(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_LIST_CLIPPING != 0
+    }
+
+    pub fn bitmap_clipping(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_BITMAP_CLIPPING != 0
+    }
+
+    pub fn local_alpha(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_LOCAL_ALPHA != 0
+    }
+
+    pub fn global_alpha(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_GLOBAL_ALPHA != 0
+    }
+
+    pub fn local_inv_alpha(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_LOCAL_INV_ALPHA != 0
+    }
+
+    /// The key is matched in the video image instead of the framebuffer
+    pub fn src_chromakey(&self) -> bool {
+        self.0 & crate::V4L2_FBUF_CAP_SRC_CHROMAKEY != 0
+    }
+}
+
+/// `V4L2_FBUF_FLAG_*`: how the overlay is blended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FbufFlags(pub u32);
+
+impl FbufFlags {
+    /// The framebuffer is the primary graphics surface
+    pub const PRIMARY: Self = Self(crate::V4L2_FBUF_FLAG_PRIMARY);
+    /// The framebuffer is an overlay surface the size of the video
+    pub const OVERLAY: Self = Self(crate::V4L2_FBUF_FLAG_OVERLAY);
+    /// Show video where the framebuffer holds the window's chroma key
+    pub const CHROMAKEY: Self = Self(crate::V4L2_FBUF_FLAG_CHROMAKEY);
+    pub const LOCAL_ALPHA: Self = Self(crate::V4L2_FBUF_FLAG_LOCAL_ALPHA);
+    /// Blend with the window's global alpha value
+    pub const GLOBAL_ALPHA: Self = Self(crate::V4L2_FBUF_FLAG_GLOBAL_ALPHA);
+    pub const LOCAL_INV_ALPHA: Self = Self(crate::V4L2_FBUF_FLAG_LOCAL_INV_ALPHA);
+    pub const SRC_CHROMAKEY: Self = Self(crate::V4L2_FBUF_FLAG_SRC_CHROMAKEY);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for FbufFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for FbufFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Typed `v4l2_framebuffer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Framebuffer {
+    /// Set by the driver
+    pub capability: FbufCapability,
+    pub flags: FbufFlags,
+    /// Physical address of the framebuffer, 0 for external overlays
+    pub base: usize,
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub bytesperline: u32,
+    pub sizeimage: u32,
+    pub colorspace: u32,
+}
+
+impl From<&crate::v4l2_framebuffer> for Framebuffer {
+    fn from(f: &crate::v4l2_framebuffer) -> Self {
+        Framebuffer {
+            capability: FbufCapability(f.capability),
+            flags: FbufFlags(f.flags),
+            base: f.base as usize,
+            width: f.fmt.width,
+            height: f.fmt.height,
+            pixelformat: f.fmt.pixelformat,
+            bytesperline: f.fmt.bytesperline,
+            sizeimage: f.fmt.sizeimage,
+            colorspace: f.fmt.colorspace,
+        }
+    }
+}
+
+impl Framebuffer {
+    pub(crate) fn to_raw(self) -> crate::v4l2_framebuffer {
+        let mut f: crate::v4l2_framebuffer = unsafe { mem::zeroed() };
+        f.capability = self.capability.0;
+        f.flags = self.flags.0;
+        f.base = self.base as *mut libc::c_void;
+        f.fmt.width = self.width;
+        f.fmt.height = self.height;
+        f.fmt.pixelformat = self.pixelformat;
+        f.fmt.bytesperline = self.bytesperline;
+        f.fmt.sizeimage = self.sizeimage;
+        f.fmt.colorspace = self.colorspace;
+        f
+    }
+}
+
+/// VIDIOC_G_FBUF
+pub fn get_framebuffer<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Framebuffer> {
+    let mut f: crate::v4l2_framebuffer = unsafe { mem::zeroed() };
+    ioctl(dev, codes::VIDIOC_G_FBUF, &mut f)?;
+    Ok(Framebuffer::from(&f))
+}
+
+/// VIDIOC_S_FBUF. Pointing a destructive overlay at memory needs
+/// CAP_SYS_ADMIN; for external overlays only the flags matter.
+pub fn set_framebuffer<D: Ioctl + ?Sized>(dev: &D, fb: &Framebuffer) -> io::Result<()> {
+    let mut f = fb.to_raw();
+    ioctl(dev, codes::VIDIOC_S_FBUF, &mut f)
+}
+
+/// Typed `v4l2_rect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(left: i32, top: i32, width: u32, height: u32) -> Self {
+        Rect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+}
+
+impl From<crate::v4l2_rect> for Rect {
+    fn from(r: crate::v4l2_rect) -> Self {
+        Rect::new(r.left, r.top, r.width, r.height)
+    }
+}
+
+impl From<Rect> for crate::v4l2_rect {
+    fn from(r: Rect) -> Self {
+        crate::v4l2_rect {
+            left: r.left,
+            top: r.top,
+            width: r.width,
+            height: r.height,
+        }
+    }
+}
+
+/// One bit per window pixel, the video shows where the bit is set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipBitmap {
+    width: u32,
+    height: u32,
+    bits: Vec<u8>,
+}
+
+impl ClipBitmap {
+    /// Bitmap for a `width` x `height` window with every pixel visible
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut bitmap = ClipBitmap {
+            width,
+            height,
+            bits: Vec::new(),
+        };
+        bitmap.bits = vec![0xff; bitmap.stride() * height as usize];
+        bitmap
+    }
+
+    /// Bytes per bitmap row
+    pub fn stride(&self) -> usize {
+        (self.width as usize + 7) >> 3
+    }
+
+    pub fn is_visible(&self, x: u32, y: u32) -> bool {
+        x < self.width
+            && y < self.height
+            && self.bits[y as usize * self.stride() + x as usize / 8] & (1 << (x & 7)) != 0
+    }
+
+    pub fn set_visible(&mut self, x: u32, y: u32, visible: bool) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let i = y as usize * self.stride() + x as usize / 8;
+        if visible {
+            self.bits[i] |= 1 << (x & 7);
+        } else {
+            self.bits[i] &= !(1 << (x & 7));
+        }
+    }
+
+    /// Hide the part of `rect` (window coordinates) inside the window
+    pub fn hide(&mut self, rect: Rect) {
+        let x0 = rect.left.max(0) as u32;
+        let y0 = rect.top.max(0) as u32;
+        let x1 = (rect.left as i64 + rect.width as i64).clamp(0, self.width as i64) as u32;
+        let y1 = (rect.top as i64 + rect.height as i64).clamp(0, self.height as i64) as u32;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.set_visible(x, y, false);
+            }
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+/// Typed `v4l2_window`: where the video goes and what hides it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Window {
+    /// Position and size, relative to the framebuffer
+    pub rect: Rect,
+    /// `V4L2_FIELD_*`
+    pub field: u32,
+    /// Key value in the framebuffer pixel format, used with
+    /// [`FbufFlags::CHROMAKEY`]
+    pub chromakey: u32,
+    /// Rectangles, relative to the window, where the video is hidden
+    pub clips: Vec<Rect>,
+    /// Clipping mask, the size of `rect`. Only sent, never read back.
+    pub bitmap: Option<ClipBitmap>,
+    /// Used with [`FbufFlags::GLOBAL_ALPHA`]
+    pub global_alpha: u8,
+}
+
+impl Window {
+    pub fn new(rect: Rect) -> Self {
+        Window {
+            rect,
+            field: crate::v4l2_field_V4L2_FIELD_ANY,
+            ..Default::default()
+        }
+    }
+
+    /// Read the window from `w`, whose `clips` point to `clipcount` entries
+    /// or are null
+    ///
+    /// # Safety
+    /// `w.clips` must be null or valid for `w.clipcount` reads.
+    pub(crate) unsafe fn from_raw(w: &crate::v4l2_window) -> Self {
+        let clips = if w.clips.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(w.clips, w.clipcount as usize)
+                .iter()
+                .map(|c| Rect::from(c.c))
+                .collect()
+        };
+        Window {
+            rect: Rect::from(w.w),
+            field: w.field,
+            chromakey: w.chromakey,
+            clips,
+            bitmap: None,
+            global_alpha: w.global_alpha,
+        }
+    }
+}
+
+fn overlay_format() -> crate::v4l2_format {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OVERLAY;
+    f
+}
+
+/// VIDIOC_G_FMT for the overlay window, clip list included
+pub fn get_window<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Window> {
+    let mut f = overlay_format();
+    ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+    let count = unsafe { f.fmt.win.clipcount };
+    if count == 0 {
+        f.fmt.win.clips = ptr::null_mut();
+        return Ok(unsafe { Window::from_raw(&f.fmt.win) });
+    }
+    // Ask again with room for the clips
+    let mut clips: Vec<crate::v4l2_clip> = vec![unsafe { mem::zeroed() }; count as usize];
+    let mut f = overlay_format();
+    f.fmt.win.clips = clips.as_mut_ptr();
+    f.fmt.win.clipcount = count;
+    ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+    f.fmt.win.clipcount = unsafe { f.fmt.win.clipcount }.min(count);
+    Ok(unsafe { Window::from_raw(&f.fmt.win) })
+}
+
+fn send_window<D: Ioctl + ?Sized>(
+    dev: &D,
+    request: libc::c_ulong,
+    window: &Window,
+) -> io::Result<Window> {
+    if let Some(bitmap) = &window.bitmap {
+        if (bitmap.width, bitmap.height) != (window.rect.width, window.rect.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "clip bitmap must have the size of the window",
+            ));
+        }
+    }
+    let mut clips: Vec<crate::v4l2_clip> = window
+        .clips
+        .iter()
+        .map(|&r| crate::v4l2_clip {
+            c: r.into(),
+            next: ptr::null_mut(),
+        })
+        .collect();
+    // The kernel only reads the bitmap
+    let mut bitmap = window.bitmap.as_ref().map(|b| b.as_bytes().to_vec());
+
+    let mut f = overlay_format();
+    f.fmt.win.w = window.rect.into();
+    f.fmt.win.field = window.field;
+    f.fmt.win.chromakey = window.chromakey;
+    if !clips.is_empty() {
+        f.fmt.win.clips = clips.as_mut_ptr();
+        f.fmt.win.clipcount = clips.len() as u32;
+    }
+    if let Some(bitmap) = &mut bitmap {
+        f.fmt.win.bitmap = bitmap.as_mut_ptr() as *mut libc::c_void;
+    }
+    f.fmt.win.global_alpha = window.global_alpha;
+    ioctl(dev, request, &mut f)?;
+
+    f.fmt.win.clipcount = unsafe { f.fmt.win.clipcount }.min(clips.len() as u32);
+    let mut adjusted = unsafe { Window::from_raw(&f.fmt.win) };
+    adjusted.bitmap = window.bitmap.clone();
+    Ok(adjusted)
+}
+
+/// VIDIOC_S_FMT for the overlay window, returning what the driver accepted
+pub fn set_window<D: Ioctl + ?Sized>(dev: &D, window: &Window) -> io::Result<Window> {
+    send_window(dev, codes::VIDIOC_S_FMT, window)
+}
+
+/// VIDIOC_TRY_FMT for the overlay window
+pub fn try_window<D: Ioctl + ?Sized>(dev: &D, window: &Window) -> io::Result<Window> {
+    send_window(dev, codes::VIDIOC_TRY_FMT, window)
+}
+
+/// VIDIOC_OVERLAY: start or stop the overlay
+pub fn set_overlay<D: Ioctl + ?Sized>(dev: &D, enable: bool) -> io::Result<()> {
+    let mut on: libc::c_int = enable as libc::c_int;
+    ioctl(dev, codes::VIDIOC_OVERLAY, &mut on)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    #[test]
+    fn clip_bitmap() {
+        let mut bitmap = ClipBitmap::new(20, 4);
+        assert_eq!((bitmap.stride(), bitmap.as_bytes().len()), (3, 12));
+        bitmap.hide(Rect::new(-2, 1, 12, 10));
+        assert!(bitmap.is_visible(10, 1) && bitmap.is_visible(0, 0));
+        assert!(!bitmap.is_visible(9, 3) && !bitmap.is_visible(0, 1));
+        assert!(!bitmap.is_visible(20, 0));
+        assert_eq!(bitmap.as_bytes()[3..6], [0x00, 0xfc, 0xff]);
+    }
+
+    #[test]
+    fn overlay_window() {
+        let dev = FakeDevice::default();
+        assert!(set_overlay(&dev, true).is_err());
+
+        let mut fb = Framebuffer {
+            width: 1024,
+            height: 768,
+            pixelformat: crate::pixel_format::V4L2_PIX_FMT_RGB565,
+            bytesperline: 2048,
+            ..Default::default()
+        };
+        fb.flags |= FbufFlags::PRIMARY | FbufFlags::CHROMAKEY;
+        set_framebuffer(&dev, &fb).unwrap();
+        let fb = get_framebuffer(&dev).unwrap();
+        assert!(fb.capability.chromakey() && fb.capability.list_clipping());
+        assert!(fb.flags.contains(FbufFlags::CHROMAKEY));
+
+        let mut window = Window::new(Rect::new(900, 100, 320, 240));
+        window.chromakey = 0xf81f;
+        window.clips = vec![Rect::new(0, 0, 32, 32), Rect::new(100, 50, 16, 16)];
+        window.bitmap = Some(ClipBitmap::new(32, 24));
+        assert_eq!(
+            set_window(&dev, &window).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        window.bitmap = None;
+        let accepted = set_window(&dev, &window).unwrap();
+        // The fake clamps the window to the framebuffer
+        assert_eq!(accepted.rect, Rect::new(900, 100, 124, 240));
+        assert_eq!(accepted.clips, window.clips);
+        assert_eq!(get_window(&dev).unwrap(), accepted);
+
+        set_overlay(&dev, true).unwrap();
+        assert!(dev.overlay.get());
+        set_overlay(&dev, false).unwrap();
+        assert!(!dev.overlay.get());
+    }
+}
@@ -0,0 +1,1091 @@
+//! Media controller pipeline configuration
+//!
+//! A [`Pipeline`] names the video node to capture from and the format wanted
+//! there. [`Pipeline::configure`] walks the media graph back from the video
+//! node to the sensor, enables the links on the way and sets the pad formats
+//! of every sub-device so that the kernel's link validation passes at
+//! STREAMON. What `media-ctl -l ... -V ...` would do by hand.
+//!
+//! Pipelines are kept in a small YAML subset:
+//!
+//! ```yaml
+//! # imx219 cropped to 720p on the rkisp1 main path
+//! video: "rkisp1_mainpath"
+//! format:
+//!   fourcc: "NV12"
+//!   width: 1280
+//!   height: 720
+//! entities:
+//!   - entity: "imx219 1-0010"
+//!     code: SRGGB10_1X10
+//!     size: [1920, 1080]
+//!   - entity: "rkisp1_isp"
+//!     crop: [320, 180, 1280, 720]
+//!     code: YUYV8_2X8
+//! ```
+//!
+//! `entities` is optional. `code` and `size` set the source pad format of
+//! an entity, `crop` the crop rectangle of its sink pad (of the source pad
+//! for a sensor). Everything left out is derived from the upstream format
+//! and the output size.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::Path;
+
+use crate::codes;
+use crate::device::{ioctl, Device, Ioctl};
+use crate::mbus;
+use crate::overlay::Rect;
+use crate::subdev::{MbusFormat, Subdev, Which};
+use crate::topology::{self, Interface, MediaDevice, Topology};
+
+/// Per-entity settings of a [`Pipeline`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stage {
+    /// Entity name as in the media graph
+    pub entity: String,
+    /// `MEDIA_BUS_FMT_*` of the source pad
+    pub code: Option<u32>,
+    /// Size of the source pad
+    pub size: Option<(u32, u32)>,
+    /// Crop rectangle of the sink pad, or of the source pad of a sensor
+    pub crop: Option<Rect>,
+}
+
+/// Desired capture format on a video node, plus optional per-entity
+/// settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    /// Entity name of the video node
+    pub video: String,
+    pub fourcc: u32,
+    pub width: u32,
+    pub height: u32,
+    pub entities: Vec<Stage>,
+}
+
+/// One entity on the route from the sensor to the video node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub entity: u32,
+    pub name: String,
+    /// Pad index the route enters through, `None` for the sensor
+    pub sink: Option<u32>,
+    /// Pad index the route leaves through, `None` for the video node
+    pub source: Option<u32>,
+    /// Data link into `sink`
+    pub link: Option<u32>,
+}
+
+/// The formats on the two ends of a link disagree, so STREAMON would fail
+/// with EPIPE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkError {
+    pub source: String,
+    pub sink: String,
+    pub message: String,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}: {}", self.source, self.sink, self.message)
+    }
+}
+
+impl error::Error for LinkError {}
+
+impl From<LinkError> for io::Error {
+    fn from(e: LinkError) -> Self {
+        io::Error::new(io::ErrorKind::BrokenPipe, e)
+    }
+}
+
+/// The [`LinkError`] inside `err`, if that is why the call failed
+pub fn link_error(err: &io::Error) -> Option<&LinkError> {
+    err.get_ref()?.downcast_ref()
+}
+
+fn describe(f: &MbusFormat) -> String {
+    match f.code_name() {
+        Some(name) => format!("{} {}x{}", name, f.width, f.height),
+        None => format!("{:#x} {}x{}", f.code, f.width, f.height),
+    }
+}
+
+fn fourcc_name(fourcc: u32) -> String {
+    let bytes = fourcc.to_le_bytes();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Pipeline {
+    pub fn new(video: &str, fourcc: u32, width: u32, height: u32) -> Self {
+        Pipeline {
+            video: video.into(),
+            fourcc,
+            width,
+            height,
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn stage(&self, entity: &str) -> Option<&Stage> {
+        self.entities.iter().find(|s| s.entity == entity)
+    }
+
+    /// Entities from the sensor to the video node.
+    ///
+    /// Where several links enter a pad, the one from an entity listed in
+    /// `entities` wins, then an enabled one.
+    pub fn route(&self, topology: &Topology) -> io::Result<Vec<Hop>> {
+        let video = topology
+            .entity_by_name(&self.video)
+            .ok_or_else(|| invalid_input(format!("no entity `{}`", self.video)))?;
+        let mut route = vec![Hop {
+            entity: video.id,
+            name: video.name.clone(),
+            sink: None,
+            source: None,
+            link: None,
+        }];
+        loop {
+            let current = &route[route.len() - 1];
+            let incoming: Vec<_> = topology.links_to(current.entity).collect();
+            if incoming.is_empty() {
+                break;
+            }
+            let upstream = |id| {
+                let pad = topology.pad(id)?;
+                topology.entity(pad.entity_id)
+            };
+            let named: Vec<_> = incoming
+                .iter()
+                .filter(|l| upstream(l.source_id).is_some_and(|e| self.stage(&e.name).is_some()))
+                .copied()
+                .collect();
+            let enabled: Vec<_> = incoming
+                .iter()
+                .filter(|l| l.is_enabled())
+                .copied()
+                .collect();
+            let link = match (named.as_slice(), enabled.as_slice(), incoming.as_slice()) {
+                ([link], _, _) | ([], [link], _) | ([], [], [link]) => **link,
+                _ => {
+                    return Err(invalid_input(format!(
+                        "several links enter `{}`, list the upstream entity under `entities`",
+                        current.name
+                    )))
+                }
+            };
+            let sink = topology.pad(link.sink_id).map(|p| p.index);
+            let source = topology
+                .pad(link.source_id)
+                .ok_or_else(|| invalid_input(format!("link {} has no source pad", link.id)))?;
+            let entity = upstream(link.source_id)
+                .ok_or_else(|| invalid_input(format!("pad {} has no entity", source.id)))?;
+            if route.iter().any(|h| h.entity == entity.id) {
+                return Err(invalid_input(format!("loop through `{}`", entity.name)));
+            }
+            let last = route.len() - 1;
+            route[last].sink = sink;
+            route[last].link = Some(link.id);
+            route.push(Hop {
+                entity: entity.id,
+                name: entity.name.clone(),
+                sink: None,
+                source: Some(source.index),
+                link: None,
+            });
+        }
+        route.reverse();
+        if let Some(stage) = self
+            .entities
+            .iter()
+            .find(|s| !route.iter().any(|h| h.name == s.entity))
+        {
+            return Err(invalid_input(format!(
+                "`{}` is not on the route to `{}`",
+                stage.entity, self.video
+            )));
+        }
+        Ok(route)
+    }
+
+    /// Enable the route found by [`Pipeline::route`], disabling other links
+    /// into the same pads, then set every pad format along it and the
+    /// capture format of the video node, and check the result with
+    /// [`validate`].
+    ///
+    /// `open` gives a handle for the device node of a hop; see [`open_node`].
+    pub fn configure<'a, M, F>(&self, media: &MediaDevice<M>, mut open: F) -> io::Result<Vec<Hop>>
+    where
+        M: Ioctl,
+        F: FnMut(&Hop) -> io::Result<Box<dyn Ioctl + 'a>>,
+    {
+        let mut topology = topology::topology(media)?;
+        let route = self.route(&topology)?;
+
+        for id in route.iter().filter_map(|h| h.link) {
+            let Some(link) = topology.link(id).copied() else {
+                continue;
+            };
+            if link.is_enabled() {
+                continue;
+            }
+            let others: Vec<u32> = topology
+                .links
+                .iter()
+                .filter(|l| l.is_data_link() && l.sink_id == link.sink_id && l.id != id)
+                .filter(|l| l.is_enabled() && !l.is_immutable())
+                .map(|l| l.id)
+                .collect();
+            for other in others {
+                topology::setup_link(media, &mut topology, other, false)?;
+            }
+            topology::setup_link(media, &mut topology, id, true)?;
+        }
+
+        let devs = route
+            .iter()
+            .map(&mut open)
+            .collect::<io::Result<Vec<_>>>()?;
+        let last = route.len() - 1;
+        let mut upstream: Option<MbusFormat> = None;
+        for (i, hop) in route[..last].iter().enumerate() {
+            let sd = Subdev::new(&*devs[i]);
+            let stage = self.stage(&hop.name);
+            let crop = stage.and_then(|s| s.crop);
+            if let (Some(pad), Some(format)) = (hop.sink, upstream) {
+                let got = sd.set_format(pad, Which::Active, &format)?;
+                if (got.code, got.width, got.height) != (format.code, format.width, format.height) {
+                    return Err(LinkError {
+                        source: route[i - 1].name.clone(),
+                        sink: hop.name.clone(),
+                        message: format!(
+                            "sink pad takes {} instead of {}",
+                            describe(&got),
+                            describe(&format)
+                        ),
+                    }
+                    .into());
+                }
+                if let Some(rect) = crop {
+                    sd.set_selection(pad, crate::V4L2_SEL_TGT_CROP, Which::Active, rect, 0)?;
+                }
+            }
+            let Some(pad) = hop.source else {
+                continue;
+            };
+            let mut want = sd.format(pad, Which::Active)?;
+            if let Some(code) = stage.and_then(|s| s.code) {
+                want.code = code;
+            }
+            if hop.sink.is_none() {
+                // A sensor: pick what it should capture
+                if stage.and_then(|s| s.code).is_none() {
+                    want.code = self.sensor_code(&sd, pad)?.unwrap_or(want.code);
+                }
+                (want.width, want.height) = match stage.and_then(|s| s.size) {
+                    Some(size) => size,
+                    None => self.sensor_size(&sd, pad, want.code)?,
+                };
+                if let Some(rect) = crop {
+                    sd.set_selection(pad, crate::V4L2_SEL_TGT_CROP, Which::Active, rect, 0)?;
+                }
+            } else if let Some(size) = stage.and_then(|s| s.size) {
+                (want.width, want.height) = size;
+            } else if i + 1 == last {
+                (want.width, want.height) = (self.width, self.height);
+            }
+            upstream = Some(sd.set_format(pad, Which::Active, &want)?);
+        }
+
+        let (fourcc, width, height) =
+            set_video_format(&*devs[last], self.fourcc, self.width, self.height)?;
+        if (fourcc, width, height) != (self.fourcc, self.width, self.height) {
+            return Err(invalid_input(format!(
+                "`{}` takes {} {}x{} instead of {} {}x{}",
+                self.video,
+                fourcc_name(fourcc),
+                width,
+                height,
+                fourcc_name(self.fourcc),
+                self.width,
+                self.height
+            )));
+        }
+        validate_with(&route, &devs)?;
+        Ok(route)
+    }
+
+    /// [`Pipeline::configure`] on real hardware, opening the nodes the media
+    /// device `media` lists
+    pub fn apply(&self, media: &MediaDevice) -> io::Result<Vec<Hop>> {
+        let topology = topology::topology(media)?;
+        self.configure(media, |hop| open_node(&topology, hop))
+    }
+
+    /// A sensor code carrying the output format as is, for raw capture
+    fn sensor_code<D: Ioctl>(&self, sd: &Subdev<D>, pad: u32) -> io::Result<Option<u32>> {
+        let codes = sd.mbus_codes(pad, Which::Active)?;
+        Ok(codes.into_iter().find(|&c| {
+            mbus::info(c)
+                .is_some_and(|i| i.fourcc == Some(self.fourcc) || i.packed == Some(self.fourcc))
+        }))
+    }
+
+    /// The smallest sensor size covering the output, or the largest one
+    fn sensor_size<D: Ioctl>(&self, sd: &Subdev<D>, pad: u32, code: u32) -> io::Result<(u32, u32)> {
+        let candidates: Vec<(u32, u32)> = sd
+            .frame_sizes(pad, code, Which::Active)?
+            .iter()
+            .map(|s| {
+                (
+                    self.width.clamp(s.min_width, s.max_width),
+                    self.height.clamp(s.min_height, s.max_height),
+                )
+            })
+            .collect();
+        let area = |&(w, h): &(u32, u32)| w as u64 * h as u64;
+        let covering = candidates
+            .iter()
+            .filter(|&&(w, h)| w >= self.width && h >= self.height)
+            .min_by_key(|s| area(s));
+        Ok(covering
+            .or_else(|| candidates.iter().max_by_key(|s| area(s)))
+            .copied()
+            .unwrap_or((self.width, self.height)))
+    }
+
+    /// Read the YAML form written by [`Pipeline::to_yaml`]
+    pub fn parse(text: &str) -> Result<Pipeline, ParseError> {
+        let node = yaml::parse(text)?;
+        let top = node.map(1)?;
+        let mut video = None;
+        let mut format = None;
+        let mut entities = Vec::new();
+        for (key, line, value) in top {
+            match key.as_str() {
+                "video" => video = Some(value.string(*line)?.to_string()),
+                "format" => {
+                    let (mut fourcc, mut width, mut height) = (None, None, None);
+                    for (key, line, value) in value.map(*line)? {
+                        match key.as_str() {
+                            "fourcc" => fourcc = Some(parse_fourcc(value.string(*line)?, *line)?),
+                            "width" => width = Some(value.number(*line)?),
+                            "height" => height = Some(value.number(*line)?),
+                            _ => return Err(unknown_key(key, *line)),
+                        }
+                    }
+                    let missing = |what: &str| ParseError {
+                        line: *line,
+                        message: format!("missing `{}`", what),
+                    };
+                    format = Some((
+                        fourcc.ok_or_else(|| missing("fourcc"))?,
+                        width.ok_or_else(|| missing("width"))?,
+                        height.ok_or_else(|| missing("height"))?,
+                    ));
+                }
+                "entities" => {
+                    for (line, item) in value.list(*line)? {
+                        entities.push(parse_stage(item, *line)?);
+                    }
+                }
+                _ => return Err(unknown_key(key, *line)),
+            }
+        }
+        let missing = |what: &str| ParseError {
+            line: 1,
+            message: format!("missing `{}`", what),
+        };
+        let video = video.ok_or_else(|| missing("video"))?;
+        let (fourcc, width, height) = format.ok_or_else(|| missing("format"))?;
+        Ok(Pipeline {
+            video,
+            fourcc,
+            width,
+            height,
+            entities,
+        })
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut out = format!("video: {}\n", quote(&self.video));
+        out.push_str(&format!(
+            "format:\n  fourcc: {}\n  width: {}\n  height: {}\n",
+            quote(&fourcc_name(self.fourcc)),
+            self.width,
+            self.height
+        ));
+        if !self.entities.is_empty() {
+            out.push_str("entities:\n");
+        }
+        for stage in &self.entities {
+            out.push_str(&format!("  - entity: {}\n", quote(&stage.entity)));
+            if let Some(code) = stage.code {
+                match mbus::name(code) {
+                    Some(name) => out.push_str(&format!("    code: {}\n", name)),
+                    None => out.push_str(&format!("    code: {:#x}\n", code)),
+                }
+            }
+            if let Some((w, h)) = stage.size {
+                out.push_str(&format!("    size: [{}, {}]\n", w, h));
+            }
+            if let Some(r) = stage.crop {
+                out.push_str(&format!(
+                    "    crop: [{}, {}, {}, {}]\n",
+                    r.left, r.top, r.width, r.height
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Pipeline> {
+        let text = fs::read_to_string(path)?;
+        Pipeline::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_yaml())
+    }
+}
+
+/// Open the device node of `hop` as listed in `topology`: video nodes
+/// through libv4l2, sub-device nodes with a plain descriptor since libv4l2
+/// issues VIDIOC_QUERYCAP on open, which sub-devices do not implement
+pub fn open_node(topology: &Topology, hop: &Hop) -> io::Result<Box<dyn Ioctl>> {
+    let path = topology.video_node(hop.entity)?;
+    let is_subdev = topology
+        .interfaces(hop.entity)
+        .next()
+        .is_some_and(Interface::is_subdev);
+    if is_subdev {
+        Ok(Box::new(Subdev::open(path)?))
+    } else {
+        Ok(Box::new(Device::open(path)?))
+    }
+}
+
+/// Check every link of `route` the way the kernel does at STREAMON: code,
+/// size and field must match on both ends. For the video node only raw
+/// (Bayer) codes have to match the pixel format, as DMA engines commonly
+/// convert YUV.
+pub fn validate<'a, F>(route: &[Hop], open: F) -> io::Result<()>
+where
+    F: FnMut(&Hop) -> io::Result<Box<dyn Ioctl + 'a>>,
+{
+    let devs = route.iter().map(open).collect::<io::Result<Vec<_>>>()?;
+    validate_with(route, &devs)
+}
+
+fn validate_with(route: &[Hop], devs: &[Box<dyn Ioctl + '_>]) -> io::Result<()> {
+    let last = route.len() - 1;
+    for i in 1..route.len() {
+        let (from, to) = (&route[i - 1], &route[i]);
+        let (Some(source_pad), Some(sink_pad)) = (from.source, to.sink) else {
+            continue;
+        };
+        let source = Subdev::new(&*devs[i - 1]).format(source_pad, Which::Active)?;
+        let error = |message: String| LinkError {
+            source: from.name.clone(),
+            sink: to.name.clone(),
+            message,
+        };
+        if i == last {
+            let (fourcc, width, height) = video_format(&*devs[i])?;
+            if (width, height) != (source.width, source.height) {
+                return Err(error(format!(
+                    "{} feeds {} {}x{}",
+                    describe(&source),
+                    fourcc_name(fourcc),
+                    width,
+                    height
+                ))
+                .into());
+            }
+            let raw = mbus::info(source.code).filter(|i| i.packed.is_some());
+            if raw.is_some_and(|i| i.fourcc != Some(fourcc) && i.packed != Some(fourcc)) {
+                return Err(error(format!(
+                    "{} cannot be stored as {}",
+                    describe(&source),
+                    fourcc_name(fourcc)
+                ))
+                .into());
+            }
+            continue;
+        }
+        let sink = Subdev::new(&*devs[i]).format(sink_pad, Which::Active)?;
+        if (source.code, source.width, source.height, source.field)
+            != (sink.code, sink.width, sink.height, sink.field)
+        {
+            return Err(error(format!("{} vs {}", describe(&source), describe(&sink))).into());
+        }
+    }
+    Ok(())
+}
+
+/// VIDIOC_S_FMT on a capture node, single- or multi-planar; returns the
+/// fourcc and size the driver chose
+fn set_video_format<D: Ioctl + ?Sized>(
+    dev: &D,
+    fourcc: u32,
+    width: u32,
+    height: u32,
+) -> io::Result<(u32, u32, u32)> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    f.fmt.pix.pixelformat = fourcc;
+    f.fmt.pix.width = width;
+    f.fmt.pix.height = height;
+    f.fmt.pix.field = crate::v4l2_field_V4L2_FIELD_NONE;
+    match ioctl(dev, codes::VIDIOC_S_FMT, &mut f) {
+        Ok(()) => {
+            let pix = unsafe { f.fmt.pix };
+            return Ok((pix.pixelformat, pix.width, pix.height));
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => (),
+        Err(e) => return Err(e),
+    }
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+    f.fmt.pix_mp.pixelformat = fourcc;
+    f.fmt.pix_mp.width = width;
+    f.fmt.pix_mp.height = height;
+    f.fmt.pix_mp.field = crate::v4l2_field_V4L2_FIELD_NONE;
+    ioctl(dev, codes::VIDIOC_S_FMT, &mut f)?;
+    let pix = unsafe { f.fmt.pix_mp };
+    Ok((pix.pixelformat, pix.width, pix.height))
+}
+
+/// VIDIOC_G_FMT counterpart of [`set_video_format`]
+fn video_format<D: Ioctl + ?Sized>(dev: &D) -> io::Result<(u32, u32, u32)> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    match ioctl(dev, codes::VIDIOC_G_FMT, &mut f) {
+        Ok(()) => {
+            let pix = unsafe { f.fmt.pix };
+            return Ok((pix.pixelformat, pix.width, pix.height));
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => (),
+        Err(e) => return Err(e),
+    }
+    f.type_ = crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+    ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+    let pix = unsafe { f.fmt.pix_mp };
+    Ok((pix.pixelformat, pix.width, pix.height))
+}
+
+/// Error from [`Pipeline::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+fn unknown_key(key: &str, line: usize) -> ParseError {
+    ParseError {
+        line,
+        message: format!("unknown key `{}`", key),
+    }
+}
+
+fn parse_fourcc(s: &str, line: usize) -> Result<u32, ParseError> {
+    if s.is_empty() || s.len() > 4 || !s.is_ascii() {
+        return Err(ParseError {
+            line,
+            message: format!("invalid fourcc `{}`", s),
+        });
+    }
+    let mut bytes = [b' '; 4];
+    bytes[..s.len()].copy_from_slice(s.as_bytes());
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn parse_stage(node: &yaml::Node, line: usize) -> Result<Stage, ParseError> {
+    let mut stage = Stage::default();
+    for (key, line, value) in node.map(line)? {
+        let line = *line;
+        match key.as_str() {
+            "entity" => stage.entity = value.string(line)?.to_string(),
+            "code" => {
+                let name = value.string(line)?;
+                let code = match mbus::from_name(name) {
+                    Some(code) => code,
+                    None => value.number(line)?,
+                };
+                stage.code = Some(code);
+            }
+            "size" => match value.numbers(line)?.as_slice() {
+                &[w, h] => stage.size = Some((w, h)),
+                _ => {
+                    return Err(ParseError {
+                        line,
+                        message: "size is [width, height]".into(),
+                    })
+                }
+            },
+            "crop" => match value.numbers(line)?.as_slice() {
+                &[left, top, w, h] => {
+                    stage.crop = Some(Rect::new(left as i32, top as i32, w, h));
+                }
+                _ => {
+                    return Err(ParseError {
+                        line,
+                        message: "crop is [left, top, width, height]".into(),
+                    })
+                }
+            },
+            _ => return Err(unknown_key(key, line)),
+        }
+    }
+    if stage.entity.is_empty() {
+        return Err(ParseError {
+            line,
+            message: "missing `entity`".into(),
+        });
+    }
+    Ok(stage)
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Block mappings, block sequences, flow sequences of scalars, plain and
+/// quoted scalars and `#` comments; enough for pipeline files.
+mod yaml {
+    use super::ParseError;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Node {
+        Scalar(String),
+        /// Items with their line numbers
+        List(Vec<(usize, Node)>),
+        /// Entries with their line numbers, in file order
+        Map(Vec<(String, usize, Node)>),
+    }
+
+    fn error(line: usize, message: &str) -> ParseError {
+        ParseError {
+            line,
+            message: message.into(),
+        }
+    }
+
+    impl Node {
+        pub fn map(&self, line: usize) -> Result<&[(String, usize, Node)], ParseError> {
+            match self {
+                Node::Map(entries) => Ok(entries),
+                _ => Err(error(line, "expected a mapping")),
+            }
+        }
+
+        pub fn list(&self, line: usize) -> Result<&[(usize, Node)], ParseError> {
+            match self {
+                Node::List(items) => Ok(items),
+                _ => Err(error(line, "expected a list")),
+            }
+        }
+
+        pub fn string(&self, line: usize) -> Result<&str, ParseError> {
+            match self {
+                Node::Scalar(s) => Ok(s),
+                _ => Err(error(line, "expected a value")),
+            }
+        }
+
+        pub fn number(&self, line: usize) -> Result<u32, ParseError> {
+            let s = self.string(line)?;
+            match s.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => s.parse(),
+            }
+            .map_err(|_| ParseError {
+                line,
+                message: format!("invalid number `{}`", s),
+            })
+        }
+
+        pub fn numbers(&self, line: usize) -> Result<Vec<u32>, ParseError> {
+            self.list(line)?
+                .iter()
+                .map(|(line, n)| n.number(*line))
+                .collect()
+        }
+    }
+
+    struct Line {
+        indent: usize,
+        number: usize,
+        text: String,
+    }
+
+    /// Strip a trailing `# comment` that is not inside quotes
+    fn strip_comment(line: &str) -> &str {
+        let mut quote = None;
+        let mut prev = ' ';
+        for (i, c) in line.char_indices() {
+            match (quote, c) {
+                (None, '#') if prev.is_whitespace() => return &line[..i],
+                (None, '"' | '\'') => quote = Some(c),
+                (Some('"'), '\\') if prev == '\\' => {
+                    prev = ' ';
+                    continue;
+                }
+                (Some(q), c) if c == q && !(q == '"' && prev == '\\') => quote = None,
+                _ => (),
+            }
+            prev = c;
+        }
+        line
+    }
+
+    fn is_item(text: &str) -> bool {
+        text == "-" || text.starts_with("- ")
+    }
+
+    /// `key: value` or `key:`; the value may be empty
+    fn split_entry(text: &str) -> Option<(String, &str)> {
+        if text.starts_with('"') || text.starts_with('\'') || text.starts_with('[') {
+            let (key, rest) = quoted(text)?;
+            let value = rest.strip_prefix(':')?;
+            if !(value.is_empty() || value.starts_with(' ')) {
+                return None;
+            }
+            return Some((key, value.trim()));
+        }
+        let bytes = text.as_bytes();
+        let i = (0..bytes.len())
+            .find(|&i| bytes[i] == b':' && (i + 1 == bytes.len() || bytes[i + 1] == b' '))?;
+        Some((text[..i].trim().to_string(), text[i + 1..].trim()))
+    }
+
+    /// A quoted string at the start of `text` and what follows it
+    fn quoted(text: &str) -> Option<(String, &str)> {
+        let q = text.chars().next()?;
+        if q != '"' && q != '\'' {
+            return None;
+        }
+        let mut s = String::new();
+        let mut chars = text[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' if q == '"' => s.push(chars.next()?.1),
+                c if c == q => {
+                    // '' is a literal quote in single-quoted strings
+                    if q == '\'' && text[1 + i + 1..].starts_with('\'') {
+                        chars.next();
+                        s.push('\'');
+                        continue;
+                    }
+                    return Some((s, &text[1 + i + 1..]));
+                }
+                c => s.push(c),
+            }
+        }
+        None
+    }
+
+    fn scalar(text: &str, line: usize) -> Result<Node, ParseError> {
+        if let Some(inner) = text.strip_prefix('[') {
+            let inner = inner
+                .strip_suffix(']')
+                .ok_or_else(|| error(line, "unterminated `[`"))?;
+            if inner.trim().is_empty() {
+                return Ok(Node::List(Vec::new()));
+            }
+            return inner
+                .split(',')
+                .map(|item| scalar(item.trim(), line).map(|n| (line, n)))
+                .collect::<Result<_, _>>()
+                .map(Node::List);
+        }
+        if text.starts_with('"') || text.starts_with('\'') {
+            let (s, rest) = quoted(text).ok_or_else(|| error(line, "unterminated string"))?;
+            if !rest.trim().is_empty() {
+                return Err(error(line, "text after closing quote"));
+            }
+            return Ok(Node::Scalar(s));
+        }
+        Ok(Node::Scalar(text.to_string()))
+    }
+
+    struct Parser {
+        lines: Vec<Line>,
+        pos: usize,
+    }
+
+    impl Parser {
+        fn block(&mut self, indent: usize) -> Result<Node, ParseError> {
+            if is_item(&self.lines[self.pos].text) {
+                self.list(indent)
+            } else {
+                self.map(indent)
+            }
+        }
+
+        fn list(&mut self, indent: usize) -> Result<Node, ParseError> {
+            let mut items = Vec::new();
+            while let Some(line) = self.lines.get(self.pos) {
+                if line.indent < indent || !is_item(&line.text) {
+                    break;
+                }
+                if line.indent > indent {
+                    return Err(error(line.number, "unexpected indentation"));
+                }
+                let number = line.number;
+                let rest = line.text[1..].trim_start();
+                if rest.is_empty() {
+                    self.pos += 1;
+                    match self.lines.get(self.pos) {
+                        Some(next) if next.indent > indent => {
+                            let node = self.block(next.indent)?;
+                            items.push((number, node));
+                        }
+                        _ => items.push((number, Node::Scalar(String::new()))),
+                    }
+                } else if split_entry(rest).is_some() {
+                    // A mapping starting on the item line, aligned after "- "
+                    let column = indent + line.text.len() - rest.len();
+                    let text = rest.to_string();
+                    self.lines[self.pos] = Line {
+                        indent: column,
+                        number,
+                        text,
+                    };
+                    let node = self.map(column)?;
+                    items.push((number, node));
+                } else {
+                    items.push((number, scalar(rest, number)?));
+                    self.pos += 1;
+                }
+            }
+            Ok(Node::List(items))
+        }
+
+        fn map(&mut self, indent: usize) -> Result<Node, ParseError> {
+            let mut entries: Vec<(String, usize, Node)> = Vec::new();
+            while let Some(line) = self.lines.get(self.pos) {
+                if line.indent < indent {
+                    break;
+                }
+                let number = line.number;
+                if line.indent > indent {
+                    return Err(error(number, "unexpected indentation"));
+                }
+                if is_item(&line.text) {
+                    return Err(error(number, "unexpected list item"));
+                }
+                let (key, value) = split_entry(&line.text)
+                    .ok_or_else(|| error(number, "expected `key: value`"))?;
+                if entries.iter().any(|(k, _, _)| *k == key) {
+                    return Err(ParseError {
+                        line: number,
+                        message: format!("duplicate key `{}`", key),
+                    });
+                }
+                let value = if value.is_empty() {
+                    None
+                } else {
+                    Some(scalar(value, number)?)
+                };
+                self.pos += 1;
+                let node = match (value, self.lines.get(self.pos)) {
+                    (Some(node), _) => node,
+                    (None, Some(next)) if next.indent > indent => self.block(next.indent)?,
+                    // Lists may sit at the indentation of their key
+                    (None, Some(next)) if next.indent == indent && is_item(&next.text) => {
+                        self.list(indent)?
+                    }
+                    (None, _) => Node::Scalar(String::new()),
+                };
+                entries.push((key, number, node));
+            }
+            Ok(Node::Map(entries))
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Node, ParseError> {
+        let mut lines = Vec::new();
+        for (n, raw) in text.lines().enumerate() {
+            let content = strip_comment(raw).trim_end();
+            let trimmed = content.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let indent = content.len() - trimmed.len();
+            if content[..indent].contains('\t') {
+                return Err(error(n + 1, "tabs are not allowed in indentation"));
+            }
+            lines.push(Line {
+                indent,
+                number: n + 1,
+                text: trimmed.to_string(),
+            });
+        }
+        if lines.is_empty() {
+            return Ok(Node::Map(Vec::new()));
+        }
+        let mut parser = Parser { lines, pos: 0 };
+        let indent = parser.lines[0].indent;
+        let node = parser.block(indent)?;
+        if let Some(line) = parser.lines.get(parser.pos) {
+            return Err(error(line.number, "unexpected indentation"));
+        }
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::{FakeDevice, FakeSubdev};
+    use crate::pixel_format::V4L2_PIX_FMT_NV12;
+
+    const GRAPH: &str = r#"
+version 1
+entity id=1 function=0x20001 name="imx219 1-0010"
+entity id=4 function=0x4009 name="rkisp1_isp"
+entity id=8 function=0x10001 name="rkisp1_mainpath"
+pad id=2 entity=1 index=0 flags=0x2
+pad id=5 entity=4 index=0 flags=0x1
+pad id=6 entity=4 index=2 flags=0x2
+pad id=9 entity=8 index=0 flags=0x1
+link id=3 source=2 sink=5 flags=0x3
+link id=7 source=6 sink=9
+"#;
+
+    const PIPELINE: &str = r#"
+# imx219 cropped to 720p on the rkisp1 main path
+video: rkisp1_mainpath
+format:
+  fourcc: "NV12"   # semi-planar
+  width: 1280
+  height: 720
+entities:
+- entity: "imx219 1-0010"
+  code: SRGGB10_1X10
+  size: [1920, 1080]
+- entity: rkisp1_isp
+  crop: [320, 180, 1280, 720]
+  code: YUYV8_2X8
+"#;
+
+    #[test]
+    fn yaml() {
+        let pipeline = Pipeline::parse(PIPELINE).unwrap();
+        assert_eq!(pipeline.video, "rkisp1_mainpath");
+        assert_eq!(
+            (pipeline.fourcc, pipeline.width, pipeline.height),
+            (V4L2_PIX_FMT_NV12, 1280, 720)
+        );
+        let isp = pipeline.stage("rkisp1_isp").unwrap();
+        assert_eq!(isp.crop, Some(Rect::new(320, 180, 1280, 720)));
+        assert_eq!(isp.code, Some(crate::MEDIA_BUS_FMT_YUYV8_2X8));
+        assert_eq!(pipeline.entities[0].size, Some((1920, 1080)));
+        assert_eq!(Pipeline::parse(&pipeline.to_yaml()).unwrap(), pipeline);
+
+        let err = Pipeline::parse("video: x\nformat:\n  fourcc: NV12\n  widht: 1\n").unwrap_err();
+        assert_eq!((err.line, err.message.as_str()), (4, "unknown key `widht`"));
+        let err = Pipeline::parse("video: x\n  format: 1\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn configure() {
+        let fake = FakeDevice::default();
+        *fake.media.borrow_mut() = Some(Topology::parse(GRAPH).unwrap());
+        let media = MediaDevice::new(&fake);
+        let sensor = FakeSubdev::new(
+            &[crate::MEDIA_BUS_FMT_SRGGB10_1X10],
+            (3280, 2464),
+            &[crate::MEDIA_PAD_FL_SOURCE],
+        );
+        let isp = FakeSubdev::new(
+            &[
+                crate::MEDIA_BUS_FMT_SRGGB10_1X10,
+                crate::MEDIA_BUS_FMT_YUYV8_2X8,
+            ],
+            (4032, 3024),
+            &[crate::MEDIA_PAD_FL_SINK, 0, crate::MEDIA_PAD_FL_SOURCE],
+        );
+        let video = FakeDevice::default();
+        let open = |hop: &Hop| -> io::Result<Box<dyn Ioctl + '_>> {
+            Ok(match hop.entity {
+                1 => Box::new(&sensor),
+                4 => Box::new(&isp),
+                _ => Box::new(&video),
+            })
+        };
+
+        let pipeline = Pipeline::parse(PIPELINE).unwrap();
+        let route = pipeline.configure(&media, open).unwrap();
+        let names: Vec<_> = route.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, ["imx219 1-0010", "rkisp1_isp", "rkisp1_mainpath"]);
+        assert_eq!((route[1].sink, route[1].source), (Some(0), Some(2)));
+        let graph = fake.media.borrow().clone().unwrap();
+        assert!(graph.link(7).unwrap().is_enabled());
+
+        let sink = Subdev::new(&isp).format(0, Which::Active).unwrap();
+        assert_eq!((sink.width, sink.height), (1920, 1080));
+        let source = Subdev::new(&isp).format(2, Which::Active).unwrap();
+        assert_eq!(source.code, crate::MEDIA_BUS_FMT_YUYV8_2X8);
+        assert_eq!((source.width, source.height), (1280, 720));
+
+        // Without settings the sensor is run at the output size
+        let plain = Pipeline::new("rkisp1_mainpath", V4L2_PIX_FMT_NV12, 640, 480);
+        plain.configure(&media, open).unwrap();
+        let f = Subdev::new(&sensor).format(0, Which::Active).unwrap();
+        assert_eq!((f.width, f.height), (640, 480));
+
+        // Someone changes the sensor behind our back
+        let f = MbusFormat::new(crate::MEDIA_BUS_FMT_SRGGB10_1X10, 800, 600);
+        Subdev::new(&sensor)
+            .set_format(0, Which::Active, &f)
+            .unwrap();
+        let err = validate(&route, open).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        let link = link_error(&err).unwrap();
+        assert_eq!(
+            (link.source.as_str(), link.sink.as_str()),
+            ("imx219 1-0010", "rkisp1_isp")
+        );
+        assert_eq!(link.message, "SRGGB10_1X10 800x600 vs SRGGB10_1X10 640x480");
+
+        let stray = Pipeline {
+            entities: vec![Stage {
+                entity: "rkisp1_selfpath".into(),
+                ..Default::default()
+            }],
+            ..plain
+        };
+        let err = stray.route(&graph).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,259 @@
+//! Access priority (VIDIOC_G_PRIORITY / VIDIOC_S_PRIORITY)
+//!
+//! Every open handle has a priority. While one handle holds a higher
+//! priority, the others get EBUSY from ioctls that change the device state;
+//! [`Device`](crate::device::Device) reports that case as a [`PriorityError`].
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-g-priority.html
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+
+/// Typed `enum v4l2_priority`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    Unset,
+    /// Lowest, for background monitoring
+    Background,
+    /// What new handles get, for applications a user controls
+    #[default]
+    Interactive,
+    /// Highest, for recording that must not be disturbed
+    Record,
+}
+
+impl Priority {
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            crate::v4l2_priority_V4L2_PRIORITY_UNSET => Some(Priority::Unset),
+            crate::v4l2_priority_V4L2_PRIORITY_BACKGROUND => Some(Priority::Background),
+            crate::v4l2_priority_V4L2_PRIORITY_INTERACTIVE => Some(Priority::Interactive),
+            crate::v4l2_priority_V4L2_PRIORITY_RECORD => Some(Priority::Record),
+            _ => None,
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            Priority::Unset => crate::v4l2_priority_V4L2_PRIORITY_UNSET,
+            Priority::Background => crate::v4l2_priority_V4L2_PRIORITY_BACKGROUND,
+            Priority::Interactive => crate::v4l2_priority_V4L2_PRIORITY_INTERACTIVE,
+            Priority::Record => crate::v4l2_priority_V4L2_PRIORITY_RECORD,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Unset => "unset",
+            Priority::Background => "background",
+            Priority::Interactive => "interactive",
+            Priority::Record => "record",
+        })
+    }
+}
+
+/// EBUSY because another handle holds a higher priority than this one.
+///
+/// Carried inside the `io::Error` returned by the failing call, see
+/// [`priority_error`]; [`raw_os_error`] still reports EBUSY for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityError {
+    /// Highest priority held on the device
+    pub held: Priority,
+    /// Priority of the handle that got EBUSY
+    pub own: Priority,
+}
+
+impl fmt::Display for PriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "device is held at {} priority, above this handle's {}",
+            self.held, self.own
+        )
+    }
+}
+
+impl error::Error for PriorityError {}
+
+impl From<PriorityError> for io::Error {
+    fn from(e: PriorityError) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, e)
+    }
+}
+
+/// The [`PriorityError`] inside `err`, if that is why the call failed
+pub fn priority_error(err: &io::Error) -> Option<&PriorityError> {
+    err.get_ref()?.downcast_ref()
+}
+
+pub fn is_priority_busy(err: &io::Error) -> bool {
+    priority_error(err).is_some()
+}
+
+/// errno of `err`, EBUSY for a [`PriorityError`]
+pub fn raw_os_error(err: &io::Error) -> Option<i32> {
+    err.raw_os_error()
+        .or_else(|| priority_error(err).map(|_| libc::EBUSY))
+}
+
+/// VIDIOC_G_PRIORITY: the highest priority of all handles on the device
+pub fn priority<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Priority> {
+    let mut raw: crate::__u32 = 0;
+    ioctl(dev, codes::VIDIOC_G_PRIORITY, &mut raw)?;
+    Priority::from_raw(raw)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown priority"))
+}
+
+/// VIDIOC_S_PRIORITY for the handle `dev`. Fails with EBUSY, a
+/// [`PriorityError`] on a [`Device`](crate::device::Device), while another
+/// handle holds a higher priority than this one.
+pub fn set_priority<D: Ioctl + ?Sized>(dev: &D, priority: Priority) -> io::Result<()> {
+    let mut raw = priority.as_raw();
+    ioctl(dev, codes::VIDIOC_S_PRIORITY, &mut raw)
+}
+
+/// The priority held on the device if it is above `own`, i.e. whether an
+/// EBUSY from a handle at `own` may be down to another application. EBUSY
+/// has other causes too, such as a grabbed control or streaming I/O.
+pub fn held_above<D: Ioctl + ?Sized>(dev: &D, own: Priority) -> io::Result<Option<Priority>> {
+    let held = priority(dev)?;
+    Ok(Some(held).filter(|&held| held > own))
+}
+
+/// Turn an EBUSY seen by a handle at `own` into a [`PriorityError`] if
+/// [`held_above`] finds a higher priority. Other errors, and EBUSY with
+/// nothing held above, e.g. from streaming I/O, are returned as they are.
+pub fn busy_error<D: Ioctl + ?Sized>(dev: &D, own: Priority, err: io::Error) -> io::Error {
+    if err.raw_os_error() != Some(libc::EBUSY) {
+        return err;
+    }
+    match held_above(dev, own) {
+        Ok(Some(held)) => PriorityError { held, own }.into(),
+        _ => err,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::control;
+    use crate::fake::{FakeControl, FakeDevice};
+    use crate::input;
+    use crate::profile::{Outcome, Profile, SkipReason};
+    use crate::standard::{detect_std, VideoStandard};
+    use std::time::Duration;
+
+    /// A handle at `.1` that reports EBUSY the way [`Device`] does
+    ///
+    /// [`Device`]: crate::device::Device
+    struct Handle<'a>(&'a FakeDevice, Priority);
+
+    impl Ioctl for Handle<'_> {
+        unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+            self.0.ioctl(request, arg).map_err(|e| match request {
+                codes::VIDIOC_G_PRIORITY => e,
+                _ => busy_error(self.0, self.1, e),
+            })
+        }
+    }
+
+    #[test]
+    fn record_priority() {
+        assert!(Priority::Record > Priority::Interactive);
+        assert_eq!(Priority::from_raw(3), Some(Priority::Record));
+        assert_eq!(Priority::from_raw(7), None);
+
+        let dev = FakeDevice::default();
+        dev.inputs.borrow_mut().push(("Camera", Default::default()));
+        assert_eq!(priority(&dev).unwrap(), Priority::Interactive);
+        set_priority(&dev, Priority::Record).unwrap();
+        assert_eq!(priority(&dev).unwrap(), Priority::Record);
+        set_priority(&dev, Priority::Interactive).unwrap();
+
+        // Another application starts recording
+        dev.other_priority
+            .set(crate::v4l2_priority_V4L2_PRIORITY_RECORD);
+        let err = input::set_input(&dev, 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+        assert_eq!(
+            held_above(&dev, Priority::Interactive).unwrap(),
+            Some(Priority::Record)
+        );
+        assert_eq!(held_above(&dev, Priority::Record).unwrap(), None);
+        let err = set_priority(&dev, Priority::Record).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+    }
+
+    #[test]
+    fn busy_paths_see_ebusy() {
+        let dev = FakeDevice::with_controls(vec![FakeControl::integer(
+            crate::V4L2_CID_GAIN,
+            "Gain",
+            0,
+            255,
+            32,
+        )]);
+        dev.other_priority
+            .set(crate::v4l2_priority_V4L2_PRIORITY_RECORD);
+
+        // A profile skips what it cannot write rather than failing
+        let profile = Profile::parse("[controls]\ngain = 64\n").unwrap();
+        let handle = Handle(&dev, Priority::Interactive);
+        let report = profile.apply(&handle).unwrap();
+        assert!(matches!(
+            report.results[0].1,
+            Outcome::Skipped(SkipReason::Grabbed)
+        ));
+        assert_eq!(dev.value(crate::V4L2_CID_GAIN), Some(32));
+
+        // and standard detection retries through a busy receiver
+        dev.query_std
+            .borrow_mut()
+            .extend([Err(libc::EBUSY), Ok(VideoStandard::PAL_BG.bits())]);
+        assert_eq!(
+            detect_std(&handle, 2, Duration::ZERO).unwrap(),
+            VideoStandard::PAL_BG
+        );
+    }
+
+    #[test]
+    fn priority_busy_error() {
+        let dev = FakeDevice::with_controls(vec![
+            FakeControl::integer(crate::V4L2_CID_GAIN, "Gain", 0, 255, 32),
+            FakeControl::integer(crate::V4L2_CID_EXPOSURE, "Exposure", 0, 255, 32)
+                .flags(crate::V4L2_CTRL_FLAG_GRABBED),
+        ]);
+        dev.inputs.borrow_mut().push(("Camera", Default::default()));
+        let handle = Handle(&dev, Priority::Interactive);
+
+        // Grabbed while streaming, with no handle above this one
+        let err = control::set_value(&handle, crate::V4L2_CID_EXPOSURE, 64).unwrap_err();
+        assert!(!is_priority_busy(&err));
+        assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+
+        dev.other_priority
+            .set(crate::v4l2_priority_V4L2_PRIORITY_RECORD);
+        let held = PriorityError {
+            held: Priority::Record,
+            own: Priority::Interactive,
+        };
+        for err in [
+            input::set_input(&handle, 0).unwrap_err(),
+            control::set_value(&handle, crate::V4L2_CID_GAIN, 64).unwrap_err(),
+        ] {
+            assert!(is_priority_busy(&err));
+            assert_eq!(priority_error(&err), Some(&held));
+            assert_eq!(raw_os_error(&err), Some(libc::EBUSY));
+        }
+        assert_eq!(
+            held.to_string(),
+            "device is held at record priority, above this handle's interactive"
+        );
+    }
+}
@@ -0,0 +1,574 @@
+//! Snapshot writable controls to a TOML file and re-apply them
+//!
+//! The file holds one `name = value` line per control under a `[controls]`
+//! table, where `name` is the control's [`canonical_name`]:
+//!
+//! ```toml
+//! [controls]
+//! # Auto Exposure: menu 0..3 (default 3)
+//! auto_exposure = 1
+//! # Exposure Time, Absolute: int 3..2047 (default 250)
+//! exposure_time_absolute = 400
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::control::{self, canonical_name, ControlInfo};
+use crate::device::Ioctl;
+use crate::priority;
+
+/// Value stored for one control
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i64),
+    String(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(v) => write!(f, "{}", v),
+            Value::String(s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Canonical control name, the key in the file
+    pub name: String,
+    pub value: Value,
+    /// Human readable description written as a comment above the entry
+    pub comment: Option<String>,
+}
+
+/// Saved control values of one device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub entries: Vec<Entry>,
+}
+
+/// Why a control in the profile was not written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The device has no control with this name
+    Unknown,
+    ReadOnly,
+    Disabled,
+    /// Another control (usually an auto mode) currently overrides it
+    Inactive,
+    /// Locked by the driver, e.g. while streaming
+    Grabbed,
+    /// Value kind does not match the control type
+    TypeMismatch,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    /// Written; holds the value the driver settled on
+    Applied(Value),
+    Skipped(SkipReason),
+    Failed(io::Error),
+}
+
+/// Result of [`Profile::apply`], one outcome per profile entry in application order
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub results: Vec<(String, Outcome)>,
+}
+
+impl ApplyReport {
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &io::Error)> {
+        self.results.iter().filter_map(|(name, o)| match o {
+            Outcome::Failed(e) => Some((name.as_str(), e)),
+            _ => None,
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+/// Result of [`Profile::capture`]
+#[derive(Debug, Default)]
+pub struct CaptureReport {
+    pub profile: Profile,
+    /// Controls that could not be read, e.g. EACCES from a write-only control
+    /// or EBUSY while the driver holds it
+    pub failures: Vec<(String, io::Error)>,
+}
+
+impl CaptureReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Error from [`Profile::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn is_int_type(type_: u32) -> bool {
+    matches!(
+        type_,
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER
+            | crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BOOLEAN
+            | crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU
+            | crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU
+            | crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BITMASK
+            | crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64
+    )
+}
+
+fn is_savable(info: &ControlInfo) -> bool {
+    (is_int_type(info.type_) || info.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING)
+        && !info.is_array()
+        && info.is_readable()
+        && info.is_writable()
+        && !info.is_disabled()
+        && info.flags & crate::V4L2_CTRL_FLAG_VOLATILE == 0
+}
+
+/// Controls that switch an automatic mode and gate the manual value controls
+fn is_auto_control(info: &ControlInfo) -> bool {
+    matches!(
+        info.id,
+        crate::V4L2_CID_AUTO_WHITE_BALANCE
+            | crate::V4L2_CID_AUTOGAIN
+            | crate::V4L2_CID_HUE_AUTO
+            | crate::V4L2_CID_AUTOBRIGHTNESS
+            | crate::V4L2_CID_EXPOSURE_AUTO
+            | crate::V4L2_CID_EXPOSURE_AUTO_PRIORITY
+            | crate::V4L2_CID_FOCUS_AUTO
+            | crate::V4L2_CID_ISO_SENSITIVITY_AUTO
+            | crate::V4L2_CID_AUTO_N_PRESET_WHITE_BALANCE
+    ) || canonical_name(&info.name).split('_').any(|w| w == "auto")
+}
+
+fn read_value<D: Ioctl + ?Sized>(dev: &D, info: &ControlInfo) -> io::Result<Value> {
+    match info.type_ {
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 => {
+            control::get_value64(dev, info.id).map(Value::Integer)
+        }
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING => {
+            control::get_string(dev, info).map(Value::String)
+        }
+        _ => control::get_value(dev, info.id).map(|v| Value::Integer(v as i64)),
+    }
+}
+
+fn write_value<D: Ioctl + ?Sized>(dev: &D, info: &ControlInfo, value: &Value) -> Outcome {
+    let result = match (value, info.type_) {
+        (Value::Integer(v), crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64) => {
+            control::set_value64(dev, info.id, *v).map(Value::Integer)
+        }
+        (Value::Integer(v), t) if is_int_type(t) => match i32::try_from(*v) {
+            Ok(v) => control::set_value(dev, info.id, v).map(|v| Value::Integer(v as i64)),
+            Err(_) => Err(io::Error::from_raw_os_error(libc::ERANGE)),
+        },
+        (Value::String(s), crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING) => {
+            control::set_string(dev, info.id, s).map(|()| Value::String(s.clone()))
+        }
+        _ => return Outcome::Skipped(SkipReason::TypeMismatch),
+    };
+    match result {
+        Ok(v) => Outcome::Applied(v),
+        Err(ref e) if priority::raw_os_error(e) == Some(libc::EBUSY) => {
+            Outcome::Skipped(SkipReason::Grabbed)
+        }
+        Err(e) => Outcome::Failed(e),
+    }
+}
+
+fn describe(info: &ControlInfo) -> String {
+    let kind = match info.type_ {
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BOOLEAN => "bool",
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU => "menu",
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU => "intmenu",
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_BITMASK => "bitmask",
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 => "int64",
+        crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING => "str",
+        _ => "int",
+    };
+    if info.type_ == crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING {
+        format!("{}: {} max {} chars", info.name, kind, info.maximum)
+    } else {
+        format!(
+            "{}: {} {}..{} (default {})",
+            info.name, kind, info.minimum, info.maximum, info.default_value
+        )
+    }
+}
+
+impl Profile {
+    /// Read every readable and writable scalar control of `dev`.
+    ///
+    /// Only failing to enumerate the device's controls is an error; controls
+    /// that cannot be read are left out of the profile and listed in the
+    /// report.
+    pub fn capture<D: Ioctl + ?Sized>(dev: &D) -> io::Result<CaptureReport> {
+        let mut report = CaptureReport::default();
+        for info in control::query_ext_ctrls(dev)? {
+            if !is_savable(&info) {
+                continue;
+            }
+            let name = canonical_name(&info.name);
+            match read_value(dev, &info) {
+                Ok(value) => report.profile.entries.push(Entry {
+                    name,
+                    value,
+                    comment: Some(describe(&info)),
+                }),
+                Err(e) => report.failures.push((name, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| &e.value)
+    }
+
+    /// Write the profile back to `dev`.
+    ///
+    /// Auto-mode controls are applied first, and every control's flags are
+    /// re-queried right before it is written, so manual values that an auto
+    /// mode still overrides are skipped as [`SkipReason::Inactive`]. Only
+    /// failing to enumerate the device's controls is an error; per-control
+    /// problems end up in the report.
+    pub fn apply<D: Ioctl + ?Sized>(&self, dev: &D) -> io::Result<ApplyReport> {
+        let controls = control::query_ext_ctrls(dev)?;
+        let mut pending: Vec<(&Entry, Option<&ControlInfo>)> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let info = controls
+                    .iter()
+                    .find(|c| !c.is_class() && canonical_name(&c.name) == e.name);
+                (e, info)
+            })
+            .collect();
+        pending.sort_by_key(|(_, info)| !info.is_some_and(is_auto_control));
+
+        let mut report = ApplyReport::default();
+        for (entry, info) in pending {
+            let outcome = match info {
+                None => Outcome::Skipped(SkipReason::Unknown),
+                Some(info) => match control::query_ext_ctrl(dev, info.id) {
+                    Err(e) => Outcome::Failed(e),
+                    Ok(ref now) if !now.is_writable() => Outcome::Skipped(SkipReason::ReadOnly),
+                    Ok(ref now) if now.is_disabled() => Outcome::Skipped(SkipReason::Disabled),
+                    Ok(ref now) if now.is_grabbed() => Outcome::Skipped(SkipReason::Grabbed),
+                    Ok(ref now) if now.is_inactive() => Outcome::Skipped(SkipReason::Inactive),
+                    Ok(now) => write_value(dev, &now, &entry.value),
+                },
+            };
+            report.results.push((entry.name.clone(), outcome));
+        }
+        Ok(report)
+    }
+
+    /// Parse the TOML subset written by [`Profile::to_toml`].
+    ///
+    /// Accepted values are integers (decimal or `0x` hex), `true`/`false`
+    /// and basic double-quoted strings.
+    pub fn parse(text: &str) -> Result<Profile, ParseError> {
+        let mut entries = Vec::new();
+        let mut comment = None;
+        let mut in_controls = false;
+        for (n, raw) in text.lines().enumerate() {
+            let err = |message: &str| ParseError {
+                line: n + 1,
+                message: message.into(),
+            };
+            let line = raw.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(c) = line.strip_prefix('#') {
+                comment = Some(c.trim().to_string());
+                continue;
+            }
+            if line.starts_with('[') {
+                let table = line
+                    .strip_prefix('[')
+                    .and_then(|l| l.strip_suffix(']'))
+                    .ok_or_else(|| err("malformed table header"))?;
+                in_controls = table.trim() == "controls";
+                continue;
+            }
+            if !in_controls {
+                return Err(err("key outside of [controls]"));
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| err("expected `=`"))?;
+            let key = key.trim();
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(err("invalid key"));
+            }
+            let value = parse_value(value.trim()).map_err(err)?;
+            entries.push(Entry {
+                name: key.to_string(),
+                value,
+                comment: comment.take(),
+            });
+        }
+        Ok(Profile { entries })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::from("[controls]\n");
+        for entry in &self.entries {
+            if let Some(comment) = &entry.comment {
+                out.push_str(&format!("# {}\n", comment));
+            }
+            out.push_str(&format!("{} = {}\n", entry.name, entry.value));
+        }
+        out
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Profile> {
+        let text = fs::read_to_string(path)?;
+        Profile::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+}
+
+/// Strip a trailing `# comment` that is not inside a string
+fn strip_comment(value: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return value[..i].trim_end(),
+            _ => {}
+        }
+    }
+    value
+}
+
+fn parse_value(value: &str) -> Result<Value, &'static str> {
+    let value = strip_comment(value);
+    if let Some(body) = value.strip_prefix('"') {
+        let body = body.strip_suffix('"').ok_or("unterminated string")?;
+        let mut s = String::with_capacity(body.len());
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                _ => return Err("unsupported escape"),
+            }
+        }
+        return Ok(Value::String(s));
+    }
+    match value {
+        "true" => return Ok(Value::Integer(1)),
+        "false" => return Ok(Value::Integer(0)),
+        _ => {}
+    }
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let digits = digits.replace('_', "");
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    }
+    .map_err(|_| "invalid value")?;
+    Ok(Value::Integer(if negative {
+        -magnitude
+    } else {
+        magnitude
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::{FakeControl, FakeDevice};
+
+    const MANUAL: i64 = crate::v4l2_exposure_auto_type_V4L2_EXPOSURE_MANUAL as i64;
+    const APERTURE: i64 = crate::v4l2_exposure_auto_type_V4L2_EXPOSURE_APERTURE_PRIORITY as i64;
+
+    fn camera() -> FakeDevice {
+        FakeDevice::with_controls(vec![
+            FakeControl::integer(crate::V4L2_CID_BRIGHTNESS, "Brightness", -64, 64, 0),
+            FakeControl::new(
+                crate::V4L2_CID_EXPOSURE_AUTO,
+                "Auto Exposure",
+                crate::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU,
+                0,
+                3,
+                APERTURE,
+            ),
+            FakeControl::integer(
+                crate::V4L2_CID_EXPOSURE_ABSOLUTE,
+                "Exposure Time, Absolute",
+                3,
+                2047,
+                250,
+            )
+            .active_when(crate::V4L2_CID_EXPOSURE_AUTO, MANUAL),
+            FakeControl::integer(crate::V4L2_CID_GAIN, "Gain", 0, 255, 32)
+                .flags(crate::V4L2_CTRL_FLAG_GRABBED),
+            FakeControl::integer(crate::V4L2_CID_HUE, "Hue", 0, 10, 0)
+                .flags(crate::V4L2_CTRL_FLAG_READ_ONLY),
+        ])
+    }
+
+    #[test]
+    fn capture_and_round_trip() {
+        let dev = camera();
+        let report = Profile::capture(&dev).unwrap();
+        assert!(report.is_success());
+        let profile = report.profile;
+        let names: Vec<_> = profile.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "brightness",
+                "gain",
+                "auto_exposure",
+                "exposure_time_absolute"
+            ]
+        );
+        assert_eq!(
+            profile.get("auto_exposure"),
+            Some(&Value::Integer(APERTURE))
+        );
+
+        let text = profile.to_toml();
+        assert!(text.contains("# Exposure Time, Absolute: int 3..2047 (default 250)\n"));
+        assert_eq!(Profile::parse(&text).unwrap(), profile);
+
+        // A control that cannot be read does not spoil the snapshot
+        dev.controls.borrow_mut().push(
+            FakeControl::integer(crate::V4L2_CID_HFLIP, "Horizontal Flip", 0, 1, 0)
+                .read_error(libc::EBUSY),
+        );
+        let report = Profile::capture(&dev).unwrap();
+        assert_eq!(report.profile, profile);
+        assert_eq!(report.failures.len(), 1);
+        let (name, err) = &report.failures[0];
+        assert_eq!(name, "horizontal_flip");
+        assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+    }
+
+    #[test]
+    fn parse_values() {
+        let text = "[controls]\n\
+                    a = -12\n\
+                    b = 0x10 # trailing\n\
+                    c = true\n\
+                    d = \"say \\\"hi\\\" # not a comment\"\n";
+        let profile = Profile::parse(text).unwrap();
+        assert_eq!(profile.get("a"), Some(&Value::Integer(-12)));
+        assert_eq!(profile.get("b"), Some(&Value::Integer(16)));
+        assert_eq!(profile.get("c"), Some(&Value::Integer(1)));
+        assert_eq!(
+            profile.get("d"),
+            Some(&Value::String("say \"hi\" # not a comment".into()))
+        );
+
+        let err = Profile::parse("[controls]\nbrightness 3\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(Profile::parse("brightness = 3\n").is_err());
+    }
+
+    #[test]
+    fn apply_sets_auto_modes_first() {
+        let dev = camera();
+        let profile = Profile::parse(
+            "[controls]\n\
+             exposure_time_absolute = 400\n\
+             gain = 10\n\
+             hue = 3\n\
+             auto_exposure = 1\n\
+             brightness = 100\n\
+             sharpness = 2\n",
+        )
+        .unwrap();
+        let report = profile.apply(&dev).unwrap();
+
+        let order: Vec<_> = report.results.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(order[0], "auto_exposure");
+        assert_eq!(
+            dev.writes.borrow()[..2],
+            [
+                (crate::V4L2_CID_EXPOSURE_AUTO, MANUAL),
+                (crate::V4L2_CID_EXPOSURE_ABSOLUTE, 400)
+            ]
+        );
+
+        let outcome = |name| &report.results.iter().find(|(n, _)| n == name).unwrap().1;
+        assert!(matches!(
+            outcome("gain"),
+            Outcome::Skipped(SkipReason::Grabbed)
+        ));
+        assert!(matches!(
+            outcome("hue"),
+            Outcome::Skipped(SkipReason::ReadOnly)
+        ));
+        assert!(matches!(
+            outcome("sharpness"),
+            Outcome::Skipped(SkipReason::Unknown)
+        ));
+        assert!(matches!(outcome("brightness"), Outcome::Failed(_)));
+        assert_eq!(report.failures().count(), 1);
+    }
+
+    #[test]
+    fn apply_skips_inactive_manual_values() {
+        let dev = camera();
+        let profile = Profile::parse("[controls]\nexposure_time_absolute = 400\n").unwrap();
+        let report = profile.apply(&dev).unwrap();
+        assert!(matches!(
+            report.results[0].1,
+            Outcome::Skipped(SkipReason::Inactive)
+        ));
+        assert!(dev.writes.borrow().is_empty());
+    }
+}
@@ -0,0 +1,567 @@
+//! RDS/RBDS decoder for radio receivers with `V4L2_CAP_RDS_CAPTURE`
+//!
+//! The driver delivers `struct v4l2_rds_data` triplets through read(); the
+//! decoder syncs them into groups and keeps the decoded station data.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-rds.html
+
+use std::fmt;
+use std::io;
+
+use crate::device::Device;
+
+/// Size of `struct v4l2_rds_data`
+pub const RDS_DATA_SIZE: usize = 3;
+
+/// One received block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RdsBlock {
+    pub data: u16,
+    /// `V4L2_RDS_BLOCK_*` id: A, B, C, D, C' or invalid
+    pub id: u8,
+    /// Errors were found and corrected by the receiver
+    pub corrected: bool,
+    /// Uncorrectable error, the data is garbage
+    pub error: bool,
+}
+
+impl RdsBlock {
+    pub fn from_bytes(raw: [u8; RDS_DATA_SIZE]) -> Self {
+        let [lsb, msb, block] = raw;
+        RdsBlock {
+            data: u16::from_le_bytes([lsb, msb]),
+            id: block & crate::V4L2_RDS_BLOCK_MSK as u8,
+            corrected: block & crate::V4L2_RDS_BLOCK_CORRECTED as u8 != 0,
+            error: block & crate::V4L2_RDS_BLOCK_ERROR as u8 != 0,
+        }
+    }
+}
+
+/// A complete group of four blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group {
+    pub blocks: [u16; 4],
+}
+
+impl Group {
+    /// Group type code, 0 to 15
+    pub fn group_type(&self) -> u8 {
+        (self.blocks[1] >> 12) as u8
+    }
+
+    /// Version B groups carry the PI code again in block C'
+    pub fn is_version_b(&self) -> bool {
+        self.blocks[1] & 0x0800 != 0
+    }
+
+    pub fn pi(&self) -> u16 {
+        self.blocks[0]
+    }
+
+    pub fn tp(&self) -> bool {
+        self.blocks[1] & 0x0400 != 0
+    }
+
+    pub fn pty(&self) -> u8 {
+        ((self.blocks[1] >> 5) & 0x1f) as u8
+    }
+}
+
+/// Prints the group type, e.g. "0A" or "2B"
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version = if self.is_version_b() { 'B' } else { 'A' };
+        write!(f, "{}{}", self.group_type(), version)
+    }
+}
+
+/// Clock time and date from group 4A
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    /// UTC
+    pub hour: u8,
+    pub minute: u8,
+    /// Local time offset in half hours
+    pub offset: i8,
+}
+
+impl ClockTime {
+    /// Offset of local time from UTC in minutes
+    pub fn offset_minutes(&self) -> i32 {
+        self.offset as i32 * 30
+    }
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset_minutes().abs();
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02} UTC{}{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
+
+/// Modified Julian Day to calendar date, IEC 62106 annex G
+fn mjd_to_date(mjd: u32) -> (u16, u8, u8) {
+    let mjd = mjd as f64;
+    let y = ((mjd - 15078.2) / 365.25).floor();
+    let m = ((mjd - 14956.1 - (y * 365.25).floor()) / 30.6001).floor();
+    let d = mjd - 14956.0 - (y * 365.25).floor() - (m * 30.6001).floor();
+    let k = if m == 14.0 || m == 15.0 { 1.0 } else { 0.0 };
+    ((y + k + 1900.0) as u16, (m - 1.0 - k * 12.0) as u8, d as u8)
+}
+
+const PTY_RDS: [&str; 32] = [
+    "None",
+    "News",
+    "Current Affairs",
+    "Information",
+    "Sport",
+    "Education",
+    "Drama",
+    "Culture",
+    "Science",
+    "Varied",
+    "Pop Music",
+    "Rock Music",
+    "Easy Listening",
+    "Light Classical",
+    "Serious Classical",
+    "Other Music",
+    "Weather",
+    "Finance",
+    "Children's Programmes",
+    "Social Affairs",
+    "Religion",
+    "Phone-in",
+    "Travel",
+    "Leisure",
+    "Jazz Music",
+    "Country Music",
+    "National Music",
+    "Oldies Music",
+    "Folk Music",
+    "Documentary",
+    "Alarm Test",
+    "Alarm",
+];
+
+const PTY_RBDS: [&str; 32] = [
+    "None",
+    "News",
+    "Information",
+    "Sports",
+    "Talk",
+    "Rock",
+    "Classic Rock",
+    "Adult Hits",
+    "Soft Rock",
+    "Top 40",
+    "Country",
+    "Oldies",
+    "Soft",
+    "Nostalgia",
+    "Jazz",
+    "Classical",
+    "Rhythm and Blues",
+    "Soft Rhythm and Blues",
+    "Language",
+    "Religious Music",
+    "Religious Talk",
+    "Personality",
+    "Public",
+    "College",
+    "Spanish Talk",
+    "Spanish Music",
+    "Hip Hop",
+    "Unassigned",
+    "Unassigned",
+    "Weather",
+    "Emergency Test",
+    "Emergency",
+];
+
+/// Programme type name; `rbds` selects the North American table
+pub fn pty_name(pty: u8, rbds: bool) -> &'static str {
+    let table = if rbds { &PTY_RBDS } else { &PTY_RDS };
+    table.get(pty as usize).copied().unwrap_or("Unknown")
+}
+
+/// Printable ASCII is kept, anything else in the RDS character set becomes '?'
+fn rds_char(c: u8) -> char {
+    match c {
+        0x20..=0x7e => c as char,
+        _ => '?',
+    }
+}
+
+/// Decoded station data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Station {
+    pub pi: Option<u16>,
+    pub pty: Option<u8>,
+    /// Traffic programme
+    pub tp: bool,
+    /// Traffic announcement in progress
+    pub ta: bool,
+    /// Music rather than speech
+    pub music: bool,
+    /// Programme service name, once all four segments have been received
+    pub ps: Option<String>,
+    /// RadioText, once every segment up to the end has been received
+    pub radio_text: Option<String>,
+    pub clock: Option<ClockTime>,
+    /// Alternative frequencies in kHz
+    pub af: Vec<u32>,
+}
+
+/// Counters for the received block stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub blocks: u64,
+    /// Blocks flagged with an uncorrectable error
+    pub errors: u64,
+    pub corrected: u64,
+    pub groups: u64,
+    /// Partial groups dropped because a block was missing or out of order
+    pub sync_losses: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RdsDecoder {
+    station: Station,
+    stats: Stats,
+    /// Blocks of the group being assembled
+    blocks: [u16; 4],
+    /// Number of blocks of the current group received in order
+    received: usize,
+    /// Leftover bytes of an incomplete triplet
+    pending: Vec<u8>,
+    ps: [u8; 8],
+    ps_segments: u8,
+    rt: [u8; 64],
+    rt_segments: u16,
+    rt_ab: Option<bool>,
+}
+
+impl Default for RdsDecoder {
+    fn default() -> Self {
+        RdsDecoder {
+            station: Station::default(),
+            stats: Stats::default(),
+            blocks: [0; 4],
+            received: 0,
+            pending: Vec::new(),
+            ps: [b' '; 8],
+            ps_segments: 0,
+            rt: [0; 64],
+            rt_segments: 0,
+            rt_ab: None,
+        }
+    }
+}
+
+impl RdsDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn station(&self) -> &Station {
+        &self.station
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Forget everything, e.g. after retuning
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Feed raw `v4l2_rds_data` bytes, as returned by read(). Incomplete
+    /// triplets are kept for the next call. Returns the decoded groups.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Group> {
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+        let chunks = data.chunks_exact(RDS_DATA_SIZE);
+        let rest = chunks.remainder().to_vec();
+        let groups = chunks
+            .filter_map(|c| self.push(RdsBlock::from_bytes([c[0], c[1], c[2]])))
+            .collect();
+        self.pending = rest;
+        groups
+    }
+
+    /// Read one buffer of blocks from `dev` and decode it
+    pub fn read_from(&mut self, dev: &Device) -> io::Result<Vec<Group>> {
+        let mut buf = [0u8; RDS_DATA_SIZE * 64];
+        let n = dev.read(&mut buf)?;
+        Ok(self.feed(&buf[..n]))
+    }
+
+    /// Add a single block, returning the group it completes
+    pub fn push(&mut self, block: RdsBlock) -> Option<Group> {
+        self.stats.blocks += 1;
+        if block.corrected {
+            self.stats.corrected += 1;
+        }
+        if block.error {
+            self.stats.errors += 1;
+            self.lose_sync();
+            return None;
+        }
+        let position = match block.id as u32 {
+            crate::V4L2_RDS_BLOCK_A => 0,
+            crate::V4L2_RDS_BLOCK_B => 1,
+            crate::V4L2_RDS_BLOCK_C | crate::V4L2_RDS_BLOCK_C_ALT => 2,
+            crate::V4L2_RDS_BLOCK_D => 3,
+            _ => {
+                self.lose_sync();
+                return None;
+            }
+        };
+        if position == 0 {
+            if self.received != 0 {
+                self.stats.sync_losses += 1;
+            }
+        } else if position != self.received {
+            self.lose_sync();
+            return None;
+        }
+        self.blocks[position] = block.data;
+        self.received = position + 1;
+        if self.received < 4 {
+            return None;
+        }
+        self.received = 0;
+        let group = Group {
+            blocks: self.blocks,
+        };
+        self.stats.groups += 1;
+        self.decode(&group);
+        Some(group)
+    }
+
+    fn lose_sync(&mut self) {
+        if self.received != 0 {
+            self.stats.sync_losses += 1;
+        }
+        self.received = 0;
+    }
+
+    fn decode(&mut self, group: &Group) {
+        if self.station.pi.is_some_and(|pi| pi != group.pi()) {
+            // a different station, drop what was decoded so far
+            *self = RdsDecoder {
+                stats: self.stats,
+                ..Default::default()
+            };
+        }
+        self.station.pi = Some(group.pi());
+        self.station.pty = Some(group.pty());
+        self.station.tp = group.tp();
+        let [_, b, c, d] = group.blocks;
+        match (group.group_type(), group.is_version_b()) {
+            (0, version_b) => {
+                self.station.ta = b & 0x10 != 0;
+                self.station.music = b & 0x08 != 0;
+                let segment = (b & 0x3) as usize;
+                self.ps[segment * 2..segment * 2 + 2].copy_from_slice(&d.to_be_bytes());
+                self.ps_segments |= 1 << segment;
+                if self.ps_segments == 0xf {
+                    self.station.ps = Some(self.ps.iter().map(|&c| rds_char(c)).collect());
+                }
+                if !version_b {
+                    self.alternative_frequencies(c);
+                }
+            }
+            (2, version_b) => {
+                let ab = b & 0x10 != 0;
+                if self.rt_ab.is_some_and(|prev| prev != ab) {
+                    // the A/B flag toggles when the broadcaster starts a new text
+                    self.rt = [0; 64];
+                    self.rt_segments = 0;
+                }
+                self.rt_ab = Some(ab);
+                let segment = (b & 0xf) as usize;
+                if version_b {
+                    self.rt[segment * 2..segment * 2 + 2].copy_from_slice(&d.to_be_bytes());
+                } else {
+                    self.rt[segment * 4..segment * 4 + 2].copy_from_slice(&c.to_be_bytes());
+                    self.rt[segment * 4 + 2..segment * 4 + 4].copy_from_slice(&d.to_be_bytes());
+                }
+                self.rt_segments |= 1 << segment;
+                self.update_radio_text(version_b);
+            }
+            (4, false) => {
+                let mjd = ((b as u32 & 0x3) << 15) | (c as u32 >> 1);
+                let hour = (((c & 0x1) << 4) | (d >> 12)) as u8;
+                let minute = ((d >> 6) & 0x3f) as u8;
+                let offset = (d & 0x1f) as i8;
+                if hour < 24 && minute < 60 && mjd != 0 {
+                    let (year, month, day) = mjd_to_date(mjd);
+                    self.station.clock = Some(ClockTime {
+                        year,
+                        month,
+                        day,
+                        hour,
+                        minute,
+                        offset: if d & 0x20 != 0 { -offset } else { offset },
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn update_radio_text(&mut self, version_b: bool) {
+        let (len, chars_per_segment) = if version_b { (32, 2) } else { (64, 4) };
+        let text = &self.rt[..len];
+        let end = text.iter().position(|&c| c == 0x0d).unwrap_or(len);
+        let needed = end / chars_per_segment + (end % chars_per_segment != 0) as usize;
+        let mask = ((1u32 << needed) - 1) as u16;
+        if needed > 0 && self.rt_segments & mask == mask {
+            let text: String = text[..end].iter().map(|&c| rds_char(c)).collect();
+            self.station.radio_text = Some(text.trim_end().to_string());
+        }
+    }
+
+    /// Method A alternative frequency codes from block C of group 0A
+    fn alternative_frequencies(&mut self, c: u16) {
+        let [first, second] = c.to_be_bytes();
+        if first == 250 {
+            // followed by an LF/MF frequency, not supported
+            return;
+        }
+        for code in [first, second] {
+            if let 1..=204 = code {
+                let khz = 87_500 + code as u32 * 100;
+                if !self.station.af.contains(&khz) {
+                    self.station.af.push(khz);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode(groups: &[[u16; 4]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for group in groups {
+            let version_b = group[1] & 0x0800 != 0;
+            for (i, data) in group.iter().enumerate() {
+                let id = if i == 2 && version_b { 4 } else { i as u8 };
+                bytes.extend_from_slice(&data.to_le_bytes());
+                bytes.push(id | id << 3);
+            }
+        }
+        bytes
+    }
+
+    /// Group 0A: PI 0xd3c2, PTY 10 (Pop Music), TP, music, PS "RADIO 1 "
+    fn ps_groups() -> Vec<[u16; 4]> {
+        (0..4u16)
+            .map(|seg| {
+                let b = 0x0400 | 10 << 5 | 0x08 | seg;
+                let c = if seg == 0 { 0xe301 } else { 0x0b7a };
+                let name = b"RADIO 1 ";
+                let d = u16::from_be_bytes([name[seg as usize * 2], name[seg as usize * 2 + 1]]);
+                [0xd3c2, b, c, d]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn programme_service() {
+        let mut dec = RdsDecoder::new();
+        let bytes = encode(&ps_groups());
+        // split in the middle of a triplet to exercise buffering
+        let groups = dec.feed(&bytes[..20]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].to_string(), "0A");
+        assert_eq!(dec.station().ps, None);
+        assert_eq!(dec.feed(&bytes[20..]).len(), 3);
+
+        let st = dec.station();
+        assert_eq!(st.pi, Some(0xd3c2));
+        assert_eq!(st.ps.as_deref(), Some("RADIO 1 "));
+        assert_eq!(pty_name(st.pty.unwrap(), false), "Pop Music");
+        assert_eq!(pty_name(st.pty.unwrap(), true), "Country");
+        assert!(st.tp && st.music && !st.ta);
+        assert_eq!(st.af, [87_600, 88_600, 99_700]);
+    }
+
+    #[test]
+    fn radio_text_and_clock() {
+        let mut dec = RdsDecoder::new();
+        let text = b"Hello RDS\r";
+        let mut groups = Vec::new();
+        for seg in 0..3u16 {
+            let chunk: Vec<u8> = (0..4)
+                .map(|i| *text.get(seg as usize * 4 + i).unwrap_or(&b' '))
+                .collect();
+            groups.push([
+                0x1234,
+                0x2000 | seg,
+                u16::from_be_bytes([chunk[0], chunk[1]]),
+                u16::from_be_bytes([chunk[2], chunk[3]]),
+            ]);
+        }
+        // 2024-03-15 (MJD 60384) 13:45 UTC, local offset +1:00
+        let mjd = 60384u16 as u32;
+        let (hour, minute, offset) = (13u16, 45u16, 2u16);
+        groups.push([
+            0x1234,
+            0x4000 | (mjd >> 15) as u16,
+            ((mjd & 0x7fff) << 1) as u16 | hour >> 4,
+            (hour & 0xf) << 12 | minute << 6 | offset,
+        ]);
+        dec.feed(&encode(&groups));
+
+        let st = dec.station();
+        assert_eq!(st.radio_text.as_deref(), Some("Hello RDS"));
+        let clock = st.clock.unwrap();
+        assert_eq!(clock.to_string(), "2024-03-15 13:45 UTC+01:00");
+        assert_eq!(clock.offset_minutes(), 60);
+    }
+
+    #[test]
+    fn errors_drop_the_group() {
+        let mut dec = RdsDecoder::new();
+        let mut bytes = encode(&ps_groups());
+        // uncorrectable error in block C of the second group
+        bytes[3 * 6 + 2] |= crate::V4L2_RDS_BLOCK_ERROR as u8;
+        // corrected block D in the third group is still used
+        bytes[3 * 11 + 2] |= crate::V4L2_RDS_BLOCK_CORRECTED as u8;
+        assert_eq!(dec.feed(&bytes).len(), 3);
+        let stats = dec.stats();
+        assert_eq!(
+            (stats.errors, stats.corrected, stats.sync_losses),
+            (1, 1, 1)
+        );
+        assert_eq!(dec.station().ps, None);
+
+        // a block out of order also loses sync
+        let mut dec = RdsDecoder::new();
+        let mut bytes = encode(&ps_groups()[..1]);
+        bytes.drain(3..6);
+        assert!(dec.feed(&bytes).is_empty());
+        assert_eq!(dec.stats().sync_losses, 1);
+    }
+}
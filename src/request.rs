@@ -0,0 +1,211 @@
+//! Request API (MEDIA_IOC_REQUEST_ALLOC, MEDIA_REQUEST_IOC_QUEUE,
+//! MEDIA_REQUEST_IOC_REINIT)
+//!
+//! A request bundles controls and buffers that the driver applies together.
+//! Stateless codecs depend on it: each OUTPUT buffer is queued with the
+//! parsed parameters of the data it holds.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/mediactl/request-api.html
+
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+
+use crate::codes;
+use crate::control::{ext_ctrls, Payload};
+use crate::device::{ioctl, Ioctl};
+use crate::media_codes;
+use crate::topology::MediaDevice;
+
+/// Something requests are allocated from: a media device, or a fake in tests
+pub trait RequestAlloc {
+    type Handle: Ioctl + AsRawFd;
+
+    /// MEDIA_IOC_REQUEST_ALLOC
+    fn alloc_request(&self) -> io::Result<Self::Handle>;
+}
+
+impl<D: Ioctl> RequestAlloc for MediaDevice<D> {
+    type Handle = RequestFd;
+
+    fn alloc_request(&self) -> io::Result<RequestFd> {
+        let mut fd: libc::c_int = -1;
+        ioctl(self, media_codes::MEDIA_IOC_REQUEST_ALLOC, &mut fd)?;
+        Ok(RequestFd { fd })
+    }
+}
+
+/// File descriptor of a request, closed on drop
+#[derive(Debug)]
+pub struct RequestFd {
+    fd: RawFd,
+}
+
+impl Ioctl for RequestFd {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        loop {
+            if libc::ioctl(self.fd, request as _, arg) != -1 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl AsRawFd for RequestFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for RequestFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Controls to stage in a request, with their values borrowed until the
+/// list is dropped
+#[derive(Default)]
+pub struct Controls<'a> {
+    raw: Vec<crate::v4l2_ext_control>,
+    values: PhantomData<&'a mut ()>,
+}
+
+impl<'a> Controls<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.raw.iter().map(|c| c.id).collect()
+    }
+
+    pub fn push_value(&mut self, id: u32, value: i32) {
+        let mut ctrl: crate::v4l2_ext_control = unsafe { mem::zeroed() };
+        ctrl.id = id;
+        ctrl.__bindgen_anon_1.value = value;
+        self.raw.push(ctrl);
+    }
+
+    /// A compound control such as `V4L2_CID_STATELESS_H264_SPS`
+    pub fn push_compound<T: Payload>(&mut self, id: u32, value: &'a mut T) {
+        self.push_array(id, std::slice::from_mut(value));
+    }
+
+    pub fn push_array<T: Payload>(&mut self, id: u32, values: &'a mut [T]) {
+        let mut ctrl: crate::v4l2_ext_control = unsafe { mem::zeroed() };
+        ctrl.id = id;
+        ctrl.size = mem::size_of_val(values) as u32;
+        ctrl.__bindgen_anon_1.ptr = values.as_mut_ptr() as *mut libc::c_void;
+        self.raw.push(ctrl);
+    }
+}
+
+/// An allocated request. It can be reused through [`Request::reinit`] once
+/// it has completed.
+#[derive(Debug)]
+pub struct Request<H = RequestFd> {
+    handle: H,
+}
+
+impl<H: Ioctl + AsRawFd> Request<H> {
+    pub fn alloc<A: RequestAlloc<Handle = H> + ?Sized>(media: &A) -> io::Result<Self> {
+        Ok(Request {
+            handle: media.alloc_request()?,
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.handle.as_raw_fd()
+    }
+
+    /// Stage `controls` in the request with VIDIOC_S_EXT_CTRLS on the video
+    /// device. Fails with EBUSY once the request is queued.
+    pub fn set_controls<D: Ioctl + ?Sized>(
+        &self,
+        dev: &D,
+        controls: &mut Controls,
+    ) -> io::Result<()> {
+        ext_ctrls(
+            dev,
+            codes::VIDIOC_S_EXT_CTRLS,
+            crate::uapi::V4L2_CTRL_WHICH_REQUEST_VAL,
+            self.fd(),
+            &mut controls.raw,
+        )
+    }
+
+    /// Read the control values a completed request was applied with
+    pub fn get_controls<D: Ioctl + ?Sized>(
+        &self,
+        dev: &D,
+        controls: &mut Controls,
+    ) -> io::Result<()> {
+        ext_ctrls(
+            dev,
+            codes::VIDIOC_G_EXT_CTRLS,
+            crate::uapi::V4L2_CTRL_WHICH_REQUEST_VAL,
+            self.fd(),
+            &mut controls.raw,
+        )
+    }
+
+    /// MEDIA_REQUEST_IOC_QUEUE. ENOENT means no buffer was queued with the
+    /// request, EBUSY that it is queued already.
+    pub fn queue(&self) -> io::Result<()> {
+        unsafe {
+            self.handle
+                .ioctl(media_codes::MEDIA_REQUEST_IOC_QUEUE, ptr::null_mut())
+        }
+    }
+
+    /// Wait for the request to complete; `Ok(false)` on timeout
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd(),
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        loop {
+            match unsafe { libc::poll(&mut pfd, 1, timeout) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+                0 => return Ok(false),
+                _ => return Ok(true),
+            }
+        }
+    }
+
+    /// MEDIA_REQUEST_IOC_REINIT; EBUSY while the request is still in flight
+    pub fn reinit(&self) -> io::Result<()> {
+        unsafe {
+            self.handle
+                .ioctl(media_codes::MEDIA_REQUEST_IOC_REINIT, ptr::null_mut())
+        }
+    }
+
+    pub fn into_inner(self) -> H {
+        self.handle
+    }
+}
@@ -0,0 +1,785 @@
+//! Sliced VBI capture (V4L2_BUF_TYPE_SLICED_VBI_CAPTURE)
+//!
+//! The driver decodes the VBI lines itself and read() returns an array of
+//! `v4l2_sliced_vbi_data` records. This module negotiates the services and
+//! turns the records into closed captions, WSS and teletext packets.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-sliced-vbi.html
+
+use std::io;
+use std::mem;
+use std::ops;
+
+use crate::codes;
+use crate::device::{ioctl, Device, Ioctl};
+
+/// Size of one `v4l2_sliced_vbi_data` record in a read() buffer
+pub const RECORD_SIZE: usize = mem::size_of::<crate::v4l2_sliced_vbi_data>();
+
+/// `V4L2_SLICED_*` service mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ServiceSet(pub u16);
+
+impl ServiceSet {
+    /// Teletext system B, 625 line
+    pub const TELETEXT_B: Self = Self(crate::V4L2_SLICED_TELETEXT_B as u16);
+    /// Video programming system, 625 line
+    pub const VPS: Self = Self(crate::V4L2_SLICED_VPS as u16);
+    /// CEA-608 closed captions, 525 line
+    pub const CAPTION_525: Self = Self(crate::V4L2_SLICED_CAPTION_525 as u16);
+    /// Wide screen signalling, 625 line
+    pub const WSS_625: Self = Self(crate::V4L2_SLICED_WSS_625 as u16);
+    pub const VBI_525: Self = Self(crate::V4L2_SLICED_VBI_525 as u16);
+    pub const VBI_625: Self = Self(crate::V4L2_SLICED_VBI_625 as u16);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl ops::BitOr for ServiceSet {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for ServiceSet {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Per field and line services, indexed `[field][line]` with the line number
+/// relative to the ITU start line of the field
+pub type ServiceLines = [[u16; 24]; 2];
+
+/// Result of VIDIOC_G_SLICED_VBI_CAP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlicedVbiCap {
+    pub service_set: ServiceSet,
+    pub service_lines: ServiceLines,
+}
+
+/// VIDIOC_G_SLICED_VBI_CAP: the services the hardware can slice
+pub fn capabilities<D: Ioctl + ?Sized>(dev: &D, output: bool) -> io::Result<SlicedVbiCap> {
+    let mut cap: crate::v4l2_sliced_vbi_cap = unsafe { mem::zeroed() };
+    cap.type_ = buf_type(output);
+    ioctl(dev, codes::VIDIOC_G_SLICED_VBI_CAP, &mut cap)?;
+    Ok(SlicedVbiCap {
+        service_set: ServiceSet(cap.service_set),
+        service_lines: cap.service_lines,
+    })
+}
+
+/// Typed `v4l2_sliced_vbi_format`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SlicedVbiFormat {
+    pub service_set: ServiceSet,
+    /// All zero lets the driver pick the lines for `service_set`
+    pub service_lines: ServiceLines,
+    /// Bytes per read(), a multiple of [`RECORD_SIZE`]
+    pub io_size: u32,
+}
+
+impl SlicedVbiFormat {
+    pub fn new(services: ServiceSet) -> Self {
+        SlicedVbiFormat {
+            service_set: services,
+            ..Default::default()
+        }
+    }
+
+    /// Records returned by one read()
+    pub fn records(&self) -> usize {
+        self.io_size as usize / RECORD_SIZE
+    }
+}
+
+impl From<&crate::v4l2_sliced_vbi_format> for SlicedVbiFormat {
+    fn from(f: &crate::v4l2_sliced_vbi_format) -> Self {
+        SlicedVbiFormat {
+            service_set: ServiceSet(f.service_set),
+            service_lines: f.service_lines,
+            io_size: f.io_size,
+        }
+    }
+}
+
+fn buf_type(output: bool) -> u32 {
+    if output {
+        crate::v4l2_buf_type_V4L2_BUF_TYPE_SLICED_VBI_OUTPUT
+    } else {
+        crate::v4l2_buf_type_V4L2_BUF_TYPE_SLICED_VBI_CAPTURE
+    }
+}
+
+/// VIDIOC_G_FMT for the sliced VBI buffer type
+pub fn get_format<D: Ioctl + ?Sized>(dev: &D, output: bool) -> io::Result<SlicedVbiFormat> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = buf_type(output);
+    ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+    Ok(SlicedVbiFormat::from(unsafe { &f.fmt.sliced }))
+}
+
+/// VIDIOC_S_FMT, returning the services the driver enabled. Setting the
+/// format also switches the device to sliced VBI.
+pub fn set_format<D: Ioctl + ?Sized>(
+    dev: &D,
+    output: bool,
+    format: &SlicedVbiFormat,
+) -> io::Result<SlicedVbiFormat> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = buf_type(output);
+    f.fmt.sliced.service_set = format.service_set.0;
+    f.fmt.sliced.service_lines = format.service_lines;
+    f.fmt.sliced.io_size = format.io_size;
+    ioctl(dev, codes::VIDIOC_S_FMT, &mut f)?;
+    Ok(SlicedVbiFormat::from(unsafe { &f.fmt.sliced }))
+}
+
+/// One `v4l2_sliced_vbi_data` record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlicedData {
+    /// The `V4L2_SLICED_*` service found on the line, 0 for an empty record
+    pub id: u32,
+    /// 0 for the first field, 1 for the second
+    pub field: u32,
+    /// Line number as in ITU-R BT.470
+    pub line: u32,
+    pub data: [u8; 48],
+}
+
+impl From<&crate::v4l2_sliced_vbi_data> for SlicedData {
+    fn from(d: &crate::v4l2_sliced_vbi_data) -> Self {
+        SlicedData {
+            id: d.id,
+            field: d.field,
+            line: d.line,
+            data: d.data,
+        }
+    }
+}
+
+impl SlicedData {
+    /// Decode a record from its in-memory layout
+    pub fn from_bytes(raw: &[u8; RECORD_SIZE]) -> Self {
+        let word = |i: usize| u32::from_ne_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+        let mut data = [0; 48];
+        data.copy_from_slice(&raw[16..]);
+        SlicedData {
+            id: word(0),
+            field: word(4),
+            line: word(8),
+            data,
+        }
+    }
+
+    pub fn service(&self) -> Service {
+        let (field, line) = (self.field, self.line);
+        match self.id {
+            crate::V4L2_SLICED_CAPTION_525 => Service::Caption {
+                field,
+                line,
+                data: [self.data[0], self.data[1]],
+            },
+            crate::V4L2_SLICED_WSS_625 => Service::Wss {
+                line,
+                wss: Wss(u16::from_le_bytes([self.data[0], self.data[1]]) & 0x3fff),
+            },
+            crate::V4L2_SLICED_TELETEXT_B => match TeletextPacket::parse(&self.data[..42]) {
+                Some(packet) => Service::Teletext {
+                    field,
+                    line,
+                    packet,
+                },
+                None => Service::Other(*self),
+            },
+            crate::V4L2_SLICED_VPS => {
+                let mut data = [0; 13];
+                data.copy_from_slice(&self.data[..13]);
+                Service::Vps { line, data }
+            }
+            _ => Service::Other(*self),
+        }
+    }
+}
+
+/// The records of a read() buffer, empty ones dropped
+pub fn parse_records(buf: &[u8]) -> Vec<SlicedData> {
+    buf.chunks_exact(RECORD_SIZE)
+        .map(|raw| SlicedData::from_bytes(raw.try_into().unwrap()))
+        .filter(|d| d.id != 0)
+        .collect()
+}
+
+/// The services of a read() buffer
+pub fn decode_buffer(buf: &[u8]) -> Vec<Service> {
+    parse_records(buf).iter().map(SlicedData::service).collect()
+}
+
+/// A decoded sliced VBI record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Service {
+    /// Two CEA-608 bytes with their parity bits, see [`CaptionDecoder`]
+    Caption {
+        field: u32,
+        line: u32,
+        data: [u8; 2],
+    },
+    Wss {
+        line: u32,
+        wss: Wss,
+    },
+    Teletext {
+        field: u32,
+        line: u32,
+        packet: TeletextPacket,
+    },
+    /// The 13 VPS bytes, starting with byte 3 of the line
+    Vps {
+        line: u32,
+        data: [u8; 13],
+    },
+    /// Unknown service or undecodable teletext address
+    Other(SlicedData),
+}
+
+/// Reads sliced VBI records from a device set up with [`set_format`]
+pub struct SlicedReader<'a> {
+    dev: &'a Device,
+    buf: Vec<u8>,
+}
+
+impl<'a> SlicedReader<'a> {
+    pub fn new(dev: &'a Device, format: &SlicedVbiFormat) -> Self {
+        let records = format.records().max(1);
+        SlicedReader {
+            dev,
+            buf: vec![0; records * RECORD_SIZE],
+        }
+    }
+
+    /// Read one frame worth of records
+    pub fn read(&mut self) -> io::Result<Vec<Service>> {
+        let n = self.dev.read(&mut self.buf)?;
+        Ok(decode_buffer(&self.buf[..n]))
+    }
+}
+
+/// Strip the odd parity bit of a CEA-608 or teletext character, `None` on a
+/// parity error
+pub fn odd_parity(byte: u8) -> Option<u8> {
+    if byte.count_ones() & 1 == 1 {
+        Some(byte & 0x7f)
+    } else {
+        None
+    }
+}
+
+/// Hamming 8/4 code words for the nibbles 0 to 15
+const HAMMING_8_4: [u8; 16] = [
+    0x15, 0x02, 0x49, 0x5e, 0x64, 0x73, 0x38, 0x2f, 0xd0, 0xc7, 0x8c, 0x9b, 0xa1, 0xb6, 0xfd, 0xea,
+];
+
+/// Decode a teletext Hamming 8/4 byte. Single bit errors are corrected,
+/// `None` when two bits are wrong.
+pub fn hamming_8_4(byte: u8) -> Option<u8> {
+    HAMMING_8_4
+        .iter()
+        .position(|&code| (code ^ byte).count_ones() <= 1)
+        .map(|nibble| nibble as u8)
+}
+
+/// Wide screen signalling bits 0 to 13 of a 625 line signal (line 23)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Wss(pub u16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AspectRatio {
+    /// 4:3 full format
+    Full4x3,
+    /// 14:9 letterbox, centred
+    Letterbox14x9,
+    /// 14:9 letterbox, top
+    Letterbox14x9Top,
+    /// 16:9 letterbox, centred
+    Letterbox16x9,
+    /// 16:9 letterbox, top
+    Letterbox16x9Top,
+    /// Wider than 16:9 letterbox, centred
+    LetterboxWide,
+    /// 14:9 full format, to be shown protected at 4:3
+    Full14x9,
+    /// 16:9 anamorphic
+    Anamorphic16x9,
+}
+
+impl Wss {
+    /// Aspect ratio group (bits 0-3), `None` on a parity error
+    pub fn aspect_ratio(&self) -> Option<AspectRatio> {
+        let group = self.0 & 0xf;
+        if group.count_ones() & 1 == 0 {
+            return None;
+        }
+        Some(match group & 7 {
+            0 => AspectRatio::Full4x3,
+            1 => AspectRatio::Letterbox14x9,
+            2 => AspectRatio::Letterbox14x9Top,
+            3 => AspectRatio::Letterbox16x9,
+            4 => AspectRatio::Letterbox16x9Top,
+            5 => AspectRatio::LetterboxWide,
+            6 => AspectRatio::Full14x9,
+            _ => AspectRatio::Anamorphic16x9,
+        })
+    }
+
+    fn bit(&self, n: u16) -> bool {
+        self.0 & (1 << n) != 0
+    }
+
+    /// Film mode rather than camera mode
+    pub fn film_mode(&self) -> bool {
+        self.bit(4)
+    }
+
+    /// Motion adaptive colour plus
+    pub fn colour_plus(&self) -> bool {
+        self.bit(5)
+    }
+
+    /// PALplus helper signals present
+    pub fn helper(&self) -> bool {
+        self.bit(6)
+    }
+
+    /// Subtitles in teletext
+    pub fn teletext_subtitles(&self) -> bool {
+        self.bit(8)
+    }
+
+    /// Open subtitles: 0 none, 1 inside the active picture, 2 outside
+    pub fn open_subtitles(&self) -> u8 {
+        ((self.0 >> 9) & 3) as u8
+    }
+
+    pub fn surround_sound(&self) -> bool {
+        self.bit(11)
+    }
+
+    pub fn copyright(&self) -> bool {
+        self.bit(12)
+    }
+
+    pub fn copy_restricted(&self) -> bool {
+        self.bit(13)
+    }
+}
+
+/// A teletext system B packet, clock run-in and framing code stripped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeletextPacket {
+    /// 1 to 8
+    pub magazine: u8,
+    /// 0 for a page header, 1 to 25 for display rows, above for extensions
+    pub packet: u8,
+    /// The 40 bytes following the address, still coded
+    pub data: [u8; 40],
+}
+
+/// Decoded packet 0 of a teletext page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageHeader {
+    pub magazine: u8,
+    /// Page number in the magazine, `0x00` to `0xff`; decimal pages are BCD
+    pub page: u8,
+    pub subcode: u16,
+    /// Control bits C4 (bit 0) to C14 (bit 10)
+    pub control: u16,
+    /// Header row text, 32 characters
+    pub text: String,
+}
+
+impl PageHeader {
+    /// Page number as shown to viewers, e.g. 100 or 888
+    pub fn page_number(&self) -> Option<u16> {
+        let (tens, units) = (self.page >> 4, self.page & 0xf);
+        if tens > 9 || units > 9 {
+            return None;
+        }
+        Some(self.magazine as u16 * 100 + tens as u16 * 10 + units as u16)
+    }
+
+    /// C4: the page replaces what was shown before
+    pub fn erase_page(&self) -> bool {
+        self.control & 1 != 0
+    }
+
+    /// C5
+    pub fn newsflash(&self) -> bool {
+        self.control & 2 != 0
+    }
+
+    /// C6
+    pub fn subtitle(&self) -> bool {
+        self.control & 4 != 0
+    }
+}
+
+/// Teletext characters with parity errors shown as spaces
+fn teletext_text(raw: &[u8]) -> String {
+    raw.iter()
+        .map(|&b| match odd_parity(b) {
+            Some(c) if c >= 0x20 => c as char,
+            _ => ' ',
+        })
+        .collect()
+}
+
+impl TeletextPacket {
+    /// Decode the magazine and packet address of 42 bytes of a line
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        if raw.len() < 42 {
+            return None;
+        }
+        let lo = hamming_8_4(raw[0])?;
+        let hi = hamming_8_4(raw[1])?;
+        let magazine = match lo & 7 {
+            0 => 8,
+            m => m,
+        };
+        let mut data = [0; 40];
+        data.copy_from_slice(&raw[2..42]);
+        Some(TeletextPacket {
+            magazine,
+            packet: (lo >> 3) | (hi << 1),
+            data,
+        })
+    }
+
+    /// The page header, for packet 0 with a readable address
+    pub fn header(&self) -> Option<PageHeader> {
+        if self.packet != 0 {
+            return None;
+        }
+        let mut n = [0u16; 8];
+        for (dst, &b) in n.iter_mut().zip(&self.data[..8]) {
+            *dst = hamming_8_4(b)? as u16;
+        }
+        let subcode = n[2] | (n[3] & 7) << 4 | n[4] << 8 | (n[5] & 3) << 12;
+        let control = n[3] >> 3 | (n[5] >> 2) << 1 | n[6] << 3 | n[7] << 7;
+        Some(PageHeader {
+            magazine: self.magazine,
+            page: (n[1] << 4 | n[0]) as u8,
+            subcode,
+            control,
+            text: teletext_text(&self.data[8..]),
+        })
+    }
+
+    /// Text of a display row, packets 1 to 25
+    pub fn text(&self) -> Option<String> {
+        if !(1..=25).contains(&self.packet) {
+            return None;
+        }
+        Some(teletext_text(&self.data))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptionMode {
+    PopOn,
+    RollUp,
+    PaintOn,
+    Text,
+}
+
+/// CEA-608 special characters, second byte 0x30 to 0x3f
+const SPECIAL: [char; 16] = [
+    '®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û',
+];
+
+/// Extended characters, first byte 0x12 then 0x13, second byte 0x20 to 0x3f
+const EXTENDED: [[char; 32]; 2] = [
+    [
+        'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '\'', '—', '©', '℠', '•', '“', '”', 'À', 'Â',
+        'Ç', 'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+    ],
+    [
+        'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~', 'Ä', 'ä',
+        'Ö', 'ö', 'ß', '¥', '¤', '│', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+    ],
+];
+
+/// CEA-608 basic character set, which differs from ASCII in a few places
+fn basic_char(c: u8) -> char {
+    match c {
+        0x2a => 'á',
+        0x5c => 'é',
+        0x5e => 'í',
+        0x5f => 'ó',
+        0x60 => 'ú',
+        0x7b => 'ç',
+        0x7c => '÷',
+        0x7d => 'Ñ',
+        0x7e => 'ñ',
+        0x7f => '█',
+        c => c as char,
+    }
+}
+
+/// Turns CEA-608 byte pairs of one caption channel into text.
+///
+/// Only the text is kept: pop-on captions are returned when they are
+/// displayed, roll-up and paint-on captions a row at a time on carriage
+/// return. Positioning and styling are dropped.
+#[derive(Debug, Clone)]
+pub struct CaptionDecoder {
+    channel: u8,
+    /// Channel the last control code was sent for
+    data_channel: u8,
+    mode: CaptionMode,
+    /// Non-displayed memory for pop-on, current row otherwise
+    pending: String,
+    last_control: Option<[u8; 2]>,
+}
+
+impl CaptionDecoder {
+    /// Decoder for caption channel CC1 to CC4
+    pub fn new(channel: u8) -> Self {
+        CaptionDecoder {
+            channel: channel.clamp(1, 4),
+            data_channel: 1,
+            mode: CaptionMode::PopOn,
+            pending: String::new(),
+            last_control: None,
+        }
+    }
+
+    /// Field carrying the channel, as in [`SlicedData::field`]
+    pub fn field(&self) -> u32 {
+        (self.channel as u32 - 1) / 2
+    }
+
+    /// Feed a sliced record; captions of other fields are ignored
+    pub fn decode(&mut self, service: &Service) -> Option<String> {
+        match service {
+            Service::Caption { field, data, .. } if *field == self.field() => self.push(*data),
+            _ => None,
+        }
+    }
+
+    /// Feed the two bytes of one caption line, parity bits included
+    pub fn push(&mut self, pair: [u8; 2]) -> Option<String> {
+        let b1 = odd_parity(pair[0])?;
+        let b2 = odd_parity(pair[1]);
+        if (0x10..0x20).contains(&b1) {
+            let b2 = b2?;
+            let code = [b1, b2];
+            // Control codes are sent twice in a row for robustness
+            if self.last_control.replace(code) == Some(code) {
+                self.last_control = None;
+                return None;
+            }
+            self.data_channel = if b1 & 0x08 != 0 { 2 } else { 1 };
+            if !self.is_selected() {
+                return None;
+            }
+            return self.control(b1 & !0x08, b2);
+        }
+        self.last_control = None;
+        if b1 == 0 || !self.is_selected() || self.mode == CaptionMode::Text {
+            return None;
+        }
+        for c in [Some(b1), b2].into_iter().flatten() {
+            if c >= 0x20 {
+                self.pending.push(basic_char(c));
+            }
+        }
+        None
+    }
+
+    fn is_selected(&self) -> bool {
+        self.data_channel == 2 - self.channel % 2
+    }
+
+    fn control(&mut self, b1: u8, b2: u8) -> Option<String> {
+        match (b1, b2) {
+            // Miscellaneous control codes, 0x15 on the second field
+            (0x14 | 0x15, 0x20..=0x2f) => return self.command(b2),
+            (0x11, 0x30..=0x3f) => self.pending.push(SPECIAL[(b2 - 0x30) as usize]),
+            // Mid-row style change, shown as a space
+            (0x11, 0x20..=0x2f) => self.pending.push(' '),
+            // Extended characters replace the basic fallback sent before them
+            (0x12 | 0x13, 0x20..=0x3f) => {
+                self.pending.pop();
+                self.pending
+                    .push(EXTENDED[(b1 - 0x12) as usize][(b2 - 0x20) as usize]);
+            }
+            // Preamble address code, a new row
+            (_, 0x40..=0x7f) if self.mode != CaptionMode::RollUp && self.ends_row() => {
+                self.pending.push('\n')
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn ends_row(&self) -> bool {
+        !self.pending.is_empty() && !self.pending.ends_with('\n')
+    }
+
+    fn command(&mut self, cmd: u8) -> Option<String> {
+        match cmd {
+            // RCL: resume caption loading
+            0x20 => self.mode = CaptionMode::PopOn,
+            // BS
+            0x21 => {
+                self.pending.pop();
+            }
+            // RU2 to RU4
+            0x25..=0x27 => self.mode = CaptionMode::RollUp,
+            // RDC: resume direct captioning
+            0x29 => self.mode = CaptionMode::PaintOn,
+            // TR, RTD: text service, not captions
+            0x2a | 0x2b => self.mode = CaptionMode::Text,
+            // CR
+            0x2d if self.mode != CaptionMode::PopOn => return self.take(),
+            // ENM: erase non-displayed memory
+            0x2e if self.mode == CaptionMode::PopOn => self.pending.clear(),
+            // EOC: end of caption, flip memories
+            0x2f => {
+                self.mode = CaptionMode::PopOn;
+                return self.take();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let text = mem::take(&mut self.pending);
+        let text = text.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parity(c: u8) -> u8 {
+        if c.count_ones() & 1 == 0 {
+            c | 0x80
+        } else {
+            c
+        }
+    }
+
+    fn record(id: u32, field: u32, line: u32, data: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(RECORD_SIZE);
+        for word in [id, field, line, 0] {
+            raw.extend_from_slice(&word.to_ne_bytes());
+        }
+        raw.extend_from_slice(data);
+        raw.resize(RECORD_SIZE, 0);
+        raw
+    }
+
+    fn caption(buf: &mut Vec<u8>, pairs: &[[u8; 2]]) {
+        for &[a, b] in pairs {
+            let data = [parity(a), parity(b)];
+            buf.extend(record(crate::V4L2_SLICED_CAPTION_525, 0, 21, &data));
+        }
+    }
+
+    #[test]
+    fn captions() {
+        let mut buf = Vec::new();
+        let (rcl, enm, eoc) = ([0x14, 0x20], [0x14, 0x2e], [0x14, 0x2f]);
+        caption(&mut buf, &[rcl, rcl, enm, enm, [0x14, 0x70], [0x14, 0x70]]);
+        caption(
+            &mut buf,
+            &[[b'H', b'I'], [b'!', 0], [0x11, 0x37], [0x11, 0x37]],
+        );
+        caption(
+            &mut buf,
+            &[[0x13, 0x70], [b'E', b'S'], [b'P', b'A'], [0x7d, b'A']],
+        );
+        // Extended characters follow a basic fallback they replace
+        caption(
+            &mut buf,
+            &[[b' ', b'C'], [b'A', b'F'], [b'E', 0], [0x12, 0x21]],
+        );
+        caption(&mut buf, &[[0x12, 0x21], eoc, eoc]);
+        // Roll-up on CC2 is not ours
+        caption(&mut buf, &[[0x1c, 0x25], [b'N', b'O'], [0x1c, 0x2d]]);
+        caption(&mut buf, &[[0x14, 0x25], [b'G', b'O'], [0x14, 0x2d]]);
+        // An empty record and one with a parity error
+        buf.extend(record(0, 0, 0, &[]));
+        buf.extend(record(crate::V4L2_SLICED_CAPTION_525, 0, 21, &[0xd8, 0x59]));
+
+        let services = decode_buffer(&buf);
+        assert_eq!(services.len(), buf.len() / RECORD_SIZE - 1);
+        let mut cc1 = CaptionDecoder::new(1);
+        let text: Vec<_> = services.iter().filter_map(|s| cc1.decode(s)).collect();
+        assert_eq!(text, ["HI!♪\nESPAÑA CAFÉ", "GO"]);
+        assert_eq!(CaptionDecoder::new(3).field(), 1);
+    }
+
+    #[test]
+    fn wss_and_teletext() {
+        // 16:9 anamorphic, film mode, subtitles in teletext
+        let wss = 0b1_0001_0111u16;
+        let mut buf = record(crate::V4L2_SLICED_WSS_625, 0, 23, &wss.to_le_bytes());
+
+        let ham = |n: u8| HAMMING_8_4[n as usize];
+        let mut header = vec![ham(1), ham(0)];
+        // Page 0x88, subcode 0x3f7f, C4 erase and C6 subtitle
+        header.extend([8, 8, 0xf, 0xf, 0xf, 0xb, 0, 0].map(ham));
+        header.extend(b"CEEFAX 1 188 Mon 01 Jan  12:00/0".map(parity));
+        // One bit error in the magazine address is corrected
+        header[0] ^= 0x80;
+        buf.extend(record(crate::V4L2_SLICED_TELETEXT_B, 0, 7, &header));
+        let mut row = vec![ham(1 | 8), ham(0)];
+        row.extend(b"Hello  ".map(parity));
+        row.resize(42, parity(b' '));
+        // Parity error
+        row[7] = b'Y';
+        buf.extend(record(crate::V4L2_SLICED_TELETEXT_B, 0, 8, &row));
+
+        let services = decode_buffer(&buf);
+        let Service::Wss { line: 23, wss } = services[0] else {
+            panic!("{:?}", services[0]);
+        };
+        assert_eq!(wss.aspect_ratio(), Some(AspectRatio::Anamorphic16x9));
+        assert!(wss.film_mode() && wss.teletext_subtitles() && !wss.copyright());
+        assert_eq!(Wss(0b1111).aspect_ratio(), None);
+        assert_eq!(Wss(0b1110).aspect_ratio(), Some(AspectRatio::Full14x9));
+
+        let Service::Teletext { packet, .. } = &services[1] else {
+            panic!("{:?}", services[1]);
+        };
+        let header = packet.header().unwrap();
+        assert_eq!(header.page_number(), Some(188));
+        assert_eq!(header.subcode, 0x3f7f);
+        assert!(header.erase_page() && header.subtitle() && !header.newsflash());
+        assert!(header.text.starts_with("CEEFAX 1 188"));
+        assert_eq!(packet.text(), None);
+
+        let Service::Teletext { packet, .. } = &services[2] else {
+            panic!("{:?}", services[2]);
+        };
+        assert_eq!((packet.magazine, packet.packet), (1, 1));
+        assert_eq!(packet.text().unwrap().trim_end(), "Hello");
+        assert_eq!(hamming_8_4(0x15 ^ 0x03), None);
+    }
+}
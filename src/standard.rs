@@ -0,0 +1,459 @@
+//! Analog video standards (VIDIOC_G_STD / S_STD / ENUMSTD / QUERYSTD)
+//!
+//! The `V4L2_STD_*` masks are defined with casts in `videodev2.h`, so bindgen
+//! does not emit them; they are spelled out here instead.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-enumstd.html
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ops;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+use crate::priority;
+use crate::streamparm::Fraction;
+
+/// Set of analog video standards, the typed form of `v4l2_std_id`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VideoStandard(pub crate::v4l2_std_id);
+
+impl VideoStandard {
+    pub const UNKNOWN: Self = Self(0);
+
+    pub const PAL_B: Self = Self(0x0000_0001);
+    pub const PAL_B1: Self = Self(0x0000_0002);
+    pub const PAL_G: Self = Self(0x0000_0004);
+    pub const PAL_H: Self = Self(0x0000_0008);
+    pub const PAL_I: Self = Self(0x0000_0010);
+    pub const PAL_D: Self = Self(0x0000_0020);
+    pub const PAL_D1: Self = Self(0x0000_0040);
+    pub const PAL_K: Self = Self(0x0000_0080);
+    pub const PAL_M: Self = Self(0x0000_0100);
+    pub const PAL_N: Self = Self(0x0000_0200);
+    pub const PAL_NC: Self = Self(0x0000_0400);
+    pub const PAL_60: Self = Self(0x0000_0800);
+
+    pub const NTSC_M: Self = Self(0x0000_1000);
+    pub const NTSC_M_JP: Self = Self(0x0000_2000);
+    pub const NTSC_443: Self = Self(0x0000_4000);
+    pub const NTSC_M_KR: Self = Self(0x0000_8000);
+
+    pub const SECAM_B: Self = Self(0x0001_0000);
+    pub const SECAM_D: Self = Self(0x0002_0000);
+    pub const SECAM_G: Self = Self(0x0004_0000);
+    pub const SECAM_H: Self = Self(0x0008_0000);
+    pub const SECAM_K: Self = Self(0x0010_0000);
+    pub const SECAM_K1: Self = Self(0x0020_0000);
+    pub const SECAM_L: Self = Self(0x0040_0000);
+    pub const SECAM_LC: Self = Self(0x0080_0000);
+
+    pub const ATSC_8_VSB: Self = Self(0x0100_0000);
+    pub const ATSC_16_VSB: Self = Self(0x0200_0000);
+
+    pub const NTSC: Self = Self(Self::NTSC_M.0 | Self::NTSC_M_JP.0 | Self::NTSC_M_KR.0);
+    pub const SECAM_DK: Self = Self(Self::SECAM_D.0 | Self::SECAM_K.0 | Self::SECAM_K1.0);
+    pub const SECAM: Self = Self(
+        Self::SECAM_B.0
+            | Self::SECAM_G.0
+            | Self::SECAM_H.0
+            | Self::SECAM_DK.0
+            | Self::SECAM_L.0
+            | Self::SECAM_LC.0,
+    );
+    pub const PAL_BG: Self = Self(Self::PAL_B.0 | Self::PAL_B1.0 | Self::PAL_G.0);
+    pub const PAL_DK: Self = Self(Self::PAL_D.0 | Self::PAL_D1.0 | Self::PAL_K.0);
+    pub const PAL: Self = Self(Self::PAL_BG.0 | Self::PAL_DK.0 | Self::PAL_H.0 | Self::PAL_I.0);
+    pub const ATSC: Self = Self(Self::ATSC_8_VSB.0 | Self::ATSC_16_VSB.0);
+
+    /// Standards with 525 lines at 60 fields per second
+    pub const STD_525_60: Self =
+        Self(Self::PAL_M.0 | Self::PAL_60.0 | Self::NTSC.0 | Self::NTSC_443.0);
+    /// Standards with 625 lines at 50 fields per second
+    pub const STD_625_50: Self = Self(Self::PAL.0 | Self::PAL_N.0 | Self::PAL_NC.0 | Self::SECAM.0);
+    pub const ALL: Self = Self(Self::STD_525_60.0 | Self::STD_625_50.0);
+
+    pub const fn bits(self) -> crate::v4l2_std_id {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Every standard in `other` is also in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn is_525_60(self) -> bool {
+        !self.is_empty() && Self::STD_525_60.contains(self)
+    }
+
+    pub fn is_625_50(self) -> bool {
+        !self.is_empty() && Self::STD_625_50.contains(self)
+    }
+}
+
+impl ops::BitOr for VideoStandard {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for VideoStandard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl ops::BitAnd for VideoStandard {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl ops::BitAndAssign for VideoStandard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl ops::Sub for VideoStandard {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 & !rhs.0)
+    }
+}
+
+struct Family {
+    prefix: &'static str,
+    mask: VideoStandard,
+    /// What the bare prefix stands for, as in `videodev2.h`
+    common: VideoStandard,
+    /// Name of each bit in `mask`, lowest first
+    names: &'static [&'static str],
+}
+
+const FAMILIES: [Family; 4] = [
+    Family {
+        prefix: "PAL",
+        mask: VideoStandard(0x0000_0fff),
+        common: VideoStandard::PAL,
+        names: &[
+            "B", "B1", "G", "H", "I", "D", "D1", "K", "M", "N", "Nc", "60",
+        ],
+    },
+    Family {
+        prefix: "NTSC",
+        mask: VideoStandard(0x0000_f000),
+        common: VideoStandard::NTSC,
+        names: &["M", "M-JP", "443", "M-KR"],
+    },
+    Family {
+        prefix: "SECAM",
+        mask: VideoStandard(0x00ff_0000),
+        common: VideoStandard::SECAM,
+        names: &["B", "D", "G", "H", "K", "K1", "L", "Lc"],
+    },
+    Family {
+        prefix: "ATSC",
+        mask: VideoStandard(0x0300_0000),
+        common: VideoStandard::ATSC,
+        names: &["8-VSB", "16-VSB"],
+    },
+];
+
+/// Shorthands accepted after a family prefix, e.g. "PAL-BG"
+const ALIASES: [(&str, &str, VideoStandard); 3] = [
+    ("PAL", "BG", VideoStandard::PAL_BG),
+    ("PAL", "DK", VideoStandard::PAL_DK),
+    ("SECAM", "DK", VideoStandard::SECAM_DK),
+];
+
+/// Prints in the style of `v4l2-ctl`, e.g. "PAL-B/G NTSC-M"
+impl fmt::Display for VideoStandard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("Unknown");
+        }
+        let mut first = true;
+        for family in &FAMILIES {
+            let bits = (self.0 & family.mask.0) >> family.mask.0.trailing_zeros();
+            if bits == 0 {
+                continue;
+            }
+            if !first {
+                f.write_str(" ")?;
+            }
+            first = false;
+            write!(f, "{}-", family.prefix)?;
+            let parts = family
+                .names
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .map(|(_, name)| *name);
+            for (i, part) in parts.enumerate() {
+                if i > 0 {
+                    f.write_str("/")?;
+                }
+                f.write_str(part)?;
+            }
+        }
+        let rest = self.0 & !(Self::ALL.0 | Self::ATSC.0);
+        if rest != 0 {
+            if !first {
+                f.write_str(" ")?;
+            }
+            write!(f, "0x{:x}", rest)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseStandardError(pub String);
+
+impl fmt::Display for ParseStandardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown video standard `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseStandardError {}
+
+fn parse_one(token: &str) -> Option<VideoStandard> {
+    let find = |prefix: &str| {
+        FAMILIES
+            .iter()
+            .find(|f| f.prefix.eq_ignore_ascii_case(prefix))
+    };
+    if let Some(family) = find(token) {
+        return Some(family.common);
+    }
+    match token.to_ascii_uppercase().as_str() {
+        "ALL" => return Some(VideoStandard::ALL),
+        "525_60" | "525-60" => return Some(VideoStandard::STD_525_60),
+        "625_50" | "625-50" => return Some(VideoStandard::STD_625_50),
+        _ => (),
+    }
+    let (prefix, rest) = token.split_once('-')?;
+    let family = find(prefix)?;
+    let mut std = VideoStandard::UNKNOWN;
+    for part in rest.split('/') {
+        let index = family
+            .names
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(part));
+        if let Some(i) = index {
+            std |= VideoStandard(1 << (family.mask.0.trailing_zeros() as usize + i));
+        } else {
+            let (_, _, alias) = ALIASES
+                .iter()
+                .find(|(p, a, _)| *p == family.prefix && a.eq_ignore_ascii_case(part))?;
+            std |= *alias;
+        }
+    }
+    Some(std)
+}
+
+/// Accepts the `Display` form as well as the kernel names ("PAL-BG",
+/// "SECAM-DK", "NTSC", "625_50"); several standards may be separated by
+/// spaces, commas or `|`.
+impl FromStr for VideoStandard {
+    type Err = ParseStandardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut std = VideoStandard::UNKNOWN;
+        let mut any = false;
+        for token in s.split(|c: char| c.is_whitespace() || c == ',' || c == '|') {
+            if token.is_empty() {
+                continue;
+            }
+            any = true;
+            std |= parse_one(token).ok_or_else(|| ParseStandardError(token.into()))?;
+        }
+        if any {
+            Ok(std)
+        } else {
+            Err(ParseStandardError(s.into()))
+        }
+    }
+}
+
+/// One entry of VIDIOC_ENUMSTD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Standard {
+    pub index: u32,
+    pub id: VideoStandard,
+    pub name: String,
+    /// Duration of one frame, e.g. 1001/30000 for NTSC
+    pub frame_period: Fraction,
+    /// Lines per frame, including blanking
+    pub frame_lines: u32,
+}
+
+impl Standard {
+    pub fn frame_rate(&self) -> Fraction {
+        self.frame_period.recip()
+    }
+}
+
+impl From<&crate::v4l2_standard> for Standard {
+    fn from(s: &crate::v4l2_standard) -> Self {
+        Standard {
+            index: s.index,
+            id: VideoStandard(s.id),
+            name: c_string(&s.name),
+            frame_period: s.frameperiod.into(),
+            frame_lines: s.framelines,
+        }
+    }
+}
+
+/// All standards supported by the current input or output
+pub fn enum_standards<D: Ioctl + ?Sized>(dev: &D) -> io::Result<Vec<Standard>> {
+    let mut standards = Vec::new();
+    for index in 0.. {
+        let mut s: crate::v4l2_standard = unsafe { mem::zeroed() };
+        s.index = index;
+        match ioctl(dev, codes::VIDIOC_ENUMSTD, &mut s) {
+            Ok(()) => standards.push(Standard::from(&s)),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(standards)
+}
+
+/// VIDIOC_G_STD
+pub fn get_std<D: Ioctl + ?Sized>(dev: &D) -> io::Result<VideoStandard> {
+    let mut id: crate::v4l2_std_id = 0;
+    ioctl(dev, codes::VIDIOC_G_STD, &mut id)?;
+    Ok(VideoStandard(id))
+}
+
+/// VIDIOC_S_STD
+pub fn set_std<D: Ioctl + ?Sized>(dev: &D, std: VideoStandard) -> io::Result<()> {
+    let mut id = std.bits();
+    ioctl(dev, codes::VIDIOC_S_STD, &mut id)
+}
+
+/// VIDIOC_QUERYSTD: the standards the receiver currently senses, possibly
+/// several while it is still narrowing down, or none without a signal.
+pub fn query_std<D: Ioctl + ?Sized>(dev: &D) -> io::Result<VideoStandard> {
+    let mut id: crate::v4l2_std_id = 0;
+    ioctl(dev, codes::VIDIOC_QUERYSTD, &mut id)?;
+    Ok(VideoStandard(id))
+}
+
+/// Poll QUERYSTD until the signal locks onto a single standard family.
+///
+/// A query is retried up to `attempts` times, `interval` apart, while it
+/// reports nothing, more than one of 525/60 and 625/50, or fails with
+/// ENOLINK / ENODATA / EBUSY. Returns `TimedOut` if the signal never settles.
+pub fn detect_std<D: Ioctl + ?Sized>(
+    dev: &D,
+    attempts: u32,
+    interval: Duration,
+) -> io::Result<VideoStandard> {
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            thread::sleep(interval);
+        }
+        match query_std(dev) {
+            Ok(std) if std.is_525_60() || std.is_625_50() => return Ok(std),
+            Ok(std) if !std.is_empty() && !std.intersects(VideoStandard::ALL) => return Ok(std),
+            Ok(_) => (),
+            Err(e)
+                if matches!(
+                    priority::raw_os_error(&e),
+                    Some(libc::ENOLINK) | Some(libc::ENODATA) | Some(libc::EBUSY)
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no video standard detected",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    #[test]
+    fn standard_names() {
+        assert_eq!(VideoStandard::PAL_BG.to_string(), "PAL-B/B1/G");
+        assert_eq!(
+            (VideoStandard::PAL_B | VideoStandard::PAL_G).to_string(),
+            "PAL-B/G"
+        );
+        assert_eq!(VideoStandard::NTSC_M.to_string(), "NTSC-M");
+        assert_eq!(VideoStandard::SECAM_L.to_string(), "SECAM-L");
+        assert_eq!(
+            (VideoStandard::PAL_I | VideoStandard::NTSC_M_JP).to_string(),
+            "PAL-I NTSC-M-JP"
+        );
+        assert_eq!(VideoStandard::UNKNOWN.to_string(), "Unknown");
+    }
+
+    #[test]
+    fn parse_standards() {
+        let p = |s: &str| s.parse::<VideoStandard>();
+        assert_eq!(
+            p("PAL-B/G"),
+            Ok(VideoStandard::PAL_B | VideoStandard::PAL_G)
+        );
+        assert_eq!(p("ntsc-m"), Ok(VideoStandard::NTSC_M));
+        assert_eq!(p("SECAM-Lc"), Ok(VideoStandard::SECAM_LC));
+        assert_eq!(p("PAL-Nc"), Ok(VideoStandard::PAL_NC));
+        assert_eq!(p("PAL-DK"), Ok(VideoStandard::PAL_DK));
+        assert_eq!(p("PAL, NTSC"), Ok(VideoStandard::PAL | VideoStandard::NTSC));
+        assert_eq!(p("625_50"), Ok(VideoStandard::STD_625_50));
+        assert!(p("PAL-X").is_err());
+        assert!(p("").is_err());
+        for std in [
+            VideoStandard::ALL,
+            VideoStandard::SECAM,
+            VideoStandard::ATSC,
+        ] {
+            assert_eq!(p(&std.to_string()), Ok(std));
+        }
+        assert!(VideoStandard::PAL_M.is_525_60());
+        assert!(!(VideoStandard::PAL_M | VideoStandard::PAL_B).is_525_60());
+    }
+
+    #[test]
+    fn detect_retries_until_locked() {
+        let dev = FakeDevice::default();
+        dev.query_std.borrow_mut().extend([
+            Err(libc::ENOLINK),
+            Ok(0),
+            Ok(VideoStandard::ALL.bits()),
+            Ok(VideoStandard::PAL_BG.bits()),
+        ]);
+        assert_eq!(
+            detect_std(&dev, 5, Duration::ZERO).unwrap(),
+            VideoStandard::PAL_BG
+        );
+        dev.query_std.borrow_mut().push_back(Ok(0));
+        let err = detect_std(&dev, 1, Duration::ZERO).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
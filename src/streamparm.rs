@@ -0,0 +1,348 @@
+//! Streaming parameters and frame rate (VIDIOC_G_PARM / VIDIOC_S_PARM)
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/vidioc-g-parm.html
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+
+/// Exact rational number, the Rust side of `struct v4l2_fract`
+///
+/// Equality is structural, so `1/30 != 2/60`; compare values with
+/// [`Fraction::cmp_value`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Fraction {
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        Fraction {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Lowest terms; `0/0` stays as is
+    pub fn reduce(self) -> Self {
+        fn gcd(a: u32, b: u32) -> u32 {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+        match gcd(self.numerator, self.denominator) {
+            0 | 1 => self,
+            g => Fraction::new(self.numerator / g, self.denominator / g),
+        }
+    }
+
+    pub fn recip(self) -> Self {
+        Fraction::new(self.denominator, self.numerator)
+    }
+
+    /// `None` for a zero denominator
+    pub fn to_f64(self) -> Option<f64> {
+        if self.denominator == 0 {
+            None
+        } else {
+            Some(self.numerator as f64 / self.denominator as f64)
+        }
+    }
+
+    /// Compare by value, so `1/30` equals `2/60`; `None` if either
+    /// denominator is zero
+    pub fn cmp_value(self, other: Self) -> Option<Ordering> {
+        if self.denominator == 0 || other.denominator == 0 {
+            return None;
+        }
+        let lhs = self.numerator as u64 * other.denominator as u64;
+        let rhs = other.numerator as u64 * self.denominator as u64;
+        Some(lhs.cmp(&rhs))
+    }
+
+    pub fn is_valid(self) -> bool {
+        self.numerator != 0 && self.denominator != 0
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl From<crate::v4l2_fract> for Fraction {
+    fn from(f: crate::v4l2_fract) -> Self {
+        Fraction::new(f.numerator, f.denominator)
+    }
+}
+
+impl From<Fraction> for crate::v4l2_fract {
+    fn from(f: Fraction) -> Self {
+        crate::v4l2_fract {
+            numerator: f.numerator,
+            denominator: f.denominator,
+        }
+    }
+}
+
+/// Capture side of `v4l2_streamparm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureParm {
+    /// `V4L2_CAP_TIMEPERFRAME`, `V4L2_MODE_HIGHQUALITY`
+    pub capability: u32,
+    /// `V4L2_MODE_*`
+    pub capturemode: u32,
+    pub timeperframe: Fraction,
+    pub extendedmode: u32,
+    /// Number of buffers used by the read() I/O method
+    pub readbuffers: u32,
+}
+
+impl CaptureParm {
+    pub fn supports_timeperframe(&self) -> bool {
+        self.capability & crate::V4L2_CAP_TIMEPERFRAME != 0
+    }
+
+    pub fn supports_high_quality(&self) -> bool {
+        self.capability & crate::V4L2_MODE_HIGHQUALITY != 0
+    }
+
+    pub fn high_quality(&self) -> bool {
+        self.capturemode & crate::V4L2_MODE_HIGHQUALITY != 0
+    }
+
+    pub fn set_high_quality(&mut self, on: bool) {
+        if on {
+            self.capturemode |= crate::V4L2_MODE_HIGHQUALITY;
+        } else {
+            self.capturemode &= !crate::V4L2_MODE_HIGHQUALITY;
+        }
+    }
+
+    /// Frames per second, the reciprocal of `timeperframe`
+    pub fn frame_rate(&self) -> Fraction {
+        self.timeperframe.recip()
+    }
+}
+
+/// Output side of `v4l2_streamparm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputParm {
+    pub capability: u32,
+    pub outputmode: u32,
+    pub timeperframe: Fraction,
+    pub extendedmode: u32,
+    /// Number of buffers used by the write() I/O method
+    pub writebuffers: u32,
+}
+
+impl OutputParm {
+    pub fn supports_timeperframe(&self) -> bool {
+        self.capability & crate::V4L2_CAP_TIMEPERFRAME != 0
+    }
+
+    pub fn frame_rate(&self) -> Fraction {
+        self.timeperframe.recip()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamParm {
+    Capture(CaptureParm),
+    Output(OutputParm),
+}
+
+impl StreamParm {
+    pub fn timeperframe(&self) -> Fraction {
+        match self {
+            StreamParm::Capture(c) => c.timeperframe,
+            StreamParm::Output(o) => o.timeperframe,
+        }
+    }
+
+    pub fn supports_timeperframe(&self) -> bool {
+        match self {
+            StreamParm::Capture(c) => c.supports_timeperframe(),
+            StreamParm::Output(o) => o.supports_timeperframe(),
+        }
+    }
+}
+
+/// `V4L2_TYPE_IS_OUTPUT`: buffer types whose parameters live in `parm.output`
+pub fn is_output_type(type_: u32) -> bool {
+    matches!(
+        type_,
+        crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OVERLAY
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_OVERLAY
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_VBI_OUTPUT
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_SLICED_VBI_OUTPUT
+            | crate::v4l2_buf_type_V4L2_BUF_TYPE_SDR_OUTPUT
+            | crate::uapi::V4L2_BUF_TYPE_META_OUTPUT
+    )
+}
+
+fn from_raw(parm: &crate::v4l2_streamparm) -> StreamParm {
+    unsafe {
+        if is_output_type(parm.type_) {
+            let o = &parm.parm.output;
+            StreamParm::Output(OutputParm {
+                capability: o.capability,
+                outputmode: o.outputmode,
+                timeperframe: o.timeperframe.into(),
+                extendedmode: o.extendedmode,
+                writebuffers: o.writebuffers,
+            })
+        } else {
+            let c = &parm.parm.capture;
+            StreamParm::Capture(CaptureParm {
+                capability: c.capability,
+                capturemode: c.capturemode,
+                timeperframe: c.timeperframe.into(),
+                extendedmode: c.extendedmode,
+                readbuffers: c.readbuffers,
+            })
+        }
+    }
+}
+
+/// VIDIOC_G_PARM for buffer type `type_`
+pub fn get_parm<D: Ioctl + ?Sized>(dev: &D, type_: u32) -> io::Result<StreamParm> {
+    let mut parm: crate::v4l2_streamparm = unsafe { mem::zeroed() };
+    parm.type_ = type_;
+    ioctl(dev, codes::VIDIOC_G_PARM, &mut parm)?;
+    Ok(from_raw(&parm))
+}
+
+/// VIDIOC_S_PARM, returning the parameters the driver actually applied
+pub fn set_parm<D: Ioctl + ?Sized>(
+    dev: &D,
+    type_: u32,
+    value: &StreamParm,
+) -> io::Result<StreamParm> {
+    let mut parm: crate::v4l2_streamparm = unsafe { mem::zeroed() };
+    parm.type_ = type_;
+    match (value, is_output_type(type_)) {
+        (StreamParm::Capture(c), false) => {
+            parm.parm.capture.capturemode = c.capturemode;
+            parm.parm.capture.timeperframe = c.timeperframe.into();
+            parm.parm.capture.extendedmode = c.extendedmode;
+            parm.parm.capture.readbuffers = c.readbuffers;
+        }
+        (StreamParm::Output(o), true) => {
+            parm.parm.output.outputmode = o.outputmode;
+            parm.parm.output.timeperframe = o.timeperframe.into();
+            parm.parm.output.extendedmode = o.extendedmode;
+            parm.parm.output.writebuffers = o.writebuffers;
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stream parameters do not match the buffer type",
+            ))
+        }
+    }
+    ioctl(dev, codes::VIDIOC_S_PARM, &mut parm)?;
+    Ok(from_raw(&parm))
+}
+
+/// Current frame rate in frames per second
+pub fn frame_rate<D: Ioctl + ?Sized>(dev: &D, type_: u32) -> io::Result<Fraction> {
+    Ok(get_parm(dev, type_)?.timeperframe().recip())
+}
+
+/// Request `fps` frames per second and return the rate the driver settled on.
+///
+/// Fails with `Unsupported` when the driver does not set `V4L2_CAP_TIMEPERFRAME`.
+pub fn set_frame_rate<D: Ioctl + ?Sized>(
+    dev: &D,
+    type_: u32,
+    fps: Fraction,
+) -> io::Result<Fraction> {
+    if !fps.is_valid() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame rate must be non-zero",
+        ));
+    }
+    let mut parm = get_parm(dev, type_)?;
+    if !parm.supports_timeperframe() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "device does not support setting timeperframe",
+        ));
+    }
+    match &mut parm {
+        StreamParm::Capture(c) => c.timeperframe = fps.recip(),
+        StreamParm::Output(o) => o.timeperframe = fps.recip(),
+    }
+    Ok(set_parm(dev, type_, &parm)?.timeperframe().recip())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fraction_arithmetic() {
+        assert_eq!(
+            Fraction::new(1001, 30000).cmp_value(Fraction::new(2002, 60000)),
+            Some(Ordering::Equal)
+        );
+        assert_ne!(Fraction::new(1001, 30000), Fraction::new(2002, 60000));
+        assert_eq!(
+            Fraction::new(1, 30).cmp_value(Fraction::new(1, 25)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(Fraction::new(30000, 1001).recip().to_string(), "1001/30000");
+        let r = Fraction::new(60, 2).reduce();
+        assert_eq!((r.numerator, r.denominator), (30, 1));
+        assert_eq!(Fraction::new(0, 0).reduce().denominator, 0);
+        // A zero denominator is not equal to, nor ordered against, anything
+        assert_ne!(Fraction::new(0, 0), Fraction::new(1, 30));
+        assert_ne!(Fraction::new(1, 0), Fraction::new(2, 0));
+        assert_eq!(Fraction::new(0, 0).cmp_value(Fraction::new(1, 30)), None);
+        assert_eq!(Fraction::new(1, 30).cmp_value(Fraction::new(5, 0)), None);
+        assert_eq!(Fraction::new(1, 0).to_f64(), None);
+        assert!((Fraction::new(30000, 1001).to_f64().unwrap() - 29.97).abs() < 0.001);
+    }
+
+    #[test]
+    fn buffer_type_direction() {
+        assert!(is_output_type(
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT
+        ));
+        assert!(is_output_type(
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE
+        ));
+        assert!(!is_output_type(
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE
+        ));
+        assert!(!is_output_type(
+            crate::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE
+        ));
+    }
+
+    #[test]
+    fn high_quality_mode() {
+        let mut c = CaptureParm {
+            capability: crate::V4L2_CAP_TIMEPERFRAME | crate::V4L2_MODE_HIGHQUALITY,
+            capturemode: 0,
+            timeperframe: Fraction::new(1, 30),
+            extendedmode: 0,
+            readbuffers: 2,
+        };
+        assert!(c.supports_timeperframe() && c.supports_high_quality());
+        c.set_high_quality(true);
+        assert!(c.high_quality());
+        assert_eq!(c.frame_rate(), Fraction::new(30, 1));
+    }
+}
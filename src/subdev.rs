@@ -0,0 +1,501 @@
+//! V4L2 sub-devices (`/dev/v4l-subdevN`): pad formats, selections, frame
+//! intervals and routing
+//!
+//! Sensors, CSI receivers and ISPs in a media controller pipeline are
+//! configured pad by pad. Every call either acts on the hardware
+//! ([`Which::Active`]) or on a scratch state private to the file handle
+//! ([`Which::Try`]), which is what negotiation should use.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-subdev.html
+
+use std::io;
+use std::mem;
+use std::path::Path;
+
+use crate::device::{ioctl, Ioctl, RawDevice};
+use crate::mbus;
+use crate::overlay::Rect;
+use crate::streamparm::Fraction;
+use crate::subdev_codes;
+
+/// `enum v4l2_subdev_format_whence`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Which {
+    Try,
+    #[default]
+    Active,
+}
+
+impl Which {
+    pub fn as_raw(self) -> u32 {
+        match self {
+            Which::Try => crate::v4l2_subdev_format_whence_V4L2_SUBDEV_FORMAT_TRY,
+            Which::Active => crate::v4l2_subdev_format_whence_V4L2_SUBDEV_FORMAT_ACTIVE,
+        }
+    }
+}
+
+/// Typed `v4l2_mbus_framefmt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MbusFormat {
+    pub width: u32,
+    pub height: u32,
+    /// `MEDIA_BUS_FMT_*`
+    pub code: u32,
+    /// `V4L2_FIELD_*`
+    pub field: u32,
+    pub colorspace: u32,
+    pub ycbcr_enc: u16,
+    pub quantization: u16,
+    pub xfer_func: u16,
+    pub flags: u16,
+}
+
+impl MbusFormat {
+    /// Progressive `code` frames with default colorimetry
+    pub fn new(code: u32, width: u32, height: u32) -> Self {
+        MbusFormat {
+            width,
+            height,
+            code,
+            field: crate::v4l2_field_V4L2_FIELD_NONE,
+            colorspace: 0,
+            ycbcr_enc: 0,
+            quantization: 0,
+            xfer_func: 0,
+            flags: 0,
+        }
+    }
+
+    /// Kernel name of `code`, see [`mbus::name`]
+    pub fn code_name(&self) -> Option<&'static str> {
+        mbus::name(self.code)
+    }
+
+    pub(crate) fn to_raw(self) -> crate::v4l2_mbus_framefmt {
+        let f = crate::uapi::v4l2_mbus_framefmt {
+            width: self.width,
+            height: self.height,
+            code: self.code,
+            field: self.field,
+            colorspace: self.colorspace,
+            ycbcr_enc: self.ycbcr_enc,
+            quantization: self.quantization,
+            xfer_func: self.xfer_func,
+            flags: self.flags,
+            reserved: [0; 10],
+        };
+        // Same 48 bytes whatever the headers declare
+        unsafe { mem::transmute(f) }
+    }
+}
+
+impl From<&crate::v4l2_mbus_framefmt> for MbusFormat {
+    fn from(f: &crate::v4l2_mbus_framefmt) -> Self {
+        let f: crate::uapi::v4l2_mbus_framefmt = unsafe { mem::transmute(*f) };
+        MbusFormat {
+            width: f.width,
+            height: f.height,
+            code: f.code,
+            field: f.field,
+            colorspace: f.colorspace,
+            ycbcr_enc: f.ycbcr_enc,
+            quantization: f.quantization,
+            xfer_func: f.xfer_func,
+            flags: f.flags,
+        }
+    }
+}
+
+/// One entry of VIDIOC_SUBDEV_ENUM_FRAME_SIZE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSize {
+    pub min_width: u32,
+    pub max_width: u32,
+    pub min_height: u32,
+    pub max_height: u32,
+}
+
+impl FrameSize {
+    /// A single size rather than a range
+    pub fn is_discrete(&self) -> bool {
+        self.min_width == self.max_width && self.min_height == self.max_height
+    }
+}
+
+/// One entry of the routing table of a sub-device with streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub sink_pad: u32,
+    pub sink_stream: u32,
+    pub source_pad: u32,
+    pub source_stream: u32,
+    /// `V4L2_SUBDEV_ROUTE_FL_*`
+    pub flags: u32,
+}
+
+impl Route {
+    /// Active route from stream 0 of `sink_pad` to stream 0 of `source_pad`
+    pub fn new(sink_pad: u32, source_pad: u32) -> Self {
+        Route {
+            sink_pad,
+            sink_stream: 0,
+            source_pad,
+            source_stream: 0,
+            flags: crate::uapi::V4L2_SUBDEV_ROUTE_FL_ACTIVE,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.flags & crate::uapi::V4L2_SUBDEV_ROUTE_FL_ACTIVE != 0
+    }
+}
+
+impl From<&crate::uapi::v4l2_subdev_route> for Route {
+    fn from(r: &crate::uapi::v4l2_subdev_route) -> Self {
+        Route {
+            sink_pad: r.sink_pad,
+            sink_stream: r.sink_stream,
+            source_pad: r.source_pad,
+            source_stream: r.source_stream,
+            flags: r.flags,
+        }
+    }
+}
+
+impl From<&Route> for crate::uapi::v4l2_subdev_route {
+    fn from(r: &Route) -> Self {
+        let mut raw: crate::uapi::v4l2_subdev_route = unsafe { mem::zeroed() };
+        raw.sink_pad = r.sink_pad;
+        raw.sink_stream = r.sink_stream;
+        raw.source_pad = r.source_pad;
+        raw.source_stream = r.source_stream;
+        raw.flags = r.flags;
+        raw
+    }
+}
+
+/// Result of VIDIOC_SUBDEV_QUERYCAP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubdevCapability {
+    pub version: u32,
+    /// `V4L2_SUBDEV_CAP_*`
+    pub capabilities: u32,
+}
+
+impl SubdevCapability {
+    /// Opened without write access to the hardware state
+    pub fn is_read_only(&self) -> bool {
+        self.capabilities & crate::uapi::V4L2_SUBDEV_CAP_RO_SUBDEV != 0
+    }
+
+    /// Multiplexed streams and routing are supported
+    pub fn has_streams(&self) -> bool {
+        self.capabilities & crate::uapi::V4L2_SUBDEV_CAP_STREAMS != 0
+    }
+}
+
+/// A sub-device, by default opened from `/dev/v4l-subdevN`.
+///
+/// Controls work on sub-devices as on video nodes, so the handle can be
+/// passed to e.g. [`control`](crate::control) functions as well.
+#[derive(Debug)]
+pub struct Subdev<D = RawDevice> {
+    dev: D,
+}
+
+impl Subdev<RawDevice> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        RawDevice::open(path).map(Subdev::new)
+    }
+}
+
+impl<D: Ioctl> Subdev<D> {
+    pub fn new(dev: D) -> Self {
+        Subdev { dev }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dev
+    }
+
+    /// VIDIOC_SUBDEV_QUERYCAP
+    pub fn capabilities(&self) -> io::Result<SubdevCapability> {
+        let mut c: crate::uapi::v4l2_subdev_capability = unsafe { mem::zeroed() };
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_QUERYCAP, &mut c)?;
+        Ok(SubdevCapability {
+            version: c.version,
+            capabilities: c.capabilities,
+        })
+    }
+
+    /// VIDIOC_SUBDEV_S_CLIENT_CAP with `V4L2_SUBDEV_CLIENT_CAP_STREAMS`, needed
+    /// before routing and non-zero streams can be used on this handle
+    pub fn enable_streams(&self) -> io::Result<()> {
+        let mut c: crate::uapi::v4l2_subdev_client_capability = unsafe { mem::zeroed() };
+        c.capabilities = crate::uapi::V4L2_SUBDEV_CLIENT_CAP_STREAMS;
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_S_CLIENT_CAP, &mut c)?;
+        if c.capabilities & crate::uapi::V4L2_SUBDEV_CLIENT_CAP_STREAMS == 0 {
+            return Err(io::Error::from_raw_os_error(libc::ENOTTY));
+        }
+        Ok(())
+    }
+
+    /// VIDIOC_SUBDEV_G_FMT
+    pub fn format(&self, pad: u32, which: Which) -> io::Result<MbusFormat> {
+        let mut f: crate::v4l2_subdev_format = unsafe { mem::zeroed() };
+        f.which = which.as_raw();
+        f.pad = pad;
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_G_FMT, &mut f)?;
+        Ok(MbusFormat::from(&f.format))
+    }
+
+    /// VIDIOC_SUBDEV_S_FMT. Returns the format the driver settled on, which
+    /// may differ from `format`.
+    pub fn set_format(
+        &self,
+        pad: u32,
+        which: Which,
+        format: &MbusFormat,
+    ) -> io::Result<MbusFormat> {
+        let mut f: crate::v4l2_subdev_format = unsafe { mem::zeroed() };
+        f.which = which.as_raw();
+        f.pad = pad;
+        f.format = format.to_raw();
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_S_FMT, &mut f)?;
+        Ok(MbusFormat::from(&f.format))
+    }
+
+    /// All media bus codes of `pad`
+    pub fn mbus_codes(&self, pad: u32, which: Which) -> io::Result<Vec<u32>> {
+        let mut codes = Vec::new();
+        for index in 0.. {
+            let mut c: crate::v4l2_subdev_mbus_code_enum = unsafe { mem::zeroed() };
+            c.pad = pad;
+            c.index = index;
+            c.which = which.as_raw();
+            match ioctl(self, subdev_codes::VIDIOC_SUBDEV_ENUM_MBUS_CODE, &mut c) {
+                Ok(()) => codes.push(c.code),
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(codes)
+    }
+
+    /// Frame sizes of `pad` for media bus `code`
+    pub fn frame_sizes(&self, pad: u32, code: u32, which: Which) -> io::Result<Vec<FrameSize>> {
+        let mut sizes = Vec::new();
+        for index in 0.. {
+            let mut s: crate::v4l2_subdev_frame_size_enum = unsafe { mem::zeroed() };
+            s.index = index;
+            s.pad = pad;
+            s.code = code;
+            s.which = which.as_raw();
+            match ioctl(self, subdev_codes::VIDIOC_SUBDEV_ENUM_FRAME_SIZE, &mut s) {
+                Ok(()) => sizes.push(FrameSize {
+                    min_width: s.min_width,
+                    max_width: s.max_width,
+                    min_height: s.min_height,
+                    max_height: s.max_height,
+                }),
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Frame intervals of `pad` for `code` at `width`x`height`
+    pub fn frame_intervals(
+        &self,
+        pad: u32,
+        code: u32,
+        width: u32,
+        height: u32,
+        which: Which,
+    ) -> io::Result<Vec<Fraction>> {
+        let mut intervals = Vec::new();
+        for index in 0.. {
+            let mut i: crate::v4l2_subdev_frame_interval_enum = unsafe { mem::zeroed() };
+            i.index = index;
+            i.pad = pad;
+            i.code = code;
+            i.width = width;
+            i.height = height;
+            i.which = which.as_raw();
+            match ioctl(
+                self,
+                subdev_codes::VIDIOC_SUBDEV_ENUM_FRAME_INTERVAL,
+                &mut i,
+            ) {
+                Ok(()) => intervals.push(Fraction::from(i.interval)),
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(intervals)
+    }
+
+    /// VIDIOC_SUBDEV_G_FRAME_INTERVAL, the active interval of `pad`
+    pub fn frame_interval(&self, pad: u32) -> io::Result<Fraction> {
+        let mut i: crate::v4l2_subdev_frame_interval = unsafe { mem::zeroed() };
+        i.pad = pad;
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_G_FRAME_INTERVAL, &mut i)?;
+        Ok(Fraction::from(i.interval))
+    }
+
+    /// VIDIOC_SUBDEV_S_FRAME_INTERVAL; returns the interval the driver chose
+    pub fn set_frame_interval(&self, pad: u32, interval: Fraction) -> io::Result<Fraction> {
+        let mut i: crate::v4l2_subdev_frame_interval = unsafe { mem::zeroed() };
+        i.pad = pad;
+        i.interval = interval.into();
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_S_FRAME_INTERVAL, &mut i)?;
+        Ok(Fraction::from(i.interval))
+    }
+
+    /// VIDIOC_SUBDEV_G_SELECTION of `target`, a `V4L2_SEL_TGT_*`
+    pub fn selection(&self, pad: u32, target: u32, which: Which) -> io::Result<Rect> {
+        let mut s: crate::v4l2_subdev_selection = unsafe { mem::zeroed() };
+        s.which = which.as_raw();
+        s.pad = pad;
+        s.target = target;
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_G_SELECTION, &mut s)?;
+        Ok(Rect::from(s.r))
+    }
+
+    /// VIDIOC_SUBDEV_S_SELECTION with `V4L2_SEL_FLAG_*` `flags`; returns the
+    /// rectangle the driver chose
+    pub fn set_selection(
+        &self,
+        pad: u32,
+        target: u32,
+        which: Which,
+        rect: Rect,
+        flags: u32,
+    ) -> io::Result<Rect> {
+        let mut s: crate::v4l2_subdev_selection = unsafe { mem::zeroed() };
+        s.which = which.as_raw();
+        s.pad = pad;
+        s.target = target;
+        s.flags = flags;
+        s.r = rect.into();
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_S_SELECTION, &mut s)?;
+        Ok(Rect::from(s.r))
+    }
+
+    /// VIDIOC_SUBDEV_G_ROUTING, see [`Subdev::enable_streams`]
+    pub fn routing(&self, which: Which) -> io::Result<Vec<Route>> {
+        let mut r: crate::uapi::v4l2_subdev_routing = unsafe { mem::zeroed() };
+        r.which = which.as_raw();
+        let mut routes: Vec<crate::uapi::v4l2_subdev_route> = Vec::new();
+        loop {
+            r.len_routes = routes.len() as u32;
+            r.routes = routes.as_mut_ptr() as u64;
+            match ioctl(self, subdev_codes::VIDIOC_SUBDEV_G_ROUTING, &mut r) {
+                Ok(()) => break,
+                // num_routes tells how many there are
+                Err(e)
+                    if e.raw_os_error() == Some(libc::ENOSPC)
+                        && r.num_routes as usize > routes.len() =>
+                {
+                    routes.resize(r.num_routes as usize, unsafe { mem::zeroed() });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        routes.truncate(r.num_routes as usize);
+        Ok(routes.iter().map(Route::from).collect())
+    }
+
+    /// VIDIOC_SUBDEV_S_ROUTING; returns the table the driver applied
+    pub fn set_routing(&self, which: Which, routes: &[Route]) -> io::Result<Vec<Route>> {
+        let mut raw: Vec<crate::uapi::v4l2_subdev_route> = routes.iter().map(Into::into).collect();
+        let mut r: crate::uapi::v4l2_subdev_routing = unsafe { mem::zeroed() };
+        r.which = which.as_raw();
+        r.num_routes = raw.len() as u32;
+        r.len_routes = raw.len() as u32;
+        r.routes = raw.as_mut_ptr() as u64;
+        ioctl(self, subdev_codes::VIDIOC_SUBDEV_S_ROUTING, &mut r)?;
+        let n = (r.num_routes as usize).min(raw.len());
+        Ok(raw[..n].iter().map(Route::from).collect())
+    }
+}
+
+impl<D: Ioctl> Ioctl for Subdev<D> {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        self.dev.ioctl(request, arg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeSubdev;
+
+    #[test]
+    fn pad_formats() {
+        let isp = Subdev::new(FakeSubdev::new(
+            &[
+                crate::MEDIA_BUS_FMT_SRGGB10_1X10,
+                crate::MEDIA_BUS_FMT_YUYV8_1X16,
+            ],
+            (1920, 1080),
+            &[crate::MEDIA_PAD_FL_SINK, crate::MEDIA_PAD_FL_SOURCE],
+        ));
+        assert_eq!(
+            isp.mbus_codes(0, Which::Active).unwrap(),
+            [
+                crate::MEDIA_BUS_FMT_SRGGB10_1X10,
+                crate::MEDIA_BUS_FMT_YUYV8_1X16
+            ]
+        );
+        let sizes = isp
+            .frame_sizes(0, crate::MEDIA_BUS_FMT_SRGGB10_1X10, Which::Active)
+            .unwrap();
+        assert_eq!((sizes[0].max_width, sizes[0].max_height), (1920, 1080));
+        assert!(!sizes[0].is_discrete());
+        let intervals = isp
+            .frame_intervals(
+                0,
+                crate::MEDIA_BUS_FMT_SRGGB10_1X10,
+                1920,
+                1080,
+                Which::Active,
+            )
+            .unwrap();
+        assert_eq!(intervals, [Fraction::new(1, 30), Fraction::new(1, 60)]);
+
+        // Oversized and unknown requests are adjusted
+        let f = MbusFormat::new(crate::MEDIA_BUS_FMT_SBGGR8_1X8, 4000, 3000);
+        let set = isp.set_format(0, Which::Active, &f).unwrap();
+        assert_eq!(set.code_name(), Some("SRGGB10_1X10"));
+        assert_eq!((set.width, set.height), (1920, 1080));
+
+        // Try formats do not touch the active state
+        let f = MbusFormat::new(crate::MEDIA_BUS_FMT_YUYV8_1X16, 1280, 720);
+        isp.set_format(0, Which::Try, &f).unwrap();
+        assert_eq!(isp.format(0, Which::Try).unwrap(), f);
+        assert_eq!(isp.format(0, Which::Active).unwrap(), set);
+
+        // The sink crop limits the source
+        let crop = Rect::new(0, 0, 1280, 720);
+        let got = isp
+            .set_selection(0, crate::V4L2_SEL_TGT_CROP, Which::Active, crop, 0)
+            .unwrap();
+        assert_eq!(got, crop);
+        let source = isp.format(1, Which::Active).unwrap();
+        assert_eq!(
+            (source.code, source.width, source.height),
+            (set.code, 1280, 720)
+        );
+        let bounds = isp
+            .selection(0, crate::V4L2_SEL_TGT_CROP_BOUNDS, Which::Active)
+            .unwrap();
+        assert_eq!(bounds, Rect::new(0, 0, 1920, 1080));
+
+        let routes = isp.set_routing(Which::Active, &[Route::new(0, 1)]).unwrap();
+        assert!(routes[0].is_active());
+        assert_eq!(isp.routing(Which::Active).unwrap(), routes);
+        assert!(isp.capabilities().unwrap().has_streams());
+    }
+}
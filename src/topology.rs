@@ -0,0 +1,739 @@
+//! Media controller graph (MEDIA_IOC_DEVICE_INFO, MEDIA_IOC_G_TOPOLOGY,
+//! MEDIA_IOC_SETUP_LINK)
+//!
+//! SoC camera pipelines are graphs of entities (sensors, CSI receivers, ISPs,
+//! DMA engines) joined by links between their pads, and are configured
+//! through `/dev/mediaN` before any video node can stream. Interfaces are the
+//! device nodes; interface links tie them to the entities they control.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/mediactl/media-controller-model.html
+//!
+//! A [`Topology`] can be written out and read back as text, one object per
+//! line:
+//!
+//! ```text
+//! version 7
+//! entity id=1 function=0x20001 flags=0x0 name="imx219 1-0010"
+//! interface id=20 type=0x200 flags=0x0 devnode=81:0
+//! pad id=2 entity=1 index=0 flags=0x2
+//! link id=3 source=2 sink=5 flags=0x3
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::device::{c_string, ioctl, Ioctl, RawDevice};
+use crate::media_codes;
+
+/// A media controller device, by default opened from `/dev/mediaN`
+#[derive(Debug)]
+pub struct MediaDevice<D = RawDevice> {
+    dev: D,
+}
+
+impl MediaDevice<RawDevice> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        RawDevice::open(path).map(MediaDevice::new)
+    }
+}
+
+impl<D: Ioctl> MediaDevice<D> {
+    pub fn new(dev: D) -> Self {
+        MediaDevice { dev }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dev
+    }
+}
+
+impl<D: Ioctl> Ioctl for MediaDevice<D> {
+    unsafe fn ioctl(&self, request: libc::c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        self.dev.ioctl(request, arg)
+    }
+}
+
+impl<D: AsRawFd> AsRawFd for MediaDevice<D> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dev.as_raw_fd()
+    }
+}
+
+/// Result of MEDIA_IOC_DEVICE_INFO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub driver: String,
+    pub model: String,
+    pub serial: String,
+    pub bus_info: String,
+    pub media_version: u32,
+    pub hw_revision: u32,
+    pub driver_version: u32,
+}
+
+impl From<&crate::media_device_info> for DeviceInfo {
+    fn from(info: &crate::media_device_info) -> Self {
+        DeviceInfo {
+            driver: c_string(&info.driver),
+            model: c_string(&info.model),
+            serial: c_string(&info.serial),
+            bus_info: c_string(&info.bus_info),
+            media_version: info.media_version,
+            hw_revision: info.hw_revision,
+            driver_version: info.driver_version,
+        }
+    }
+}
+
+/// MEDIA_IOC_DEVICE_INFO
+pub fn device_info<D: Ioctl>(dev: &MediaDevice<D>) -> io::Result<DeviceInfo> {
+    let mut info: crate::media_device_info = unsafe { mem::zeroed() };
+    ioctl(dev, media_codes::MEDIA_IOC_DEVICE_INFO, &mut info)?;
+    Ok(DeviceInfo::from(&info))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entity {
+    pub id: u32,
+    pub name: String,
+    /// `MEDIA_ENT_F_*`
+    pub function: u32,
+    /// `MEDIA_ENT_FL_*`
+    pub flags: u32,
+}
+
+impl Entity {
+    /// DMA engine with a video node, where a pipeline ends
+    pub fn is_io(&self) -> bool {
+        self.function == crate::MEDIA_ENT_F_IO_V4L
+    }
+}
+
+impl From<&crate::media_v2_entity> for Entity {
+    fn from(e: &crate::media_v2_entity) -> Self {
+        Entity {
+            id: e.id,
+            name: c_string(&e.name),
+            function: e.function,
+            flags: e.flags,
+        }
+    }
+}
+
+/// A device node through which entities are controlled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interface {
+    pub id: u32,
+    /// `MEDIA_INTF_T_*`
+    pub intf_type: u32,
+    pub flags: u32,
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Interface {
+    pub fn is_video(&self) -> bool {
+        self.intf_type == crate::MEDIA_INTF_T_V4L_VIDEO
+    }
+
+    pub fn is_subdev(&self) -> bool {
+        self.intf_type == crate::MEDIA_INTF_T_V4L_SUBDEV
+    }
+
+    /// Path of the device node, as named by udev in `/sys/dev/char`
+    pub fn path(&self) -> io::Result<PathBuf> {
+        devnode_path(self.major, self.minor)
+    }
+}
+
+impl From<&crate::media_v2_interface> for Interface {
+    fn from(i: &crate::media_v2_interface) -> Self {
+        let devnode = unsafe { i.__bindgen_anon_1.devnode };
+        Interface {
+            id: i.id,
+            intf_type: i.intf_type,
+            flags: i.flags,
+            major: devnode.major,
+            minor: devnode.minor,
+        }
+    }
+}
+
+/// `/dev` path of the character device `major:minor`
+pub fn devnode_path(major: u32, minor: u32) -> io::Result<PathBuf> {
+    let uevent = fs::read_to_string(format!("/sys/dev/char/{}:{}/uevent", major, minor))?;
+    uevent
+        .lines()
+        .find_map(|l| l.strip_prefix("DEVNAME="))
+        .map(|name| PathBuf::from("/dev").join(name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no DEVNAME in uevent"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pad {
+    pub id: u32,
+    pub entity_id: u32,
+    /// Position among the pads of the entity, as used by sub-device ioctls
+    pub index: u32,
+    /// `MEDIA_PAD_FL_*`
+    pub flags: u32,
+}
+
+impl Pad {
+    pub fn is_sink(&self) -> bool {
+        self.flags & crate::MEDIA_PAD_FL_SINK != 0
+    }
+
+    pub fn is_source(&self) -> bool {
+        self.flags & crate::MEDIA_PAD_FL_SOURCE != 0
+    }
+}
+
+impl From<&crate::media_v2_pad> for Pad {
+    fn from(p: &crate::media_v2_pad) -> Self {
+        Pad {
+            id: p.id,
+            entity_id: p.entity_id,
+            index: p.index,
+            flags: p.flags,
+        }
+    }
+}
+
+/// A data link from a source pad to a sink pad, or an interface link from
+/// an interface to an entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub id: u32,
+    pub source_id: u32,
+    pub sink_id: u32,
+    /// `MEDIA_LNK_FL_*`
+    pub flags: u32,
+}
+
+impl Link {
+    pub fn is_enabled(&self) -> bool {
+        self.flags & crate::MEDIA_LNK_FL_ENABLED != 0
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        self.flags & crate::MEDIA_LNK_FL_IMMUTABLE != 0
+    }
+
+    pub fn is_data_link(&self) -> bool {
+        self.flags & crate::MEDIA_LNK_FL_LINK_TYPE == crate::MEDIA_LNK_FL_DATA_LINK
+    }
+
+    pub fn is_interface_link(&self) -> bool {
+        self.flags & crate::MEDIA_LNK_FL_LINK_TYPE == crate::MEDIA_LNK_FL_INTERFACE_LINK
+    }
+}
+
+impl From<&crate::media_v2_link> for Link {
+    fn from(l: &crate::media_v2_link) -> Self {
+        Link {
+            id: l.id,
+            source_id: l.source_id,
+            sink_id: l.sink_id,
+            flags: l.flags,
+        }
+    }
+}
+
+/// The whole graph of a media device. Ids are unique across all objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// Bumped by the kernel whenever the graph changes
+    pub version: u64,
+    pub entities: Vec<Entity>,
+    pub interfaces: Vec<Interface>,
+    pub pads: Vec<Pad>,
+    pub links: Vec<Link>,
+}
+
+impl Topology {
+    pub fn entity(&self, id: u32) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.id == id)
+    }
+
+    pub fn entity_by_name(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.name == name)
+    }
+
+    pub fn pad(&self, id: u32) -> Option<&Pad> {
+        self.pads.iter().find(|p| p.id == id)
+    }
+
+    /// Pad `index` of `entity`
+    pub fn pad_at(&self, entity: u32, index: u32) -> Option<&Pad> {
+        self.pads
+            .iter()
+            .find(|p| p.entity_id == entity && p.index == index)
+    }
+
+    pub fn link(&self, id: u32) -> Option<&Link> {
+        self.links.iter().find(|l| l.id == id)
+    }
+
+    /// The data link from pad `source` to pad `sink`
+    pub fn link_between(&self, source: u32, sink: u32) -> Option<&Link> {
+        self.links
+            .iter()
+            .find(|l| l.is_data_link() && l.source_id == source && l.sink_id == sink)
+    }
+
+    /// Data links leaving `entity`
+    pub fn links_from(&self, entity: u32) -> impl Iterator<Item = &Link> {
+        self.links.iter().filter(move |l| {
+            l.is_data_link() && self.pad(l.source_id).map(|p| p.entity_id) == Some(entity)
+        })
+    }
+
+    /// Data links entering `entity`
+    pub fn links_to(&self, entity: u32) -> impl Iterator<Item = &Link> {
+        self.links.iter().filter(move |l| {
+            l.is_data_link() && self.pad(l.sink_id).map(|p| p.entity_id) == Some(entity)
+        })
+    }
+
+    /// Interfaces controlling `entity`
+    pub fn interfaces(&self, entity: u32) -> impl Iterator<Item = &Interface> {
+        self.links
+            .iter()
+            .filter(move |l| l.is_interface_link() && l.sink_id == entity)
+            .filter_map(|l| self.interfaces.iter().find(|i| i.id == l.source_id))
+    }
+
+    /// Device node of `entity`, e.g. `/dev/video0` for a DMA engine or
+    /// `/dev/v4l-subdev2` for a sensor
+    pub fn video_node(&self, entity: u32) -> io::Result<PathBuf> {
+        self.interfaces(entity)
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "entity has no device node"))?
+            .path()
+    }
+
+    /// Read back the [`Display`](fmt::Display) form. Blank lines and lines
+    /// starting with `#` are skipped, and `flags` may be omitted.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut topology = Topology::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            parse_line(&mut topology, line).map_err(|message| ParseError {
+                line: n + 1,
+                message,
+            })?;
+        }
+        Ok(topology)
+    }
+
+    fn link_desc(&self, id: u32) -> Option<crate::media_link_desc> {
+        let link = self.link(id).filter(|l| l.is_data_link())?;
+        let pad_desc = |id| {
+            let pad = self.pad(id)?;
+            let mut desc: crate::media_pad_desc = unsafe { mem::zeroed() };
+            desc.entity = pad.entity_id;
+            desc.index = pad.index as u16;
+            desc.flags = pad.flags;
+            Some(desc)
+        };
+        let mut desc: crate::media_link_desc = unsafe { mem::zeroed() };
+        desc.source = pad_desc(link.source_id)?;
+        desc.sink = pad_desc(link.sink_id)?;
+        desc.flags = link.flags;
+        Some(desc)
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "version {}", self.version)?;
+        for e in &self.entities {
+            writeln!(
+                f,
+                "entity id={} function={:#x} flags={:#x} name=\"{}\"",
+                e.id,
+                e.function,
+                e.flags,
+                escape(&e.name)
+            )?;
+        }
+        for i in &self.interfaces {
+            writeln!(
+                f,
+                "interface id={} type={:#x} flags={:#x} devnode={}:{}",
+                i.id, i.intf_type, i.flags, i.major, i.minor
+            )?;
+        }
+        for p in &self.pads {
+            writeln!(
+                f,
+                "pad id={} entity={} index={} flags={:#x}",
+                p.id, p.entity_id, p.index, p.flags
+            )?;
+        }
+        for l in &self.links {
+            writeln!(
+                f,
+                "link id={} source={} sink={} flags={:#x}",
+                l.id, l.source_id, l.sink_id, l.flags
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Error from [`Topology::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn number(value: &str) -> Result<u64, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("invalid number `{}`", value))
+}
+
+/// Backslash-escape `\`, `"` and line breaks so a name stays one quoted word
+fn escape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' | '"' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Read a quoted name written by [`escape`], which must end the line
+fn unescape(quoted: &str) -> Result<String, String> {
+    let mut chars = quoted
+        .strip_prefix('"')
+        .ok_or("name must be quoted")?
+        .chars();
+    let mut name = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.as_str().is_empty() => return Ok(name),
+            '"' => return Err("unexpected text after name".into()),
+            '\\' => match chars.next() {
+                Some('n') => name.push('\n'),
+                Some(c @ ('\\' | '"')) => name.push(c),
+                _ => return Err("invalid escape in name".into()),
+            },
+            _ => name.push(c),
+        }
+    }
+    Err("name must be quoted".into())
+}
+
+fn parse_line(topology: &mut Topology, line: &str) -> Result<(), String> {
+    // The name goes last and may contain spaces; no other field contains
+    // ` name=`, so the first one starts it
+    let (line, name) = match line.split_once(" name=") {
+        Some((head, name)) => (head, Some(unescape(name)?)),
+        None => (line, None),
+    };
+    let mut words = line.split_whitespace();
+    let kind = words.next().unwrap_or_default();
+    if kind == "version" {
+        topology.version = number(words.next().ok_or("missing version")?)?;
+        return Ok(());
+    }
+    let fields = words
+        .map(|w| {
+            w.split_once('=')
+                .ok_or_else(|| format!("expected key=value, got `{}`", w))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let raw = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| format!("missing `{}`", key))
+    };
+    let get = |key: &str| {
+        let value = raw(key)?;
+        u32::try_from(number(value)?).map_err(|_| format!("`{}` out of range", value))
+    };
+    let flags = if fields.iter().any(|(k, _)| *k == "flags") {
+        get("flags")?
+    } else {
+        0
+    };
+    match kind {
+        "entity" => topology.entities.push(Entity {
+            id: get("id")?,
+            name: name.ok_or("missing `name`")?,
+            function: get("function")?,
+            flags,
+        }),
+        "interface" => {
+            let devnode = raw("devnode")?;
+            let (major, minor) = devnode
+                .split_once(':')
+                .ok_or_else(|| format!("invalid devnode `{}`", devnode))?;
+            topology.interfaces.push(Interface {
+                id: get("id")?,
+                intf_type: get("type")?,
+                flags,
+                major: major
+                    .parse()
+                    .map_err(|_| format!("invalid major `{}`", major))?,
+                minor: minor
+                    .parse()
+                    .map_err(|_| format!("invalid minor `{}`", minor))?,
+            })
+        }
+        "pad" => topology.pads.push(Pad {
+            id: get("id")?,
+            entity_id: get("entity")?,
+            index: get("index")?,
+            flags,
+        }),
+        "link" => topology.links.push(Link {
+            id: get("id")?,
+            source_id: get("source")?,
+            sink_id: get("sink")?,
+            flags,
+        }),
+        _ => return Err(format!("unknown object `{}`", kind)),
+    }
+    Ok(())
+}
+
+/// MEDIA_IOC_G_TOPOLOGY
+pub fn topology<D: Ioctl>(dev: &MediaDevice<D>) -> io::Result<Topology> {
+    loop {
+        let mut t: crate::media_v2_topology = unsafe { mem::zeroed() };
+        ioctl(dev, media_codes::MEDIA_IOC_G_TOPOLOGY, &mut t)?;
+        let version = t.topology_version;
+        let mut entities: Vec<crate::media_v2_entity> =
+            vec![unsafe { mem::zeroed() }; t.num_entities as usize];
+        let mut interfaces: Vec<crate::media_v2_interface> =
+            vec![unsafe { mem::zeroed() }; t.num_interfaces as usize];
+        let mut pads: Vec<crate::media_v2_pad> =
+            vec![unsafe { mem::zeroed() }; t.num_pads as usize];
+        let mut links: Vec<crate::media_v2_link> =
+            vec![unsafe { mem::zeroed() }; t.num_links as usize];
+        t.ptr_entities = entities.as_mut_ptr() as u64;
+        t.ptr_interfaces = interfaces.as_mut_ptr() as u64;
+        t.ptr_pads = pads.as_mut_ptr() as u64;
+        t.ptr_links = links.as_mut_ptr() as u64;
+        match ioctl(dev, media_codes::MEDIA_IOC_G_TOPOLOGY, &mut t) {
+            // The graph grew in between
+            Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => continue,
+            r => r?,
+        }
+        if t.topology_version != version {
+            continue;
+        }
+        entities.truncate(t.num_entities as usize);
+        interfaces.truncate(t.num_interfaces as usize);
+        pads.truncate(t.num_pads as usize);
+        links.truncate(t.num_links as usize);
+        return Ok(Topology {
+            version,
+            entities: entities.iter().map(Entity::from).collect(),
+            interfaces: interfaces.iter().map(Interface::from).collect(),
+            pads: pads.iter().map(Pad::from).collect(),
+            links: links.iter().map(Link::from).collect(),
+        });
+    }
+}
+
+/// MEDIA_IOC_ENUM_ENTITIES, the enumeration of kernels without
+/// MEDIA_IOC_G_TOPOLOGY. `function` holds the legacy entity type.
+pub fn enum_entities<D: Ioctl>(dev: &MediaDevice<D>) -> io::Result<Vec<Entity>> {
+    let mut entities = Vec::new();
+    let mut id = 0;
+    loop {
+        let mut desc: crate::media_entity_desc = unsafe { mem::zeroed() };
+        desc.id = id | crate::MEDIA_ENT_ID_FLAG_NEXT;
+        match ioctl(dev, media_codes::MEDIA_IOC_ENUM_ENTITIES, &mut desc) {
+            Ok(()) => (),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+        id = desc.id;
+        entities.push(Entity {
+            id: desc.id,
+            name: c_string(&desc.name),
+            function: desc.type_,
+            flags: desc.flags,
+        });
+    }
+    Ok(entities)
+}
+
+/// MEDIA_IOC_SETUP_LINK: enable or disable the data link `link` and record
+/// the new state in `topology`. Immutable links are refused by the kernel.
+pub fn setup_link<D: Ioctl>(
+    dev: &MediaDevice<D>,
+    topology: &mut Topology,
+    link: u32,
+    enable: bool,
+) -> io::Result<()> {
+    let mut desc = topology
+        .link_desc(link)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such data link"))?;
+    if enable {
+        desc.flags |= crate::MEDIA_LNK_FL_ENABLED;
+    } else {
+        desc.flags &= !crate::MEDIA_LNK_FL_ENABLED;
+    }
+    ioctl(dev, media_codes::MEDIA_IOC_SETUP_LINK, &mut desc)?;
+    for l in topology.links.iter_mut().filter(|l| l.id == link) {
+        l.flags = desc.flags;
+    }
+    Ok(())
+}
+
+/// Disable every enabled data link that is not immutable, the starting
+/// point for configuring a new pipeline
+pub fn reset_links<D: Ioctl>(dev: &MediaDevice<D>, topology: &mut Topology) -> io::Result<()> {
+    let ids: Vec<u32> = topology
+        .links
+        .iter()
+        .filter(|l| l.is_data_link() && l.is_enabled() && !l.is_immutable())
+        .map(|l| l.id)
+        .collect();
+    for id in ids {
+        setup_link(dev, topology, id, false)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    /// A sensor feeding an ISP with two output paths
+    const RKISP1: &str = r#"
+# rkisp1 with an imx219
+version 7
+entity id=1 function=0x20001 name="imx219 1-0010"
+entity id=4 function=0x4009 name="rkisp1_isp"
+entity id=8 function=0x10001 name="rkisp1_mainpath"
+entity id=12 function=0x10001 name="rkisp1_selfpath"
+interface id=20 type=0x200 devnode=81:0
+interface id=21 type=0x200 devnode=81:1
+interface id=22 type=0x203 devnode=81:2
+pad id=2 entity=1 index=0 flags=0x2
+pad id=5 entity=4 index=0 flags=0x1
+pad id=6 entity=4 index=2 flags=0x2
+pad id=9 entity=8 index=0 flags=0x1
+pad id=13 entity=12 index=0 flags=0x1
+link id=3 source=2 sink=5 flags=0x3
+link id=7 source=6 sink=9 flags=0x1
+link id=11 source=6 sink=13
+link id=23 source=20 sink=8 flags=0x10000003
+link id=24 source=21 sink=12 flags=0x10000003
+link id=25 source=22 sink=1 flags=0x10000003
+"#;
+
+    #[test]
+    fn serialized_graph() {
+        let topology = Topology::parse(RKISP1).unwrap();
+        assert_eq!(Topology::parse(&topology.to_string()).unwrap(), topology);
+
+        let isp = topology.entity_by_name("rkisp1_isp").unwrap();
+        let sinks: Vec<_> = topology
+            .links_from(isp.id)
+            .map(|l| topology.pad(l.sink_id).unwrap().entity_id)
+            .collect();
+        assert_eq!(sinks, [8, 12]);
+        assert_eq!(topology.links_to(isp.id).count(), 1);
+        assert!(topology.pad_at(isp.id, 2).unwrap().is_source());
+
+        let selfpath = topology.entity_by_name("rkisp1_selfpath").unwrap();
+        assert!(selfpath.is_io());
+        let node = topology.interfaces(selfpath.id).next().unwrap();
+        assert!(node.is_video());
+        assert_eq!((node.major, node.minor), (81, 1));
+        let sensor = topology.interfaces(1).next().unwrap();
+        assert!(sensor.is_subdev());
+        assert!(topology.interfaces(isp.id).next().is_none());
+
+        let err = Topology::parse("version 1\n\nlink id=3 source=2").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "missing `sink`");
+    }
+
+    #[test]
+    fn quoted_names() {
+        let mut topology = Topology::default();
+        for (id, name) in [
+            (1, r#"say "cheese""#),
+            (2, "a name=\"b\" \\ c"),
+            (3, "two\nlines"),
+            (4, ""),
+        ] {
+            topology.entities.push(Entity {
+                id,
+                name: name.into(),
+                function: crate::MEDIA_ENT_F_CAM_SENSOR,
+                flags: 0,
+            });
+        }
+        let text = topology.to_string();
+        assert_eq!(text.lines().count(), 5);
+        assert!(text.contains(r#"name="a name=\"b\" \\ c""#));
+        assert_eq!(Topology::parse(&text).unwrap(), topology);
+
+        for bad in [
+            r#"entity id=1 function=0 name="a"b""#,
+            r#"entity id=1 function=0 name="a\x""#,
+            r#"entity id=1 function=0 name="open"#,
+            "entity id=1 function=0 name=bare",
+        ] {
+            assert!(Topology::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn setup_links() {
+        let fake = FakeDevice::default();
+        *fake.media.borrow_mut() = Some(Topology::parse(RKISP1).unwrap());
+        let dev = MediaDevice::new(&fake);
+        assert_eq!(device_info(&dev).unwrap().driver, "fake-media");
+
+        let mut topology = topology(&dev).unwrap();
+        assert_eq!(Some(&topology), fake.media.borrow().as_ref());
+        reset_links(&dev, &mut topology).unwrap();
+        setup_link(&dev, &mut topology, 11, true).unwrap();
+        assert!(!topology.link(7).unwrap().is_enabled());
+        assert!(topology.link_between(6, 13).unwrap().is_enabled());
+        assert_eq!(Some(&topology), fake.media.borrow().as_ref());
+
+        let err = setup_link(&dev, &mut topology, 3, false).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        let err = setup_link(&dev, &mut topology, 23, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,504 @@
+//! Tuners, modulators and radio frequencies
+//!
+//! Frequencies are in Hz on the Rust side; the driver's tuning unit
+//! (62.5 kHz, 62.5 Hz with `V4L2_TUNER_CAP_LOW`, or 1 Hz with
+//! `V4L2_TUNER_CAP_1HZ`) is applied on the way in and out.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/tuner.html
+
+use std::fmt;
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{c_string, ioctl, Ioctl};
+
+/// `enum v4l2_tuner_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TunerType {
+    Radio,
+    AnalogTv,
+    DigitalTv,
+    Sdr,
+    Rf,
+    Other(u32),
+}
+
+impl TunerType {
+    pub fn from_raw(type_: u32) -> Self {
+        match type_ {
+            crate::v4l2_tuner_type_V4L2_TUNER_RADIO => TunerType::Radio,
+            crate::v4l2_tuner_type_V4L2_TUNER_ANALOG_TV => TunerType::AnalogTv,
+            crate::v4l2_tuner_type_V4L2_TUNER_DIGITAL_TV => TunerType::DigitalTv,
+            crate::v4l2_tuner_type_V4L2_TUNER_SDR => TunerType::Sdr,
+            crate::v4l2_tuner_type_V4L2_TUNER_RF => TunerType::Rf,
+            other => TunerType::Other(other),
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            TunerType::Radio => crate::v4l2_tuner_type_V4L2_TUNER_RADIO,
+            TunerType::AnalogTv => crate::v4l2_tuner_type_V4L2_TUNER_ANALOG_TV,
+            TunerType::DigitalTv => crate::v4l2_tuner_type_V4L2_TUNER_DIGITAL_TV,
+            TunerType::Sdr => crate::v4l2_tuner_type_V4L2_TUNER_SDR,
+            TunerType::Rf => crate::v4l2_tuner_type_V4L2_TUNER_RF,
+            TunerType::Other(raw) => raw,
+        }
+    }
+}
+
+/// Tuning unit selected by the `capability` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrequencyUnit {
+    /// 62.5 kHz, the default
+    Khz62_5,
+    /// 62.5 Hz, `V4L2_TUNER_CAP_LOW`
+    Hz62_5,
+    /// 1 Hz, `V4L2_TUNER_CAP_1HZ`
+    Hz1,
+}
+
+impl FrequencyUnit {
+    pub fn from_capability(capability: u32) -> Self {
+        if capability & crate::V4L2_TUNER_CAP_1HZ != 0 {
+            FrequencyUnit::Hz1
+        } else if capability & crate::V4L2_TUNER_CAP_LOW != 0 {
+            FrequencyUnit::Hz62_5
+        } else {
+            FrequencyUnit::Khz62_5
+        }
+    }
+
+    pub fn to_hz(self, units: u32) -> u64 {
+        let units = units as u64;
+        match self {
+            FrequencyUnit::Khz62_5 => units * 62_500,
+            FrequencyUnit::Hz62_5 => units * 125 / 2,
+            FrequencyUnit::Hz1 => units,
+        }
+    }
+
+    /// Nearest representable value, saturating at `u32::MAX` units
+    pub fn from_hz(self, hz: u64) -> u32 {
+        let units = match self {
+            FrequencyUnit::Khz62_5 => hz.saturating_add(31_250) / 62_500,
+            FrequencyUnit::Hz62_5 => hz.saturating_mul(2).saturating_add(62) / 125,
+            FrequencyUnit::Hz1 => hz,
+        };
+        units.min(u32::MAX as u64) as u32
+    }
+}
+
+/// `V4L2_TUNER_MODE_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioMode {
+    Mono,
+    Stereo,
+    /// Second language, or SAP
+    Lang2,
+    Lang1,
+    Lang1Lang2,
+    Other(u32),
+}
+
+impl AudioMode {
+    pub fn from_raw(mode: u32) -> Self {
+        match mode {
+            crate::V4L2_TUNER_MODE_MONO => AudioMode::Mono,
+            crate::V4L2_TUNER_MODE_STEREO => AudioMode::Stereo,
+            crate::V4L2_TUNER_MODE_LANG2 => AudioMode::Lang2,
+            crate::V4L2_TUNER_MODE_LANG1 => AudioMode::Lang1,
+            crate::V4L2_TUNER_MODE_LANG1_LANG2 => AudioMode::Lang1Lang2,
+            other => AudioMode::Other(other),
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            AudioMode::Mono => crate::V4L2_TUNER_MODE_MONO,
+            AudioMode::Stereo => crate::V4L2_TUNER_MODE_STEREO,
+            AudioMode::Lang2 => crate::V4L2_TUNER_MODE_LANG2,
+            AudioMode::Lang1 => crate::V4L2_TUNER_MODE_LANG1,
+            AudioMode::Lang1Lang2 => crate::V4L2_TUNER_MODE_LANG1_LANG2,
+            AudioMode::Other(raw) => raw,
+        }
+    }
+}
+
+/// `V4L2_TUNER_SUB_*` flags, received (`rxsubchans`) or transmitted
+/// (`txsubchans`) audio subprograms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SubChannels(pub u32);
+
+impl SubChannels {
+    pub fn mono(self) -> bool {
+        self.0 & crate::V4L2_TUNER_SUB_MONO != 0
+    }
+
+    pub fn stereo(self) -> bool {
+        self.0 & crate::V4L2_TUNER_SUB_STEREO != 0
+    }
+
+    pub fn lang1(self) -> bool {
+        self.0 & crate::V4L2_TUNER_SUB_LANG1 != 0
+    }
+
+    pub fn lang2(self) -> bool {
+        self.0 & crate::V4L2_TUNER_SUB_LANG2 != 0
+    }
+
+    pub fn rds(self) -> bool {
+        self.0 & crate::V4L2_TUNER_SUB_RDS != 0
+    }
+}
+
+impl fmt::Display for SubChannels {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const NAMES: [(u32, &str); 5] = [
+            (crate::V4L2_TUNER_SUB_MONO, "mono"),
+            (crate::V4L2_TUNER_SUB_STEREO, "stereo"),
+            (crate::V4L2_TUNER_SUB_LANG1, "lang1"),
+            (crate::V4L2_TUNER_SUB_LANG2, "lang2"),
+            (crate::V4L2_TUNER_SUB_RDS, "rds"),
+        ];
+        let names: Vec<_> = NAMES
+            .iter()
+            .filter(|(flag, _)| self.0 & flag != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        f.write_str(&names.join(" "))
+    }
+}
+
+/// VIDIOC_G_TUNER
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuner {
+    pub index: u32,
+    pub name: String,
+    pub type_: TunerType,
+    /// `V4L2_TUNER_CAP_*`
+    pub capability: u32,
+    pub rangelow_hz: u64,
+    pub rangehigh_hz: u64,
+    pub rxsubchans: SubChannels,
+    pub audmode: AudioMode,
+    /// Signal strength, 0 to 65535
+    pub signal: i32,
+    /// Automatic frequency control: negative means tuned too low, positive
+    /// too high
+    pub afc: i32,
+}
+
+impl Tuner {
+    pub fn unit(&self) -> FrequencyUnit {
+        FrequencyUnit::from_capability(self.capability)
+    }
+
+    /// `signal` scaled to 0..=100
+    pub fn signal_percent(&self) -> u8 {
+        (self.signal.clamp(0, 65535) * 100 / 65535) as u8
+    }
+
+    pub fn has_capability(&self, cap: u32) -> bool {
+        self.capability & cap == cap
+    }
+}
+
+impl From<&crate::v4l2_tuner> for Tuner {
+    fn from(t: &crate::v4l2_tuner) -> Self {
+        let unit = FrequencyUnit::from_capability(t.capability);
+        Tuner {
+            index: t.index,
+            name: c_string(&t.name),
+            type_: TunerType::from_raw(t.type_),
+            capability: t.capability,
+            rangelow_hz: unit.to_hz(t.rangelow),
+            rangehigh_hz: unit.to_hz(t.rangehigh),
+            rxsubchans: SubChannels(t.rxsubchans),
+            audmode: AudioMode::from_raw(t.audmode),
+            signal: t.signal,
+            afc: t.afc,
+        }
+    }
+}
+
+/// VIDIOC_G_MODULATOR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Modulator {
+    pub index: u32,
+    pub name: String,
+    pub type_: TunerType,
+    /// `V4L2_TUNER_CAP_*`
+    pub capability: u32,
+    pub rangelow_hz: u64,
+    pub rangehigh_hz: u64,
+    pub txsubchans: SubChannels,
+}
+
+impl Modulator {
+    pub fn unit(&self) -> FrequencyUnit {
+        FrequencyUnit::from_capability(self.capability)
+    }
+}
+
+impl From<&crate::v4l2_modulator> for Modulator {
+    fn from(m: &crate::v4l2_modulator) -> Self {
+        let unit = FrequencyUnit::from_capability(m.capability);
+        Modulator {
+            index: m.index,
+            name: c_string(&m.name),
+            type_: TunerType::from_raw(m.type_),
+            capability: m.capability,
+            rangelow_hz: unit.to_hz(m.rangelow),
+            rangehigh_hz: unit.to_hz(m.rangehigh),
+            txsubchans: SubChannels(m.txsubchans),
+        }
+    }
+}
+
+/// One entry of VIDIOC_ENUM_FREQ_BANDS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequencyBand {
+    pub index: u32,
+    /// `V4L2_TUNER_CAP_*` valid within this band
+    pub capability: u32,
+    pub rangelow_hz: u64,
+    pub rangehigh_hz: u64,
+    /// `V4L2_BAND_MODULATION_*`
+    pub modulation: u32,
+}
+
+impl FrequencyBand {
+    pub fn contains(&self, hz: u64) -> bool {
+        (self.rangelow_hz..=self.rangehigh_hz).contains(&hz)
+    }
+}
+
+pub fn tuner<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<Tuner> {
+    let mut t: crate::v4l2_tuner = unsafe { mem::zeroed() };
+    t.index = index;
+    ioctl(dev, codes::VIDIOC_G_TUNER, &mut t)?;
+    Ok(Tuner::from(&t))
+}
+
+/// VIDIOC_S_TUNER; the audio mode is the only writable field
+pub fn set_audio_mode<D: Ioctl + ?Sized>(dev: &D, index: u32, mode: AudioMode) -> io::Result<()> {
+    let mut t: crate::v4l2_tuner = unsafe { mem::zeroed() };
+    t.index = index;
+    t.audmode = mode.as_raw();
+    ioctl(dev, codes::VIDIOC_S_TUNER, &mut t)
+}
+
+pub fn modulator<D: Ioctl + ?Sized>(dev: &D, index: u32) -> io::Result<Modulator> {
+    let mut m: crate::v4l2_modulator = unsafe { mem::zeroed() };
+    m.index = index;
+    ioctl(dev, codes::VIDIOC_G_MODULATOR, &mut m)?;
+    Ok(Modulator::from(&m))
+}
+
+/// VIDIOC_S_MODULATOR; the transmitted subchannels are the only writable field
+pub fn set_txsubchans<D: Ioctl + ?Sized>(
+    dev: &D,
+    index: u32,
+    txsubchans: SubChannels,
+) -> io::Result<()> {
+    let mut m: crate::v4l2_modulator = unsafe { mem::zeroed() };
+    m.index = index;
+    m.txsubchans = txsubchans.0;
+    ioctl(dev, codes::VIDIOC_S_MODULATOR, &mut m)
+}
+
+fn g_frequency<D: Ioctl + ?Sized>(
+    dev: &D,
+    index: u32,
+    type_: TunerType,
+    unit: FrequencyUnit,
+) -> io::Result<u64> {
+    let mut f: crate::v4l2_frequency = unsafe { mem::zeroed() };
+    f.tuner = index;
+    f.type_ = type_.as_raw();
+    ioctl(dev, codes::VIDIOC_G_FREQUENCY, &mut f)?;
+    Ok(unit.to_hz(f.frequency))
+}
+
+fn s_frequency<D: Ioctl + ?Sized>(
+    dev: &D,
+    index: u32,
+    type_: TunerType,
+    unit: FrequencyUnit,
+    hz: u64,
+) -> io::Result<u64> {
+    let mut f: crate::v4l2_frequency = unsafe { mem::zeroed() };
+    f.tuner = index;
+    f.type_ = type_.as_raw();
+    f.frequency = unit.from_hz(hz);
+    ioctl(dev, codes::VIDIOC_S_FREQUENCY, &mut f)?;
+    // drivers clamp to the nearest supported value, so read it back
+    g_frequency(dev, index, type_, unit)
+}
+
+/// Current frequency of `tuner` in Hz
+pub fn frequency<D: Ioctl + ?Sized>(dev: &D, tuner: &Tuner) -> io::Result<u64> {
+    g_frequency(dev, tuner.index, tuner.type_, tuner.unit())
+}
+
+/// Tune to `hz` and return the frequency the driver settled on
+pub fn set_frequency<D: Ioctl + ?Sized>(dev: &D, tuner: &Tuner, hz: u64) -> io::Result<u64> {
+    s_frequency(dev, tuner.index, tuner.type_, tuner.unit(), hz)
+}
+
+pub fn modulator_frequency<D: Ioctl + ?Sized>(dev: &D, m: &Modulator) -> io::Result<u64> {
+    g_frequency(dev, m.index, m.type_, m.unit())
+}
+
+pub fn set_modulator_frequency<D: Ioctl + ?Sized>(
+    dev: &D,
+    m: &Modulator,
+    hz: u64,
+) -> io::Result<u64> {
+    s_frequency(dev, m.index, m.type_, m.unit(), hz)
+}
+
+/// VIDIOC_ENUM_FREQ_BANDS. Without `V4L2_TUNER_CAP_FREQ_BANDS` the tuner's own
+/// range is returned as the only band.
+pub fn enum_freq_bands<D: Ioctl + ?Sized>(
+    dev: &D,
+    tuner: &Tuner,
+) -> io::Result<Vec<FrequencyBand>> {
+    if !tuner.has_capability(crate::V4L2_TUNER_CAP_FREQ_BANDS) {
+        return Ok(vec![FrequencyBand {
+            index: 0,
+            capability: tuner.capability,
+            rangelow_hz: tuner.rangelow_hz,
+            rangehigh_hz: tuner.rangehigh_hz,
+            modulation: 0,
+        }]);
+    }
+    let mut bands = Vec::new();
+    for index in 0.. {
+        let mut b: crate::v4l2_frequency_band = unsafe { mem::zeroed() };
+        b.tuner = tuner.index;
+        b.type_ = tuner.type_.as_raw();
+        b.index = index;
+        match ioctl(dev, codes::VIDIOC_ENUM_FREQ_BANDS, &mut b) {
+            Ok(()) => {
+                // a band may use a finer unit than the tuner
+                let unit = FrequencyUnit::from_capability(b.capability);
+                bands.push(FrequencyBand {
+                    index,
+                    capability: b.capability,
+                    rangelow_hz: unit.to_hz(b.rangelow),
+                    rangehigh_hz: unit.to_hz(b.rangehigh),
+                    modulation: b.modulation,
+                });
+            }
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(bands)
+}
+
+/// Parameters of a hardware seek
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Seek {
+    pub upward: bool,
+    /// Continue from the other end of the range, needs
+    /// `V4L2_TUNER_CAP_HWSEEK_WRAP`
+    pub wrap_around: bool,
+    /// Seek resolution in Hz, 0 for the driver default
+    pub spacing_hz: u32,
+    /// Limit the seek to this range, needs `V4L2_TUNER_CAP_HWSEEK_PROG_LIM`
+    pub range_hz: Option<(u64, u64)>,
+}
+
+/// VIDIOC_S_HW_FREQ_SEEK, returning the frequency of the station found.
+///
+/// Fails with ENODATA when no station was found and EAGAIN on timeout.
+pub fn seek<D: Ioctl + ?Sized>(dev: &D, tuner: &Tuner, params: &Seek) -> io::Result<u64> {
+    let unit = tuner.unit();
+    let mut s: crate::v4l2_hw_freq_seek = unsafe { mem::zeroed() };
+    s.tuner = tuner.index;
+    s.type_ = tuner.type_.as_raw();
+    s.seek_upward = params.upward as u32;
+    s.wrap_around = params.wrap_around as u32;
+    s.spacing = params.spacing_hz;
+    if let Some((low, high)) = params.range_hz {
+        s.rangelow = unit.from_hz(low);
+        s.rangehigh = unit.from_hz(high);
+    }
+    ioctl(dev, codes::VIDIOC_S_HW_FREQ_SEEK, &mut s)?;
+    frequency(dev, tuner)
+}
+
+/// Scan upward from the bottom of the tuner's range and collect every
+/// station the hardware seek stops at.
+pub fn scan<D: Ioctl + ?Sized>(dev: &D, tuner: &Tuner, spacing_hz: u32) -> io::Result<Vec<u64>> {
+    set_frequency(dev, tuner, tuner.rangelow_hz)?;
+    let params = Seek {
+        upward: true,
+        wrap_around: false,
+        spacing_hz,
+        range_hz: None,
+    };
+    let mut stations: Vec<u64> = Vec::new();
+    loop {
+        match seek(dev, tuner, &params) {
+            // a seek that stops at or below the last station has wrapped
+            Ok(hz) if stations.last().is_some_and(|&last| hz <= last) => break,
+            Ok(hz) => stations.push(hz),
+            Err(e) if e.raw_os_error() == Some(libc::ENODATA) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(stations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeDevice;
+
+    #[test]
+    fn unit_conversion() {
+        let unit = FrequencyUnit::from_capability(crate::V4L2_TUNER_CAP_LOW);
+        assert_eq!(unit, FrequencyUnit::Hz62_5);
+        assert_eq!(unit.to_hz(1_600_000), 100_000_000);
+        assert_eq!(unit.from_hz(100_000_000), 1_600_000);
+        assert_eq!(unit.from_hz(100_000_031), 1_600_000);
+        assert_eq!(unit.from_hz(100_000_032), 1_600_001);
+
+        let tv = FrequencyUnit::from_capability(crate::V4L2_TUNER_CAP_NORM);
+        assert_eq!(tv.to_hz(8_000), 500_000_000);
+        assert_eq!(tv.from_hz(500_020_000), 8_000);
+
+        let hz =
+            FrequencyUnit::from_capability(crate::V4L2_TUNER_CAP_LOW | crate::V4L2_TUNER_CAP_1HZ);
+        assert_eq!(hz.from_hz(u64::MAX), u32::MAX);
+        assert_eq!(unit.from_hz(u64::MAX), u32::MAX);
+        assert_eq!(tv.from_hz(u64::MAX), u32::MAX);
+        assert_eq!(
+            SubChannels(crate::V4L2_TUNER_SUB_STEREO | crate::V4L2_TUNER_SUB_RDS).to_string(),
+            "stereo rds"
+        );
+    }
+
+    #[test]
+    fn station_scan() {
+        let dev = FakeDevice::default();
+        let unit = FrequencyUnit::Hz62_5;
+        dev.stations
+            .borrow_mut()
+            .extend([88_100_000, 95_500_000, 104_300_000].map(|hz| unit.from_hz(hz)));
+        let t = tuner(&dev, 0).unwrap();
+        assert_eq!(t.type_, TunerType::Radio);
+        assert_eq!((t.rangelow_hz, t.rangehigh_hz), (87_500_000, 108_000_000));
+        assert_eq!(
+            scan(&dev, &t, 100_000).unwrap(),
+            [88_100_000, 95_500_000, 104_300_000]
+        );
+        assert_eq!(set_frequency(&dev, &t, 99_000_000).unwrap(), 99_000_000);
+        assert_eq!(frequency(&dev, &t).unwrap(), 99_000_000);
+        assert!(tuner(&dev, 1).is_err());
+    }
+}
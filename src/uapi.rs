@@ -0,0 +1,485 @@
+//! Kernel uapi definitions newer than linux 4.19, the oldest headers the
+//! crate is built against
+//!
+//! bindgen only sees what the installed headers declare, so these are
+//! written out here with the layout of the release that introduced them and
+//! used from this module rather than from the crate root. Names follow the C
+//! headers; anonymous unions are flattened to the member the crate uses.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/videodev.html
+
+/// `struct v4l2_area` (5.5)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_area {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// `V4L2_CTRL_TYPE_AREA` (5.5)
+pub const V4L2_CTRL_TYPE_AREA: u32 = 0x0106;
+
+/// `V4L2_CTRL_FLAG_DYNAMIC_ARRAY` (5.19)
+pub const V4L2_CTRL_FLAG_DYNAMIC_ARRAY: u32 = 0x0800;
+
+/// `V4L2_CID_CAMERA_ORIENTATION` (5.8)
+pub const V4L2_CID_CAMERA_ORIENTATION: u32 = 0x009a_0922;
+/// `V4L2_CAMERA_ORIENTATION_*` (5.8)
+pub const V4L2_CAMERA_ORIENTATION_FRONT: u32 = 0;
+pub const V4L2_CAMERA_ORIENTATION_BACK: u32 = 1;
+pub const V4L2_CAMERA_ORIENTATION_EXTERNAL: u32 = 2;
+
+/// `V4L2_CID_CAMERA_SENSOR_ROTATION` (5.8)
+pub const V4L2_CID_CAMERA_SENSOR_ROTATION: u32 = 0x009a_0923;
+
+/// `V4L2_CID_UNIT_CELL_SIZE` (5.5)
+pub const V4L2_CID_UNIT_CELL_SIZE: u32 = 0x009e_0908;
+
+/// `V4L2_CID_MPEG_VIDEO_VP8_PROFILE` (5.0); the same id as the older
+/// `V4L2_CID_MPEG_VIDEO_VPX_PROFILE`
+pub const V4L2_CID_MPEG_VIDEO_VP8_PROFILE: u32 = 0x0099_0aff;
+
+/// `V4L2_CID_MPEG_VIDEO_VP9_PROFILE` (5.0)
+pub const V4L2_CID_MPEG_VIDEO_VP9_PROFILE: u32 = 0x0099_0b00;
+
+/// `struct v4l2_ext_controls` with `request_fd` (4.20)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_ext_controls {
+    /// `which`, or `ctrl_class` before 4.4
+    pub which: u32,
+    pub count: u32,
+    pub error_idx: u32,
+    pub request_fd: i32,
+    pub reserved: [u32; 1],
+    pub controls: *mut crate::v4l2_ext_control,
+}
+
+/// `MEDIA_BUS_FMT_Y16_1X16`, not in 6.1
+pub const MEDIA_BUS_FMT_Y16_1X16: u32 = 0x202e;
+
+/// `MEDIA_BUS_FMT_METADATA_FIXED`, not in 4.19
+pub const MEDIA_BUS_FMT_METADATA_FIXED: u32 = 0x7001;
+
+/// `struct v4l2_mbus_framefmt` with `flags` (5.10), which older headers
+/// declare as the first reserved word
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_mbus_framefmt {
+    pub width: u32,
+    pub height: u32,
+    pub code: u32,
+    pub field: u32,
+    pub colorspace: u32,
+    /// `ycbcr_enc`, or `hsv_enc` for HSV formats
+    pub ycbcr_enc: u16,
+    pub quantization: u16,
+    pub xfer_func: u16,
+    pub flags: u16,
+    pub reserved: [u16; 10],
+}
+
+/// `V4L2_SUBDEV_CAP_RO_SUBDEV` (5.10)
+pub const V4L2_SUBDEV_CAP_RO_SUBDEV: u32 = 0x1;
+
+/// `V4L2_SUBDEV_CAP_STREAMS` (6.3)
+pub const V4L2_SUBDEV_CAP_STREAMS: u32 = 0x2;
+
+/// `struct v4l2_subdev_capability` (5.10)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_subdev_capability {
+    pub version: u32,
+    pub capabilities: u32,
+    pub reserved: [u32; 14],
+}
+
+/// `V4L2_SUBDEV_ROUTE_FL_ACTIVE` (6.3)
+pub const V4L2_SUBDEV_ROUTE_FL_ACTIVE: u32 = 0x1;
+
+/// `struct v4l2_subdev_route` (6.3)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_subdev_route {
+    pub sink_pad: u32,
+    pub sink_stream: u32,
+    pub source_pad: u32,
+    pub source_stream: u32,
+    pub flags: u32,
+    pub reserved: [u32; 5],
+}
+
+/// `struct v4l2_subdev_routing` with `len_routes` (6.8). The 6.3 layout was
+/// only reachable with the then experimental streams API enabled.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_subdev_routing {
+    pub which: u32,
+    /// Entries the `routes` array has room for
+    pub len_routes: u32,
+    /// Pointer to `struct v4l2_subdev_route[len_routes]`
+    pub routes: u64,
+    /// Entries in the table; set by the driver on return
+    pub num_routes: u32,
+    pub reserved: [u32; 11],
+}
+
+/// `V4L2_SUBDEV_CLIENT_CAP_STREAMS` (6.3)
+pub const V4L2_SUBDEV_CLIENT_CAP_STREAMS: u64 = 0x1;
+
+/// `struct v4l2_subdev_client_capability` (6.3)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_subdev_client_capability {
+    pub capabilities: u64,
+}
+
+/// `V4L2_BUF_TYPE_META_OUTPUT` (5.0)
+pub const V4L2_BUF_TYPE_META_OUTPUT: u32 = 14;
+
+/// `V4L2_CAP_META_OUTPUT` (5.0)
+pub const V4L2_CAP_META_OUTPUT: u32 = 0x0800_0000;
+
+/// `V4L2_BUF_CAP_SUPPORTS_MMAP` (4.20)
+pub const V4L2_BUF_CAP_SUPPORTS_MMAP: u32 = 0x1;
+/// `V4L2_BUF_CAP_SUPPORTS_USERPTR` (4.20)
+pub const V4L2_BUF_CAP_SUPPORTS_USERPTR: u32 = 0x2;
+/// `V4L2_BUF_CAP_SUPPORTS_DMABUF` (4.20)
+pub const V4L2_BUF_CAP_SUPPORTS_DMABUF: u32 = 0x4;
+/// `V4L2_BUF_CAP_SUPPORTS_REQUESTS` (4.20)
+pub const V4L2_BUF_CAP_SUPPORTS_REQUESTS: u32 = 0x8;
+/// `V4L2_BUF_CAP_SUPPORTS_M2M_HOLD_CAPTURE_BUF` (5.7)
+pub const V4L2_BUF_CAP_SUPPORTS_M2M_HOLD_CAPTURE_BUF: u32 = 0x20;
+
+/// `struct v4l2_requestbuffers` with `capabilities` (4.20). 6.0 splits the
+/// reserved word into `flags` and padding.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_requestbuffers {
+    pub count: u32,
+    pub type_: u32,
+    pub memory: u32,
+    pub capabilities: u32,
+    pub reserved: [u32; 1],
+}
+
+/// `V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF` (5.7)
+pub const V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF: u32 = 0x0000_0200;
+/// `V4L2_BUF_FLAG_REQUEST_FD` (4.20)
+pub const V4L2_BUF_FLAG_REQUEST_FD: u32 = 0x0080_0000;
+
+/// `struct v4l2_buffer` with `request_fd` (4.20), which older headers declare
+/// as the last reserved word
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct v4l2_buffer {
+    pub index: u32,
+    pub type_: u32,
+    pub bytesused: u32,
+    pub flags: u32,
+    pub field: u32,
+    pub timestamp: crate::timeval,
+    pub timecode: crate::v4l2_timecode,
+    pub sequence: u32,
+    pub memory: u32,
+    pub m: crate::v4l2_buffer__bindgen_ty_1,
+    pub length: u32,
+    pub reserved2: u32,
+    pub request_fd: i32,
+}
+
+/// `V4L2_CTRL_WHICH_REQUEST_VAL` (4.20)
+pub const V4L2_CTRL_WHICH_REQUEST_VAL: u32 = 0x0f01_0000;
+
+/// `V4L2_CID_STATELESS_H264_DECODE_MODE` (5.11)
+pub const V4L2_CID_STATELESS_H264_DECODE_MODE: u32 = 0x00a4_0900;
+/// `V4L2_CID_STATELESS_H264_START_CODE` (5.11)
+pub const V4L2_CID_STATELESS_H264_START_CODE: u32 = 0x00a4_0901;
+/// `V4L2_CID_STATELESS_H264_SPS` (5.11)
+pub const V4L2_CID_STATELESS_H264_SPS: u32 = 0x00a4_0902;
+/// `V4L2_CID_STATELESS_H264_PPS` (5.11)
+pub const V4L2_CID_STATELESS_H264_PPS: u32 = 0x00a4_0903;
+/// `V4L2_CID_STATELESS_H264_SCALING_MATRIX` (5.11)
+pub const V4L2_CID_STATELESS_H264_SCALING_MATRIX: u32 = 0x00a4_0904;
+/// `V4L2_CID_STATELESS_H264_PRED_WEIGHTS` (5.11)
+pub const V4L2_CID_STATELESS_H264_PRED_WEIGHTS: u32 = 0x00a4_0905;
+/// `V4L2_CID_STATELESS_H264_SLICE_PARAMS` (5.11)
+pub const V4L2_CID_STATELESS_H264_SLICE_PARAMS: u32 = 0x00a4_0906;
+/// `V4L2_CID_STATELESS_H264_DECODE_PARAMS` (5.11)
+pub const V4L2_CID_STATELESS_H264_DECODE_PARAMS: u32 = 0x00a4_0907;
+
+/// `enum v4l2_stateless_h264_decode_mode` (5.11)
+pub const V4L2_STATELESS_H264_DECODE_MODE_SLICE_BASED: u32 = 0;
+pub const V4L2_STATELESS_H264_DECODE_MODE_FRAME_BASED: u32 = 1;
+
+/// `enum v4l2_stateless_h264_start_code` (5.11)
+pub const V4L2_STATELESS_H264_START_CODE_NONE: u32 = 0;
+pub const V4L2_STATELESS_H264_START_CODE_ANNEX_B: u32 = 1;
+
+/// `V4L2_H264_SPS_CONSTRAINT_SET*_FLAG` (5.11)
+pub const V4L2_H264_SPS_CONSTRAINT_SET0_FLAG: u32 = 0x01;
+pub const V4L2_H264_SPS_CONSTRAINT_SET1_FLAG: u32 = 0x02;
+pub const V4L2_H264_SPS_CONSTRAINT_SET2_FLAG: u32 = 0x04;
+pub const V4L2_H264_SPS_CONSTRAINT_SET3_FLAG: u32 = 0x08;
+pub const V4L2_H264_SPS_CONSTRAINT_SET4_FLAG: u32 = 0x10;
+pub const V4L2_H264_SPS_CONSTRAINT_SET5_FLAG: u32 = 0x20;
+
+/// `V4L2_H264_SPS_FLAG_*` (5.11)
+pub const V4L2_H264_SPS_FLAG_SEPARATE_COLOUR_PLANE: u32 = 0x01;
+pub const V4L2_H264_SPS_FLAG_QPPRIME_Y_ZERO_TRANSFORM_BYPASS: u32 = 0x02;
+pub const V4L2_H264_SPS_FLAG_DELTA_PIC_ORDER_ALWAYS_ZERO: u32 = 0x04;
+pub const V4L2_H264_SPS_FLAG_GAPS_IN_FRAME_NUM_VALUE_ALLOWED: u32 = 0x08;
+pub const V4L2_H264_SPS_FLAG_FRAME_MBS_ONLY: u32 = 0x10;
+pub const V4L2_H264_SPS_FLAG_MB_ADAPTIVE_FRAME_FIELD: u32 = 0x20;
+pub const V4L2_H264_SPS_FLAG_DIRECT_8X8_INFERENCE: u32 = 0x40;
+
+/// `struct v4l2_ctrl_h264_sps` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_ctrl_h264_sps {
+    pub profile_idc: u8,
+    pub constraint_set_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_frame_num_minus4: u8,
+    pub pic_order_cnt_type: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub max_num_ref_frames: u8,
+    pub num_ref_frames_in_pic_order_cnt_cycle: u8,
+    pub offset_for_ref_frame: [i32; 255],
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub pic_width_in_mbs_minus1: u16,
+    pub pic_height_in_map_units_minus1: u16,
+    pub flags: u32,
+}
+
+/// `V4L2_H264_PPS_FLAG_*` (5.11)
+pub const V4L2_H264_PPS_FLAG_ENTROPY_CODING_MODE: u32 = 0x0001;
+pub const V4L2_H264_PPS_FLAG_BOTTOM_FIELD_PIC_ORDER_IN_FRAME_PRESENT: u32 = 0x0002;
+pub const V4L2_H264_PPS_FLAG_WEIGHTED_PRED: u32 = 0x0004;
+pub const V4L2_H264_PPS_FLAG_DEBLOCKING_FILTER_CONTROL_PRESENT: u32 = 0x0008;
+pub const V4L2_H264_PPS_FLAG_CONSTRAINED_INTRA_PRED: u32 = 0x0010;
+pub const V4L2_H264_PPS_FLAG_REDUNDANT_PIC_CNT_PRESENT: u32 = 0x0020;
+pub const V4L2_H264_PPS_FLAG_TRANSFORM_8X8_MODE: u32 = 0x0040;
+pub const V4L2_H264_PPS_FLAG_SCALING_MATRIX_PRESENT: u32 = 0x0080;
+
+/// `struct v4l2_ctrl_h264_pps` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_h264_pps {
+    pub pic_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub num_slice_groups_minus1: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub weighted_bipred_idc: u8,
+    pub pic_init_qp_minus26: i8,
+    pub pic_init_qs_minus26: i8,
+    pub chroma_qp_index_offset: i8,
+    pub second_chroma_qp_index_offset: i8,
+    pub flags: u16,
+}
+
+/// `struct v4l2_ctrl_h264_scaling_matrix` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_ctrl_h264_scaling_matrix {
+    pub scaling_list_4x4: [[u8; 16]; 6],
+    pub scaling_list_8x8: [[u8; 64]; 6],
+}
+
+/// `struct v4l2_h264_weight_factors` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_h264_weight_factors {
+    pub luma_weight: [i16; 32],
+    pub luma_offset: [i16; 32],
+    pub chroma_weight: [[i16; 2]; 32],
+    pub chroma_offset: [[i16; 2]; 32],
+}
+
+/// `struct v4l2_ctrl_h264_pred_weights` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_ctrl_h264_pred_weights {
+    pub luma_log2_weight_denom: u16,
+    pub chroma_log2_weight_denom: u16,
+    pub weight_factors: [v4l2_h264_weight_factors; 2],
+}
+
+/// `V4L2_H264_SLICE_TYPE_*` (5.11)
+pub const V4L2_H264_SLICE_TYPE_P: u32 = 0;
+pub const V4L2_H264_SLICE_TYPE_B: u32 = 1;
+pub const V4L2_H264_SLICE_TYPE_I: u32 = 2;
+pub const V4L2_H264_SLICE_TYPE_SP: u32 = 3;
+pub const V4L2_H264_SLICE_TYPE_SI: u32 = 4;
+
+/// `V4L2_H264_SLICE_FLAG_*` (5.11)
+pub const V4L2_H264_SLICE_FLAG_DIRECT_SPATIAL_MV_PRED: u32 = 0x01;
+pub const V4L2_H264_SLICE_FLAG_SP_FOR_SWITCH: u32 = 0x02;
+
+/// `V4L2_H264_*_REF` (5.11)
+pub const V4L2_H264_TOP_FIELD_REF: u32 = 0x1;
+pub const V4L2_H264_BOTTOM_FIELD_REF: u32 = 0x2;
+pub const V4L2_H264_FRAME_REF: u32 = 0x3;
+
+/// `struct v4l2_h264_reference` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_h264_reference {
+    pub fields: u8,
+    pub index: u8,
+}
+
+/// `V4L2_H264_NUM_DPB_ENTRIES` (5.11)
+pub const V4L2_H264_NUM_DPB_ENTRIES: u32 = 16;
+/// `V4L2_H264_REF_LIST_LEN` (5.11)
+pub const V4L2_H264_REF_LIST_LEN: u32 = 2 * V4L2_H264_NUM_DPB_ENTRIES;
+
+/// `struct v4l2_ctrl_h264_slice_params` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_ctrl_h264_slice_params {
+    pub header_bit_size: u32,
+    pub first_mb_in_slice: u32,
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub redundant_pic_cnt: u8,
+    pub cabac_init_idc: u8,
+    pub slice_qp_delta: i8,
+    pub slice_qs_delta: i8,
+    pub disable_deblocking_filter_idc: u8,
+    pub slice_alpha_c0_offset_div2: i8,
+    pub slice_beta_offset_div2: i8,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    pub reserved: u8,
+    pub ref_pic_list0: [v4l2_h264_reference; V4L2_H264_REF_LIST_LEN as usize],
+    pub ref_pic_list1: [v4l2_h264_reference; V4L2_H264_REF_LIST_LEN as usize],
+    pub flags: u32,
+}
+
+/// `V4L2_H264_DPB_ENTRY_FLAG_*` (5.11)
+pub const V4L2_H264_DPB_ENTRY_FLAG_VALID: u32 = 0x01;
+pub const V4L2_H264_DPB_ENTRY_FLAG_ACTIVE: u32 = 0x02;
+pub const V4L2_H264_DPB_ENTRY_FLAG_LONG_TERM: u32 = 0x04;
+pub const V4L2_H264_DPB_ENTRY_FLAG_FIELD: u32 = 0x08;
+
+/// `struct v4l2_h264_dpb_entry` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct v4l2_h264_dpb_entry {
+    pub reference_ts: u64,
+    pub pic_num: u32,
+    pub frame_num: u16,
+    pub fields: u8,
+    pub reserved: [u8; 5],
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub flags: u32,
+}
+
+/// `V4L2_H264_DECODE_PARAM_FLAG_*` (5.11)
+pub const V4L2_H264_DECODE_PARAM_FLAG_IDR_PIC: u32 = 0x01;
+pub const V4L2_H264_DECODE_PARAM_FLAG_FIELD_PIC: u32 = 0x02;
+pub const V4L2_H264_DECODE_PARAM_FLAG_BOTTOM_FIELD: u32 = 0x04;
+pub const V4L2_H264_DECODE_PARAM_FLAG_PFRAME: u32 = 0x08;
+pub const V4L2_H264_DECODE_PARAM_FLAG_BFRAME: u32 = 0x10;
+
+/// `struct v4l2_ctrl_h264_decode_params` (5.11)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_h264_decode_params {
+    pub dpb: [v4l2_h264_dpb_entry; V4L2_H264_NUM_DPB_ENTRIES as usize],
+    pub nal_ref_idc: u16,
+    pub frame_num: u16,
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt_lsb: u16,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt0: i32,
+    pub delta_pic_order_cnt1: i32,
+    pub dec_ref_pic_marking_bit_size: u32,
+    pub pic_order_cnt_bit_size: u32,
+    pub slice_group_change_cycle: u32,
+    pub reserved: u32,
+    pub flags: u32,
+}
+
+/// `struct cec_connector_info` (5.5)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct cec_connector_info {
+    /// `CEC_CONNECTOR_TYPE_*`
+    pub type_: u32,
+    /// `drm`, or `raw` for other connector types
+    pub raw: [u32; 16],
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn layout() {
+        // Same size as whatever the installed headers declare
+        assert_eq!(
+            mem::size_of::<v4l2_ext_controls>(),
+            mem::size_of::<crate::v4l2_ext_controls>()
+        );
+        assert_eq!(mem::size_of::<v4l2_area>(), 8);
+        assert_eq!(
+            mem::size_of::<v4l2_mbus_framefmt>(),
+            mem::size_of::<crate::v4l2_mbus_framefmt>()
+        );
+        assert_eq!(mem::size_of::<v4l2_subdev_capability>(), 64);
+        assert_eq!(mem::size_of::<v4l2_subdev_route>(), 40);
+        assert_eq!(mem::size_of::<v4l2_subdev_routing>(), 64);
+        assert_eq!(
+            mem::size_of::<v4l2_requestbuffers>(),
+            mem::size_of::<crate::v4l2_requestbuffers>()
+        );
+        assert_eq!(
+            mem::size_of::<v4l2_buffer>(),
+            mem::size_of::<crate::v4l2_buffer>()
+        );
+        assert_eq!(mem::size_of::<cec_connector_info>(), 68);
+        // The kernel rejects compound controls whose size doesn't match
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_sps>(), 1048);
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_pps>(), 12);
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_scaling_matrix>(), 480);
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_pred_weights>(), 772);
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_slice_params>(), 152);
+        assert_eq!(mem::size_of::<v4l2_ctrl_h264_decode_params>(), 560);
+    }
+
+    #[test]
+    fn control_ids() {
+        // Class base plus offset, as the headers define them
+        assert_eq!(
+            V4L2_CID_CAMERA_ORIENTATION,
+            crate::V4L2_CID_CAMERA_CLASS_BASE + 34
+        );
+        assert_eq!(
+            V4L2_CID_CAMERA_SENSOR_ROTATION,
+            crate::V4L2_CID_CAMERA_CLASS_BASE + 35
+        );
+        assert_eq!(
+            V4L2_CID_UNIT_CELL_SIZE,
+            crate::V4L2_CID_IMAGE_SOURCE_CLASS_BASE + 8
+        );
+        assert_eq!(
+            V4L2_CID_MPEG_VIDEO_VP8_PROFILE,
+            crate::V4L2_CID_MPEG_BASE + 511
+        );
+        assert_eq!(
+            V4L2_CID_MPEG_VIDEO_VP9_PROFILE,
+            crate::V4L2_CID_MPEG_BASE + 512
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! UVC extension unit controls (UVCIOC_CTRL_QUERY)
+//!
+//! USB cameras expose vendor features such as LEDs, HDR or firmware
+//! information through extension units (XUs). A unit is identified on the
+//! bus by its GUID, but queries address it by the unit id the device picked,
+//! which [`find_extension_unit`] looks up from the USB descriptors in sysfs.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/drivers/uvcvideo.html
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::device::{ioctl, Ioctl};
+use crate::uvc_codes;
+
+/// `bDescriptorType` of interface descriptors (USB 2.0, 9.4)
+const USB_DT_INTERFACE: u8 = 0x04;
+/// `bDescriptorType` of class specific interface descriptors
+const USB_DT_CS_INTERFACE: u8 = 0x24;
+/// `bInterfaceClass` of video interfaces
+const USB_CLASS_VIDEO: u8 = 0x0e;
+
+/// GUID of an extension unit, in the byte order of the descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+/// The usual `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form; the first three
+/// fields are stored little-endian.
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]])
+        )?;
+        for (i, b) in g[8..].iter().enumerate() {
+            if i == 2 {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGuidError(pub String);
+
+impl fmt::Display for ParseGuidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid GUID `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseGuidError {}
+
+/// Accepts the `Display` form, optionally in braces
+impl FromStr for Guid {
+    type Err = ParseGuidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseGuidError(s.into());
+        let text = s.trim();
+        let text = text
+            .strip_prefix('{')
+            .and_then(|t| t.strip_suffix('}'))
+            .unwrap_or(text);
+        let groups: Vec<&str> = text.split('-').collect();
+        if groups.iter().map(|g| g.len()).ne([8, 4, 4, 4, 12]) {
+            return Err(err());
+        }
+        let hex: String = groups.concat();
+        let mut bytes = [0u8; 16];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(hex.get(2 * i..2 * i + 2).ok_or_else(err)?, 16)
+                .map_err(|_| err())?;
+        }
+        // Back to the little-endian fields of the wire format
+        bytes[..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        Ok(Guid(bytes))
+    }
+}
+
+/// An extension unit descriptor of a VideoControl interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionUnit {
+    /// `bUnitID`, used to address the unit in queries
+    pub unit: u8,
+    pub guid: Guid,
+    pub num_controls: u8,
+    /// `bmControls`; bit n set means selector n + 1 is implemented
+    pub controls: Vec<u8>,
+}
+
+impl ExtensionUnit {
+    /// Implemented control selectors
+    pub fn selectors(&self) -> Vec<u8> {
+        (0..self.controls.len() * 8)
+            .filter(|&bit| self.controls[bit / 8] & (1 << (bit % 8)) != 0)
+            .map(|bit| bit as u8 + 1)
+            .collect()
+    }
+}
+
+/// Extension units in raw USB descriptors, as found in the sysfs
+/// `descriptors` file of a device. With `interface`, only the
+/// VideoControl interface with that `bInterfaceNumber` is considered.
+pub fn parse_extension_units(descriptors: &[u8], interface: Option<u8>) -> Vec<ExtensionUnit> {
+    let mut units = Vec::new();
+    let mut in_vc = false;
+    let mut rest = descriptors;
+    while rest.len() >= 2 {
+        let len = rest[0] as usize;
+        if len < 2 || len > rest.len() {
+            break;
+        }
+        let (d, next) = rest.split_at(len);
+        rest = next;
+        match d[1] {
+            USB_DT_INTERFACE if len >= 9 => {
+                in_vc = d[5] == USB_CLASS_VIDEO
+                    && d[6] as u32 == crate::UVC_SC_VIDEOCONTROL
+                    && interface.unwrap_or(d[2]) == d[2];
+            }
+            USB_DT_CS_INTERFACE if in_vc && len >= 24 => {
+                if d[2] as u32 != crate::UVC_VC_EXTENSION_UNIT {
+                    continue;
+                }
+                // bNrInPins baSourceID[p] bControlSize bmControls[n] iExtension
+                let pins = d[21] as usize;
+                let Some(&size) = d.get(22 + pins) else {
+                    continue;
+                };
+                let start = 23 + pins;
+                let Some(controls) = d.get(start..start + size as usize) else {
+                    continue;
+                };
+                let mut guid = [0u8; 16];
+                guid.copy_from_slice(&d[4..20]);
+                units.push(ExtensionUnit {
+                    unit: d[3],
+                    guid: Guid(guid),
+                    num_controls: d[20],
+                    controls: controls.to_vec(),
+                });
+            }
+            _ => {}
+        }
+    }
+    units
+}
+
+/// Extension units of the camera behind `video_node` (e.g. `/dev/video0`),
+/// read from `/sys/class/video4linux`
+pub fn extension_units<P: AsRef<Path>>(video_node: P) -> io::Result<Vec<ExtensionUnit>> {
+    // Resolve /dev/v4l/by-id style links to the videoN name
+    let node = fs::canonicalize(video_node)?;
+    let name = node
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a device node"))?;
+    let class = Path::new("/sys/class/video4linux").join(name);
+    units_from_sysfs(&class.join("device"))
+}
+
+/// `interface` is the sysfs directory of the USB interface the video node
+/// belongs to; the descriptors live in the parent device directory.
+fn units_from_sysfs(interface: &Path) -> io::Result<Vec<ExtensionUnit>> {
+    let number = fs::read_to_string(interface.join("bInterfaceNumber"))?;
+    let number = u8::from_str_radix(number.trim(), 16)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let device = fs::canonicalize(interface)?;
+    let device = device
+        .parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no USB device"))?;
+    let descriptors = fs::read(device.join("descriptors"))?;
+    Ok(parse_extension_units(&descriptors, Some(number)))
+}
+
+/// The extension unit with `guid`; NotFound if the camera has none
+pub fn find_extension_unit<P: AsRef<Path>>(
+    video_node: P,
+    guid: &Guid,
+) -> io::Result<ExtensionUnit> {
+    extension_units(video_node)?
+        .into_iter()
+        .find(|u| u.guid == *guid)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no extension unit {}", guid),
+            )
+        })
+}
+
+/// GET_INFO capabilities of a control, `UVC_CONTROL_CAP_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XuInfo(pub u8);
+
+impl XuInfo {
+    pub fn can_get(self) -> bool {
+        self.0 as u32 & crate::UVC_CONTROL_CAP_GET != 0
+    }
+
+    pub fn can_set(self) -> bool {
+        self.0 as u32 & crate::UVC_CONTROL_CAP_SET != 0
+    }
+
+    /// Currently disabled by an automatic mode
+    pub fn is_disabled(self) -> bool {
+        self.0 as u32 & crate::UVC_CONTROL_CAP_DISABLED != 0
+    }
+
+    /// The device may change the value on its own
+    pub fn is_autoupdate(self) -> bool {
+        self.0 as u32 & crate::UVC_CONTROL_CAP_AUTOUPDATE != 0
+    }
+
+    pub fn is_asynchronous(self) -> bool {
+        self.0 as u32 & crate::UVC_CONTROL_CAP_ASYNCHRONOUS != 0
+    }
+}
+
+/// UVCIOC_CTRL_QUERY with a `UVC_*` request code, e.g. `UVC_GET_DEF`.
+/// `data` must be as long as the device expects.
+pub fn query<D: Ioctl + ?Sized>(
+    dev: &D,
+    unit: u8,
+    selector: u8,
+    request: u8,
+    data: &mut [u8],
+) -> io::Result<()> {
+    let mut q: crate::uvc_xu_control_query = unsafe { mem::zeroed() };
+    q.unit = unit;
+    q.selector = selector;
+    q.query = request;
+    q.size = data.len() as u16;
+    q.data = data.as_mut_ptr();
+    ioctl(dev, uvc_codes::UVCIOC_CTRL_QUERY, &mut q)
+}
+
+/// GET_LEN: size of the control value in bytes
+pub fn get_len<D: Ioctl + ?Sized>(dev: &D, unit: u8, selector: u8) -> io::Result<u16> {
+    let mut len = [0u8; 2];
+    query(dev, unit, selector, crate::UVC_GET_LEN as u8, &mut len)?;
+    Ok(u16::from_le_bytes(len))
+}
+
+/// GET_INFO
+pub fn get_info<D: Ioctl + ?Sized>(dev: &D, unit: u8, selector: u8) -> io::Result<XuInfo> {
+    let mut info = [0u8];
+    query(dev, unit, selector, crate::UVC_GET_INFO as u8, &mut info)?;
+    Ok(XuInfo(info[0]))
+}
+
+/// GET_CUR, sized with GET_LEN
+pub fn get_cur<D: Ioctl + ?Sized>(dev: &D, unit: u8, selector: u8) -> io::Result<Vec<u8>> {
+    let mut data = vec![0u8; get_len(dev, unit, selector)? as usize];
+    query(dev, unit, selector, crate::UVC_GET_CUR as u8, &mut data)?;
+    Ok(data)
+}
+
+/// SET_CUR; `data` must be GET_LEN bytes long. EBADRQC if the control
+/// cannot be set.
+pub fn set_cur<D: Ioctl + ?Sized>(dev: &D, unit: u8, selector: u8, data: &[u8]) -> io::Result<()> {
+    let mut data = data.to_vec();
+    query(dev, unit, selector, crate::UVC_SET_CUR as u8, &mut data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeUvc;
+
+    /// VideoControl interface 0 with a Logitech style XU (unit 3,
+    /// selectors 1 and 3) followed by a VideoStreaming interface
+    fn descriptors() -> Vec<u8> {
+        // Device and configuration
+        let mut d = vec![
+            18, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x6d, 0x04, 0x5a, 0x08, 0x10, 0x00, 0x00,
+            0x02, 0x01, 0x01,
+        ];
+        d.extend([9, 0x02, 0x00, 0x01, 0x02, 0x01, 0x00, 0x80, 0xfa]);
+        d.extend([9, 0x04, 0x00, 0x00, 0x01, 0x0e, 0x01, 0x00, 0x00]);
+        let mut xu = vec![0, 0x24, 0x06, 3];
+        xu.extend([
+            0x82, 0x06, 0x61, 0x63, 0x70, 0x50, 0xab, 0x49, 0xb8, 0xcc, 0xb3, 0x85, 0x5e, 0x8d,
+            0x22, 0x1d,
+        ]);
+        // 2 controls, 1 input pin from unit 2, 2 bytes bmControls, iExtension
+        xu.extend([2, 1, 2, 2, 0b101, 0, 0]);
+        xu[0] = xu.len() as u8;
+        d.extend(xu);
+        // Same subtype on a VideoStreaming interface means something else
+        d.extend([9, 0x04, 0x01, 0x00, 0x01, 0x0e, 0x02, 0x00, 0x00]);
+        d.extend([26, 0x24, 0x06, 9]);
+        d.extend([0u8; 22]);
+        d
+    }
+
+    #[test]
+    fn extension_unit_discovery() {
+        let guid: Guid = "{63610682-5070-49ab-b8cc-b3855e8d221d}".parse().unwrap();
+        assert_eq!(guid.to_string(), "63610682-5070-49ab-b8cc-b3855e8d221d");
+        assert!("63610682-5070-49ab-b8cc".parse::<Guid>().is_err());
+        assert!("6361068g-5070-49ab-b8cc-b3855e8d221d"
+            .parse::<Guid>()
+            .is_err());
+
+        let units = parse_extension_units(&descriptors(), None);
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].unit, 3);
+        assert_eq!(units[0].guid, guid);
+        assert_eq!(units[0].selectors(), [1, 3]);
+        assert!(parse_extension_units(&descriptors(), Some(1)).is_empty());
+        // Truncated descriptors end the walk without panicking
+        let d = descriptors();
+        assert!(parse_extension_units(&d[..40], None).is_empty());
+
+        let root = std::env::temp_dir().join(format!("libv4l-uvc-{}", std::process::id()));
+        let interface = root.join("1-1:1.0");
+        fs::create_dir_all(&interface).unwrap();
+        fs::write(interface.join("bInterfaceNumber"), "00\n").unwrap();
+        fs::write(root.join("descriptors"), descriptors()).unwrap();
+        let units = units_from_sysfs(&interface);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(units.unwrap()[0].guid, guid);
+    }
+
+    #[test]
+    fn xu_query() {
+        let fake = FakeUvc::new(3);
+        fake.add(1, XuInfo(0x03), vec![0x00, 0x01]);
+        fake.add(2, XuInfo(0x01), b"1.2.3".to_vec());
+
+        assert_eq!(get_len(&fake, 3, 1).unwrap(), 2);
+        let info = get_info(&fake, 3, 1).unwrap();
+        assert!(info.can_get() && info.can_set() && !info.is_autoupdate());
+        assert_eq!(get_cur(&fake, 3, 2).unwrap(), b"1.2.3");
+
+        set_cur(&fake, 3, 1, &[0x01, 0x01]).unwrap();
+        assert_eq!(get_cur(&fake, 3, 1).unwrap(), [0x01, 0x01]);
+        // Read-only control, wrong length, unknown selector and unit
+        let err = set_cur(&fake, 3, 2, b"9.9.9").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADRQC));
+        let err = set_cur(&fake, 3, 1, &[1]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        let err = get_info(&fake, 3, 7).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        let err = get_len(&fake, 4, 1).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+    }
+}
@@ -0,0 +1,400 @@
+//! UVC payload header metadata (V4L2_META_FMT_UVC) and device clock recovery
+//!
+//! uvcvideo copies the payload headers of every frame to a metadata capture
+//! node, stamped with the host `CLOCK_MONOTONIC` time and USB frame number
+//! of the packet that carried them. The SCR field samples the device clock
+//! at a USB start of frame; [`Clock`] fits those samples against host time
+//! so PTS values can be turned into host timestamps that are comparable
+//! across cameras.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/pixfmt-meta-uvc.html
+
+use std::collections::VecDeque;
+use std::io;
+
+use crate::buffer::Mmap;
+use crate::meta::MetaStream;
+
+/// `ns`, `sof`, `length` and `flags` of `struct uvc_meta_buf`
+const BLOCK_HEADER: usize = 12;
+/// USB frame numbers are 11 bits, one per millisecond
+const SOF_MASK: u16 = 0x7ff;
+
+/// Source clock reference: the device clock sampled at a USB start of frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scr {
+    /// Source time clock, in device clock ticks
+    pub stc: u32,
+    /// USB frame number the STC was sampled at
+    pub sof: u16,
+}
+
+/// One payload header, a `struct uvc_meta_buf`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// Host `CLOCK_MONOTONIC` time the packet arrived at, in nanoseconds
+    pub ns: u64,
+    /// Host USB frame number at arrival
+    pub sof: u16,
+    /// `bmHeaderInfo`, `UVC_STREAM_*`
+    pub flags: u8,
+    /// Presentation time in device clock ticks
+    pub pts: Option<u32>,
+    pub scr: Option<Scr>,
+    /// Vendor data after the standard fields
+    pub extra: Vec<u8>,
+}
+
+impl Block {
+    pub fn is_error(&self) -> bool {
+        self.flags as u32 & crate::UVC_STREAM_ERR != 0
+    }
+
+    pub fn is_end_of_frame(&self) -> bool {
+        self.flags as u32 & crate::UVC_STREAM_EOF != 0
+    }
+
+    /// Frame id bit, toggling with every frame
+    pub fn frame_id(&self) -> bool {
+        self.flags as u32 & crate::UVC_STREAM_FID != 0
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Split a V4L2_META_FMT_UVC buffer into its blocks
+pub fn parse(buf: &[u8]) -> io::Result<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let mut rest = buf;
+    while !rest.is_empty() {
+        if rest.len() < BLOCK_HEADER {
+            return Err(invalid("truncated UVC metadata block"));
+        }
+        // `length` counts itself and `flags`
+        let length = rest[10] as usize;
+        if length < 2 || rest.len() < BLOCK_HEADER - 2 + length {
+            return Err(invalid("invalid UVC payload header length"));
+        }
+        let (block, next) = rest.split_at(BLOCK_HEADER - 2 + length);
+        rest = next;
+
+        let flags = block[11];
+        let mut fields = &block[BLOCK_HEADER..];
+        let mut take = |present: bool, n: usize| -> io::Result<Option<&[u8]>> {
+            if !present {
+                return Ok(None);
+            }
+            if fields.len() < n {
+                return Err(invalid("UVC payload header shorter than its flags"));
+            }
+            let (field, tail) = fields.split_at(n);
+            fields = tail;
+            Ok(Some(field))
+        };
+        let pts = take(flags as u32 & crate::UVC_STREAM_PTS != 0, 4)?
+            .map(|f| u32::from_le_bytes([f[0], f[1], f[2], f[3]]));
+        let scr = take(flags as u32 & crate::UVC_STREAM_SCR != 0, 6)?.map(|f| Scr {
+            stc: u32::from_le_bytes([f[0], f[1], f[2], f[3]]),
+            sof: u16::from_le_bytes([f[4], f[5]]) & SOF_MASK,
+        });
+        blocks.push(Block {
+            ns: u64::from_le_bytes(block[..8].try_into().unwrap()),
+            sof: u16::from_le_bytes([block[8], block[9]]),
+            flags,
+            pts,
+            scr,
+            extra: fields.to_vec(),
+        });
+    }
+    Ok(blocks)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Unwrapped STC
+    stc: i64,
+    /// Host time of the start of frame the STC was sampled at
+    host: u64,
+}
+
+/// Device clock recovered from SCR samples by a least squares fit over a
+/// sliding window, as uvcvideo does for its own buffer timestamps
+#[derive(Debug, Clone)]
+pub struct Clock {
+    samples: VecDeque<Sample>,
+    window: usize,
+    /// Last STC, extended past 32 bit wraparounds
+    last: Option<i64>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(32)
+    }
+}
+
+impl Clock {
+    /// Fit over the last `window` samples, at least 2
+    pub fn new(window: usize) -> Self {
+        Clock {
+            samples: VecDeque::new(),
+            window: window.max(2),
+            last: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Extend a 32 bit device time to the nearest value around the last STC
+    fn unwrap(&self, ticks: u32) -> i64 {
+        match self.last {
+            Some(last) => last + ticks.wrapping_sub(last as u32) as i32 as i64,
+            None => ticks as i64,
+        }
+    }
+
+    /// Add the SCR of `block` as a sample; false if it has none or repeats
+    /// the previous one, as the headers of one frame usually do
+    pub fn push(&mut self, block: &Block) -> bool {
+        let Some(scr) = block.scr else {
+            return false;
+        };
+        let stc = self.unwrap(scr.stc);
+        if self.last == Some(stc) {
+            return false;
+        }
+        self.last = Some(stc);
+        // Whole USB frames between the STC sample and the packet arrival
+        let frames = block.sof.wrapping_sub(scr.sof) & SOF_MASK;
+        let host = block.ns.saturating_sub(frames as u64 * 1_000_000);
+        self.samples.push_back(Sample { stc, host });
+        if self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+        true
+    }
+
+    /// First sample, intercept and slope in nanoseconds per tick
+    fn fit(&self) -> Option<(Sample, f64, f64)> {
+        let origin = *self.samples.front()?;
+        let n = self.samples.len() as f64;
+        let points = || {
+            self.samples.iter().map(move |s| {
+                (
+                    (s.stc - origin.stc) as f64,
+                    s.host as f64 - origin.host as f64,
+                )
+            })
+        };
+        let (sx, sy) = points().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (mx, my) = (sx / n, sy / n);
+        let (sxx, sxy) = points().fold((0.0, 0.0), |(sxx, sxy), (x, y)| {
+            (sxx + (x - mx) * (x - mx), sxy + (x - mx) * (y - my))
+        });
+        if sxx == 0.0 {
+            return None;
+        }
+        let slope = sxy / sxx;
+        Some((origin, my - slope * mx, slope))
+    }
+
+    /// Estimated device clock frequency in Hz
+    pub fn frequency(&self) -> Option<f64> {
+        self.fit().map(|(_, _, slope)| 1e9 / slope)
+    }
+
+    /// Host `CLOCK_MONOTONIC` time of the device time `pts`, in nanoseconds.
+    /// `pts` must lie within half a wraparound of the latest sample.
+    pub fn host_time(&self, pts: u32) -> Option<u64> {
+        let (origin, intercept, slope) = self.fit()?;
+        let x = (self.unwrap(pts) - origin.stc) as f64;
+        let host = origin.host as i64 + (intercept + slope * x).round() as i64;
+        u64::try_from(host).ok()
+    }
+}
+
+/// Metadata of one video frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaBuffer {
+    /// Sequence number of the video frame the headers belong to
+    pub sequence: u32,
+    pub blocks: Vec<Block>,
+}
+
+impl MetaBuffer {
+    /// PTS of the frame, from the first header carrying one
+    pub fn pts(&self) -> Option<u32> {
+        self.blocks.iter().find_map(|b| b.pts)
+    }
+}
+
+/// Streaming capture from a UVC metadata node, the second `/dev/videoN`
+/// of a camera
+pub struct MetaCapture<'a, D: Mmap + ?Sized> {
+    stream: MetaStream<'a, D>,
+}
+
+impl<'a, D: Mmap + ?Sized> MetaCapture<'a, D> {
+    /// Select V4L2_META_FMT_UVC, queue `count` buffers and start streaming
+    pub fn new(dev: &'a D, count: u32) -> io::Result<Self> {
+        let stream = MetaStream::capture(dev, crate::pixel_format::V4L2_META_FMT_UVC, count)?;
+        Ok(MetaCapture { stream })
+    }
+
+    /// Dequeue the headers of the next frame and requeue the buffer
+    pub fn dequeue(&self) -> io::Result<MetaBuffer> {
+        let meta = self.stream.dequeue()?;
+        Ok(MetaBuffer {
+            sequence: meta.sequence,
+            blocks: parse(&meta.data)?,
+        })
+    }
+}
+
+/// Pairs metadata with video frames by sequence number and stamps the
+/// frames with recovered host time
+#[derive(Debug, Clone, Default)]
+pub struct FrameClock {
+    pub clock: Clock,
+    /// Sequence and PTS of frames whose video buffer is still to come
+    pending: VecDeque<(u32, Option<u32>)>,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_metadata(&mut self, meta: &MetaBuffer) {
+        for block in &meta.blocks {
+            self.clock.push(block);
+        }
+        self.pending.push_back((meta.sequence, meta.pts()));
+        if self.pending.len() > 64 {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Host `CLOCK_MONOTONIC` capture time of video frame `sequence`, in
+    /// nanoseconds. None if its metadata was lost, had no PTS or the clock
+    /// has too few samples. Metadata of older frames is dropped.
+    pub fn frame_time(&mut self, sequence: u32) -> Option<u64> {
+        while let Some(&(seq, pts)) = self.pending.front() {
+            // Sequence numbers wrap; anything behind `sequence` is stale
+            let behind = (sequence.wrapping_sub(seq) as i32) > 0;
+            if seq != sequence && !behind {
+                return None;
+            }
+            self.pending.pop_front();
+            if seq == sequence {
+                return self.clock.host_time(pts?);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake::FakeMeta;
+
+    const FRAME_NS: u64 = 33_333_333;
+
+    fn block(ns: u64, sof: u16, pts: u32, scr: Scr) -> Vec<u8> {
+        let flags = crate::UVC_STREAM_EOH | crate::UVC_STREAM_PTS | crate::UVC_STREAM_SCR;
+        let mut b = ns.to_le_bytes().to_vec();
+        b.extend(sof.to_le_bytes());
+        b.extend([12, flags as u8]);
+        b.extend(pts.to_le_bytes());
+        b.extend(scr.stc.to_le_bytes());
+        b.extend(scr.sof.to_le_bytes());
+        b
+    }
+
+    /// Frame `k` of a 48 MHz camera whose STC wraps after a few frames.
+    /// Returns the metadata and the host time the frame was captured at.
+    fn frame(k: u32) -> (Vec<u8>, u64) {
+        let stc = (u32::MAX - 3_000_000).wrapping_add(k * 1_600_000);
+        let sof = (2000 + k as u16 * 33) & SOF_MASK;
+        let host_sof = 7_000_000_000 + k as u64 * FRAME_NS;
+        // Arrives two USB frames later, at some point within the frame
+        let ns = host_sof + 2_000_000 + (k as u64 * 370_000) % 1_000_000;
+        let scr = Scr { stc, sof };
+        // Exposure started 16.7 ms before the SOF
+        let pts = stc.wrapping_sub(800_000);
+        let mut data = block(ns, (sof + 2) & SOF_MASK, pts, scr);
+        // A repeated header later in the same frame
+        data.extend(block(ns + 125_000, (sof + 2) & SOF_MASK, pts, scr));
+        (data, host_sof - 16_666_667)
+    }
+
+    #[test]
+    fn clock_recovery() {
+        let (data, _) = frame(0);
+        let blocks = parse(&data).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].pts, Some((u32::MAX - 3_000_000) - 800_000));
+        assert_eq!(blocks[0].scr.unwrap().sof, 2000);
+        assert!(blocks[0].extra.is_empty() && !blocks[0].is_error());
+        assert!(parse(&data[..20]).is_err());
+        let mut short = data[..22].to_vec();
+        short[10] = 7;
+        assert!(parse(&short[..17]).is_err());
+
+        let mut clock = Clock::default();
+        assert!(clock.push(&blocks[0]));
+        assert!(!clock.push(&blocks[1]));
+        assert_eq!(clock.host_time(0), None);
+        for k in 1..40 {
+            for b in parse(&frame(k).0).unwrap() {
+                clock.push(&b);
+            }
+        }
+        assert_eq!(clock.len(), 32);
+        let freq = clock.frequency().unwrap();
+        // Arrival jitter within the USB frame limits the precision
+        assert!((freq / 48e6 - 1.0).abs() < 1e-4, "{}", freq);
+        for k in [35, 39] {
+            let (data, expected) = frame(k);
+            let pts = parse(&data).unwrap()[0].pts.unwrap();
+            let got = clock.host_time(pts).unwrap();
+            assert!(got.abs_diff(expected) < 1_000_000, "{} {}", got, expected);
+        }
+    }
+
+    #[test]
+    fn capture_and_pair() {
+        let fake = FakeMeta::new(crate::pixel_format::V4L2_META_FMT_UVC);
+        for k in 0..6 {
+            fake.pending.borrow_mut().push_back((k, frame(k).0));
+        }
+        // Metadata of frame 4 was lost
+        fake.pending.borrow_mut().retain(|(seq, _)| *seq != 4);
+
+        let capture = MetaCapture::new(&fake, 2).unwrap();
+        let mut clock = FrameClock::new();
+        for _ in 0..5 {
+            clock.push_metadata(&capture.dequeue().unwrap());
+        }
+        assert_eq!(clock.clock.len(), 5);
+        // Frame 1 is stamped, frames 2 and 3 were dropped by the application
+        let t = clock.frame_time(1).unwrap();
+        assert!(t.abs_diff(frame(1).1) < 1_000_000);
+        assert_eq!(clock.frame_time(4), None);
+        assert!(clock.frame_time(5).is_some());
+        assert_eq!(clock.frame_time(5), None);
+        drop(capture);
+        assert!(!fake.streaming.get());
+
+        let other = FakeMeta::new(crate::pixel_format::V4L2_META_FMT_VSP1_HGO);
+        assert!(MetaCapture::new(&other, 2).is_err());
+    }
+}
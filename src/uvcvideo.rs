@@ -0,0 +1,35 @@
+///! import linux/uvcvideo.h
+
+/// ioctl codes of the uvcvideo driver
+/// ref. https://www.kernel.org/doc/html/latest/userspace-api/media/drivers/uvcvideo.html
+pub mod uvc_codes {
+    const UVC_IOC_MAGIC: u8 = b'u';
+
+    /// Map an extension unit control to a V4L2 control; needs CAP_SYS_ADMIN.
+    pub const UVCIOC_CTRL_MAP: libc::c_ulong =
+        iowr!(UVC_IOC_MAGIC, 0x20, crate::uvc_xu_control_mapping);
+    /// Issue a UVC class request to an extension unit control.
+    pub const UVCIOC_CTRL_QUERY: libc::c_ulong =
+        iowr!(UVC_IOC_MAGIC, 0x21, crate::uvc_xu_control_query);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ioctl_code() {
+        // Both structs hold a pointer, so the size field differs on 32-bit
+        let iowr =
+            |nr: libc::c_ulong, size: usize| 0xc000_7500 | (size as libc::c_ulong) << 16 | nr;
+        let map = std::mem::size_of::<crate::uvc_xu_control_mapping>();
+        let query = std::mem::size_of::<crate::uvc_xu_control_query>();
+        assert_eq!(uvc_codes::UVCIOC_CTRL_MAP, iowr(0x20, map));
+        assert_eq!(uvc_codes::UVCIOC_CTRL_QUERY, iowr(0x21, query));
+        #[cfg(target_pointer_width = "64")]
+        {
+            assert_eq!(uvc_codes::UVCIOC_CTRL_MAP, 0xc0607520);
+            assert_eq!(uvc_codes::UVCIOC_CTRL_QUERY, 0xc0107521);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+///! import linux/v4l2-subdev.h
+
+/// ioctl codes for sub-devices (`/dev/v4l-subdevN`)
+/// ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/user-func.html
+pub mod subdev_codes {
+    const VIDEODEV2_IOC_MAGIC: u8 = b'V';
+
+    /// Query sub-device capabilities.
+    pub const VIDIOC_SUBDEV_QUERYCAP: libc::c_ulong =
+        ior!(VIDEODEV2_IOC_MAGIC, 0, crate::uapi::v4l2_subdev_capability);
+    /// Enumerate media bus formats.
+    pub const VIDIOC_SUBDEV_ENUM_MBUS_CODE: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 2, crate::v4l2_subdev_mbus_code_enum);
+    /// Get the data format on a sub-device pad.
+    pub const VIDIOC_SUBDEV_G_FMT: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 4, crate::v4l2_subdev_format);
+    /// Set the data format on a sub-device pad.
+    pub const VIDIOC_SUBDEV_S_FMT: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 5, crate::v4l2_subdev_format);
+    /// Get the frame interval on a sub-device pad.
+    pub const VIDIOC_SUBDEV_G_FRAME_INTERVAL: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 21, crate::v4l2_subdev_frame_interval);
+    /// Set the frame interval on a sub-device pad.
+    pub const VIDIOC_SUBDEV_S_FRAME_INTERVAL: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 22, crate::v4l2_subdev_frame_interval);
+    /// Get the routing table of a sub-device.
+    pub const VIDIOC_SUBDEV_G_ROUTING: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 38, crate::uapi::v4l2_subdev_routing);
+    /// Set the routing table of a sub-device.
+    pub const VIDIOC_SUBDEV_S_ROUTING: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 39, crate::uapi::v4l2_subdev_routing);
+    /// Get the crop rectangle on a sub-device pad.
+    pub const VIDIOC_SUBDEV_G_CROP: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 59, crate::v4l2_subdev_crop);
+    /// Set the crop rectangle on a sub-device pad.
+    pub const VIDIOC_SUBDEV_S_CROP: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 60, crate::v4l2_subdev_crop);
+    /// Get a selection rectangle on a sub-device pad.
+    pub const VIDIOC_SUBDEV_G_SELECTION: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 61, crate::v4l2_subdev_selection);
+    /// Set a selection rectangle on a sub-device pad.
+    pub const VIDIOC_SUBDEV_S_SELECTION: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 62, crate::v4l2_subdev_selection);
+    /// Enumerate media bus frame sizes.
+    pub const VIDIOC_SUBDEV_ENUM_FRAME_SIZE: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 74, crate::v4l2_subdev_frame_size_enum);
+    /// Enumerate frame intervals.
+    pub const VIDIOC_SUBDEV_ENUM_FRAME_INTERVAL: libc::c_ulong = iowr!(
+        VIDEODEV2_IOC_MAGIC,
+        75,
+        crate::v4l2_subdev_frame_interval_enum
+    );
+    /// Get the client capabilities of the file handle.
+    pub const VIDIOC_SUBDEV_G_CLIENT_CAP: libc::c_ulong = ior!(
+        VIDEODEV2_IOC_MAGIC,
+        101,
+        crate::uapi::v4l2_subdev_client_capability
+    );
+    /// Set the client capabilities of the file handle.
+    pub const VIDIOC_SUBDEV_S_CLIENT_CAP: libc::c_ulong = iowr!(
+        VIDEODEV2_IOC_MAGIC,
+        102,
+        crate::uapi::v4l2_subdev_client_capability
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate as v4l;
+    use std::mem;
+
+    #[test]
+    fn ioctl_code() {
+        let VIDIOC_SUBDEV_S_FMT: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (5 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_subdev_format>() as libc::c_ulong) << 16);
+        assert_eq!(subdev_codes::VIDIOC_SUBDEV_S_FMT, VIDIOC_SUBDEV_S_FMT);
+
+        let VIDIOC_SUBDEV_S_SELECTION: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (62 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_subdev_selection>() as libc::c_ulong) << 16);
+        assert_eq!(
+            subdev_codes::VIDIOC_SUBDEV_S_SELECTION,
+            VIDIOC_SUBDEV_S_SELECTION
+        );
+
+        let VIDIOC_SUBDEV_G_CLIENT_CAP: libc::c_ulong = ((2 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (101 as libc::c_ulong)
+            | ((mem::size_of::<v4l::uapi::v4l2_subdev_client_capability>() as libc::c_ulong) << 16);
+        assert_eq!(
+            subdev_codes::VIDIOC_SUBDEV_G_CLIENT_CAP,
+            VIDIOC_SUBDEV_G_CLIENT_CAP
+        );
+        // Fixed by the kernel ABI
+        assert_eq!(subdev_codes::VIDIOC_SUBDEV_G_FMT, 0xc0585604);
+        assert_eq!(subdev_codes::VIDIOC_SUBDEV_S_ROUTING, 0xc0405627);
+    }
+}
@@ -0,0 +1,516 @@
+//! Raw VBI capture (V4L2_BUF_TYPE_VBI_CAPTURE) and a software slicer
+//!
+//! Cards without a hardware slicer return the VBI lines as 8-bit luma
+//! samples. [`Slicer`] recovers closed captions, WSS and teletext from them
+//! and produces the same records as sliced VBI capture.
+//! ref. https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/dev-raw-vbi.html
+
+use std::io;
+use std::mem;
+
+use crate::codes;
+use crate::device::{ioctl, Ioctl};
+use crate::sliced::{ServiceSet, SlicedData};
+
+/// Typed `v4l2_vbi_format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RawVbiFormat {
+    /// Samples per second
+    pub sampling_rate: u32,
+    /// Samples from the leading edge of the horizontal sync to the first
+    /// sample of a line
+    pub offset: u32,
+    pub samples_per_line: u32,
+    /// Only `V4L2_PIX_FMT_GREY` is defined
+    pub sample_format: u32,
+    /// First line captured in each field, ITU numbering, 0 if unknown
+    pub start: [i32; 2],
+    /// Lines captured in each field
+    pub count: [u32; 2],
+    /// `V4L2_VBI_*`
+    pub flags: u32,
+}
+
+impl From<&crate::v4l2_vbi_format> for RawVbiFormat {
+    fn from(f: &crate::v4l2_vbi_format) -> Self {
+        RawVbiFormat {
+            sampling_rate: f.sampling_rate,
+            offset: f.offset,
+            samples_per_line: f.samples_per_line,
+            sample_format: f.sample_format,
+            start: f.start,
+            count: f.count,
+            flags: f.flags,
+        }
+    }
+}
+
+impl RawVbiFormat {
+    /// Field order is unknown, the first field may be either
+    pub fn is_unsync(&self) -> bool {
+        self.flags & crate::V4L2_VBI_UNSYNC != 0
+    }
+
+    /// Lines of both fields are interleaved instead of stored field after field
+    pub fn is_interlaced(&self) -> bool {
+        self.flags & crate::V4L2_VBI_INTERLACED != 0
+    }
+
+    pub fn lines(&self) -> usize {
+        (self.count[0] + self.count[1]) as usize
+    }
+
+    /// Bytes of one read()
+    pub fn frame_size(&self) -> usize {
+        self.lines() * self.samples_per_line as usize
+    }
+
+    /// ITU line number of line `index` of `field` (0 or 1), `None` if not known
+    pub fn line_number(&self, field: usize, index: u32) -> Option<u32> {
+        match *self.start.get(field)? {
+            start if start > 0 => (start as u32).checked_add(index),
+            _ => None,
+        }
+    }
+
+    /// Samples of line `index` of `field` (0 or 1) in a captured frame
+    pub fn line<'a>(&self, buf: &'a [u8], field: usize, index: u32) -> Option<&'a [u8]> {
+        if index >= *self.count.get(field)? {
+            return None;
+        }
+        let n = if self.is_interlaced() {
+            index as usize * 2 + field
+        } else {
+            field * self.count[0] as usize + index as usize
+        };
+        let len = self.samples_per_line as usize;
+        buf.get(n * len..(n + 1) * len)
+    }
+}
+
+fn buf_type(output: bool) -> u32 {
+    if output {
+        crate::v4l2_buf_type_V4L2_BUF_TYPE_VBI_OUTPUT
+    } else {
+        crate::v4l2_buf_type_V4L2_BUF_TYPE_VBI_CAPTURE
+    }
+}
+
+/// VIDIOC_G_FMT for the raw VBI buffer type
+pub fn get_format<D: Ioctl + ?Sized>(dev: &D, output: bool) -> io::Result<RawVbiFormat> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = buf_type(output);
+    ioctl(dev, codes::VIDIOC_G_FMT, &mut f)?;
+    Ok(RawVbiFormat::from(unsafe { &f.fmt.vbi }))
+}
+
+/// VIDIOC_S_FMT, returning the format the driver picked. Setting the
+/// format also switches the device to raw VBI.
+pub fn set_format<D: Ioctl + ?Sized>(
+    dev: &D,
+    output: bool,
+    format: &RawVbiFormat,
+) -> io::Result<RawVbiFormat> {
+    let mut f: crate::v4l2_format = unsafe { mem::zeroed() };
+    f.type_ = buf_type(output);
+    f.fmt.vbi.sampling_rate = format.sampling_rate;
+    f.fmt.vbi.offset = format.offset;
+    f.fmt.vbi.samples_per_line = format.samples_per_line;
+    f.fmt.vbi.sample_format = format.sample_format;
+    f.fmt.vbi.start = format.start;
+    f.fmt.vbi.count = format.count;
+    f.fmt.vbi.flags = format.flags;
+    ioctl(dev, codes::VIDIOC_S_FMT, &mut f)?;
+    Ok(RawVbiFormat::from(unsafe { &f.fmt.vbi }))
+}
+
+/// How a service is modulated onto a line.
+///
+/// A line matches when the end of the clock run-in (`cri`, sent at
+/// `cri_rate`) is immediately followed by the framing code (`frc`, at
+/// `bit_rate`). Both patterns are written in transmission order, first bit
+/// in the most significant position.
+struct Modulation {
+    id: u32,
+    /// Inclusive line ranges of the first and second field, `(0, 0)` for none
+    lines: [(u32, u32); 2],
+    cri_rate: f64,
+    cri: u32,
+    cri_bits: u32,
+    bit_rate: f64,
+    frc: u32,
+    frc_bits: u32,
+    /// Payload bits, or elements when `biphase`
+    payload: usize,
+    biphase: bool,
+}
+
+const MODULATIONS: [Modulation; 3] = [
+    // CEA-608: 7 sine cycles, start bits 001, two bytes
+    Modulation {
+        id: crate::V4L2_SLICED_CAPTION_525,
+        lines: [(21, 21), (284, 284)],
+        cri_rate: 2.0 * 503_496.5,
+        cri: 0b101010,
+        cri_bits: 6,
+        bit_rate: 503_496.5,
+        frc: 0b001,
+        frc_bits: 3,
+        payload: 16,
+        biphase: false,
+    },
+    // EN 300 294: run-in and start code at 5 MHz, 14 bits of 6 elements
+    Modulation {
+        id: crate::V4L2_SLICED_WSS_625,
+        lines: [(23, 23), (0, 0)],
+        cri_rate: 5_000_000.0,
+        cri: 0xc7,
+        cri_bits: 8,
+        bit_rate: 5_000_000.0,
+        frc: 0x1e3c1f,
+        frc_bits: 24,
+        payload: 14 * 6,
+        biphase: true,
+    },
+    // EN 300 706: 1010... run-in, framing code 0x27, 42 bytes
+    Modulation {
+        id: crate::V4L2_SLICED_TELETEXT_B,
+        lines: [(6, 22), (318, 335)],
+        cri_rate: 6_937_500.0,
+        cri: 0xaa,
+        cri_bits: 8,
+        bit_rate: 6_937_500.0,
+        frc: 0xe4,
+        frc_bits: 8,
+        payload: 42 * 8,
+        biphase: false,
+    },
+];
+
+/// Peak to peak level below which a line is taken to carry no data
+const MIN_AMPLITUDE: u8 = 32;
+
+/// Sample value at fractional position `t`, linearly interpolated
+fn level(samples: &[u8], t: f64) -> f64 {
+    let i = t as usize;
+    let a = samples[i] as f64;
+    let b = *samples.get(i + 1).unwrap_or(&samples[i]) as f64;
+    a + (b - a) * (t - i as f64)
+}
+
+impl Modulation {
+    fn on_line(&self, field: usize, line: u32) -> bool {
+        let (first, last) = self.lines[field];
+        first != 0 && (first..=last).contains(&line)
+    }
+
+    /// Bits following the framing code, `None` if the line does not carry
+    /// the service
+    fn slice(&self, samples: &[u8], sampling_rate: f64) -> Option<Vec<bool>> {
+        let (min, max) = samples
+            .iter()
+            .fold((u8::MAX, 0), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+        if max.saturating_sub(min) < MIN_AMPLITUDE {
+            return None;
+        }
+        let threshold = (min as f64 + max as f64) / 2.0;
+        let bit = |t: f64| level(samples, t) > threshold;
+        let cri_step = sampling_rate / self.cri_rate;
+        let step = sampling_rate / self.bit_rate;
+        let frc_start = self.cri_bits as f64 * cri_step;
+        let total = frc_start + (self.frc_bits as usize + self.payload) as f64 * step;
+        let last = samples.len() as f64 - 1.0 - total;
+
+        let matches = |t: f64| {
+            let cri = (0..self.cri_bits).all(|i| {
+                let expect = self.cri >> (self.cri_bits - 1 - i) & 1 != 0;
+                bit(t + (i as f64 + 0.5) * cri_step) == expect
+            });
+            cri && (0..self.frc_bits).all(|i| {
+                let expect = self.frc >> (self.frc_bits - 1 - i) & 1 != 0;
+                bit(t + frc_start + (i as f64 + 0.5) * step) == expect
+            })
+        };
+
+        // Scan in quarter samples and sync on the middle of the matching run
+        let mut run: Option<(f64, f64)> = None;
+        let mut t = 0.0;
+        while t <= last {
+            if matches(t) {
+                run = Some((run.map_or(t, |(first, _)| first), t));
+            } else if run.is_some() {
+                break;
+            }
+            t += 0.25;
+        }
+        let (first, end) = run?;
+        let start = (first + end) / 2.0 + frc_start + self.frc_bits as f64 * step;
+        Some(
+            (0..self.payload)
+                .map(|i| bit(start + (i as f64 + 0.5) * step))
+                .collect(),
+        )
+    }
+
+    fn to_record(&self, bits: &[bool], field: u32, line: u32) -> Option<SlicedData> {
+        let mut data = [0u8; 48];
+        if self.biphase {
+            // A 1 is sent as 111000, a 0 as 000111
+            for (i, element) in bits.chunks_exact(6).enumerate() {
+                let value = match element {
+                    [true, true, true, false, false, false] => true,
+                    [false, false, false, true, true, true] => false,
+                    _ => return None,
+                };
+                data[i / 8] |= (value as u8) << (i % 8);
+            }
+        } else {
+            for (i, &value) in bits.iter().enumerate() {
+                data[i / 8] |= (value as u8) << (i % 8);
+            }
+        }
+        Some(SlicedData {
+            id: self.id,
+            field,
+            line,
+            data,
+        })
+    }
+}
+
+/// Software slicer for 8-bit raw VBI
+#[derive(Debug, Clone)]
+pub struct Slicer {
+    format: RawVbiFormat,
+    services: ServiceSet,
+}
+
+impl Slicer {
+    /// Slicer for frames in `format`, looking for `services` (captions, WSS
+    /// and teletext B are supported)
+    pub fn new(format: &RawVbiFormat, services: ServiceSet) -> io::Result<Self> {
+        if format.sample_format != crate::pixel_format::V4L2_PIX_FMT_GREY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "raw VBI slicing needs GREY samples",
+            ));
+        }
+        if format.sampling_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "raw VBI sampling rate is 0",
+            ));
+        }
+        Ok(Slicer {
+            format: *format,
+            services,
+        })
+    }
+
+    /// Slice one line; `line` is the ITU line number
+    pub fn slice_line(&self, samples: &[u8], field: u32, line: u32) -> Option<SlicedData> {
+        let rate = self.format.sampling_rate as f64;
+        MODULATIONS
+            .iter()
+            .filter(|m| self.services.0 as u32 & m.id != 0)
+            .filter(|m| m.on_line(field as usize, line))
+            .find_map(|m| m.to_record(&m.slice(samples, rate)?, field, line))
+    }
+
+    /// Slice every line of a frame read from the device. Lines without a
+    /// known line number are skipped.
+    pub fn slice(&self, buf: &[u8]) -> Vec<SlicedData> {
+        let mut records = Vec::new();
+        for field in 0..2 {
+            for index in 0..self.format.count[field] {
+                let Some(line) = self.format.line_number(field, index) else {
+                    continue;
+                };
+                let Some(samples) = self.format.line(buf, field, index) else {
+                    continue;
+                };
+                records.extend(self.slice_line(samples, field as u32, line));
+            }
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sliced::{AspectRatio, CaptionDecoder, Service};
+
+    const RATE: f64 = 35_468_950.0;
+
+    fn format(start: [i32; 2], count: [u32; 2], flags: u32) -> RawVbiFormat {
+        RawVbiFormat {
+            sampling_rate: RATE as u32,
+            offset: 244,
+            samples_per_line: 2048,
+            sample_format: crate::pixel_format::V4L2_PIX_FMT_GREY,
+            start,
+            count,
+            flags,
+        }
+    }
+
+    /// `n` bits of `value`, most significant first
+    fn pattern(value: u32, n: u32) -> Vec<bool> {
+        (0..n).rev().map(|i| value >> i & 1 != 0).collect()
+    }
+
+    fn lsb_first(bytes: &[u8]) -> Vec<bool> {
+        bytes
+            .iter()
+            .flat_map(|&b| (0..8).map(move |i| b >> i & 1 != 0))
+            .collect()
+    }
+
+    /// A line with the given (bit rate, bits) segments starting at sample
+    /// `start`, levels 40 and 200 with the transitions smoothed
+    fn synth(start: f64, segments: &[(f64, Vec<bool>)]) -> Vec<u8> {
+        let mut edges = Vec::new();
+        let mut t = start;
+        for (rate, bits) in segments {
+            let step = RATE / rate;
+            for &b in bits {
+                edges.push((t, t + step, b));
+                t += step;
+            }
+        }
+        let square: Vec<f64> = (0..2048)
+            .map(|i| {
+                let t = i as f64;
+                let high = edges.iter().any(|&(a, b, v)| v && a <= t && t < b);
+                if high {
+                    200.0
+                } else {
+                    40.0
+                }
+            })
+            .collect();
+        (0..square.len())
+            .map(|i| {
+                let window = &square[i.saturating_sub(1)..(i + 2).min(square.len())];
+                (window.iter().sum::<f64>() / window.len() as f64) as u8
+            })
+            .collect()
+    }
+
+    fn parity(c: u8) -> u8 {
+        if c.count_ones() & 1 == 0 {
+            c | 0x80
+        } else {
+            c
+        }
+    }
+
+    fn caption_line(a: u8, b: u8) -> Vec<u8> {
+        let mut bits = pattern(0b001, 3);
+        bits.extend(lsb_first(&[parity(a), parity(b)]));
+        synth(
+            150.0,
+            &[(2.0 * 503_496.5, pattern(0x2aaa, 14)), (503_496.5, bits)],
+        )
+    }
+
+    #[test]
+    fn slice_captions() {
+        // 525 lines, interlaced, captions on lines 21 and 284
+        let fmt = format([10, 273], [12, 12], crate::V4L2_VBI_INTERLACED);
+        let blank = vec![40u8; 2048];
+        assert_eq!(fmt.line_number(0, 11), Some(21));
+        assert_eq!(fmt.line_number(1, 11), Some(284));
+        // a frame has two fields
+        assert_eq!(fmt.line_number(2, 0), None);
+        assert_eq!(fmt.line(&blank, 2, 0), None);
+        let mut frames = Vec::new();
+        for pair in [
+            [0x14, 0x2c],
+            [0x14, 0x2f],
+            [0x14, 0x25],
+            [b'O', b'K'],
+            [0x14, 0x2d],
+        ] {
+            let mut frame = Vec::new();
+            for index in 0..12 {
+                for field in 0..2 {
+                    frame.extend(match (index, field) {
+                        (11, 0) => caption_line(pair[0], pair[1]),
+                        (11, 1) => caption_line(b'X', b'X'),
+                        _ => blank.clone(),
+                    });
+                }
+            }
+            assert_eq!(frame.len(), fmt.frame_size());
+            frames.push(frame);
+        }
+
+        let slicer = Slicer::new(&fmt, ServiceSet::CAPTION_525).unwrap();
+        let mut cc1 = CaptionDecoder::new(1);
+        let mut text = Vec::new();
+        for frame in &frames {
+            let records = slicer.slice(frame);
+            assert_eq!(records.len(), 2);
+            assert_eq!((records[1].field, records[1].line), (1, 284));
+            text.extend(records.iter().filter_map(|r| cc1.decode(&r.service())));
+        }
+        assert_eq!(text, ["OK"]);
+    }
+
+    #[test]
+    fn slice_wss_and_teletext() {
+        // 625 lines, field after field
+        let fmt = format([6, 318], [18, 18], 0);
+        let mut frame = vec![40u8; fmt.frame_size()];
+
+        let mut wss = pattern(0x1f1c71c7, 29);
+        wss.extend(pattern(0x1e3c1f, 24));
+        // 16:9 anamorphic, copyright
+        for i in 0..14 {
+            let one = (0b01_0000_0000_0111 >> i) & 1 != 0;
+            wss.extend(pattern(if one { 0b111000 } else { 0b000111 }, 6));
+        }
+        let line = synth(400.0, &[(5_000_000.0, wss)]);
+        frame[17 * 2048..18 * 2048].copy_from_slice(&line);
+
+        // Magazine 1, packet 1
+        let mut packet = vec![0xc7, 0x15];
+        packet.extend(b"Raw VBI teletext".map(parity));
+        packet.resize(42, parity(b' '));
+        let mut bits = pattern(0xaaaa, 16);
+        bits.extend(lsb_first(&[0x27]));
+        bits.extend(lsb_first(&packet));
+        let line = synth(120.0, &[(6_937_500.0, bits)]);
+        frame[(18 + 2) * 2048..(18 + 3) * 2048].copy_from_slice(&line);
+
+        let slicer = Slicer::new(&fmt, ServiceSet::VBI_625).unwrap();
+        let records = slicer.slice(&frame);
+        assert_eq!(records.len(), 2);
+        let Service::Wss { line: 23, wss } = records[0].service() else {
+            panic!("{:?}", records[0]);
+        };
+        assert_eq!(wss.aspect_ratio(), Some(AspectRatio::Anamorphic16x9));
+        assert!(wss.copyright());
+        let Service::Teletext {
+            field,
+            line,
+            packet,
+        } = records[1].service()
+        else {
+            panic!("{:?}", records[1]);
+        };
+        assert_eq!(
+            (field, line, packet.magazine, packet.packet),
+            (1, 320, 1, 1)
+        );
+        assert_eq!(packet.text().unwrap().trim_end(), "Raw VBI teletext");
+
+        // Nothing on flat lines, and only GREY samples are handled
+        assert!(slicer.slice(&vec![128u8; fmt.frame_size()]).is_empty());
+        let mut yuyv = fmt;
+        yuyv.sample_format = crate::pixel_format::V4L2_PIX_FMT_YUYV;
+        assert!(Slicer::new(&yuyv, ServiceSet::VBI_625).is_err());
+    }
+}
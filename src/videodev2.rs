@@ -98,6 +98,24 @@ pub mod codes {
         iowr!(VIDEODEV2_IOC_MAGIC, 77, crate::v4l2_encoder_cmd);
     pub const VIDIOC_TRY_ENCODER_CMD: libc::c_ulong =
         iowr!(VIDEODEV2_IOC_MAGIC, 78, crate::v4l2_encoder_cmd);
+    /// Write a chip register; needs CONFIG_VIDEO_ADV_DEBUG and CAP_SYS_ADMIN.
+    pub const VIDIOC_DBG_S_REGISTER: libc::c_ulong =
+        iow!(VIDEODEV2_IOC_MAGIC, 79, crate::v4l2_dbg_register);
+    /// Read a chip register; needs CONFIG_VIDEO_ADV_DEBUG and CAP_SYS_ADMIN.
+    pub const VIDIOC_DBG_G_REGISTER: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 80, crate::v4l2_dbg_register);
+    /// Start a hardware seek for the next station.
+    pub const VIDIOC_S_HW_FREQ_SEEK: libc::c_ulong =
+        iow!(VIDEODEV2_IOC_MAGIC, 82, crate::v4l2_hw_freq_seek);
+    /// Enumerate the frequency bands of a tuner or modulator.
+    pub const VIDIOC_ENUM_FREQ_BANDS: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 101, crate::v4l2_frequency_band);
+    /// Name and register access flags of a bridge or sub-device chip.
+    pub const VIDIOC_DBG_G_CHIP_INFO: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 102, crate::v4l2_dbg_chip_info);
+    /// Enumerate controls including compound/array ones, reporting their dimensions.
+    pub const VIDIOC_QUERY_EXT_CTRL: libc::c_ulong =
+        iowr!(VIDEODEV2_IOC_MAGIC, 103, crate::v4l2_query_ext_ctrl);
 }
 
 /// Construct four-character-code (FOURCC)
@@ -330,6 +348,14 @@ pub mod pixel_format {
             pub const V4L2_PIX_FMT_SRGGB10P: u32 = fourcc!(b'p', b'R', b'A', b'A');
         }
         pub use raw_bayer_packed_10bit::*;
+        /// 12bit raw bayer packed, 3 bytes for every 2 pixels
+        mod raw_bayer_packed_12bit {
+            pub const V4L2_PIX_FMT_SBGGR12P: u32 = fourcc!(b'p', b'B', b'C', b'C');
+            pub const V4L2_PIX_FMT_SGBRG12P: u32 = fourcc!(b'p', b'G', b'C', b'C');
+            pub const V4L2_PIX_FMT_SGRBG12P: u32 = fourcc!(b'p', b'g', b'C', b'C');
+            pub const V4L2_PIX_FMT_SRGGB12P: u32 = fourcc!(b'p', b'R', b'C', b'C');
+        }
+        pub use raw_bayer_packed_12bit::*;
         /// 10bit raw bayer a-law compressed to 8 bits
         mod raw_bayer_law_compressed {
             pub const V4L2_PIX_FMT_SBGGR10ALAW8: u32 = fourcc!(b'a', b'B', b'A', b'8');
@@ -388,6 +414,8 @@ pub mod pixel_format {
         pub const V4L2_PIX_FMT_H264_NO_SC: u32 = fourcc!(b'A', b'V', b'C', b'1');
         ///  H264 MVC
         pub const V4L2_PIX_FMT_H264_MVC: u32 = fourcc!(b'M', b'2', b'6', b'4');
+        ///  H264 parsed slices
+        pub const V4L2_PIX_FMT_H264_SLICE: u32 = fourcc!(b'S', b'2', b'6', b'4');
         ///  H263
         pub const V4L2_PIX_FMT_H263: u32 = fourcc!(b'H', b'2', b'6', b'3');
         ///  MPEG-1 ES
@@ -516,6 +544,14 @@ pub mod pixel_format {
         pub const V4L2_META_FMT_VSP1_HGO: u32 = fourcc!(b'V', b'S', b'P', b'H');
         ///  R-Car VSP1 2-D Histogram
         pub const V4L2_META_FMT_VSP1_HGT: u32 = fourcc!(b'V', b'S', b'P', b'T');
+        ///  UVC Payload Header metadata
+        pub const V4L2_META_FMT_UVC: u32 = fourcc!(b'U', b'V', b'C', b'H');
+        ///  D4XX Payload Header metadata
+        pub const V4L2_META_FMT_D4XX: u32 = fourcc!(b'D', b'4', b'X', b'X');
+        ///  Rockchip ISP1 3A Parameters
+        pub const V4L2_META_FMT_RK_ISP1_PARAMS: u32 = fourcc!(b'R', b'K', b'1', b'P');
+        ///  Rockchip ISP1 3A Statistics
+        pub const V4L2_META_FMT_RK_ISP1_STAT_3A: u32 = fourcc!(b'R', b'K', b'1', b'S');
     }
     pub use meta_data::*;
 }
@@ -583,5 +619,41 @@ mod test {
             | (19 as libc::c_ulong)
             | ((mem::size_of::<libc::c_int>() as libc::c_ulong) << 16);
         assert_eq!(codes::VIDIOC_STREAMOFF, VIDIOC_STREAMOFF);
+
+        let VIDIOC_QUERY_EXT_CTRL: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (103 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_query_ext_ctrl>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_QUERY_EXT_CTRL, VIDIOC_QUERY_EXT_CTRL);
+
+        let VIDIOC_S_HW_FREQ_SEEK: libc::c_ulong = ((1 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (82 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_hw_freq_seek>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_S_HW_FREQ_SEEK, VIDIOC_S_HW_FREQ_SEEK);
+
+        let VIDIOC_ENUM_FREQ_BANDS: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (101 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_frequency_band>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_ENUM_FREQ_BANDS, VIDIOC_ENUM_FREQ_BANDS);
+
+        let VIDIOC_DBG_S_REGISTER: libc::c_ulong = ((1 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (79 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_dbg_register>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_DBG_S_REGISTER, VIDIOC_DBG_S_REGISTER);
+
+        let VIDIOC_DBG_G_REGISTER: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (80 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_dbg_register>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_DBG_G_REGISTER, VIDIOC_DBG_G_REGISTER);
+
+        let VIDIOC_DBG_G_CHIP_INFO: libc::c_ulong = ((3 as libc::c_ulong) << 30)
+            | ((b'V' as libc::c_ulong) << 8)
+            | (102 as libc::c_ulong)
+            | ((mem::size_of::<v4l::v4l2_dbg_chip_info>() as libc::c_ulong) << 16);
+        assert_eq!(codes::VIDIOC_DBG_G_CHIP_INFO, VIDIOC_DBG_G_CHIP_INFO);
     }
 }
@@ -1,3 +1,8 @@
 #include <libv4l1.h>
 #include <libv4l2.h>
 #include <libv4lconvert.h>
+#include <linux/cec.h>
+#include <linux/media.h>
+#include <linux/usb/video.h>
+#include <linux/uvcvideo.h>
+#include <linux/v4l2-subdev.h>